@@ -0,0 +1,143 @@
+/// A source passage and its translation
+pub struct Segment {
+    pub source: String,
+    pub target: String,
+}
+
+/// Pair the OCR text of a page with its translation, sentence by sentence
+/// where possible. Paragraphs are aligned first (the translation normally
+/// keeps the paragraph structure), then the sentences inside each aligned
+/// paragraph pair. Both steps use length-based dynamic programming, so a
+/// sentence that was split or merged in translation ends up in one segment
+/// with its counterpart instead of shifting every following pair.
+pub fn align(source: &str, target: &str) -> Vec<Segment> {
+    let source_paragraphs: Vec<&str> = source.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+    let target_paragraphs: Vec<&str> = target.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+
+    let mut segments = Vec::new();
+    for (src, tgt) in align_units(&source_paragraphs, &target_paragraphs) {
+        // Headings and list items stay whole
+        if src.len() > 1 || tgt.len() > 1 || is_structural(src.first()) || is_structural(tgt.first()) {
+            segments.push(Segment { source: join(&src), target: join(&tgt) });
+            continue;
+        }
+        let source_sentences = src.first().map(|p| split_sentences(p)).unwrap_or_default();
+        let target_sentences = tgt.first().map(|p| split_sentences(p)).unwrap_or_default();
+        for (s, t) in align_units(&source_sentences, &target_sentences) {
+            segments.push(Segment { source: join(&s), target: join(&t) });
+        }
+    }
+    segments
+}
+
+fn is_structural(paragraph: Option<&&str>) -> bool {
+    paragraph.is_some_and(|p| p.starts_with('#') || crate::pdf::is_list_item(p))
+}
+
+/// Cost of leaving a unit unpaired, and the extra cost of a 2–1 / 1–2 merge
+const SKIP_COST: f64 = 1.0;
+const MERGE_COST: f64 = 0.25;
+
+/// Align two sequences of text units by length (1–1, 2–1, 1–2, 1–0, 0–1),
+/// scaled by the overall length ratio between the two languages
+fn align_units<'a>(source: &[&'a str], target: &[&'a str]) -> Vec<(Vec<&'a str>, Vec<&'a str>)> {
+    let (n, m) = (source.len(), target.len());
+    let len = |s: &str| s.chars().filter(|c| !c.is_whitespace()).count() as f64;
+    let source_total: f64 = source.iter().map(|s| len(s)).sum();
+    let target_total: f64 = target.iter().map(|s| len(s)).sum();
+    let ratio = if source_total > 0.0 { target_total / source_total } else { 1.0 };
+    let mismatch = |a: f64, b: f64| {
+        let a = a * ratio;
+        (a - b).abs() / (a + b + 1.0)
+    };
+
+    const MOVES: [(usize, usize); 5] = [(1, 1), (2, 1), (1, 2), (1, 0), (0, 1)];
+    let mut cost = vec![vec![f64::INFINITY; m + 1]; n + 1];
+    let mut back = vec![vec![(0, 0); m + 1]; n + 1];
+    cost[0][0] = 0.0;
+    for i in 0..=n {
+        for j in 0..=m {
+            if cost[i][j].is_infinite() {
+                continue;
+            }
+            for (di, dj) in MOVES {
+                if i + di > n || j + dj > m {
+                    continue;
+                }
+                let step = match (di, dj) {
+                    (1, 0) | (0, 1) => SKIP_COST,
+                    _ => {
+                        let a: f64 = source[i..i + di].iter().map(|s| len(s)).sum();
+                        let b: f64 = target[j..j + dj].iter().map(|s| len(s)).sum();
+                        let merge = if di + dj > 2 { MERGE_COST } else { 0.0 };
+                        mismatch(a, b) + merge
+                    }
+                };
+                if cost[i][j] + step < cost[i + di][j + dj] {
+                    cost[i + di][j + dj] = cost[i][j] + step;
+                    back[i + di][j + dj] = (di, dj);
+                }
+            }
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (n, m);
+    while i > 0 || j > 0 {
+        let (di, dj) = back[i][j];
+        pairs.push((source[i - di..i].to_vec(), target[j - dj..j].to_vec()));
+        i -= di;
+        j -= dj;
+    }
+    pairs.reverse();
+    pairs
+}
+
+/// Split a paragraph after sentence-ending punctuation, keeping closing
+/// quotes/brackets with their sentence. A Latin full stop only ends a
+/// sentence when followed by whitespace (so "3.14" and "e.g.x" stay whole).
+fn split_sentences(paragraph: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = paragraph.char_indices().collect();
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let (_, c) = chars[i];
+        let ends = match c {
+            '。' | '！' | '？' | '；' | '…' => true,
+            '.' | '!' | '?' | ';' => chars.get(i + 1).is_none_or(|(_, next)| next.is_whitespace() || !next.is_ascii()),
+            _ => false,
+        };
+        i += 1;
+        if ends {
+            while let Some((_, next)) = chars.get(i)
+                && matches!(next, '」' | '』' | '”' | '’' | '"' | '\'' | ')' | '）' | '】' | '》' | '。' | '！' | '？' | '…')
+            {
+                i += 1;
+            }
+            let end = chars.get(i).map_or(paragraph.len(), |(pos, _)| *pos);
+            let sentence = paragraph[start..end].trim();
+            if !sentence.is_empty() {
+                sentences.push(sentence);
+            }
+            start = end;
+        }
+    }
+    let rest = paragraph[start..].trim();
+    if !rest.is_empty() {
+        sentences.push(rest);
+    }
+    sentences
+}
+
+/// Join units back into one passage; Latin text needs a space between them
+fn join(units: &[&str]) -> String {
+    let mut out = String::new();
+    for unit in units {
+        if out.ends_with(|c: char| c.is_ascii_graphic()) {
+            out.push(' ');
+        }
+        out.push_str(unit);
+    }
+    out
+}
@@ -0,0 +1,169 @@
+use lopdf::{Dictionary, Document, Object, ObjectId, StringFormat};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::error::PdfError;
+use crate::pdf::decode_text_string;
+
+/// Text a PDF carries outside its page content: the comment of a note,
+/// free-text or markup annotation, or the value of a text form field
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Annotation {
+    /// Object number and generation of the annotation or field
+    pub object: ObjectId,
+    /// `Contents` for a comment, `V` for a field value
+    pub key: String,
+    pub text: String,
+}
+
+/// Every comment and text field value in the document. Annotations written
+/// inline in a page's list rather than as objects of their own cannot be
+/// addressed afterwards and are left out.
+pub fn extract(data: &[u8]) -> Result<Vec<Annotation>, PdfError> {
+    let doc = Document::load_mem(data).map_err(|e| PdfError::Parse(e.to_string()))?;
+    let mut found = Vec::new();
+    for page_id in doc.get_pages().into_values() {
+        let Ok(page) = doc.get_dictionary(page_id) else { continue };
+        for id in references(&doc, page.get(b"Annots").ok()) {
+            let Ok(annot) = doc.get_dictionary(id) else { continue };
+            // Widgets are form fields, found below; links and pop-ups have no comment of their own
+            let subtype = annot.get(b"Subtype").and_then(Object::as_name).unwrap_or_default();
+            if !matches!(subtype, b"Widget" | b"Link" | b"Popup") {
+                push_text(&doc, &mut found, id, annot, b"Contents");
+            }
+        }
+    }
+
+    let fields = doc.catalog().ok()
+        .and_then(|catalog| catalog.get(b"AcroForm").ok())
+        .and_then(|form| doc.dereference(form).ok())
+        .and_then(|(_, form)| form.as_dict().ok())
+        .and_then(|form| form.get(b"Fields").ok());
+    let mut pending = references(&doc, fields);
+    // Malformed files can make Kids loop back
+    let mut seen = HashSet::new();
+    while let Some(id) = pending.pop() {
+        if !seen.insert(id) {
+            continue;
+        }
+        let Ok(field) = doc.get_dictionary(id) else { continue };
+        pending.extend(references(&doc, field.get(b"Kids").ok()));
+        if field_type(&doc, field) == Some(b"Tx".as_slice()) {
+            push_text(&doc, &mut found, id, field, b"V");
+        }
+    }
+    Ok(found)
+}
+
+/// A copy of the document with each annotation's text replaced. Viewers are
+/// asked to redraw form fields, whose stored appearance shows the old value.
+pub fn apply(data: &[u8], annotations: &[Annotation]) -> Result<Vec<u8>, PdfError> {
+    let mut doc = Document::load_mem(data).map_err(|e| PdfError::Parse(e.to_string()))?;
+    let mut fields = false;
+    for annotation in annotations {
+        let Ok(dict) = doc.get_object_mut(annotation.object).and_then(Object::as_dict_mut) else { continue };
+        dict.set(annotation.key.as_bytes().to_vec(), text_string(&annotation.text));
+        fields |= annotation.key == "V";
+    }
+    if fields {
+        let form = match doc.catalog().and_then(|c| c.get(b"AcroForm")) {
+            Ok(Object::Reference(id)) => {
+                let id = *id;
+                doc.get_object_mut(id).and_then(Object::as_dict_mut).ok()
+            }
+            _ => doc.catalog_mut().and_then(|c| c.get_mut(b"AcroForm")).and_then(Object::as_dict_mut).ok(),
+        };
+        if let Some(form) = form {
+            form.set("NeedAppearances", true);
+        }
+    }
+    let mut out = Vec::new();
+    doc.save_to(&mut out)
+        .map_err(PdfError::io("Failed to write annotated PDF"))?;
+    Ok(out)
+}
+
+/// Object references in an array, itself possibly behind a reference
+fn references(doc: &Document, array: Option<&Object>) -> Vec<ObjectId> {
+    array
+        .and_then(|a| doc.dereference(a).ok())
+        .and_then(|(_, a)| a.as_array().ok())
+        .map(|items| items.iter().filter_map(|item| item.as_reference().ok()).collect())
+        .unwrap_or_default()
+}
+
+/// A field's type, inherited from its parent when not set on the field
+fn field_type<'a>(doc: &'a Document, field: &'a Dictionary) -> Option<&'a [u8]> {
+    let mut current = field;
+    for _ in 0..8 {
+        if let Ok(kind) = current.get(b"FT").and_then(Object::as_name) {
+            return Some(kind);
+        }
+        current = doc.get_dictionary(current.get(b"Parent").and_then(Object::as_reference).ok()?).ok()?;
+    }
+    None
+}
+
+fn push_text(doc: &Document, found: &mut Vec<Annotation>, object: ObjectId, dict: &Dictionary, key: &[u8]) {
+    let Some((_, Object::String(bytes, _))) = dict.get(key).ok().and_then(|v| doc.dereference(v).ok()) else { return };
+    let text = decode_text_string(bytes);
+    if !text.trim().is_empty() {
+        found.push(Annotation { object, key: String::from_utf8_lossy(key).into_owned(), text });
+    }
+}
+
+/// UTF-16BE with a byte order mark, which every PDF reader takes for text strings
+fn text_string(text: &str) -> Object {
+    let mut bytes = vec![0xFE, 0xFF];
+    bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+    Object::String(bytes, StringFormat::Hexadecimal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One page with a sticky note, a link and a text field
+    fn annotated_pdf() -> Vec<u8> {
+        use lopdf::{Document, Object, dictionary};
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let note = doc.add_object(dictionary! { "Type" => "Annot", "Subtype" => "Text", "Contents" => Object::string_literal("Check this figure") });
+        let link = doc.add_object(dictionary! { "Type" => "Annot", "Subtype" => "Link", "Contents" => Object::string_literal("https://example.com") });
+        let field = doc.add_object(dictionary! { "FT" => "Tx", "T" => Object::string_literal("name"), "V" => Object::string_literal("Full name") });
+        let page = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+            "Annots" => vec![note.into(), link.into()],
+        });
+        doc.objects.insert(pages_id, Object::Dictionary(dictionary! { "Type" => "Pages", "Kids" => vec![page.into()], "Count" => 1 }));
+        let catalog = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+            "AcroForm" => dictionary! { "Fields" => vec![field.into()] },
+        });
+        doc.trailer.set("Root", catalog);
+        let mut out = Vec::new();
+        doc.save_to(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn annotations_are_translated_in_place() {
+        let data = annotated_pdf();
+        let found = extract(&data).unwrap();
+        let texts: Vec<(&str, &str)> = found.iter().map(|a| (a.key.as_str(), a.text.as_str())).collect();
+        assert_eq!(texts, [("Contents", "Check this figure"), ("V", "Full name")]);
+
+        let translated: Vec<_> = found.into_iter()
+            .map(|a| Annotation { text: format!("译:{}", a.text), ..a })
+            .collect();
+        let output = apply(&data, &translated).unwrap();
+        let texts: Vec<String> = extract(&output).unwrap().into_iter().map(|a| a.text).collect();
+        assert_eq!(texts, ["译:Check this figure", "译:Full name"]);
+        let doc = lopdf::Document::load_mem(&output).unwrap();
+        let form = doc.catalog().unwrap().get(b"AcroForm").unwrap().as_dict().unwrap();
+        assert!(form.get(b"NeedAppearances").unwrap().as_bool().unwrap());
+    }
+}
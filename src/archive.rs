@@ -0,0 +1,66 @@
+use async_zip::base::write::ZipFileWriter;
+use async_zip::{Compression, ZipEntryBuilder};
+use axum::body::Body;
+use std::path::PathBuf;
+use tokio::io::DuplexStream;
+use tokio_util::compat::FuturesAsyncWriteCompatExt;
+use tokio_util::io::ReaderStream;
+
+/// Bytes buffered between the archive writer and the response
+const PIPE_SIZE: usize = 64 * 1024;
+
+/// What goes into an archive under a given name
+pub enum ArchiveEntry {
+    /// Copied from disk while the archive is sent; skipped if missing
+    File { name: String, path: PathBuf },
+    Text { name: String, text: String },
+}
+
+/// A ZIP archive of `entries` as a response body. Entries are read and
+/// compressed one at a time while the client downloads, so memory use stays
+/// flat however large the bundle is; a write error cuts the archive short.
+pub fn stream_zip(entries: Vec<ArchiveEntry>) -> Body {
+    let (reader, writer) = tokio::io::duplex(PIPE_SIZE);
+    tokio::spawn(async move {
+        if let Err(e) = write_zip(writer, entries).await {
+            eprintln!("[archive] 写入压缩包失败: {}", e);
+        }
+    });
+    Body::from_stream(ReaderStream::new(reader))
+}
+
+async fn write_zip(writer: DuplexStream, entries: Vec<ArchiveEntry>) -> Result<(), String> {
+    let mut zip = ZipFileWriter::with_tokio(writer);
+    for entry in entries {
+        match entry {
+            ArchiveEntry::File { name, path } => {
+                let Ok(mut file) = tokio::fs::File::open(&path).await else {
+                    continue;
+                };
+                let mut out = zip.write_entry_stream(entry_builder(&name)).await
+                    .map_err(|e| format!("{}: {}", name, e))?
+                    .compat_write();
+                tokio::io::copy(&mut file, &mut out).await
+                    .map_err(|e| format!("{}: {}", name, e))?;
+                out.into_inner().close().await.map_err(|e| format!("{}: {}", name, e))?;
+            }
+            ArchiveEntry::Text { name, text } => {
+                zip.write_entry_whole(entry_builder(&name), text.as_bytes()).await
+                    .map_err(|e| format!("{}: {}", name, e))?;
+            }
+        }
+    }
+    // Dropping the writer ends the response
+    zip.close().await.map(drop).map_err(|e| e.to_string())
+}
+
+/// PDFs and images are already compressed; only text is deflated
+fn entry_builder(name: &str) -> ZipEntryBuilder {
+    let lower = name.to_ascii_lowercase();
+    let compression = if [".txt", ".md", ".json"].iter().any(|ext| lower.ends_with(ext)) {
+        Compression::Deflate
+    } else {
+        Compression::Stored
+    };
+    ZipEntryBuilder::new(name.to_string().into(), compression)
+}
@@ -0,0 +1,112 @@
+use crate::lang::TargetLang;
+
+/// Thresholds for the post-translation checks; 0 turns a rule off
+#[derive(Clone, Copy, Debug)]
+pub struct CheckRules {
+    /// Longest run of ordinary English-like words allowed in a non-Latin translation
+    pub latin_words: usize,
+    /// Most letters from a script the target language doesn't use allowed on one line
+    pub foreign_chars: usize,
+    /// Quote the offending text in the description; off in privacy mode
+    pub quote: bool,
+}
+
+/// Look for signs that part of a page was left untranslated, depending on the
+/// target language: long runs of Latin words in a CJK/Cyrillic translation
+/// (code, URLs and capitalised names don't count), or letters from another
+/// script such as CJK left in an English one. Returns a description of the
+/// first problem found.
+pub fn check_translation(text: &str, target: TargetLang, rules: &CheckRules) -> Option<String> {
+    let text = strip_code(text);
+    if rules.foreign_chars > 0
+        && let Some(line) = foreign_line(&text, target, rules.foreign_chars)
+    {
+        return Some(format!("译文中残留其他文字: {}", excerpt(&line, rules.quote)));
+    }
+    if rules.latin_words > 0
+        && !target.is_latin()
+        && let Some(run) = latin_run(&text, rules.latin_words)
+    {
+        return Some(format!("译文中疑似有未翻译的段落: {}", excerpt(&run, rules.quote)));
+    }
+    None
+}
+
+/// Drop fenced blocks, inline code and URLs, which are expected to stay as-is
+fn strip_code(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_fence = false;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+        for (i, part) in line.split('`').enumerate() {
+            // Odd parts sit between backticks
+            if i % 2 == 0 {
+                let words: Vec<&str> = part
+                    .split(' ')
+                    .filter(|w| !w.starts_with("http://") && !w.starts_with("https://") && !w.starts_with("www."))
+                    .collect();
+                out.push_str(&words.join(" "));
+            }
+            out.push(' ');
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// First line holding at least `min_chars` letters of a foreign script;
+/// counted per line rather than per run, since leftover Japanese alternates
+/// kana with kanji that a Chinese translation would share
+fn foreign_line(text: &str, target: TargetLang, min_chars: usize) -> Option<String> {
+    text.lines()
+        .find(|line| line.chars().filter(|c| target.is_foreign_letter(*c)).count() >= min_chars)
+        .map(|line| line.trim().to_string())
+}
+
+fn latin_run(text: &str, min_words: usize) -> Option<String> {
+    // Non-ASCII characters (the translation itself) end a run
+    for segment in text.split(|c: char| !c.is_ascii() || c == '\n') {
+        let mut run: Vec<&str> = Vec::new();
+        for token in segment.split_whitespace().chain([""]) {
+            let word = token.trim_matches(|c: char| c.is_ascii_punctuation());
+            let letters = word.chars().filter(|c| c.is_ascii_alphabetic()).count();
+            if letters >= 2 && letters * 2 > word.len() {
+                run.push(word);
+                continue;
+            }
+            // Numbers and symbols don't count but don't end the run either
+            if !token.is_empty() && letters == 0 {
+                continue;
+            }
+            if is_prose(&run, min_words) {
+                return Some(run.join(" "));
+            }
+            run.clear();
+        }
+    }
+    None
+}
+
+/// Enough words, mostly lowercase (a run of capitalised words is a name or title)
+fn is_prose(words: &[&str], min_words: usize) -> bool {
+    if words.len() < min_words {
+        return false;
+    }
+    let capitalised = words.iter().filter(|w| w.starts_with(|c: char| c.is_ascii_uppercase())).count();
+    capitalised * 2 < words.len()
+}
+
+fn excerpt(run: &str, quote: bool) -> String {
+    if !quote {
+        return format!("({} 字符)", run.chars().count());
+    }
+    let mut chars = run.chars();
+    let head: String = chars.by_ref().take(40).collect();
+    if chars.next().is_some() { format!("\"{}…\"", head) } else { format!("\"{}\"", head) }
+}
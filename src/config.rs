@@ -1,26 +1,550 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use crate::check::CheckRules;
+use crate::connector::SourceKind;
+use crate::destination::{DestinationKind, S3Credentials};
+use crate::filename::{OutputNamePattern, PageNamePattern};
+use crate::font::FallbackFont;
+use crate::hooks::HookPoint;
+use crate::lang::TargetLang;
+use crate::pdf::{ImageFormat, Layout, OutputMode, Romanize};
+use crate::pii::PiiPolicy;
+use crate::policy::ContentPolicy;
+use crate::provider::ProviderKind;
+
 #[derive(Clone)]
 pub struct Config {
+    /// API the endpoint speaks (PROVIDER)
+    pub provider: ProviderKind,
     pub base_url: String,
+    /// Empty for a local Ollama server without authentication
     pub api_key: String,
     pub ocr_model: String,
     pub translate_model: String,
     pub ocr_model_fallback: Option<String>,
+    /// Page image encoding sent to the OCR model
+    pub ocr_image_format: ImageFormat,
+    /// JPEG quality of the normal rendition; downgrades never exceed it
+    pub ocr_image_quality: u8,
+    /// Encoding for the fallback OCR model, if it differs; images are converted before sending
+    pub ocr_image_format_fallback: Option<ImageFormat>,
     pub translate_model_fallback: Option<String>,
+    /// Models an upload may pick instead of OCR_MODEL or MODEL (ALLOWED_MODELS)
+    pub allowed_models: Vec<String>,
+    pub ocr_timeout_secs: u64,
+    pub translate_timeout_secs: u64,
+    pub ocr_max_retries: u32,
+    pub translate_max_retries: u32,
+    /// Streaming responses; only honoured by the OpenAI-compatible provider
+    pub api_stream: bool,
+    /// Closing paragraphs of the previous page's translation given with each
+    /// page for consistent terms; pages of a batch are then translated in
+    /// order. 0 translates every page on its own, fully in parallel.
+    pub translate_context: usize,
+    /// Tasks processed at the same time; further uploads are refused
+    pub max_concurrent_tasks: usize,
+    /// Background tasks processed at the same time, on top of the regular ones
+    pub max_background_tasks: usize,
+    /// Page-level API requests in flight across all tasks
+    pub api_concurrency: usize,
+    /// Pages of one task being recognized at once
+    pub page_concurrency: usize,
+    /// Worker threads for rendering, image re-encoding and PDF assembly
+    pub cpu_workers: usize,
+    /// Cloud storage connectors uploads may pull from; none by default
+    pub remote_sources: Vec<SourceKind>,
+    pub remote_timeout_secs: u64,
+    /// POST /upload-url fetches documents from client-given links
+    pub url_upload: bool,
+    /// Redirects followed when fetching a document by URL
+    pub url_upload_max_redirects: usize,
+    /// Cloud storage finished results may be pushed to; none by default
+    pub remote_destinations: Vec<DestinationKind>,
+    /// Credentials for S3 destinations, when configured
+    pub s3: Option<S3Credentials>,
+    /// Refuse to start unless every outbound endpoint is local
+    pub local_only: bool,
+    /// Host names accepted as local besides loopback and private addresses
+    pub local_hosts: Vec<String>,
+    /// Previews, logs and page warnings carry only counts and hashes, never document text
+    pub privacy_mode: bool,
+    /// Masking of personal data before translation
+    pub pii_redaction: PiiPolicy,
+    /// Model that finds person names to mask as well; best a local one
+    pub pii_name_model: Option<String>,
+    /// Categories of content to refuse or route elsewhere, checked after OCR
+    pub content_policy: Option<ContentPolicy>,
+    /// External HTTP hooks by pipeline stage; none by default
+    pub hooks: HashMap<HookPoint, String>,
+    pub hook_timeout_secs: u64,
+    /// A page with no API activity for this long is aborted as stalled; 0 disables
+    pub stall_timeout_secs: u64,
+    pub quota_monthly_tokens: Option<u64>,
+    pub quota_monthly_cost: Option<f64>,
+    pub token_price_per_million: Option<f64>,
+    pub admin_token: Option<String>,
+    /// (name, token) pairs; a request with `Authorization: Bearer <token>` acts as that user
+    pub user_tokens: Vec<(String, String)>,
+    pub best_effort: bool,
+    pub output_layout: Layout,
+    pub output_mode: OutputMode,
+    pub target_lang: TargetLang,
+    pub romanize: Romanize,
+    pub already_translated: AlreadyTranslated,
+    /// Pages processed by a sample (preview) upload
+    pub sample_pages: usize,
+    /// Output pages rendered as preview images once a task completes; 0 disables
+    pub preview_pages: usize,
+    /// Names of the per-page text files in a ZIP download
+    pub page_file_pattern: PageNamePattern,
+    /// Names of downloaded and pushed output files
+    pub output_name_pattern: OutputNamePattern,
+    /// Also translate the source PDF's comments and text form fields into a
+    /// copy of it
+    pub translate_annotations: bool,
+    /// Untranslated-text checks on each page's translation; None when disabled
+    pub post_check: Option<CheckRules>,
+    pub cover_template: Option<String>,
+    pub cover_disclaimer: Option<String>,
+    pub api_warmup: bool,
+    pub api_keepalive_secs: Option<u64>,
+    /// Directory holding tasks, jobs and the server's stores
+    pub data_dir: std::path::PathBuf,
+    /// Budget for everything under data/tasks and data/jobs; uploads are
+    /// refused once it is reached
+    pub data_max_bytes: Option<u64>,
+    /// Hours a complete or skipped task is kept before its files are deleted; forever when None
+    pub retain_complete_hours: Option<u64>,
+    /// Hours a failed task is kept; forever when None
+    pub retain_failed_hours: Option<u64>,
+    /// Hours a deleted task stays in the trash, restorable, before its files
+    /// go; 0 deletes right away
+    pub trash_hours: u64,
+    /// Seconds between writes of running tasks' progress changes that are
+    /// not saved as they happen; None turns snapshots off
+    pub progress_snapshot_secs: Option<u64>,
+    /// Rendered index page; None when the built-in UI is disabled (API-only)
+    pub index_page: Option<String>,
+    /// Embedded body font per output language (`FONT_PATH` / `FONT_PATH_<LANG>`)
+    pub body_fonts: HashMap<TargetLang, Arc<FallbackFont>>,
+    pub fallback_fonts: Vec<Arc<FallbackFont>>,
+    pub hyphenation: bool,
+    pub pdf_timestamp: bool,
+    pub source_date_epoch: Option<i64>,
+    /// pdfium library file or directory; searched next to the binary and on the system path otherwise
+    pub pdfium_path: Option<String>,
+    pub renderer: crate::render::Renderer,
+    /// pdftoppm executable or the directory holding it; looked up on PATH when unset
+    pub pdftoppm_path: Option<std::path::PathBuf>,
+    /// Directory pdftoppm renders pages into; the system temp dir when unset
+    pub render_temp_dir: Option<std::path::PathBuf>,
+}
+
+/// What to do with an upload that is already in the target language
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AlreadyTranslated {
+    /// Translate as usual (pages in the target language are passed through)
+    Off,
+    /// Stop early with a "nothing to translate" result
+    #[default]
+    Detect,
+    /// Skip OCR and translation and typeset the text layer as the output
+    Retypeset,
+}
+
+impl AlreadyTranslated {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "off" | "0" => Some(AlreadyTranslated::Off),
+            "detect" | "skip" | "1" => Some(AlreadyTranslated::Detect),
+            "retypeset" => Some(AlreadyTranslated::Retypeset),
+            _ => None,
+        }
+    }
 }
 
 impl Config {
     pub fn from_env() -> Self {
-        Self {
-            base_url: std::env::var("BASE_URL")
+        let provider = std::env::var("PROVIDER").ok()
+            .filter(|s| !s.is_empty())
+            .map(|s| ProviderKind::parse(&s).unwrap_or_else(|| panic!("Unknown PROVIDER: {}", s)))
+            .unwrap_or_default();
+        let config = Self {
+            provider,
+            base_url: std::env::var("BASE_URL").ok()
+                .filter(|s| !s.is_empty())
+                .or_else(|| provider.default_base_url().map(String::from))
                 .expect("BASE_URL environment variable is required"),
-            api_key: std::env::var("API_KEY")
-                .expect("API_KEY environment variable is required"),
+            api_key: match std::env::var("API_KEY") {
+                Ok(key) => key,
+                Err(_) if provider == ProviderKind::Ollama => String::new(),
+                Err(_) => panic!("API_KEY environment variable is required"),
+            },
             ocr_model: std::env::var("OCR_MODEL")
                 .unwrap_or_else(|_| "gemini-3-flash-preview".to_string()),
             translate_model: std::env::var("MODEL")
                 .unwrap_or_else(|_| "gpt-5.2".to_string()),
             ocr_model_fallback: std::env::var("OCR_MODEL_FALLBACK").ok().filter(|s| !s.is_empty()),
+            ocr_image_format: image_format("OCR_IMAGE_FORMAT").unwrap_or_default(),
+            ocr_image_quality: env_parse("OCR_IMAGE_QUALITY").filter(|q| (1..=100).contains(q)).unwrap_or(70),
+            ocr_image_format_fallback: image_format("OCR_IMAGE_FORMAT_FALLBACK"),
             translate_model_fallback: std::env::var("MODEL_FALLBACK").ok().filter(|s| !s.is_empty()),
+            allowed_models: std::env::var("ALLOWED_MODELS").unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+                .collect(),
+            ocr_timeout_secs: env_parse("OCR_TIMEOUT_SECS").filter(|s| *s > 0).unwrap_or(90),
+            translate_timeout_secs: env_parse("TRANSLATE_TIMEOUT_SECS").filter(|s| *s > 0).unwrap_or(30),
+            ocr_max_retries: env_parse("OCR_MAX_RETRIES").unwrap_or(3),
+            translate_max_retries: env_parse("TRANSLATE_MAX_RETRIES").unwrap_or(3),
+            api_stream: env_flag("API_STREAM", false) && provider.supports_stream(),
+            translate_context: env_parse("TRANSLATE_CONTEXT").unwrap_or(3),
+            max_concurrent_tasks: env_count("MAX_CONCURRENT_TASKS").unwrap_or(1),
+            max_background_tasks: env_parse("MAX_BACKGROUND_TASKS").unwrap_or(1),
+            api_concurrency: env_count("API_CONCURRENCY").unwrap_or(3),
+            page_concurrency: env_count("PAGE_CONCURRENCY").unwrap_or(3),
+            cpu_workers: env_parse("CPU_WORKERS").filter(|n| *n > 0)
+                .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get())),
+            remote_sources: std::env::var("REMOTE_SOURCES").unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(|s| SourceKind::parse(s).unwrap_or_else(|| panic!("Unknown REMOTE_SOURCES entry: {}", s)))
+                .collect(),
+            remote_timeout_secs: env_parse("REMOTE_TIMEOUT_SECS").filter(|s| *s > 0).unwrap_or(300),
+            url_upload: env_flag("URL_UPLOAD", true),
+            url_upload_max_redirects: env_parse("URL_UPLOAD_MAX_REDIRECTS").unwrap_or(5),
+            remote_destinations: std::env::var("REMOTE_DESTINATIONS").unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(|s| DestinationKind::parse(s).unwrap_or_else(|| panic!("Unknown REMOTE_DESTINATIONS entry: {}", s)))
+                .collect(),
+            s3: s3_credentials(),
+            local_only: env_flag("LOCAL_ONLY", false),
+            local_hosts: std::env::var("LOCAL_HOSTS").unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_ascii_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            privacy_mode: env_flag("PRIVACY_MODE", false),
+            pii_redaction: std::env::var("PII_REDACTION").ok()
+                .map(|s| PiiPolicy::parse(&s).unwrap_or_else(|| panic!("Unknown PII_REDACTION: {}", s)))
+                .unwrap_or_default(),
+            pii_name_model: std::env::var("PII_NAME_MODEL").ok().filter(|s| !s.is_empty()),
+            content_policy: std::env::var("CONTENT_POLICY").ok().filter(|s| !s.trim().is_empty()).map(|s| ContentPolicy {
+                rules: ContentPolicy::parse_rules(&s).unwrap_or_else(|rule| panic!("Unknown CONTENT_POLICY rule: {}", rule)),
+                model: std::env::var("CONTENT_POLICY_MODEL").ok().filter(|s| !s.is_empty()),
+            }),
+            hooks: HookPoint::ALL.iter()
+                .filter_map(|point| std::env::var(point.env_name()).ok().filter(|s| !s.is_empty()).map(|url| (*point, url)))
+                .collect(),
+            hook_timeout_secs: env_parse("HOOK_TIMEOUT_SECS").filter(|s| *s > 0).unwrap_or(30),
+            stall_timeout_secs: env_parse("STALL_TIMEOUT_SECS").unwrap_or(300),
+            quota_monthly_tokens: env_parse("QUOTA_MONTHLY_TOKENS").filter(|v| *v > 0),
+            quota_monthly_cost: env_parse("QUOTA_MONTHLY_COST").filter(|v: &f64| *v > 0.0),
+            token_price_per_million: env_parse("TOKEN_PRICE_PER_MILLION"),
+            admin_token: std::env::var("ADMIN_TOKEN").ok().filter(|s| !s.is_empty()),
+            user_tokens: load_user_tokens(),
+            best_effort: env_flag("BEST_EFFORT", false),
+            output_layout: std::env::var("OUTPUT_LAYOUT").ok()
+                .filter(|s| !s.is_empty())
+                .map(|s| Layout::parse(&s).unwrap_or_else(|| panic!("Unknown OUTPUT_LAYOUT: {}", s)))
+                .unwrap_or_default(),
+            output_mode: std::env::var("OUTPUT_MODE").ok()
+                .filter(|s| !s.is_empty())
+                .map(|s| OutputMode::parse(&s).unwrap_or_else(|| panic!("Unknown OUTPUT_MODE: {}", s)))
+                .unwrap_or_default(),
+            target_lang: std::env::var("TARGET_LANG").ok()
+                .filter(|s| !s.is_empty())
+                .map(|s| TargetLang::parse(&s).unwrap_or_else(|| panic!("Unknown TARGET_LANG: {}", s)))
+                .unwrap_or_default(),
+            romanize: std::env::var("ROMANIZE").ok()
+                .filter(|s| !s.is_empty())
+                .map(|s| Romanize::parse(&s).unwrap_or_else(|| panic!("Unknown ROMANIZE: {}", s)))
+                .unwrap_or_default(),
+            already_translated: std::env::var("ALREADY_TRANSLATED").ok()
+                .filter(|s| !s.is_empty())
+                .map(|s| AlreadyTranslated::parse(&s).unwrap_or_else(|| panic!("Unknown ALREADY_TRANSLATED: {}", s)))
+                .unwrap_or_default(),
+            sample_pages: env_parse("SAMPLE_PAGES").filter(|n| *n > 0).unwrap_or(3),
+            preview_pages: env_parse("PREVIEW_PAGES").unwrap_or(3),
+            page_file_pattern: std::env::var("PAGE_FILE_PATTERN").ok()
+                .filter(|s| !s.trim().is_empty())
+                .map(|s| PageNamePattern::parse(&s).unwrap_or_else(|e| panic!("Invalid PAGE_FILE_PATTERN: {}", e)))
+                .unwrap_or_default(),
+            output_name_pattern: std::env::var("OUTPUT_NAME_PATTERN").ok()
+                .filter(|s| !s.trim().is_empty())
+                .map(|s| OutputNamePattern::parse(&s).unwrap_or_else(|e| panic!("Invalid OUTPUT_NAME_PATTERN: {}", e)))
+                .unwrap_or_default(),
+            translate_annotations: env_flag("TRANSLATE_ANNOTATIONS", false),
+            post_check: env_flag("POST_CHECK", true).then(|| CheckRules {
+                latin_words: env_parse("POST_CHECK_LATIN_WORDS").unwrap_or(8),
+                foreign_chars: env_parse("POST_CHECK_FOREIGN_CHARS").unwrap_or(6),
+                quote: !env_flag("PRIVACY_MODE", false),
+            }),
+            cover_template: load_cover_template(),
+            cover_disclaimer: std::env::var("COVER_DISCLAIMER").ok().filter(|s| !s.is_empty()),
+            api_warmup: env_flag("API_WARMUP", false),
+            api_keepalive_secs: env_parse::<u64>("API_KEEPALIVE_SECS").filter(|s| *s > 0),
+            data_dir: std::env::var_os("DATA_DIR").filter(|s| !s.is_empty()).map_or_else(|| "data".into(), Into::into),
+            data_max_bytes: env_parse::<u64>("DATA_MAX_BYTES").filter(|b| *b > 0),
+            retain_complete_hours: env_parse::<u64>("RETAIN_COMPLETE_HOURS").filter(|h| *h > 0),
+            retain_failed_hours: env_parse::<u64>("RETAIN_FAILED_HOURS").filter(|h| *h > 0),
+            trash_hours: env_parse::<u64>("TRASH_HOURS").unwrap_or(72),
+            progress_snapshot_secs: Some(env_parse::<u64>("PROGRESS_SNAPSHOT_SECS").unwrap_or(10)).filter(|s| *s > 0),
+            index_page: load_index_page(),
+            body_fonts: load_body_fonts(),
+            fallback_fonts: load_fallback_fonts(),
+            hyphenation: env_flag("HYPHENATION", false),
+            pdf_timestamp: env_flag("PDF_TIMESTAMP", false),
+            source_date_epoch: env_parse("SOURCE_DATE_EPOCH"),
+            pdfium_path: std::env::var("PDFIUM_PATH").ok().filter(|s| !s.is_empty()),
+            renderer: std::env::var("RENDERER").ok()
+                .filter(|s| !s.trim().is_empty())
+                .map(|s| crate::render::Renderer::parse(&s).unwrap_or_else(|| panic!("Unknown RENDERER: {}", s)))
+                .unwrap_or_default(),
+            pdftoppm_path: std::env::var("PDFTOPPM_PATH").ok().filter(|s| !s.trim().is_empty()).map(Into::into),
+            render_temp_dir: std::env::var("RENDER_TEMP_DIR").ok().filter(|s| !s.trim().is_empty()).map(Into::into),
+        };
+        if config.romanize == Romanize::Original && !config.output_mode.needs_originals() {
+            panic!("ROMANIZE=original requires OUTPUT_MODE=bilingual or interlinear");
+        }
+        if config.local_only
+            && let Err(e) = config.check_local_only()
+        {
+            panic!("LOCAL_ONLY: {}", e);
+        }
+        config
+    }
+
+    /// Time stamped into outputs: SOURCE_DATE_EPOCH when set (reproducible output), otherwise now
+    pub fn output_time(&self) -> chrono::DateTime<chrono::Utc> {
+        self.source_date_epoch
+            .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+            .unwrap_or_else(chrono::Utc::now)
+    }
+
+    /// Every endpoint the server may call must be local; connectors that
+    /// fetch from or push to client-given URLs must be off (URL uploads are
+    /// checked per request)
+    fn check_local_only(&self) -> Result<(), String> {
+        if !is_local_url(&self.base_url, &self.local_hosts) {
+            return Err(format!("BASE_URL {} is not a local endpoint (add its host to LOCAL_HOSTS if it is)", self.base_url));
+        }
+        for point in HookPoint::ALL {
+            if let Some(url) = self.hooks.get(&point)
+                && !is_local_url(url, &self.local_hosts)
+            {
+                return Err(format!("{} {} is not a local endpoint", point.env_name(), url));
+            }
+        }
+        if !self.remote_sources.is_empty() {
+            return Err("REMOTE_SOURCES must be empty".to_string());
+        }
+        if !self.remote_destinations.is_empty() {
+            return Err("REMOTE_DESTINATIONS must be empty".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Loopback, private or link-local address, `localhost`, or one of `local_hosts`
+pub fn is_local_url(url: &str, local_hosts: &[String]) -> bool {
+    let Some(host) = reqwest::Url::parse(url).ok().and_then(|u| u.host_str().map(str::to_ascii_lowercase)) else {
+        return false;
+    };
+    match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => ip.is_loopback() || ip.is_private() || ip.is_link_local(),
+        Ok(IpAddr::V6(ip)) => ip.is_loopback() || ip.is_unique_local() || ip.is_unicast_link_local(),
+        Err(_) => host == "localhost" || host.ends_with(".localhost") || local_hosts.contains(&host),
+    }
+}
+
+/// COVER_TEMPLATE_PATH points at a custom template; COVER_PAGE=1 uses the built-in one
+fn load_cover_template() -> Option<String> {
+    if let Ok(path) = std::env::var("COVER_TEMPLATE_PATH")
+        && !path.is_empty()
+    {
+        let template = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("Failed to read COVER_TEMPLATE_PATH {}: {}", path, e));
+        return Some(template);
+    }
+    env_flag("COVER_PAGE", false).then(|| crate::pdf::DEFAULT_COVER_TEMPLATE.to_string())
+}
+
+/// FONT_FALLBACK: comma-separated TrueType/OpenType files, tried in order
+/// FONT_PATH_<LANG> (e.g. FONT_PATH_JA, FONT_PATH_ZH_TW) picks the font for
+/// one output language, FONT_PATH for all others; LANG is any name a target
+/// language is accepted by (FONT_PATH_ZH, FONT_PATH_JP). A file shared by
+/// several languages is loaded once.
+fn load_body_fonts() -> HashMap<TargetLang, Arc<FallbackFont>> {
+    let default = std::env::var("FONT_PATH").ok().filter(|s| !s.is_empty());
+    // vars() would panic on any variable that isn't UTF-8, ours or not
+    let vars = std::env::vars_os().filter_map(|(var, path)| {
+        let var = var.into_string().ok().filter(|var| var.starts_with("FONT_PATH_"))?;
+        let path = path.into_string().unwrap_or_else(|_| panic!("Invalid {}: not valid UTF-8", var));
+        Some((var, path))
+    });
+    let paths = body_font_paths(default, vars);
+
+    let mut loaded: HashMap<String, Arc<FallbackFont>> = HashMap::new();
+    let mut fonts = HashMap::new();
+    for (lang, (var, path)) in paths {
+        let font = loaded.entry(path.clone()).or_insert_with(|| {
+            let font = FallbackFont::load(std::path::Path::new(&path))
+                .unwrap_or_else(|e| panic!("Invalid {}: {}", var, e));
+            Arc::new(font)
+        });
+        fonts.insert(lang, font.clone());
+    }
+    fonts
+}
+
+/// Font file per output language, with the variable that set it: FONT_PATH
+/// (`default`) for every language, overridden by the FONT_PATH_<LANG> `vars`
+pub(crate) fn body_font_paths(
+    default: Option<String>,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> HashMap<TargetLang, (String, String)> {
+    let mut paths: HashMap<TargetLang, (String, String)> = HashMap::new();
+    if let Some(path) = default {
+        for lang in TargetLang::ALL {
+            paths.insert(lang, ("FONT_PATH".to_string(), path.clone()));
+        }
+    }
+    // A misspelt language would otherwise leave it on the default font unnoticed
+    let mut set_by: HashMap<TargetLang, String> = HashMap::new();
+    for (var, path) in vars.into_iter().filter(|(var, path)| var.starts_with("FONT_PATH_") && !path.is_empty()) {
+        let lang = TargetLang::parse(&var["FONT_PATH_".len()..])
+            .unwrap_or_else(|| panic!("Unknown language in {}", var));
+        if let Some(other) = set_by.insert(lang, var.clone()) {
+            panic!("Invalid {}: {} already sets the {} font", var, other, lang.code());
         }
+        paths.insert(lang, (var, path));
+    }
+    paths
+}
+
+/// USER_TOKENS: comma-separated `name:token` pairs
+fn load_user_tokens() -> Vec<(String, String)> {
+    std::env::var("USER_TOKENS").unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+        .map(|entry| match entry.split_once(':') {
+            Some((name, token)) if !name.trim().is_empty() && !token.trim().is_empty() => {
+                (name.trim().to_string(), token.trim().to_string())
+            }
+            _ => panic!("Invalid USER_TOKENS entry: {}", entry),
+        })
+        .collect()
+}
+
+fn load_fallback_fonts() -> Vec<Arc<FallbackFont>> {
+    std::env::var("FONT_FALLBACK").unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|path| {
+            let font = FallbackFont::load(std::path::Path::new(path))
+                .unwrap_or_else(|e| panic!("Invalid FONT_FALLBACK entry: {}", e));
+            Arc::new(font)
+        })
+        .collect()
+}
+
+/// UI_ENABLED=0 disables the page; INDEX_HTML_PATH replaces the built-in one;
+/// BRAND_* variables fill the template placeholders
+fn load_index_page() -> Option<String> {
+    if !env_flag("UI_ENABLED", true) {
+        return None;
+    }
+    let template = match std::env::var("INDEX_HTML_PATH") {
+        Ok(path) if !path.is_empty() => std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("Failed to read INDEX_HTML_PATH {}: {}", path, e)),
+        _ => crate::ui::DEFAULT_INDEX_HTML.to_string(),
+    };
+    let branding = crate::ui::Branding {
+        title: std::env::var("BRAND_TITLE").ok()
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| "PDF 多语言翻译器".to_string()),
+        logo_url: std::env::var("BRAND_LOGO_URL").ok().filter(|s| !s.is_empty()),
+        disclaimer: std::env::var("BRAND_DISCLAIMER").ok().filter(|s| !s.is_empty()),
+    };
+    Some(crate::ui::render_index(&template, &branding))
+}
+
+fn image_format(name: &str) -> Option<ImageFormat> {
+    std::env::var(name).ok()
+        .filter(|s| !s.is_empty())
+        .map(|s| ImageFormat::parse(&s).unwrap_or_else(|| panic!("Unknown {}: {}", name, s)))
+}
+
+fn s3_credentials() -> Option<S3Credentials> {
+    let access_key_id = std::env::var("S3_ACCESS_KEY_ID").ok().filter(|s| !s.is_empty())?;
+    let secret_access_key = std::env::var("S3_SECRET_ACCESS_KEY").ok().filter(|s| !s.is_empty())?;
+    let region = std::env::var("S3_REGION").ok().filter(|s| !s.is_empty()).unwrap_or_else(|| "us-east-1".to_string());
+    let endpoint = std::env::var("S3_ENDPOINT").ok().filter(|s| !s.is_empty())
+        .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region));
+    let allowed_buckets = std::env::var("S3_ALLOWED_BUCKETS").unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|b| !b.is_empty())
+        .map(str::to_string)
+        .collect();
+    Some(S3Credentials { endpoint, region, access_key_id, secret_access_key, allowed_buckets })
+}
+
+fn env_flag(name: &str, default: bool) -> bool {
+    match std::env::var(name) {
+        Ok(v) => matches!(v.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes" | "on"),
+        Err(_) => default,
+    }
+}
+
+fn env_parse<T: std::str::FromStr>(name: &str) -> Option<T> {
+    std::env::var(name).ok().and_then(|v| v.trim().parse().ok())
+}
+
+/// A concurrency limit: unset or empty means the default; anything but a
+/// positive number stops startup rather than running with a surprise limit
+fn env_count(name: &str) -> Option<usize> {
+    let value = std::env::var(name).ok().filter(|v| !v.trim().is_empty())?;
+    match value.trim().parse() {
+        Ok(n) if n > 0 => Some(n),
+        _ => panic!("Invalid {}: {} (expected a positive number)", name, value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font_vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|(var, path)| (var.to_string(), path.to_string())).collect()
+    }
+
+    #[test]
+    fn body_font_variables_accept_language_aliases() {
+        let paths = body_font_paths(Some("noto.ttf".to_string()), font_vars(&[
+            ("FONT_PATH_JP", "mincho.otf"),
+            ("FONT_PATH_ZH_TW", "ming.ttf"),
+            ("FONT_PATH_KO", ""),
+        ]));
+        assert_eq!(paths[&TargetLang::Ja], ("FONT_PATH_JP".to_string(), "mincho.otf".to_string()));
+        assert_eq!(paths[&TargetLang::ZhTw].1, "ming.ttf");
+        assert_eq!(paths[&TargetLang::Ko], ("FONT_PATH".to_string(), "noto.ttf".to_string()));
+        assert_eq!(paths[&TargetLang::En].1, "noto.ttf");
+    }
+
+    #[test]
+    #[should_panic(expected = "already sets the ja font")]
+    fn body_font_set_twice_for_a_language_is_refused() {
+        body_font_paths(None, font_vars(&[("FONT_PATH_JA", "a.otf"), ("FONT_PATH_JP", "b.otf")]));
     }
 }
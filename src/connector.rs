@@ -0,0 +1,411 @@
+use reqwest::Url;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use serde::Deserialize;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use crate::config::{self, Config};
+
+/// Where a document can be pulled from instead of being uploaded through the browser
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SourceKind {
+    /// Any presigned (or otherwise public) HTTPS link, e.g. an S3 GET URL
+    S3,
+    /// A file on a WebDAV server, optionally with basic auth
+    WebDav,
+    /// A Google Drive file, read with an OAuth token the client supplies
+    GoogleDrive,
+}
+
+impl SourceKind {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "s3" | "url" | "presigned" => Some(SourceKind::S3),
+            "webdav" | "dav" => Some(SourceKind::WebDav),
+            "gdrive" | "google-drive" | "drive" => Some(SourceKind::GoogleDrive),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SourceKind::S3 => "s3",
+            SourceKind::WebDav => "webdav",
+            SourceKind::GoogleDrive => "gdrive",
+        }
+    }
+}
+
+/// A remote document as described by the upload form
+pub struct RemoteSource {
+    pub kind: SourceKind,
+    /// Presigned or WebDAV URL
+    pub url: Option<String>,
+    /// Google Drive file id
+    pub file_id: Option<String>,
+    /// Google Drive OAuth access token
+    pub token: Option<String>,
+    /// WebDAV basic auth
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+/// An open download: the file's name and the response to stream its body from
+pub struct RemoteFile {
+    pub filename: String,
+    /// Size announced by the server, if any
+    pub size: Option<u64>,
+    pub response: reqwest::Response,
+}
+
+const DRIVE_API: &str = "https://www.googleapis.com/drive/v3/files";
+
+static HTTP_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+static PUBLIC_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+/// Redirects a storage link may take, as many as reqwest follows by default
+const STORAGE_MAX_REDIRECTS: usize = 10;
+
+/// Client for the endpoints the server is configured with (S3_ENDPOINT, the
+/// Drive API); separate from the API client since transfers may be large
+/// and slow
+pub fn get_client(timeout_secs: u64) -> &'static reqwest::Client {
+    HTTP_CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(timeout_secs))
+            .connect_timeout(Duration::from_secs(10))
+            .build()
+            .expect("Failed to create HTTP client")
+    })
+}
+
+/// Client for links the client supplies (presigned and WebDAV URLs, in both
+/// directions): reaches public addresses only, redirects included
+pub fn public_client(timeout_secs: u64) -> &'static reqwest::Client {
+    PUBLIC_CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(timeout_secs))
+            .connect_timeout(Duration::from_secs(10))
+            .redirect(public_redirects(STORAGE_MAX_REDIRECTS))
+            .dns_resolver(Arc::new(PublicResolver))
+            .build()
+            .expect("Failed to create HTTP client")
+    })
+}
+
+/// Refuse a source whose link leads into the server's network. Drive files
+/// are always fetched from Google; a missing or malformed link is left for
+/// `open` to report.
+pub async fn check_source(source: &RemoteSource) -> Result<(), String> {
+    if source.kind == SourceKind::GoogleDrive {
+        return Ok(());
+    }
+    match source.url.as_deref().and_then(|url| Url::parse(url).ok()) {
+        Some(url) => check_public_url(&url).await,
+        None => Ok(()),
+    }
+}
+
+/// Start downloading a remote document; the caller streams the body
+pub async fn open(source: &RemoteSource, timeout_secs: u64) -> Result<RemoteFile, String> {
+    let (request, filename) = match source.kind {
+        SourceKind::S3 | SourceKind::WebDav => {
+            let url = source.url.as_deref().filter(|u| !u.is_empty())
+                .ok_or_else(|| "缺少远程文件地址 (url)".to_string())?;
+            let url = reqwest::Url::parse(url)
+                .map_err(|e| format!("无效的远程文件地址: {}", e))?;
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err(format!("不支持的地址协议: {}", url.scheme()));
+            }
+            // Host names are checked by the resolver
+            if let Some(ip) = literal_ip(&url).filter(|ip| !is_public_ip(*ip)) {
+                return Err(format!("不允许访问内网地址: {}", ip));
+            }
+            let filename = url.path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .map(percent_decode)
+                .unwrap_or_default();
+            let mut request = public_client(timeout_secs).get(url);
+            if source.kind == SourceKind::WebDav && let Some(username) = &source.username {
+                request = request.basic_auth(username, source.password.as_ref());
+            }
+            (request, filename)
+        }
+        SourceKind::GoogleDrive => {
+            let file_id = source.file_id.as_deref().filter(|id| !id.is_empty())
+                .ok_or_else(|| "缺少 Google Drive 文件 ID (file_id)".to_string())?;
+            if !file_id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_')) {
+                return Err(format!("无效的 Google Drive 文件 ID: {}", file_id));
+            }
+            let token = source.token.as_deref().filter(|t| !t.is_empty())
+                .ok_or_else(|| "缺少 Google Drive 访问令牌 (token)".to_string())?;
+            let client = get_client(timeout_secs);
+            let filename = drive_file_name(client, file_id, token).await?;
+            let request = client.get(format!("{}/{}?alt=media", DRIVE_API, file_id)).bearer_auth(token);
+            (request, filename)
+        }
+    };
+
+    let response = request.send().await
+        .map_err(|e| format!("下载远程文件失败: {}", root_cause(&e)))?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("下载远程文件失败: HTTP {}", status));
+    }
+    Ok(RemoteFile { filename, size: response.content_length(), response })
+}
+
+static URL_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+/// Media types a linked document may be served as; servers often send
+/// PDFs as a generic binary
+const URL_CONTENT_TYPES: &[&str] = &[
+    "application/pdf", "application/x-pdf", "image/jpeg", "image/png",
+    "application/octet-stream", "binary/octet-stream",
+];
+
+/// Whether a client-given link may lead to `ip`. Loopback, private and
+/// link-local addresses (where cloud metadata services answer) are the
+/// server's own network.
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => !(ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified() || ip.is_broadcast()),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ip(IpAddr::V4(ip)),
+            None => !(ip.is_loopback() || ip.is_unique_local() || ip.is_unicast_link_local() || ip.is_unspecified()),
+        },
+    }
+}
+
+/// The address of a URL whose host is an IP literal, which is never resolved
+fn literal_ip(url: &Url) -> Option<IpAddr> {
+    url.host_str()?.trim_start_matches('[').trim_end_matches(']').parse().ok()
+}
+
+/// Refuse a link into the server's own network: one whose host is, or
+/// resolves to, a loopback, private or link-local address
+pub async fn check_public_url(url: &Url) -> Result<(), String> {
+    let host = url.host_str().filter(|h| !h.is_empty())
+        .ok_or_else(|| "文件地址缺少主机名".to_string())?;
+    let addrs: Vec<IpAddr> = match literal_ip(url) {
+        Some(ip) => vec![ip],
+        None => tokio::net::lookup_host((host, url.port_or_known_default().unwrap_or(80))).await
+            .map_err(|e| format!("无法解析 {}: {}", host, e))?
+            .map(|addr| addr.ip())
+            .collect(),
+    };
+    match addrs.into_iter().find(|ip| !is_public_ip(*ip)) {
+        Some(ip) => Err(format!("不允许访问内网地址: {} ({})", host, ip)),
+        None => Ok(()),
+    }
+}
+
+/// Resolves host names to public addresses only, so neither a redirect nor
+/// a DNS answer that changed since `check_public_url` leads into the
+/// server's network
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let host = name.as_str();
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, 0)).await?.collect();
+            if let Some(addr) = addrs.iter().find(|addr| !is_public_ip(addr.ip())) {
+                return Err(format!("不允许访问内网地址: {} ({})", host, addr.ip()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Follow at most `max_redirects` redirects, none to an internal IP literal
+/// (host names are checked by the resolver)
+fn public_redirects(max_redirects: usize) -> reqwest::redirect::Policy {
+    reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() > max_redirects {
+            attempt.error(format!("重定向超过 {} 次", max_redirects))
+        } else if literal_ip(attempt.url()).is_some_and(|ip| !is_public_ip(ip)) {
+            let url = attempt.url().to_string();
+            attempt.error(format!("不允许重定向到内网地址 {}", url))
+        } else {
+            attempt.follow()
+        }
+    })
+}
+
+/// The cause at the end of a request error's chain, which says why: a
+/// refused redirect's policy reason, a refused address or the connection error
+pub fn root_cause(error: &reqwest::Error) -> &dyn std::error::Error {
+    let mut reason: &dyn std::error::Error = error;
+    while let Some(source) = reason.source() {
+        reason = source;
+    }
+    reason
+}
+
+/// Start downloading a document from a link given to `/upload-url` (already
+/// checked to be http(s), and local in local-only mode, else public). Follows
+/// at most URL_UPLOAD_MAX_REDIRECTS redirects, in local-only mode only to
+/// local hosts and otherwise only to public ones; anything not served as a
+/// PDF or image is refused.
+pub async fn open_url(config: &Config, url: reqwest::Url) -> Result<RemoteFile, String> {
+    let client = URL_CLIENT.get_or_init(|| {
+        let max_redirects = config.url_upload_max_redirects;
+        let policy = if config.local_only {
+            let local_hosts = config.local_hosts.clone();
+            reqwest::redirect::Policy::custom(move |attempt| {
+                let url = attempt.url().to_string();
+                if attempt.previous().len() > max_redirects {
+                    attempt.error(format!("重定向超过 {} 次", max_redirects))
+                } else if config::is_local_url(&url, &local_hosts) {
+                    attempt.follow()
+                } else {
+                    attempt.error(format!("本地模式下不允许重定向到 {}", url))
+                }
+            })
+        } else {
+            public_redirects(max_redirects)
+        };
+        let mut builder = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.remote_timeout_secs))
+            .connect_timeout(Duration::from_secs(10))
+            .redirect(policy);
+        if !config.local_only {
+            builder = builder.dns_resolver(Arc::new(PublicResolver));
+        }
+        builder.build().expect("Failed to create HTTP client")
+    });
+
+    let response = client.get(url).send().await
+        .map_err(|e| format!("下载文件失败: {}", root_cause(&e)))?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("下载文件失败: HTTP {}", status));
+    }
+    let content_type = response.headers().get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(';').next().unwrap_or_default().trim().to_ascii_lowercase());
+    if let Some(content_type) = &content_type
+        && !URL_CONTENT_TYPES.contains(&content_type.as_str())
+    {
+        return Err(format!("链接指向的不是 PDF 或图片 (Content-Type: {})", content_type));
+    }
+
+    // Named by the server if it says so, else after the last path segment of
+    // the final URL; links such as arXiv's /pdf/2401.01234 carry no extension
+    let mut filename = response.headers().get(reqwest::header::CONTENT_DISPOSITION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(disposition_filename)
+        .or_else(|| response.url().path_segments().and_then(|mut s| s.next_back()).map(percent_decode))
+        .unwrap_or_default();
+    let extension = match content_type.as_deref() {
+        Some("image/jpeg") => ".jpg",
+        Some("image/png") => ".png",
+        _ => ".pdf",
+    };
+    let lower = filename.to_ascii_lowercase();
+    if !filename.is_empty() && ![".pdf", ".jpg", ".jpeg", ".png"].iter().any(|e| lower.ends_with(e)) {
+        filename.push_str(extension);
+    }
+    Ok(RemoteFile { filename, size: response.content_length(), response })
+}
+
+/// Filename from a Content-Disposition header, preferring the RFC 5987 UTF-8 form
+fn disposition_filename(value: &str) -> Option<String> {
+    let params: Vec<(&str, &str)> = value.split(';')
+        .filter_map(|p| p.split_once('='))
+        .map(|(k, v)| (k.trim(), v.trim()))
+        .collect();
+    let encoded = params.iter().find(|(k, _)| k.eq_ignore_ascii_case("filename*"))
+        .and_then(|(_, v)| v.split_once("''"))
+        .map(|(_, name)| percent_decode(name));
+    let plain = params.iter().find(|(k, _)| k.eq_ignore_ascii_case("filename"))
+        .map(|(_, v)| v.trim_matches('"').to_string());
+    encoded.or(plain).filter(|name| !name.is_empty())
+}
+
+async fn drive_file_name(client: &reqwest::Client, file_id: &str, token: &str) -> Result<String, String> {
+    #[derive(Deserialize)]
+    struct Metadata {
+        name: String,
+    }
+
+    let response = client.get(format!("{}/{}?fields=name", DRIVE_API, file_id))
+        .bearer_auth(token)
+        .send()
+        .await
+        .map_err(|e| format!("读取 Google Drive 文件信息失败: {}", e))?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("读取 Google Drive 文件信息失败: HTTP {}", status));
+    }
+    response.json::<Metadata>().await
+        .map(|m| m.name)
+        .map_err(|e| format!("读取 Google Drive 文件信息失败: {}", e))
+}
+
+/// Decode %XX escapes in a URL path segment
+fn percent_decode(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = segment.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            out.push(byte);
+            i += 3;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::config;
+
+    #[tokio::test]
+    async fn url_downloads_refuse_redirects_into_the_network() {
+        let app = axum::Router::new()
+            .route("/metadata", axum::routing::get(|| async { axum::response::Redirect::temporary("http://169.254.169.254/latest/meta-data/") }))
+            .route("/loopback", axum::routing::get(|| async { axum::response::Redirect::temporary("http://localhost:9/a.pdf") }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let config = config();
+        let error = open_url(&config, format!("{}/metadata", base).parse().unwrap()).await.err().unwrap();
+        assert!(error.contains("不允许重定向到内网地址"), "{}", error);
+        let error = open_url(&config, format!("{}/loopback", base).parse().unwrap()).await.err().unwrap();
+        assert!(error.contains("不允许访问内网地址"), "{}", error);
+    }
+
+    fn presigned(url: &str) -> RemoteSource {
+        RemoteSource { kind: SourceKind::S3, url: Some(url.to_string()), file_id: None, token: None, username: None, password: None }
+    }
+
+    #[tokio::test]
+    async fn sources_refuse_internal_addresses() {
+        for url in ["http://127.0.0.1:9/a.pdf", "http://169.254.169.254/latest/meta-data/", "http://localhost:9/a.pdf"] {
+            let error = check_source(&presigned(url)).await.err().unwrap();
+            assert!(error.contains("不允许访问内网地址"), "{}", error);
+            // Refused on its own too, by the literal check or the resolver
+            let error = open(&presigned(url), 5).await.err().unwrap();
+            assert!(error.contains("不允许访问内网地址"), "{}", error);
+        }
+
+        // A public-looking link may still redirect inward
+        let app = axum::Router::new()
+            .route("/metadata", axum::routing::get(|| async { axum::response::Redirect::temporary("http://169.254.169.254/latest/meta-data/") }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/metadata", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        let error = public_client(5).get(url).send().await.err().unwrap();
+        assert!(root_cause(&error).to_string().contains("不允许重定向到内网地址"), "{}", error);
+    }
+}
@@ -0,0 +1,95 @@
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::state;
+
+/// A page that failed for good: retries, the fallback model and the stall
+/// retry are all used up. Kept until its task is retried, so an operator can
+/// see why pages fail and re-drive them after fixing the cause.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub task_id: String,
+    pub filename: String,
+    pub page_num: usize,
+    /// `ocr` or `translate`
+    pub stage: String,
+    pub error: String,
+    pub failed_at: u64,
+    pub request: RequestParams,
+}
+
+/// What was sent for the failed step, as configured at the time
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RequestParams {
+    pub model: String,
+    pub fallback_model: Option<String>,
+    /// Translation target; None for OCR
+    pub target_lang: Option<String>,
+    pub timeout_secs: u64,
+    pub max_retries: u32,
+    pub stream: bool,
+    /// Base64 image length for OCR, source text length (characters) for translation
+    pub input_len: usize,
+}
+
+/// Persistent dead-letter list, saved as a whole on every change
+pub struct DeadLetterStore {
+    entries: Mutex<Vec<DeadLetter>>,
+}
+
+impl DeadLetterStore {
+    pub fn load() -> Self {
+        let entries = fs::read_to_string(state::data_dir().join("dead_letters.json"))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        Self { entries: Mutex::new(entries) }
+    }
+
+    fn save(entries: &[DeadLetter]) {
+        let path = state::data_dir().join("dead_letters.json");
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let tmp_path = path.with_extension("json.tmp");
+        if let Ok(json) = serde_json::to_string_pretty(entries)
+            && fs::write(&tmp_path, json).is_ok()
+        {
+            let _ = fs::rename(tmp_path, path);
+        }
+    }
+
+    /// Add a failure, replacing an earlier one for the same page and stage
+    pub fn record(&self, letter: DeadLetter) {
+        let mut entries = self.entries.lock();
+        entries.retain(|e| !(e.task_id == letter.task_id && e.page_num == letter.page_num && e.stage == letter.stage));
+        entries.push(letter);
+        Self::save(&entries);
+    }
+
+    pub fn list(&self) -> Vec<DeadLetter> {
+        self.entries.lock().clone()
+    }
+
+    /// Tasks with dead letters, in order of their first failure
+    pub fn task_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = Vec::new();
+        for entry in self.entries.lock().iter() {
+            if !ids.contains(&entry.task_id) {
+                ids.push(entry.task_id.clone());
+            }
+        }
+        ids
+    }
+
+    /// Drop a task's entries once it is being processed again
+    pub fn remove_task(&self, task_id: &str) {
+        let mut entries = self.entries.lock();
+        let before = entries.len();
+        entries.retain(|e| e.task_id != task_id);
+        if entries.len() != before {
+            Self::save(&entries);
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+use crate::connector;
+
+/// Cloud storage that finished results can be pushed to
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DestinationKind {
+    /// A bucket and key prefix, written with the server's S3 credentials
+    S3,
+    /// A WebDAV collection, optionally with basic auth
+    WebDav,
+}
+
+impl DestinationKind {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "s3" => Some(DestinationKind::S3),
+            "webdav" | "dav" => Some(DestinationKind::WebDav),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DestinationKind::S3 => "s3",
+            DestinationKind::WebDav => "webdav",
+        }
+    }
+}
+
+/// Server-side credentials for S3 destinations (any S3-compatible store)
+#[derive(Clone)]
+pub struct S3Credentials {
+    /// e.g. https://s3.eu-west-1.amazonaws.com; buckets are addressed path-style
+    pub endpoint: String,
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Buckets clients may write to (S3_ALLOWED_BUCKETS); the credentials
+    /// may reach others that are none of the clients' business
+    pub allowed_buckets: Vec<String>,
+}
+
+impl S3Credentials {
+    fn check_bucket(&self, bucket: &str) -> Result<(), String> {
+        if self.allowed_buckets.iter().any(|b| b == bucket) {
+            Ok(())
+        } else {
+            Err(format!("不允许上传到存储桶: {}", bucket))
+        }
+    }
+}
+
+/// Where a task's results go, as given on upload
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Destination {
+    pub kind: DestinationKind,
+    /// `s3://bucket/prefix` or the WebDAV collection URL
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// Kept in memory only; a task reloaded after a restart pushes without it
+    #[serde(default, skip_serializing)]
+    pub password: Option<String>,
+}
+
+/// A file pushed to a destination
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PublishedFile {
+    pub name: String,
+    pub url: String,
+}
+
+impl Destination {
+    /// Validate a destination URL against the enabled kinds
+    pub fn parse(config: &Config, url: &str, username: Option<String>, password: Option<String>) -> Result<Self, String> {
+        let kind = if url.starts_with("s3://") {
+            DestinationKind::S3
+        } else if url.starts_with("https://") || url.starts_with("http://") {
+            DestinationKind::WebDav
+        } else {
+            return Err(format!("无效的结果上传地址: {}", url));
+        };
+        if !config.remote_destinations.contains(&kind) {
+            return Err(format!("未启用的结果上传目标: {}", kind.as_str()));
+        }
+        if kind == DestinationKind::S3 {
+            let s3 = config.s3.as_ref().ok_or_else(|| "服务器未配置 S3 凭据".to_string())?;
+            let bucket = url["s3://".len()..].split('/').next().unwrap_or_default();
+            if bucket.is_empty() {
+                return Err(format!("无效的结果上传地址: {}", url));
+            }
+            s3.check_bucket(bucket)?;
+        } else {
+            reqwest::Url::parse(url).map_err(|e| format!("无效的结果上传地址: {}", e))?;
+        }
+        Ok(Self { kind, url: url.to_string(), username, password })
+    }
+
+    /// Refuse a WebDAV collection inside the server's network; checked for
+    /// every upload and scheduled run, and again by `put`
+    pub async fn check_public(&self) -> Result<(), String> {
+        match self.kind {
+            DestinationKind::S3 => Ok(()),
+            DestinationKind::WebDav => {
+                let url = reqwest::Url::parse(&self.url).map_err(|e| format!("无效的结果上传地址: {}", e))?;
+                connector::check_public_url(&url).await
+            }
+        }
+    }
+
+    /// Upload one file under the destination; returns its URL
+    pub async fn put(&self, config: &Config, name: &str, data: Vec<u8>, content_type: &str) -> Result<String, String> {
+        match self.kind {
+            DestinationKind::S3 => {
+                let s3 = config.s3.as_ref().ok_or_else(|| "服务器未配置 S3 凭据".to_string())?;
+                let (bucket, prefix) = self.url["s3://".len()..].split_once('/').unwrap_or((&self.url["s3://".len()..], ""));
+                // The allowlist may have shrunk since the task was created
+                s3.check_bucket(bucket)?;
+                let key = join_key(prefix, name);
+                put_s3(config, s3, bucket, &key, data, content_type).await?;
+                Ok(format!("s3://{}/{}", bucket, key))
+            }
+            DestinationKind::WebDav => {
+                self.check_public().await?;
+                let url = format!("{}/{}", self.url.trim_end_matches('/'), uri_encode(name, true));
+                let mut request = connector::public_client(config.remote_timeout_secs)
+                    .put(&url)
+                    .header("content-type", content_type)
+                    .body(data);
+                if let Some(username) = &self.username {
+                    request = request.basic_auth(username, self.password.as_ref());
+                }
+                send(request).await?;
+                Ok(url)
+            }
+        }
+    }
+}
+
+fn join_key(prefix: &str, name: &str) -> String {
+    let prefix = prefix.trim_matches('/');
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", prefix, name)
+    }
+}
+
+async fn send(request: reqwest::RequestBuilder) -> Result<(), String> {
+    let response = request.send().await.map_err(|e| format!("上传失败: {}", connector::root_cause(&e)))?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(format!("上传失败: HTTP {} {}", status, body.chars().take(200).collect::<String>()));
+    }
+    Ok(())
+}
+
+/// PUT an object, signed with AWS Signature Version 4
+async fn put_s3(config: &Config, s3: &S3Credentials, bucket: &str, key: &str, data: Vec<u8>, content_type: &str) -> Result<(), String> {
+    let endpoint = reqwest::Url::parse(&s3.endpoint).map_err(|e| format!("无效的 S3_ENDPOINT: {}", e))?;
+    let host = match endpoint.port() {
+        Some(port) => format!("{}:{}", endpoint.host_str().unwrap_or_default(), port),
+        None => endpoint.host_str().unwrap_or_default().to_string(),
+    };
+    let path = format!("{}/{}/{}", endpoint.path().trim_end_matches('/'), uri_encode(bucket, false), uri_encode(key, false));
+
+    let now = chrono::Utc::now();
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let day = now.format("%Y%m%d").to_string();
+    let payload_hash = hex(&Sha256::digest(&data));
+
+    let canonical_request = format!(
+        "PUT\n{}\n\ncontent-type:{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\ncontent-type;host;x-amz-content-sha256;x-amz-date\n{}",
+        path, content_type, host, payload_hash, amz_date, payload_hash
+    );
+    let scope = format!("{}/{}/s3/aws4_request", day, s3.region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date, scope, hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+    let mut signing_key = hmac(format!("AWS4{}", s3.secret_access_key).as_bytes(), day.as_bytes());
+    for part in [s3.region.as_str(), "s3", "aws4_request"] {
+        signing_key = hmac(&signing_key, part.as_bytes());
+    }
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders=content-type;host;x-amz-content-sha256;x-amz-date, Signature={}",
+        s3.access_key_id, scope, hex(&hmac(&signing_key, string_to_sign.as_bytes()))
+    );
+
+    let url = format!("{}://{}{}", endpoint.scheme(), host, path);
+    let request = connector::get_client(config.remote_timeout_secs)
+        .put(url)
+        .header("content-type", content_type)
+        .header("x-amz-content-sha256", payload_hash)
+        .header("x-amz-date", amz_date)
+        .header("authorization", authorization)
+        .body(data);
+    send(request).await
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Percent-encode everything but unreserved characters, and `/` only when asked
+fn uri_encode(s: &str, encode_slash: bool) -> String {
+    let mut out = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => out.push(byte as char),
+            b'/' if !encode_slash => out.push('/'),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::config;
+
+    fn destinations() -> Config {
+        let s3 = S3Credentials {
+            endpoint: "http://127.0.0.1:9".to_string(),
+            region: "us-east-1".to_string(),
+            access_key_id: "key".to_string(),
+            secret_access_key: "secret".to_string(),
+            allowed_buckets: vec!["results".to_string()],
+        };
+        Config { remote_destinations: vec![DestinationKind::S3, DestinationKind::WebDav], s3: Some(s3), ..config() }
+    }
+
+    #[tokio::test]
+    async fn s3_destinations_only_reach_allowed_buckets() {
+        let config = destinations();
+        assert!(Destination::parse(&config, "s3://results/papers", None, None).is_ok());
+        let error = Destination::parse(&config, "s3://backups/papers", None, None).err().unwrap();
+        assert!(error.contains("不允许上传到存储桶"), "{}", error);
+
+        // A task created before the bucket was dropped from the list
+        let stale = Destination { kind: DestinationKind::S3, url: "s3://backups".to_string(), username: None, password: None };
+        let error = stale.put(&config, "a.pdf", Vec::new(), "application/pdf").await.err().unwrap();
+        assert!(error.contains("不允许上传到存储桶"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn webdav_destinations_refuse_internal_addresses() {
+        let config = destinations();
+        for url in ["http://127.0.0.1:9/dav", "http://169.254.169.254/dav", "http://localhost:9/dav"] {
+            let destination = Destination::parse(&config, url, None, None).unwrap();
+            let error = destination.check_public().await.err().unwrap();
+            assert!(error.contains("不允许访问内网地址"), "{}", error);
+            let error = destination.put(&config, "a.pdf", Vec::new(), "application/pdf").await.err().unwrap();
+            assert!(error.contains("不允许访问内网地址"), "{}", error);
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use axum::{
+    Json,
+    extract::multipart::MultipartRejection,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use thiserror::Error;
+
+/// Reading, rasterizing and writing PDFs and page images
+#[derive(Debug, Error)]
+pub enum PdfError {
+    #[error("Failed to parse PDF: {0}")]
+    Parse(String),
+    #[error("PDF has no pages")]
+    NoPages,
+    #[error("页码超出范围: 文档共 {0} 页")]
+    PageOutOfRange(usize),
+    #[error("Image for page {0} not found")]
+    MissingPage(usize),
+    #[error("Unknown render level {0}")]
+    RenderLevel(usize),
+    #[error("Failed to decode image: {0}")]
+    Decode(String),
+    #[error("Failed to encode {format} image: {message}")]
+    Encode { format: &'static str, message: String },
+    #[error("Failed to render page {page}: {message}")]
+    Render { page: usize, message: String },
+    #[error("No PDF renderer available. Put the pdfium library next to the binary (or set PDFIUM_PATH), or install poppler-utils:\n  macOS: brew install poppler\n  Ubuntu: apt install poppler-utils\n  Windows: unpack a poppler release and set PDFTOPPM_PATH to its bin directory")]
+    NoRenderer,
+    /// Checked before rendering with pdftoppm, which writes every page to disk
+    #[error("渲染临时目录 {dir} 空间不足: 预计需要 {} MB，仅剩 {} MB", .needed.div_ceil(1 << 20), .available >> 20)]
+    NoSpace { dir: String, needed: u64, available: u64 },
+    #[error("{context}: {source}")]
+    Io { context: String, source: std::io::Error },
+}
+
+impl PdfError {
+    pub fn io(context: impl Into<String>) -> impl FnOnce(std::io::Error) -> Self {
+        let context = context.into();
+        move |source| PdfError::Io { context, source }
+    }
+
+    /// Whether the document itself is at fault rather than the server
+    fn is_invalid_input(&self) -> bool {
+        matches!(self, PdfError::Parse(_) | PdfError::NoPages | PdfError::PageOutOfRange(_) | PdfError::Decode(_))
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            PdfError::Parse(_) => "invalid_pdf",
+            PdfError::NoPages => "empty_pdf",
+            PdfError::PageOutOfRange(_) => "page_out_of_range",
+            PdfError::Decode(_) => "invalid_image",
+            PdfError::NoRenderer => "no_renderer",
+            PdfError::MissingPage(_) | PdfError::RenderLevel(_) | PdfError::Render { .. } => "render_failed",
+            PdfError::Encode { .. } => "encode_failed",
+            PdfError::NoSpace { .. } => "insufficient_storage",
+            PdfError::Io { .. } => "pdf_io_failed",
+        }
+    }
+}
+
+/// A page whose text could not be recognized
+#[derive(Debug, Error)]
+pub enum OcrError {
+    /// The API call or the post-OCR hook failed
+    #[error("第 {page} 页 OCR 失败: {message}")]
+    Page { page: usize, message: String },
+    #[error("OCR 任务执行错误: {0}")]
+    Join(String),
+}
+
+/// A page that could not be translated into one of the task's languages
+#[derive(Debug, Error)]
+pub enum TranslateError {
+    #[error("第 {page} 页翻译失败 ({lang}): {message}")]
+    Page { page: usize, lang: &'static str, message: String },
+    #[error("翻译任务执行错误: {0}")]
+    Join(String),
+}
+
+/// The content policy check of a page before translation
+#[derive(Debug, Error)]
+pub enum PolicyError {
+    #[error("第 {page} 页内容属于「{category}」，按内容策略拒绝翻译")]
+    Denied { page: usize, category: String },
+    #[error("第 {page} 页内容分类失败: {message}")]
+    Classify { page: usize, message: String },
+}
+
+/// Task files on disk
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("保存文件失败: {0}")]
+    SaveInput(std::io::Error),
+    #[error("读取文件失败: {0}")]
+    ReadInput(std::io::Error),
+    #[error("任务已过期，原始 PDF 已清理")]
+    InputGone,
+    #[error("保存第 {page} 页译文失败: {source}")]
+    SavePage { page: usize, source: std::io::Error },
+    #[error("保存输出文件失败: {0}")]
+    SaveOutput(std::io::Error),
+    /// Rebuilding the output PDF failed; the message names the language
+    #[error("{0}")]
+    Generate(String),
+}
+
+/// Settings the page renderer can't be set up with
+#[derive(Debug, Error)]
+pub enum SetupError {
+    #[error("Invalid PDFTOPPM_PATH: {0}")]
+    Pdftoppm(String),
+    #[error("Invalid RENDER_TEMP_DIR {}: {source}", .dir.display())]
+    TempDir { dir: std::path::PathBuf, source: std::io::Error },
+}
+
+/// Every error a request can end in. Responses carry the status that fits
+/// the variant and a JSON body `{"code": "...", "message": "..."}`; `code`
+/// is stable for clients to match on, `message` is for people.
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error(transparent)]
+    Pdf(#[from] PdfError),
+    #[error(transparent)]
+    Ocr(#[from] OcrError),
+    #[error(transparent)]
+    Translate(#[from] TranslateError),
+    #[error(transparent)]
+    Policy(#[from] PolicyError),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Multipart(#[from] MultipartRejection),
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("文件过大，最大支持 50MB")]
+    TooLarge,
+    #[error("无效的文件，仅支持 PDF、JPEG 与 PNG")]
+    UnsupportedFile,
+    /// Missing or unknown user token
+    #[error("{0}")]
+    Unauthorized(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    NotFound(String),
+    /// The task is not in a state that allows the request
+    #[error("{0}")]
+    Conflict(String),
+    #[error("任务已取消")]
+    Cancelled,
+    /// No free task slot
+    #[error("{0}")]
+    Busy(String),
+    #[error("{0}")]
+    QuotaExceeded(String),
+    /// data/tasks and data/jobs have reached DATA_MAX_BYTES
+    #[error("{0}")]
+    StorageFull(String),
+    /// A remote source or URL could not be fetched
+    #[error("{0}")]
+    Upstream(String),
+}
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::Pdf(e) if e.is_invalid_input() => StatusCode::BAD_REQUEST,
+            AppError::Pdf(PdfError::NoSpace { .. }) => StatusCode::INSUFFICIENT_STORAGE,
+            AppError::Pdf(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Ocr(_) | AppError::Translate(_) => StatusCode::BAD_GATEWAY,
+            AppError::Policy(PolicyError::Denied { .. }) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Policy(PolicyError::Classify { .. }) => StatusCode::BAD_GATEWAY,
+            AppError::Storage(StorageError::InputGone) => StatusCode::GONE,
+            AppError::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Multipart(e) => e.status(),
+            AppError::BadRequest(_) | AppError::UnsupportedFile => StatusCode::BAD_REQUEST,
+            AppError::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) | AppError::Cancelled => StatusCode::CONFLICT,
+            AppError::Busy(_) | AppError::QuotaExceeded(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::StorageFull(_) => StatusCode::INSUFFICIENT_STORAGE,
+            AppError::Upstream(_) => StatusCode::BAD_GATEWAY,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Pdf(e) => e.code(),
+            AppError::Ocr(_) => "ocr_failed",
+            AppError::Translate(_) => "translate_failed",
+            AppError::Policy(PolicyError::Denied { .. }) => "content_denied",
+            AppError::Policy(PolicyError::Classify { .. }) => "classify_failed",
+            AppError::Storage(StorageError::InputGone) => "input_gone",
+            AppError::Storage(_) => "storage_failed",
+            AppError::Multipart(_) => "invalid_multipart",
+            AppError::BadRequest(_) => "invalid_request",
+            AppError::TooLarge => "file_too_large",
+            AppError::UnsupportedFile => "unsupported_file",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::NotFound(_) => "not_found",
+            AppError::Conflict(_) => "conflict",
+            AppError::Cancelled => "cancelled",
+            AppError::Busy(_) => "busy",
+            AppError::QuotaExceeded(_) => "quota_exceeded",
+            AppError::StorageFull(_) => "storage_full",
+            AppError::Upstream(_) => "upstream_failed",
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let body = serde_json::json!({ "code": self.code(), "message": self.to_string() });
+        (self.status(), Json(body)).into_response()
+    }
+}
@@ -0,0 +1,59 @@
+use crate::lang::TargetLang;
+use crate::state;
+
+/// Download formats besides the generated PDF
+#[derive(Clone, Copy, PartialEq)]
+pub enum TextFormat {
+    Markdown,
+    Plain,
+}
+
+impl TextFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            TextFormat::Markdown => "md",
+            TextFormat::Plain => "txt",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            TextFormat::Markdown => "text/markdown; charset=utf-8",
+            TextFormat::Plain => "text/plain; charset=utf-8",
+        }
+    }
+}
+
+/// The translated pages (the recognized text of an OCR-only task) as one
+/// document, each page introduced by a separator with its number in the
+/// uploaded file
+pub fn export_text(task_id: &str, progress: &state::TaskProgress, options: &state::TaskOptions, lang: TargetLang, format: TextFormat) -> String {
+    let mut out = String::new();
+    for page_num in 1..=progress.total_pages {
+        let source_page = progress.source_page(page_num);
+        let text = state::load_page_output(task_id, page_num, options, lang)
+            .unwrap_or_else(|| format!("【第 {} 页未能翻译】", source_page));
+        match format {
+            TextFormat::Markdown => {
+                if page_num > 1 {
+                    out.push_str("\n---\n\n");
+                }
+                out.push_str(&format!("<!-- 第 {} 页 -->\n\n", source_page));
+                out.push_str(text.trim());
+            }
+            TextFormat::Plain => {
+                if page_num > 1 {
+                    out.push('\n');
+                }
+                out.push_str(&format!("===== 第 {} 页 =====\n\n", source_page));
+                // Heading markers mean nothing in plain text
+                let lines: Vec<&str> = text.trim().lines()
+                    .map(|l| if l.starts_with('#') { l.trim_start_matches('#').trim_start() } else { l })
+                    .collect();
+                out.push_str(&lines.join("\n"));
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,303 @@
+/// Longest stored filename, in characters (most filesystems cap at 255 bytes)
+const MAX_FILENAME_CHARS: usize = 120;
+
+/// Clean an uploaded filename for storage and later display: keep Unicode
+/// (CJK, spaces, accents) but drop any path, control and reserved characters.
+pub fn sanitize(name: &str) -> String {
+    // Browsers on Windows may send the full client path
+    let base = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let cleaned: String = base
+        .chars()
+        .filter(|c| !c.is_control() && !matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*'))
+        .take(MAX_FILENAME_CHARS)
+        .collect();
+    let cleaned = cleaned.trim().trim_matches('.').trim();
+    if cleaned.is_empty() {
+        "document.pdf".to_string()
+    } else {
+        cleaned.to_string()
+    }
+}
+
+/// Longest task title, in characters
+const MAX_TITLE_CHARS: usize = 80;
+
+/// A PDF's metadata title, unless it is a placeholder or just the name of the
+/// file the PDF was made from ("untitled", "Microsoft Word - a.docx", "scan0001.tif")
+pub fn clean_title(raw: &str) -> Option<String> {
+    let title = raw.trim();
+    let lower = title.to_ascii_lowercase();
+    let placeholder = ["untitled", "title", "document", "无标题"].contains(&lower.as_str());
+    let converted = lower.starts_with("microsoft word - ")
+        || lower.rsplit_once('.').is_some_and(|(stem, ext)| {
+            !stem.is_empty() && (2..=4).contains(&ext.len()) && ext.chars().all(|c| c.is_ascii_alphanumeric())
+        });
+    (title.chars().count() >= 2 && !placeholder && !converted).then(|| truncate_title(title))
+}
+
+/// First heading of a page's text, or its first line when it has none
+pub fn heading_title(text: &str) -> Option<String> {
+    let lines = || text.lines().map(str::trim).filter(|l| !l.is_empty());
+    lines()
+        .find(|l| l.starts_with('#'))
+        .or_else(|| lines().next())
+        .map(|l| l.trim_start_matches('#').trim())
+        .filter(|l| !l.is_empty())
+        .map(truncate_title)
+}
+
+fn truncate_title(title: &str) -> String {
+    title.chars().take(MAX_TITLE_CHARS).collect()
+}
+
+/// The filename without its extension: "report.pdf" → "report"
+pub fn stem(source: &str) -> &str {
+    match source.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem,
+        _ => source,
+    }
+}
+
+/// What an output file is named after
+pub struct OutputName<'a> {
+    /// The uploaded file's name
+    pub source: &'a str,
+    pub title: Option<&'a str>,
+    /// Language code, `ocr` for recognized text, or several codes joined for a ZIP
+    pub tag: &'a str,
+    /// Task start, ms since the epoch
+    pub started_at: u64,
+}
+
+/// How downloads and pushed files are named (OUTPUT_NAME_PATTERN), without
+/// the extension, which follows the format. Placeholders: `{stem}`, the
+/// uploaded file's name without extension; `{lang}` (or `{target_lang}`), the
+/// language code; `{date}`, the task's start date (UTC, 2024-05-01);
+/// `{title}`, the document title, or the stem when it has none.
+#[derive(Clone, Debug)]
+pub struct OutputNamePattern(String);
+
+impl OutputNamePattern {
+    pub const DEFAULT: &str = "{stem}_{lang}";
+
+    /// Names must tell languages apart, so `{lang}` is required; a trailing
+    /// `.pdf` is dropped, the extension being added per format
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        let pattern = pattern.trim();
+        let pattern = pattern.strip_suffix(".pdf").unwrap_or(pattern);
+        if pattern.contains(['/', '\\']) {
+            return Err(format!("不能包含路径分隔符: {}", pattern));
+        }
+        let mut has_lang = false;
+        for token in PageNamePattern::placeholders(pattern)? {
+            match token {
+                "stem" | "date" | "title" => {}
+                "lang" | "target_lang" => has_lang = true,
+                _ => return Err(format!("未知的占位符 {{{}}}", token)),
+            }
+        }
+        if !has_lang {
+            return Err(format!("必须包含 {{lang}}: {}", pattern));
+        }
+        Ok(Self(pattern.to_string()))
+    }
+
+    pub fn render(&self, name: &OutputName, extension: &str) -> String {
+        let stem = stem(name.source);
+        let date = chrono::DateTime::from_timestamp_millis(name.started_at as i64)
+            .map(|d| d.format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+        let mut out = String::new();
+        let mut rest = self.0.as_str();
+        while let Some(start) = rest.find('{') {
+            out.push_str(&rest[..start]);
+            // Checked in `parse`
+            let end = start + rest[start..].find('}').unwrap_or_default();
+            match &rest[start + 1..end] {
+                "stem" => out.push_str(stem),
+                "lang" | "target_lang" => out.push_str(name.tag),
+                "date" => out.push_str(&date),
+                _ => out.push_str(&name_part(name.title.unwrap_or(stem))),
+            }
+            rest = &rest[end + 1..];
+        }
+        out.push_str(rest);
+        format!("{}.{}", out, extension)
+    }
+}
+
+impl Default for OutputNamePattern {
+    fn default() -> Self {
+        Self(Self::DEFAULT.to_string())
+    }
+}
+
+/// A title made safe to put in a filename
+fn name_part(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_control() || matches!(c, '/' | '\\' | '<' | '>' | ':' | '"' | '|' | '?' | '*') { '_' } else { c })
+        .take(MAX_FILENAME_CHARS)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// How exported per-page files are named (PAGE_FILE_PATTERN). Placeholders:
+/// `{page}`, or `{page:04}` zero-padded to 4 digits so names sort in page
+/// order; `{stem}`, the uploaded file's name without extension; `{lang}`, the
+/// language code, or `ocr` for the recognized text.
+#[derive(Clone, Debug)]
+pub struct PageNamePattern(String);
+
+impl PageNamePattern {
+    pub const DEFAULT: &str = "{page:04}.{lang}.txt";
+
+    /// Names must tell pages and languages apart, so both placeholders are
+    /// required; path separators are not allowed
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        let pattern = pattern.trim();
+        if pattern.contains(['/', '\\']) {
+            return Err(format!("不能包含路径分隔符: {}", pattern));
+        }
+        let mut has_page = false;
+        let mut has_lang = false;
+        for token in Self::placeholders(pattern)? {
+            match token {
+                "stem" => {}
+                "lang" => has_lang = true,
+                "page" => has_page = true,
+                _ => match token.strip_prefix("page:").map(Self::width) {
+                    Some(Some(_)) => has_page = true,
+                    _ => return Err(format!("未知的占位符 {{{}}}", token)),
+                },
+            }
+        }
+        if !has_page || !has_lang {
+            return Err(format!("必须包含 {{page}} 与 {{lang}}: {}", pattern));
+        }
+        Ok(Self(pattern.to_string()))
+    }
+
+    fn placeholders(pattern: &str) -> Result<Vec<&str>, String> {
+        let mut tokens = Vec::new();
+        let mut rest = pattern;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..].find('}').ok_or_else(|| format!("占位符缺少 }}: {}", pattern))?;
+            tokens.push(&rest[start + 1..start + end]);
+            rest = &rest[start + end + 1..];
+        }
+        Ok(tokens)
+    }
+
+    /// `04` → 4; the width must be zero-prefixed, as in Rust's format strings
+    fn width(spec: &str) -> Option<usize> {
+        spec.strip_prefix('0').and_then(|w| w.parse().ok()).filter(|w| (1..=9).contains(w))
+    }
+
+    pub fn render(&self, page: usize, stem: &str, lang: &str) -> String {
+        let mut name = String::new();
+        let mut rest = self.0.as_str();
+        while let Some(start) = rest.find('{') {
+            name.push_str(&rest[..start]);
+            // Checked in `parse`
+            let end = start + rest[start..].find('}').unwrap_or_default();
+            match &rest[start + 1..end] {
+                "stem" => name.push_str(stem),
+                "lang" => name.push_str(lang),
+                "page" => name.push_str(&page.to_string()),
+                spec => {
+                    let width = spec.strip_prefix("page:").and_then(Self::width).unwrap_or_default();
+                    name.push_str(&format!("{:0width$}", page, width = width));
+                }
+            }
+            rest = &rest[end + 1..];
+        }
+        name.push_str(rest);
+        name
+    }
+}
+
+impl Default for PageNamePattern {
+    fn default() -> Self {
+        Self(Self::DEFAULT.to_string())
+    }
+}
+
+/// Content-Disposition value with an ASCII fallback and the RFC 5987 UTF-8 form
+pub fn content_disposition(name: &str) -> String {
+    let fallback: String = name
+        .chars()
+        .map(|c| if c.is_ascii() && !c.is_ascii_control() && c != '"' && c != '\\' { c } else { '_' })
+        .collect();
+    format!("attachment; filename=\"{}\"; filename*=UTF-8''{}", fallback, percent_encode(name))
+}
+
+fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() * 3);
+    for b in s.bytes() {
+        // attr-char from RFC 5987
+        if b.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_names_follow_pattern() {
+        let name = OutputName { source: "report.pdf", title: Some("Q1: Sales/Costs"), tag: "ja", started_at: 1_714_560_000_000 };
+        assert_eq!(OutputNamePattern::default().render(&name, "pdf"), "report_ja.pdf");
+        let pattern = OutputNamePattern::parse("{stem}_{target_lang}_{date}.pdf").unwrap();
+        assert_eq!(pattern.render(&name, "md"), "report_ja_2024-05-01.md");
+        let pattern = OutputNamePattern::parse("{title}-{lang}").unwrap();
+        assert_eq!(pattern.render(&name, "pdf"), "Q1_ Sales_Costs-ja.pdf");
+        assert!(OutputNamePattern::parse("{stem}_{date}").is_err());
+        assert!(OutputNamePattern::parse("out/{lang}").is_err());
+        assert!(OutputNamePattern::parse("{stem}_{lang}_{time}").is_err());
+    }
+
+    #[test]
+    fn output_names_make_unsafe_titles_safe() {
+        let name = |title| OutputName { source: "report.pdf", title: Some(title), tag: "en", started_at: 0 };
+        let pattern = OutputNamePattern::parse("{title}_{lang}").unwrap();
+        assert_eq!(pattern.render(&name("..\\..\\etc/passwd"), "pdf"), ".._.._etc_passwd_en.pdf");
+        assert_eq!(pattern.render(&name(" a\tb<c>|d? "), "pdf"), "a_b_c__d__en.pdf");
+        // Without {lang} every language would get the same name
+        assert!(OutputNamePattern::parse("{title}").is_err());
+        assert!(OutputNamePattern::parse("..\\{lang}").is_err());
+
+        assert_eq!(sanitize("C:\\Users\\me\\report?.pdf"), "report.pdf");
+        assert_eq!(sanitize("../../etc/passwd"), "passwd");
+        assert_eq!(sanitize("..."), "document.pdf");
+        assert_eq!(sanitize(" 季度报告\u{7}.pdf "), "季度报告.pdf");
+    }
+
+    #[test]
+    fn page_names_never_collide() {
+        for pattern in [PageNamePattern::DEFAULT, "{page}-{lang}", "{stem}{page}{lang}.txt"] {
+            let pattern = PageNamePattern::parse(pattern).unwrap();
+            let names: Vec<String> = (1..=120)
+                .flat_map(|page| ["ocr", "en", "zh-TW"].map(|lang| pattern.render(page, "v2", lang)))
+                .collect();
+            let distinct: std::collections::HashSet<&String> = names.iter().collect();
+            assert_eq!(distinct.len(), names.len(), "{:?}", pattern);
+        }
+
+        // Zero-padded names sort in page order
+        let pattern = PageNamePattern::default();
+        let mut names: Vec<String> = [2, 10, 1, 100].iter().map(|page| pattern.render(*page, "report", "en")).collect();
+        names.sort();
+        assert_eq!(names, ["0001.en.txt", "0002.en.txt", "0010.en.txt", "0100.en.txt"]);
+
+        // Patterns that would reuse a name or leave the pages directory
+        for pattern in ["{page}.txt", "{lang}.txt", "{stem}.{lang}", "../{page}.{lang}", "{page}\\{lang}", "{page:4}.{lang}", "{page:010}.{lang}", "{page.{lang}"] {
+            assert!(PageNamePattern::parse(pattern).is_err(), "{}", pattern);
+        }
+    }
+}
@@ -0,0 +1,349 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+/// A TrueType/OpenType font embedded in the output: the body font configured
+/// for the output language, or a fallback for characters the built-in CJK
+/// font (STSong-Light) has no glyphs for.
+///
+/// Glyphs are addressed directly by glyph id (Identity-H/V), so no shaping
+/// is done: scripts that need contextual forms (Arabic, Indic) render as
+/// isolated glyphs, and bitmap-only emoji fonts have nothing to embed.
+pub struct FallbackFont {
+    pub name: String,
+    pub data: Vec<u8>,
+    /// CFF outlines ("OTTO") embed as FontFile3, glyf outlines as FontFile2
+    pub cff: bool,
+    cmap: HashMap<u32, u16>,
+    advances: Vec<u16>,
+    units_per_em: u16,
+    pub bbox: [i16; 4],
+    pub ascent: i16,
+    pub descent: i16,
+    /// Table tag → (offset, length), kept for subsetting
+    tables: HashMap<[u8; 4], (usize, usize)>,
+}
+
+impl FallbackFont {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = std::fs::read(path).map_err(|e| format!("读取字体失败 {}: {}", path.display(), e))?;
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("Fallback");
+        Self::parse(stem, data).map_err(|e| format!("解析字体失败 {}: {}", path.display(), e))
+    }
+
+    fn parse(stem: &str, data: Vec<u8>) -> Result<Self, String> {
+        let tag = data.get(0..4).ok_or("文件过短")?;
+        let cff = match tag {
+            b"OTTO" => true,
+            [0, 1, 0, 0] | b"true" => false,
+            b"ttcf" => return Err("不支持字体集合 (TTC)".to_string()),
+            _ => return Err("不是 TrueType/OpenType 字体".to_string()),
+        };
+
+        let tables = table_directory(&data)?;
+        let table = |name: &[u8; 4]| {
+            tables.get(name).map(|(offset, _)| *offset).ok_or_else(|| format!("缺少 {} 表", String::from_utf8_lossy(name)))
+        };
+
+        let head = table(b"head")?;
+        let units_per_em = read_u16(&data, head + 18)?;
+        let bbox = [
+            read_u16(&data, head + 36)? as i16,
+            read_u16(&data, head + 38)? as i16,
+            read_u16(&data, head + 40)? as i16,
+            read_u16(&data, head + 42)? as i16,
+        ];
+
+        let hhea = table(b"hhea")?;
+        let ascent = read_u16(&data, hhea + 4)? as i16;
+        let descent = read_u16(&data, hhea + 6)? as i16;
+        let num_h_metrics = read_u16(&data, hhea + 34)? as usize;
+        let num_glyphs = read_u16(&data, table(b"maxp")? + 4)? as usize;
+
+        let hmtx = table(b"hmtx")?;
+        let mut advances = Vec::with_capacity(num_glyphs);
+        for i in 0..num_h_metrics.min(num_glyphs) {
+            advances.push(read_u16(&data, hmtx + i * 4)?);
+        }
+        // Glyphs past numberOfHMetrics repeat the last advance
+        let last = advances.last().copied().unwrap_or(units_per_em);
+        advances.resize(num_glyphs, last);
+
+        let cmap = parse_cmap(&data, table(b"cmap")?)?;
+        if cmap.is_empty() {
+            return Err("没有可用的 Unicode cmap".to_string());
+        }
+
+        // PDF names must not contain delimiters or whitespace
+        let name: String = stem.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '-').collect();
+
+        Ok(Self {
+            name: if name.is_empty() { "Fallback".to_string() } else { name },
+            data,
+            cff,
+            cmap,
+            advances,
+            units_per_em: units_per_em.max(1),
+            bbox,
+            ascent,
+            descent,
+            tables,
+        })
+    }
+
+    /// A font with a glyph (numbered from 1) for each of `chars` and no
+    /// outlines, for tests of font selection
+    #[cfg(test)]
+    pub fn covering(name: &str, chars: &str) -> Self {
+        let cmap: HashMap<u32, u16> = chars.chars().zip(1..).map(|(c, gid)| (c as u32, gid)).collect();
+        Self {
+            name: name.to_string(),
+            data: Vec::new(),
+            cff: false,
+            advances: vec![500; cmap.len() + 1],
+            cmap,
+            units_per_em: 1000,
+            bbox: [0, -200, 1000, 800],
+            ascent: 800,
+            descent: -200,
+            tables: HashMap::new(),
+        }
+    }
+
+    pub fn glyph(&self, c: char) -> Option<u16> {
+        self.cmap.get(&(c as u32)).copied().filter(|gid| *gid != 0)
+    }
+
+    /// Advance width in PDF glyph space (1/1000 em)
+    pub fn advance(&self, gid: u16) -> u32 {
+        let units = self.advances.get(gid as usize).copied().unwrap_or(0) as u32;
+        units * 1000 / self.units_per_em as u32
+    }
+
+    /// Scale a font-unit metric to 1/1000 em
+    pub fn scale(&self, value: i16) -> i32 {
+        value as i32 * 1000 / self.units_per_em as i32
+    }
+
+    /// A copy of a TrueType (glyf) font holding only the given glyphs, plus
+    /// .notdef and the components of composite glyphs. Glyph ids are kept
+    /// (dropped glyphs become empty), so the Identity CIDToGIDMap still holds;
+    /// only the tables a PDF viewer needs are written. None for CFF fonts and
+    /// fonts that can't be subset, which are embedded whole.
+    pub fn subset(&self, glyphs: impl IntoIterator<Item = u16>) -> Option<Vec<u8>> {
+        if self.cff {
+            return None;
+        }
+        let data = &self.data;
+        let table = |tag: &[u8; 4]| {
+            self.tables.get(tag).and_then(|(offset, len)| data.get(*offset..offset + len))
+        };
+        let head = table(b"head")?;
+        let glyf = table(b"glyf")?;
+        let loca = table(b"loca")?;
+        let long_loca = read_u16(head, 50).ok()? == 1;
+        let num_glyphs = self.advances.len();
+        let glyph_range = |gid: usize| -> Option<std::ops::Range<usize>> {
+            let (start, end) = if long_loca {
+                (read_u32(loca, gid * 4).ok()? as usize, read_u32(loca, gid * 4 + 4).ok()? as usize)
+            } else {
+                (read_u16(loca, gid * 2).ok()? as usize * 2, read_u16(loca, gid * 2 + 2).ok()? as usize * 2)
+            };
+            (start <= end && end <= glyf.len()).then_some(start..end)
+        };
+
+        // Composite glyphs pull in their components
+        let mut keep: BTreeSet<u16> = glyphs.into_iter().filter(|g| (*g as usize) < num_glyphs).collect();
+        keep.insert(0);
+        let mut pending: Vec<u16> = keep.iter().copied().collect();
+        while let Some(gid) = pending.pop() {
+            for component in composite_components(&glyf[glyph_range(gid as usize)?]) {
+                if (component as usize) < num_glyphs && keep.insert(component) {
+                    pending.push(component);
+                }
+            }
+        }
+
+        let mut new_glyf = Vec::new();
+        let mut new_loca = Vec::with_capacity((num_glyphs + 1) * 4);
+        for gid in 0..num_glyphs {
+            new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+            if keep.contains(&(gid as u16)) {
+                new_glyf.extend_from_slice(&glyf[glyph_range(gid)?]);
+                new_glyf.resize(new_glyf.len().next_multiple_of(4), 0);
+            }
+        }
+        new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+
+        // Long loca offsets; the checksum adjustment is left for readers to ignore
+        let mut new_head = head.to_vec();
+        new_head.get_mut(8..12)?.copy_from_slice(&[0; 4]);
+        new_head.get_mut(50..52)?.copy_from_slice(&1u16.to_be_bytes());
+
+        let mut out_tables: Vec<([u8; 4], Vec<u8>)> = vec![
+            (*b"glyf", new_glyf),
+            (*b"head", new_head),
+            (*b"loca", new_loca),
+        ];
+        for tag in [b"cvt ", b"fpgm", b"hhea", b"hmtx", b"maxp", b"prep"] {
+            if let Some(bytes) = table(tag) {
+                out_tables.push((*tag, bytes.to_vec()));
+            }
+        }
+        out_tables.sort_by_key(|(tag, _)| *tag);
+        Some(write_sfnt(&out_tables))
+    }
+}
+
+/// Glyph ids referenced by a composite glyph (empty for simple glyphs)
+fn composite_components(glyph: &[u8]) -> Vec<u16> {
+    const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+    const WE_HAVE_A_SCALE: u16 = 0x0008;
+    const MORE_COMPONENTS: u16 = 0x0020;
+    const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+    const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+    let mut components = Vec::new();
+    let is_composite = read_u16(glyph, 0).is_ok_and(|contours| (contours as i16) < 0);
+    if !is_composite {
+        return components;
+    }
+    let mut offset = 10;
+    while let (Ok(flags), Ok(gid)) = (read_u16(glyph, offset), read_u16(glyph, offset + 2)) {
+        components.push(gid);
+        offset += 4 + if flags & ARG_1_AND_2_ARE_WORDS != 0 { 4 } else { 2 };
+        offset += if flags & WE_HAVE_A_SCALE != 0 {
+            2
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            4
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            8
+        } else {
+            0
+        };
+        if flags & MORE_COMPONENTS == 0 {
+            break;
+        }
+    }
+    components
+}
+
+/// Assemble an sfnt file from tables sorted by tag
+fn write_sfnt(tables: &[([u8; 4], Vec<u8>)]) -> Vec<u8> {
+    let num_tables = tables.len() as u16;
+    let entry_selector = 15 - num_tables.max(1).leading_zeros() as u16;
+    let search_range = (1u16 << entry_selector) * 16;
+    let mut out = Vec::new();
+    out.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+    for value in [num_tables, search_range, entry_selector, num_tables * 16 - search_range] {
+        out.extend_from_slice(&value.to_be_bytes());
+    }
+
+    let mut offset = 12 + tables.len() * 16;
+    for (tag, bytes) in tables {
+        let checksum = bytes.chunks(4).fold(0u32, |sum, chunk| {
+            let mut word = [0u8; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            sum.wrapping_add(u32::from_be_bytes(word))
+        });
+        out.extend_from_slice(tag);
+        out.extend_from_slice(&checksum.to_be_bytes());
+        out.extend_from_slice(&(offset as u32).to_be_bytes());
+        out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+        offset += bytes.len().next_multiple_of(4);
+    }
+    for (_, bytes) in tables {
+        out.extend_from_slice(bytes);
+        out.resize(out.len().next_multiple_of(4), 0);
+    }
+    out
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, String> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .ok_or_else(|| "字体数据越界".to_string())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, String> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| "字体数据越界".to_string())
+}
+
+fn table_directory(data: &[u8]) -> Result<HashMap<[u8; 4], (usize, usize)>, String> {
+    let num_tables = read_u16(data, 4)? as usize;
+    let mut tables = HashMap::new();
+    for i in 0..num_tables {
+        let record = 12 + i * 16;
+        let tag = data.get(record..record + 4).ok_or("字体数据越界")?;
+        let offset = read_u32(data, record + 8)? as usize;
+        let length = read_u32(data, record + 12)? as usize;
+        tables.insert([tag[0], tag[1], tag[2], tag[3]], (offset, length));
+    }
+    Ok(tables)
+}
+
+/// Unicode → glyph id from the best available subtable (format 12 for full
+/// Unicode including emoji, otherwise format 4 for the BMP).
+fn parse_cmap(data: &[u8], cmap: usize) -> Result<HashMap<u32, u16>, String> {
+    let num_subtables = read_u16(data, cmap + 2)? as usize;
+    let mut bmp = None;
+    let mut full = None;
+    for i in 0..num_subtables {
+        let record = cmap + 4 + i * 8;
+        let platform = read_u16(data, record)?;
+        let encoding = read_u16(data, record + 2)?;
+        let offset = cmap + read_u32(data, record + 4)? as usize;
+        let unicode = platform == 0 || (platform == 3 && (encoding == 1 || encoding == 10));
+        if !unicode {
+            continue;
+        }
+        match read_u16(data, offset)? {
+            12 => full = Some(offset),
+            4 => bmp = Some(offset),
+            _ => {}
+        }
+    }
+
+    let mut map = HashMap::new();
+    if let Some(offset) = full {
+        let groups = read_u32(data, offset + 12)? as usize;
+        for g in 0..groups {
+            let group = offset + 16 + g * 12;
+            let start = read_u32(data, group)?;
+            let end = read_u32(data, group + 4)?;
+            let start_gid = read_u32(data, group + 8)?;
+            for code in start..=end.min(0x10FFFF) {
+                map.insert(code, (start_gid + code - start) as u16);
+            }
+        }
+    } else if let Some(offset) = bmp {
+        let seg_count = read_u16(data, offset + 6)? as usize / 2;
+        let end_codes = offset + 14;
+        let start_codes = end_codes + seg_count * 2 + 2;
+        let deltas = start_codes + seg_count * 2;
+        let range_offsets = deltas + seg_count * 2;
+        for s in 0..seg_count {
+            let end = read_u16(data, end_codes + s * 2)? as u32;
+            let start = read_u16(data, start_codes + s * 2)? as u32;
+            let delta = read_u16(data, deltas + s * 2)?;
+            let range_offset = read_u16(data, range_offsets + s * 2)? as usize;
+            if start == 0xFFFF {
+                continue;
+            }
+            for code in start..=end {
+                let gid = if range_offset == 0 {
+                    (code as u16).wrapping_add(delta)
+                } else {
+                    let addr = range_offsets + s * 2 + range_offset + (code - start) as usize * 2;
+                    match read_u16(data, addr)? {
+                        0 => 0,
+                        raw => raw.wrapping_add(delta),
+                    }
+                };
+                map.insert(code, gid);
+            }
+        }
+    }
+    Ok(map)
+}
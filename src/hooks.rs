@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::lang::TargetLang;
+
+/// Points in the pipeline where an operator's HTTP endpoint may rewrite the
+/// text, e.g. to redact personal data or enforce a glossary
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HookPoint {
+    /// OCR text of a page, before it is saved
+    PostOcr,
+    /// Source text of a page, before it is sent for one target language
+    PreTranslate,
+    /// A page's translation, before it is saved
+    PostTranslate,
+    /// All pages of one language, before the PDF is assembled
+    PreGenerate,
+}
+
+impl HookPoint {
+    pub const ALL: [HookPoint; 4] = [HookPoint::PostOcr, HookPoint::PreTranslate, HookPoint::PostTranslate, HookPoint::PreGenerate];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HookPoint::PostOcr => "post-ocr",
+            HookPoint::PreTranslate => "pre-translate",
+            HookPoint::PostTranslate => "post-translate",
+            HookPoint::PreGenerate => "pre-generate",
+        }
+    }
+
+    /// Variable holding the hook's URL
+    pub fn env_name(&self) -> &'static str {
+        match self {
+            HookPoint::PostOcr => "HOOK_POST_OCR",
+            HookPoint::PreTranslate => "HOOK_PRE_TRANSLATE",
+            HookPoint::PostTranslate => "HOOK_POST_TRANSLATE",
+            HookPoint::PreGenerate => "HOOK_PRE_GENERATE",
+        }
+    }
+}
+
+/// POSTed to the hook; a page hook gets `text`, the pre-generate hook `pages`
+#[derive(Serialize)]
+struct HookRequest<'a> {
+    hook: &'static str,
+    task_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target_lang: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pages: Option<&'a [String]>,
+}
+
+/// A field left out keeps the text as it was
+#[derive(Deserialize)]
+struct HookResponse {
+    text: Option<String>,
+    pages: Option<Vec<String>>,
+}
+
+static HTTP_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+fn get_client(timeout_secs: u64) -> &'static reqwest::Client {
+    HTTP_CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(timeout_secs))
+            .connect_timeout(Duration::from_secs(10))
+            .build()
+            .expect("Failed to create HTTP client")
+    })
+}
+
+/// Run a page hook over `text`; without a hook configured the text is returned as is
+pub async fn page_text(
+    config: &Config,
+    point: HookPoint,
+    task_id: &str,
+    page_num: usize,
+    lang: Option<TargetLang>,
+    text: String,
+) -> Result<String, String> {
+    let Some(url) = config.hooks.get(&point) else {
+        return Ok(text);
+    };
+    let request = HookRequest {
+        hook: point.as_str(),
+        task_id,
+        page: Some(page_num),
+        target_lang: lang.map(|l| l.code()),
+        text: Some(&text),
+        pages: None,
+    };
+    let response = call(config, point, url, &request).await?;
+    Ok(response.text.unwrap_or(text))
+}
+
+/// Run the pre-generate hook over the pages of one language
+pub async fn document_pages(config: &Config, task_id: &str, lang: TargetLang, pages: Vec<String>) -> Result<Vec<String>, String> {
+    let point = HookPoint::PreGenerate;
+    let Some(url) = config.hooks.get(&point) else {
+        return Ok(pages);
+    };
+    let request = HookRequest {
+        hook: point.as_str(),
+        task_id,
+        page: None,
+        target_lang: Some(lang.code()),
+        text: None,
+        pages: Some(&pages),
+    };
+    match call(config, point, url, &request).await?.pages {
+        Some(changed) if changed.len() != pages.len() => Err(format!(
+            "{} 钩子返回了 {} 页，应为 {} 页", point.as_str(), changed.len(), pages.len()
+        )),
+        Some(changed) => Ok(changed),
+        None => Ok(pages),
+    }
+}
+
+async fn call(config: &Config, point: HookPoint, url: &str, request: &HookRequest<'_>) -> Result<HookResponse, String> {
+    let response = get_client(config.hook_timeout_secs)
+        .post(url)
+        .json(request)
+        .send()
+        .await
+        .map_err(|e| format!("{} 钩子调用失败: {}", point.as_str(), e))?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("{} 钩子调用失败: HTTP {}", point.as_str(), status));
+    }
+    response.json::<HookResponse>().await
+        .map_err(|e| format!("{} 钩子返回无效: {}", point.as_str(), e))
+}
@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::lang::TargetLang;
+use crate::state::{self, TaskOptions};
+
+/// Something a pipeline stage wrote, identified for the manifest
+#[derive(Clone, Copy)]
+pub enum Artifact {
+    /// The document as processed (after page selection or sampling)
+    Input,
+    /// The rendered image sent to OCR; the last one sent if the page was downgraded
+    Image(usize),
+    Ocr(usize),
+    Translation(usize, TargetLang),
+    Output(TargetLang),
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// SHA-256 of every artifact of a task, recorded as each stage produces it
+/// and kept in data/tasks/{id}/manifest.json. Re-checking it against the
+/// files shows the delivered translation comes from the uploaded original.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<String>,
+    #[serde(default)]
+    pub pages: BTreeMap<usize, PageChecksums>,
+    /// Output PDF per language code
+    #[serde(default)]
+    pub outputs: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct PageChecksums {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ocr: Option<String>,
+    /// Per language code
+    #[serde(default)]
+    pub translations: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    pub fn record(&mut self, artifact: Artifact, hash: String) {
+        match artifact {
+            Artifact::Input => self.input = Some(hash),
+            Artifact::Image(page_num) => self.page(page_num).image = Some(hash),
+            Artifact::Ocr(page_num) => self.page(page_num).ocr = Some(hash),
+            Artifact::Translation(page_num, lang) => {
+                self.page(page_num).translations.insert(lang.code().to_string(), hash);
+            }
+            Artifact::Output(lang) => {
+                self.outputs.insert(lang.code().to_string(), hash);
+            }
+        }
+    }
+
+    fn page(&mut self, page_num: usize) -> &mut PageChecksums {
+        self.pages.entry(page_num).or_default()
+    }
+
+    /// Hash the task's files again and compare them with the recorded values
+    pub fn verify(&self, task_id: &str, options: &TaskOptions) -> Verification {
+        let mut verification = Verification::default();
+        if let Some(expected) = &self.input {
+            verification.check("input".to_string(), expected, &state::input_path(task_id));
+        }
+        for (&page_num, page) in &self.pages {
+            // Tasks from before page images were kept have none to check
+            if let Some(expected) = &page.image
+                && let Some((path, _)) = state::find_page_image(task_id, page_num)
+            {
+                verification.check(format!("pages/{}/image", page_num), expected, &path);
+            }
+            if let Some(expected) = &page.ocr {
+                verification.check(format!("pages/{}/ocr", page_num), expected, &state::page_ocr_path(task_id, page_num));
+            }
+            for (code, expected) in &page.translations {
+                let Some(lang) = TargetLang::parse(code) else { continue };
+                let path = state::page_translation_path(task_id, page_num, options.lang_suffix(lang));
+                verification.check(format!("pages/{}/{}", page_num, code), expected, &path);
+            }
+        }
+        for (code, expected) in &self.outputs {
+            let Some(lang) = TargetLang::parse(code) else { continue };
+            let path = state::output_path(task_id, options.lang_suffix(lang));
+            verification.check(format!("output/{}", code), expected, &path);
+        }
+        verification.ok = verification.mismatches.is_empty();
+        verification
+    }
+}
+
+/// Outcome of re-checking a manifest
+#[derive(Default, Serialize)]
+pub struct Verification {
+    pub ok: bool,
+    /// Number of files hashed
+    pub checked: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+#[derive(Serialize)]
+pub struct Mismatch {
+    pub artifact: String,
+    pub expected: String,
+    /// None when the file is gone
+    pub actual: Option<String>,
+}
+
+impl Verification {
+    fn check(&mut self, artifact: String, expected: &str, path: &Path) {
+        self.checked += 1;
+        let actual = std::fs::read(path).ok().map(|data| sha256_hex(&data));
+        if actual.as_deref() != Some(expected) {
+            self.mismatches.push(Mismatch { artifact, expected: expected.to_string(), actual });
+        }
+    }
+}
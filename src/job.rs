@@ -0,0 +1,168 @@
+use async_zip::base::read::mem::ZipFileReader;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use tokio::sync::Notify;
+
+use crate::{pdf, state};
+use crate::schedule::SECRET_FIELDS;
+
+/// Files of a job's archive waiting for their task to start
+pub fn files_dir() -> PathBuf {
+    state::data_dir().join("jobs")
+}
+
+/// Most documents one archive may hold
+pub const MAX_JOB_FILES: usize = 100;
+
+/// Documents uploaded together in one ZIP archive, one task each
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: String,
+    /// Name of the uploaded archive
+    pub filename: String,
+    /// Upload form fields every task of the job is created with
+    pub fields: Vec<(String, String)>,
+    /// User whose saved preferences fill the options left out
+    #[serde(default)]
+    pub user: Option<String>,
+    pub created_at: u64,
+    pub files: Vec<JobFile>,
+    /// Archive entries that are not a PDF or an image
+    pub skipped: Vec<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct JobFile {
+    /// Path of the document inside the archive
+    pub name: String,
+    /// Given up front; the task exists once the file leaves the queue
+    pub task_id: String,
+    pub started: bool,
+    /// Why the task could not be created
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl Job {
+    /// The job without credential fields, for responses
+    pub fn redacted(&self) -> Self {
+        let mut job = self.clone();
+        job.fields.retain(|(name, _)| !SECRET_FIELDS.contains(&name.as_str()));
+        job
+    }
+}
+
+/// Where a queued file of a job waits until its task starts
+pub fn queued_file_path(job_id: &str, index: usize) -> PathBuf {
+    files_dir().join(job_id).join(format!("{:03}", index))
+}
+
+/// Persistent job list, saved as a whole on every change
+pub struct JobStore {
+    jobs: Mutex<Vec<Job>>,
+    /// Wakes the runner when files are queued
+    wake: Notify,
+}
+
+impl JobStore {
+    pub fn load() -> Self {
+        let jobs = fs::read_to_string(state::data_dir().join("jobs.json"))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        Self { jobs: Mutex::new(jobs), wake: Notify::new() }
+    }
+
+    fn save(jobs: &[Job]) {
+        let path = state::data_dir().join("jobs.json");
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let tmp_path = path.with_extension("json.tmp");
+        if let Ok(json) = serde_json::to_string_pretty(jobs)
+            && fs::write(&tmp_path, json).is_ok()
+        {
+            let _ = fs::rename(tmp_path, path);
+        }
+    }
+
+    /// Store a new job and have the runner start its files
+    pub fn add(&self, job: Job) {
+        let mut jobs = self.jobs.lock();
+        jobs.push(job);
+        Self::save(&jobs);
+        self.wake.notify_one();
+    }
+
+    /// Wait until a job is added
+    pub async fn added(&self) {
+        self.wake.notified().await;
+    }
+
+    pub fn get(&self, id: &str) -> Option<Job> {
+        self.jobs.lock().iter().find(|j| j.id == id).cloned()
+    }
+
+    /// Jobs with files still waiting for a task slot, oldest first
+    pub fn queued(&self) -> Vec<Job> {
+        self.jobs.lock().iter()
+            .filter(|j| j.files.iter().any(|f| !f.started && f.error.is_none()))
+            .cloned()
+            .collect()
+    }
+
+    /// Record the outcome of starting one of a job's files
+    pub fn update_file(&self, id: &str, index: usize, apply: impl FnOnce(&mut JobFile)) {
+        let mut jobs = self.jobs.lock();
+        if let Some(file) = jobs.iter_mut().find(|j| j.id == id).and_then(|j| j.files.get_mut(index)) {
+            apply(file);
+            Self::save(&jobs);
+        }
+    }
+}
+
+/// Save the PDFs and images in a ZIP archive as the job's queued files, in
+/// archive order; returns their paths in the archive and the names of the
+/// other entries. Folders and macOS metadata are left out without a mention.
+pub async fn extract_documents(data: Vec<u8>, job_id: &str, max_size: usize) -> Result<(Vec<String>, Vec<String>), String> {
+    let reader = ZipFileReader::new(data).await
+        .map_err(|e| format!("无法读取 ZIP 文件: {}", e))?;
+    let mut documents = Vec::new();
+    let mut skipped = Vec::new();
+    for (index, entry) in reader.file().entries().iter().enumerate() {
+        let name = entry.filename().as_str().map_err(|e| format!("无法读取 ZIP 文件: {}", e))?.to_string();
+        let hidden = name.split('/').any(|part| part.starts_with('.') || part == "__MACOSX");
+        if entry.dir().unwrap_or(false) || hidden {
+            continue;
+        }
+        if entry.uncompressed_size() > max_size as u64 {
+            skipped.push(name);
+            continue;
+        }
+        let mut content = Vec::new();
+        reader.reader_with_entry(index).await
+            .map_err(|e| format!("{}: {}", name, e))?
+            .read_to_end_checked(&mut content).await
+            .map_err(|e| format!("{}: {}", name, e))?;
+        if !content.starts_with(b"%PDF") && !pdf::is_image(&content) {
+            skipped.push(name);
+            continue;
+        }
+        if documents.len() == MAX_JOB_FILES {
+            return Err(format!("ZIP 中的文件过多，最多 {} 个", MAX_JOB_FILES));
+        }
+        let path = queued_file_path(job_id, documents.len());
+        fs::create_dir_all(path.parent().unwrap_or(&files_dir()))
+            .and_then(|_| fs::write(&path, &content))
+            .map_err(|e| format!("保存文件失败: {}", e))?;
+        documents.push(name);
+    }
+    Ok((documents, skipped))
+}
+
+/// Delete what is left of a job's queued files
+pub fn remove_queued_files(job_id: &str) {
+    let _ = fs::remove_dir_all(files_dir().join(job_id));
+}
@@ -0,0 +1,195 @@
+/// Output languages the translator can prompt for
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TargetLang {
+    #[default]
+    ZhCn,
+    ZhTw,
+    En,
+    Ja,
+    Ko,
+    Es,
+    Fr,
+    De,
+    Pt,
+    Ru,
+}
+
+impl TargetLang {
+    pub const ALL: [TargetLang; 10] = [
+        TargetLang::ZhCn, TargetLang::ZhTw, TargetLang::En, TargetLang::Ja, TargetLang::Ko,
+        TargetLang::Es, TargetLang::Fr, TargetLang::De, TargetLang::Pt, TargetLang::Ru,
+    ];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().replace('_', "-").as_str() {
+            "zh" | "zh-cn" | "zh-hans" | "chs" => Some(TargetLang::ZhCn),
+            "zh-tw" | "zh-hk" | "zh-hant" | "cht" => Some(TargetLang::ZhTw),
+            "en" | "en-us" | "en-gb" => Some(TargetLang::En),
+            "ja" | "ja-jp" | "jp" => Some(TargetLang::Ja),
+            "ko" | "ko-kr" | "kr" => Some(TargetLang::Ko),
+            "es" | "es-es" | "es-mx" => Some(TargetLang::Es),
+            "fr" | "fr-fr" => Some(TargetLang::Fr),
+            "de" | "de-de" => Some(TargetLang::De),
+            "pt" | "pt-br" | "pt-pt" => Some(TargetLang::Pt),
+            "ru" | "ru-ru" => Some(TargetLang::Ru),
+            _ => None,
+        }
+    }
+
+    /// BCP 47 tag, also used as the PDF document language
+    pub fn code(&self) -> &'static str {
+        match self {
+            TargetLang::ZhCn => "zh-CN",
+            TargetLang::ZhTw => "zh-TW",
+            TargetLang::En => "en",
+            TargetLang::Ja => "ja",
+            TargetLang::Ko => "ko",
+            TargetLang::Es => "es",
+            TargetLang::Fr => "fr",
+            TargetLang::De => "de",
+            TargetLang::Pt => "pt",
+            TargetLang::Ru => "ru",
+        }
+    }
+
+    /// Name used in the translation prompt and on the cover page
+    pub fn name(&self) -> &'static str {
+        match self {
+            TargetLang::ZhCn => "简体中文",
+            TargetLang::ZhTw => "繁體中文",
+            TargetLang::En => "英语",
+            TargetLang::Ja => "日语",
+            TargetLang::Ko => "韩语",
+            TargetLang::Es => "西班牙语",
+            TargetLang::Fr => "法语",
+            TargetLang::De => "德语",
+            TargetLang::Pt => "葡萄牙语",
+            TargetLang::Ru => "俄语",
+        }
+    }
+
+    /// Whether the text already appears to be in this language, so translation can be skipped
+    pub fn is_mostly_target(&self, text: &str) -> bool {
+        let total = text.chars().filter(|c| !c.is_whitespace()).count().max(1) as f32;
+        let ratio = |pred: fn(char) -> bool| text.chars().filter(|c| pred(*c)).count() as f32 / total;
+        match self {
+            // Kana-free ideographs: Japanese would otherwise pass as Chinese
+            TargetLang::ZhCn | TargetLang::ZhTw => ratio(is_han) > 0.7 && ratio(is_kana) < 0.05,
+            TargetLang::Ja => ratio(|c| is_han(c) || is_kana(c)) > 0.7 && ratio(is_kana) > 0.05,
+            TargetLang::Ko => ratio(is_hangul) > 0.6,
+            TargetLang::Ru => ratio(is_cyrillic) > 0.6,
+            TargetLang::En | TargetLang::Es | TargetLang::Fr | TargetLang::De | TargetLang::Pt => {
+                ratio(|c| c.is_alphabetic() && (c.is_ascii() || ('\u{C0}'..='\u{24F}').contains(&c))) > 0.7
+                    && latin_language(text) == Some(*self)
+            }
+        }
+    }
+
+    /// Written in the Latin alphabet
+    pub fn is_latin(&self) -> bool {
+        matches!(self, TargetLang::En | TargetLang::Es | TargetLang::Fr | TargetLang::De | TargetLang::Pt)
+    }
+
+    /// A letter from a script this language isn't written in. Latin doesn't
+    /// count for any language, since names and terms keep it everywhere.
+    pub fn is_foreign_letter(&self, c: char) -> bool {
+        match self {
+            TargetLang::ZhCn | TargetLang::ZhTw => is_kana(c) || is_hangul(c) || is_cyrillic(c),
+            TargetLang::Ja => is_hangul(c) || is_cyrillic(c),
+            TargetLang::Ko => is_kana(c) || is_cyrillic(c),
+            TargetLang::Ru => is_han(c) || is_kana(c) || is_hangul(c),
+            TargetLang::En | TargetLang::Es | TargetLang::Fr | TargetLang::De | TargetLang::Pt => {
+                is_han(c) || is_kana(c) || is_hangul(c) || is_cyrillic(c)
+            }
+        }
+    }
+}
+
+/// Romanized reading of a line: pinyin syllables for Han characters (Mandarin,
+/// toneless, so Japanese kanji come out as pinyin too), romaji for kana,
+/// Revised Romanization for Hangul, Latin letters for Cyrillic. None when the
+/// line has no non-Latin letters, so Latin text isn't repeated.
+pub fn romanize(text: &str) -> Option<String> {
+    let needs_reading = text.chars()
+        .any(|c| c.is_alphabetic() && !c.is_ascii() && !('\u{C0}'..='\u{24F}').contains(&c));
+    if !needs_reading {
+        return None;
+    }
+
+    let mut out = String::new();
+    let mut run = String::new();
+    let separate = |out: &mut String| {
+        if out.ends_with(|c: char| !c.is_whitespace() && !"([{\"'".contains(c)) {
+            out.push(' ');
+        }
+    };
+    for c in text.chars() {
+        // Syllabic scripts become one lowercase syllable per character
+        if is_han(c) || ('\u{AC00}'..='\u{D7AF}').contains(&c) {
+            out.push_str(&any_ascii::any_ascii(&run));
+            run.clear();
+            separate(&mut out);
+            out.push_str(&any_ascii::any_ascii_char(c).to_lowercase());
+        } else {
+            if run.is_empty() && c.is_alphanumeric() {
+                separate(&mut out);
+            }
+            run.push(c);
+        }
+    }
+    out.push_str(&any_ascii::any_ascii(&run));
+
+    // Full-width punctuation turns into ASCII without the following space
+    let mut spaced = String::with_capacity(out.len());
+    let mut prev = ' ';
+    for c in out.trim().chars() {
+        if ",.;:!?)".contains(prev) && c.is_alphabetic() {
+            spaced.push(' ');
+        }
+        spaced.push(c);
+        prev = c;
+    }
+    (!spaced.is_empty()).then_some(spaced)
+}
+
+fn is_han(c: char) -> bool {
+    let code = c as u32;
+    (0x4E00..=0x9FFF).contains(&code) || (0x3400..=0x4DBF).contains(&code) || (0x20000..=0x2A6DF).contains(&code)
+}
+
+fn is_kana(c: char) -> bool {
+    ('\u{3040}'..='\u{30FF}').contains(&c)
+}
+
+fn is_hangul(c: char) -> bool {
+    ('\u{AC00}'..='\u{D7AF}').contains(&c) || ('\u{1100}'..='\u{11FF}').contains(&c)
+}
+
+fn is_cyrillic(c: char) -> bool {
+    ('\u{0400}'..='\u{04FF}').contains(&c)
+}
+
+/// Guess among the Latin-script targets by counting common function words;
+/// None when no language stands out (too little text, or a different language)
+fn latin_language(text: &str) -> Option<TargetLang> {
+    const STOPWORDS: [(TargetLang, &[&str]); 5] = [
+        (TargetLang::En, &["the", "and", "of", "to", "is", "in", "that", "for", "with", "are"]),
+        (TargetLang::Es, &["el", "la", "de", "que", "y", "los", "las", "en", "por", "es"]),
+        (TargetLang::Fr, &["le", "la", "les", "de", "des", "et", "est", "un", "une", "que"]),
+        (TargetLang::De, &["der", "die", "das", "und", "ist", "nicht", "mit", "ein", "eine", "zu"]),
+        (TargetLang::Pt, &["o", "os", "as", "de", "que", "e", "não", "em", "um", "uma"]),
+    ];
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect();
+    if words.len() < 5 {
+        return None;
+    }
+    let (lang, hits) = STOPWORDS
+        .iter()
+        .map(|(lang, list)| (*lang, words.iter().filter(|w| list.contains(&w.as_str())).count()))
+        .max_by_key(|(_, hits)| *hits)?;
+    (hits as f32 / words.len() as f32 >= 0.15).then_some(lang)
+}
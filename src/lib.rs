@@ -0,0 +1,57 @@
+//! PDF translation: pages are rendered, recognized by a vision model and
+//! translated, then typeset into a new PDF per target language.
+//!
+//! [`Translator`] runs the pipeline from another program and reports
+//! progress as a stream of [`ProgressEvent`]s; the `pdftrans` binary serves
+//! the same pipeline over HTTP. Both are configured with [`Config`], normally
+//! read from the environment.
+//!
+//! [`serve`] is what the binary runs; the rest of the crate is internal.
+
+mod align;
+mod annotate;
+mod archive;
+mod check;
+mod config;
+mod connector;
+mod deadletter;
+mod destination;
+mod error;
+mod export;
+mod filename;
+mod font;
+mod hooks;
+mod integrity;
+mod job;
+mod lang;
+mod migrate;
+mod pdf;
+mod pii;
+mod pipeline;
+mod policy;
+mod preferences;
+mod provider;
+mod render;
+mod resources;
+mod retention;
+mod routes;
+mod schedule;
+mod scheduler;
+mod server;
+mod state;
+mod stats;
+#[cfg(test)]
+mod testing;
+mod translate;
+mod translator;
+mod ui;
+mod usage;
+mod watchdog;
+mod workers;
+
+pub use config::Config;
+pub use error::SetupError;
+pub use lang::TargetLang;
+pub use server::serve;
+pub use state::{LogEntry, PageSummary, TaskOptions, TaskStatus};
+pub use translator::{ProgressEvent, StatusChange, Translator};
@@ -0,0 +1,152 @@
+use serde_json::Value;
+use std::fs;
+
+use crate::integrity::{self, Artifact, Manifest};
+use crate::lang::TargetLang;
+use crate::state;
+
+/// Layout of a task directory written by this build. task.json carries it as
+/// `schema_version`; records from before versioning count as 0.
+pub const TASK_SCHEMA_VERSION: u32 = 1;
+
+/// Edits a task's record and the files next to it
+type Step = fn(&str, &mut Value) -> Result<(), String>;
+
+/// Upgrade steps in order; the step at index `n` takes a task from version
+/// `n` to `n + 1`
+const MIGRATIONS: &[(&str, Step)] = &[
+    ("页面文件名补零，补建校验清单", v0_padded_pages_and_manifest),
+];
+
+/// Bring a task record read from task.json up to TASK_SCHEMA_VERSION. Returns
+/// the version it was at when any step ran, so the caller saves the upgraded
+/// record; a record from a newer build is refused rather than misread.
+pub fn migrate_task(task_id: &str, record: &mut Value) -> Result<Option<u32>, String> {
+    let from = record["schema_version"].as_u64().unwrap_or(0) as u32;
+    if from > TASK_SCHEMA_VERSION {
+        return Err(format!("数据版本 {} 高于当前支持的 {}", from, TASK_SCHEMA_VERSION));
+    }
+    if from == TASK_SCHEMA_VERSION {
+        return Ok(None);
+    }
+    // The record as it was, in case an upgrade has to be looked into
+    let backup = state::task_dir(task_id).join(format!("task.v{}.json", from));
+    if !backup.exists() {
+        let _ = fs::write(&backup, record.to_string());
+    }
+    for (version, (describe, step)) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        step(task_id, record).map_err(|e| format!("升级到版本 {} ({}) 失败: {}", version + 1, describe, e))?;
+        record["schema_version"] = (version as u32 + 1).into();
+    }
+    Ok(Some(from))
+}
+
+/// Page files were written without zero padding (`7.ocr.txt`), and tasks
+/// from before integrity checks have no manifest.json
+fn v0_padded_pages_and_manifest(task_id: &str, record: &mut Value) -> Result<(), String> {
+    let dir = state::pages_dir(task_id);
+    if let Ok(entries) = fs::read_dir(&dir) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some((number, rest)) = name.split_once('.') else { continue };
+            let Ok(page_num) = number.parse::<usize>() else { continue };
+            let padded = format!("{:0w$}.{}", page_num, rest, w = state::PAGE_NUM_WIDTH);
+            // A padded file that already exists wins
+            if padded != name && !dir.join(&padded).exists() {
+                fs::rename(entry.path(), dir.join(&padded))
+                    .map_err(|e| format!("页面文件 {} 重命名失败: {}", name, e))?;
+            }
+        }
+    }
+
+    if state::task_dir(task_id).join("manifest.json").exists() {
+        return Ok(());
+    }
+    let langs: Vec<TargetLang> = record["target_langs"].as_array().into_iter().flatten()
+        .filter_map(|code| code.as_str().and_then(TargetLang::parse))
+        .collect();
+    let primary = langs.first().copied().unwrap_or_default();
+    let hash = |path: &std::path::Path| fs::read(path).ok().map(|data| integrity::sha256_hex(&data));
+
+    let mut manifest = Manifest::default();
+    if let Some(input) = hash(&state::input_path(task_id)) {
+        manifest.record(Artifact::Input, input);
+    }
+    if let Ok(entries) = fs::read_dir(&dir) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some((number, rest)) = name.split_once('.') else { continue };
+            let Ok(page_num) = number.parse::<usize>() else { continue };
+            let artifact = match rest {
+                "ocr.txt" => Artifact::Ocr(page_num),
+                "translated.txt" => Artifact::Translation(page_num, primary),
+                _ => match rest.strip_prefix("translated.").and_then(|r| r.strip_suffix(".txt")).and_then(TargetLang::parse) {
+                    Some(lang) => Artifact::Translation(page_num, lang),
+                    None => continue,
+                },
+            };
+            if let Some(checksum) = hash(&entry.path()) {
+                manifest.record(artifact, checksum);
+            }
+        }
+    }
+    for &lang in &langs {
+        let suffix = (lang != primary).then(|| lang.code());
+        if let Some(output) = hash(&state::output_path(task_id, suffix)) {
+            manifest.record(Artifact::Output(lang), output);
+        }
+    }
+    state::save_manifest(task_id, &manifest);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_0_task_directories_are_upgraded() {
+        let task_id = "test-migrate-v0";
+        let dir = state::task_dir(task_id);
+        let _ = std::fs::remove_dir_all(&dir);
+        let pages = state::pages_dir(task_id);
+        std::fs::create_dir_all(&pages).unwrap();
+        std::fs::write(state::input_path(task_id), b"%PDF-1.4 input").unwrap();
+        std::fs::write(pages.join("7.ocr.txt"), "ocr seven").unwrap();
+        std::fs::write(pages.join("7.translated.txt"), "译文七").unwrap();
+        std::fs::write(pages.join("7.translated.en.txt"), "seven").unwrap();
+        let original = serde_json::json!({ "target_langs": ["zh-CN", "en"], "progress": {} });
+        let mut record = original.clone();
+
+        assert_eq!(migrate_task(task_id, &mut record), Ok(Some(0)));
+        assert_eq!(record["schema_version"], TASK_SCHEMA_VERSION);
+        let backup: serde_json::Value = serde_json::from_slice(&std::fs::read(dir.join("task.v0.json")).unwrap()).unwrap();
+        assert_eq!(backup, original);
+        for name in ["0007.ocr.txt", "0007.translated.txt", "0007.translated.en.txt"] {
+            assert!(pages.join(name).exists(), "{} missing", name);
+        }
+        assert!(!pages.join("7.ocr.txt").exists());
+
+        let manifest: serde_json::Value = serde_json::from_slice(&std::fs::read(dir.join("manifest.json")).unwrap()).unwrap();
+        let sha = |data: &[u8]| crate::integrity::sha256_hex(data);
+        assert_eq!(manifest["input"], sha(b"%PDF-1.4 input"));
+        assert_eq!(manifest["pages"]["7"]["ocr"], sha("ocr seven".as_bytes()));
+        assert_eq!(manifest["pages"]["7"]["translations"]["zh-CN"], sha("译文七".as_bytes()));
+        assert_eq!(manifest["pages"]["7"]["translations"]["en"], sha(b"seven"));
+
+        // Already current: nothing runs again
+        assert_eq!(migrate_task(task_id, &mut record), Ok(None));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn task_records_from_a_newer_build_are_refused() {
+        let newer = TASK_SCHEMA_VERSION + 1;
+        let mut record = serde_json::json!({ "schema_version": newer, "progress": {} });
+        let before = record.clone();
+        let error = migrate_task("test-migrate-newer", &mut record).unwrap_err();
+        assert!(error.contains(&newer.to_string()), "{}", error);
+        assert_eq!(record, before);
+        assert!(!state::task_dir("test-migrate-newer").exists());
+    }
+}
@@ -1,9 +1,17 @@
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
-use lopdf::Document;
+use lopdf::{Document, Object};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::Write as _;
 use std::process::Command;
+use std::sync::Arc;
 use tempfile::TempDir;
 use std::fs;
 
+use crate::error::PdfError;
+use crate::font::FallbackFont;
+
 #[derive(Clone)]
 pub struct PdfPage {
     pub page_num: usize,
@@ -13,13 +21,17 @@ pub struct PdfPage {
 
 /// Process PDF pages: always use OCR for reliable text extraction
 /// Text extraction from PDF is unreliable due to font encoding issues
-pub fn process_pdf_pages(data: &[u8]) -> Result<Vec<PdfPage>, String> {
-    let doc = Document::load_mem(data)
-        .map_err(|e| format!("Failed to parse PDF: {}", e))?;
-    
-    let page_count = doc.get_pages().len();
+pub fn process_pdf_pages(data: &[u8], format: ImageFormat, quality: u8) -> Result<Vec<PdfPage>, PdfError> {
+    let page_count = if is_image(data) {
+        1
+    } else {
+        Document::load_mem(data)
+            .map_err(|e| PdfError::Parse(e.to_string()))?
+            .get_pages()
+            .len()
+    };
     if page_count == 0 {
-        return Err("PDF has no pages".to_string());
+        return Err(PdfError::NoPages);
     }
     
     // Always use OCR - PDF text extraction is unreliable
@@ -33,44 +45,369 @@ pub fn process_pdf_pages(data: &[u8]) -> Result<Vec<PdfPage>, String> {
     }
     
     // Render all pages to images for OCR
-    let temp_dir = TempDir::new()
-        .map_err(|e| format!("Failed to create temp dir: {}", e))?;
+    let images = render_pages(data, None, &RENDER_LEVELS[0].limit(quality), format)?;
+    for (page_num, image_data) in images {
+        if let Some(page) = pages.get_mut(page_num - 1) {
+            page.image_base64 = Some(BASE64.encode(&image_data));
+        }
+    }
+    Ok(pages)
+}
+
+/// A JPEG or PNG upload, translated as a one-page document in place of a PDF
+pub fn is_image(data: &[u8]) -> bool {
+    data.starts_with(&[0xFF, 0xD8, 0xFF]) || data.starts_with(b"\x89PNG")
+}
+
+/// Output settings for OCR images
+pub struct RenderLevel {
+    pub scale_to: u32,
+    /// JPEG quality
+    pub quality: u8,
+}
+
+impl RenderLevel {
+    /// This level with its JPEG quality capped at `quality`
+    pub fn limit(&self, quality: u8) -> RenderLevel {
+        RenderLevel { scale_to: self.scale_to, quality: self.quality.min(quality) }
+    }
+}
+
+/// Encoding of the page images sent to the OCR model. Some vision models read
+/// lossless PNG/WebP better than compressed JPEG, and some gateways reject
+/// large JPEGs; PNG and WebP ignore the quality setting (WebP is lossless).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ImageFormat {
+    #[default]
+    Jpeg,
+    Png,
+    Webp,
+}
+
+impl ImageFormat {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "jpeg" | "jpg" => Some(ImageFormat::Jpeg),
+            "png" => Some(ImageFormat::Png),
+            "webp" => Some(ImageFormat::Webp),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "jpeg",
+            ImageFormat::Png => "png",
+            ImageFormat::Webp => "webp",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Png => "image/png",
+            ImageFormat::Webp => "image/webp",
+        }
+    }
+}
+
+/// Encode a rendered page in the given format
+pub fn encode_image(image: &image::DynamicImage, format: ImageFormat, quality: u8) -> Result<Vec<u8>, PdfError> {
+    use image::codecs::{jpeg::JpegEncoder, png::PngEncoder, webp::WebPEncoder};
+    let rgb = image.to_rgb8();
+    let mut out = Vec::new();
+    let result = match format {
+        ImageFormat::Jpeg => rgb.write_with_encoder(JpegEncoder::new_with_quality(&mut out, quality)),
+        ImageFormat::Png => rgb.write_with_encoder(PngEncoder::new(&mut out)),
+        ImageFormat::Webp => rgb.write_with_encoder(WebPEncoder::new_lossless(&mut out)),
+    };
+    result.map_err(|e| PdfError::Encode { format: format.as_str(), message: e.to_string() })?;
+    Ok(out)
+}
+
+/// Re-encode an already rendered page image (any supported format)
+pub fn convert_image(data: &[u8], format: ImageFormat, quality: u8) -> Result<Vec<u8>, PdfError> {
+    let image = image::load_from_memory(data)
+        .map_err(|e| PdfError::Decode(e.to_string()))?;
+    encode_image(&image, format, quality)
+}
+
+/// Index 0 is the normal rendition; later entries are progressively smaller
+/// downgrades used when OCR keeps timing out on a page.
+pub const RENDER_LEVELS: [RenderLevel; 3] = [
+    RenderLevel { scale_to: 800, quality: 70 },
+    RenderLevel { scale_to: 600, quality: 55 },
+    RenderLevel { scale_to: 450, quality: 40 },
+];
+
+/// Re-render a single page at the given level for OCR, returning the base64 image
+pub fn render_page_downgraded(data: &[u8], page_num: usize, level: usize, format: ImageFormat, quality: u8) -> Result<String, PdfError> {
+    render_page(data, page_num, level, format, quality).map(|image_data| BASE64.encode(&image_data))
+}
+
+/// Render a single page at the given level, returning the raw JPEG bytes
+pub fn render_page_jpeg(data: &[u8], page_num: usize, level: usize) -> Result<Vec<u8>, PdfError> {
+    render_page(data, page_num, level, ImageFormat::Jpeg, 100)
+}
+
+fn render_page(data: &[u8], page_num: usize, level: usize, format: ImageFormat, quality: u8) -> Result<Vec<u8>, PdfError> {
+    let level = RENDER_LEVELS.get(level)
+        .ok_or(PdfError::RenderLevel(level))?;
+    let images = render_pages(data, Some(page_num), &level.limit(quality), format)?;
+    images.into_iter()
+        .find(|(n, _)| *n == page_num)
+        .map(|(_, image_data)| image_data)
+        .ok_or(PdfError::MissingPage(page_num))
+}
+
+/// Longer side of an output preview image, in pixels
+const PREVIEW_SIZE: u32 = 1200;
+
+/// PNG images of the first `count` pages of a generated PDF, showing fonts and
+/// layout as they will print
+pub fn render_previews(data: &[u8], count: usize) -> Result<Vec<Vec<u8>>, PdfError> {
+    let page_count = Document::load_mem(data)
+        .map_err(|e| PdfError::Parse(e.to_string()))?
+        .get_pages()
+        .len();
+    let level = RenderLevel { scale_to: PREVIEW_SIZE, quality: 100 };
+    (1..=count.min(page_count))
+        .map(|page_num| {
+            render_pages(data, Some(page_num), &level, ImageFormat::Png)?
+                .into_iter()
+                .next()
+                .map(|(_, image)| image)
+                .ok_or(PdfError::MissingPage(page_num))
+        })
+        .collect()
+}
+
+/// Rasterize the whole document (or a single page) in-process with pdfium,
+/// falling back to pdftoppm when the pdfium library isn't available
+fn render_pages(data: &[u8], only_page: Option<usize>, level: &RenderLevel, format: ImageFormat) -> Result<Vec<(usize, Vec<u8>)>, PdfError> {
+    // An image is its own single page; it only needs scaling like a rendered one
+    if is_image(data) {
+        if only_page.is_some_and(|n| n != 1) {
+            return Err(PdfError::MissingPage(only_page.unwrap_or_default()));
+        }
+        let image = image::load_from_memory(data)
+            .map_err(|e| PdfError::Decode(e.to_string()))?;
+        let image = if image.width().max(image.height()) > level.scale_to {
+            image.resize(level.scale_to, level.scale_to, image::imageops::FilterType::Lanczos3)
+        } else {
+            image
+        };
+        return encode_image(&image, format, level.quality).map(|image_data| vec![(1, image_data)]);
+    }
+    if crate::render::is_available() {
+        return crate::render::render_pages(data, only_page, level.scale_to, format, level.quality);
+    }
+    let images = render_pages_pdftoppm(data, only_page, level, format == ImageFormat::Jpeg)?;
+    if format != ImageFormat::Webp {
+        return Ok(images);
+    }
+    // pdftoppm has no WebP output; convert its lossless PNGs
+    images.into_iter()
+        .map(|(page_num, png)| convert_image(&png, format, level.quality).map(|webp| (page_num, webp)))
+        .collect()
+}
+
+/// Rough upper bound of one page image on disk: a square page at the level's
+/// size, 3 bytes a pixel, compressed about 8:1 as JPEG or 2:1 as PNG
+fn rendered_page_estimate(level: &RenderLevel, jpeg: bool) -> u64 {
+    let raw = u64::from(level.scale_to).pow(2) * 3;
+    raw / if jpeg { 8 } else { 2 }
+}
+
+/// Run pdftoppm over the whole document (or a single page) and read the
+/// JPEGs (or PNGs) back
+fn render_pages_pdftoppm(data: &[u8], only_page: Option<usize>, level: &RenderLevel, jpeg: bool) -> Result<Vec<(usize, Vec<u8>)>, PdfError> {
+    let page_count = Document::load_mem(data)
+        .map_err(|e| PdfError::Parse(e.to_string()))?
+        .get_pages()
+        .len();
+    
+    let rendered = if only_page.is_some() { 1 } else { page_count };
+    let work_dir = crate::render::temp_dir();
+    let needed = data.len() as u64 + rendered as u64 * rendered_page_estimate(level, jpeg);
+    if let Some(available) = crate::render::free_space(&work_dir)
+        && available < needed
+    {
+        return Err(PdfError::NoSpace { dir: work_dir.display().to_string(), needed, available });
+    }
+    let temp_dir = TempDir::new_in(&work_dir)
+        .map_err(PdfError::io("Failed to create temp dir"))?;
     
     let pdf_path = temp_dir.path().join("input.pdf");
     fs::write(&pdf_path, data)
-        .map_err(|e| format!("Failed to write temp PDF: {}", e))?;
+        .map_err(PdfError::io("Failed to write temp PDF"))?;
     
     let output_prefix = temp_dir.path().join("page");
-    let result = Command::new("pdftoppm")
-        .args([
-            "-jpeg",
-            "-jpegopt", "quality=70",
-            "-r", "72",
-            "-scale-to", "800",
-            pdf_path.to_str().unwrap(),
-            output_prefix.to_str().unwrap(),
-        ])
-        .output();
-    
-    match result {
+    let quality = format!("quality={}", level.quality);
+    let scale = level.scale_to.to_string();
+    let mut cmd = Command::new(crate::render::pdftoppm());
+    if jpeg {
+        cmd.args(["-jpeg", "-jpegopt", &quality]);
+    } else {
+        cmd.arg("-png");
+    }
+    cmd.args(["-r", "72", "-scale-to", &scale]);
+    if let Some(page_num) = only_page {
+        let page = page_num.to_string();
+        cmd.args(["-f", &page, "-l", &page]);
+    }
+    cmd.arg(&pdf_path).arg(&output_prefix);
+    
+    match cmd.output() {
         Ok(output) if output.status.success() => {
-            for page_num in 1..=page_count {
-                let image_path = find_page_image(temp_dir.path(), page_num)?;
+            let page_nums: Vec<usize> = match only_page {
+                Some(n) => vec![n],
+                None => (1..=page_count).collect(),
+            };
+            let mut images = Vec::with_capacity(page_nums.len());
+            for page_num in page_nums {
+                let image_path = find_page_image(temp_dir.path(), page_num, if jpeg { "jpg" } else { "png" })?;
                 let image_data = fs::read(&image_path)
-                    .map_err(|e| format!("Failed to read page {} image: {}", page_num, e))?;
-                
-                pages[page_num - 1].image_base64 = Some(BASE64.encode(&image_data));
+                    .map_err(PdfError::io(format!("Failed to read page {} image", page_num)))?;
+                images.push((page_num, image_data));
+            }
+            Ok(images)
+        }
+        _ => Err(PdfError::NoRenderer),
+    }
+}
+
+/// The Title entry of the document information dictionary, if any
+pub fn document_title(data: &[u8]) -> Option<String> {
+    let doc = Document::load_mem(data).ok()?;
+    let info = match doc.trailer.get(b"Info").ok()? {
+        Object::Reference(id) => doc.get_object(*id).ok()?,
+        other => other,
+    };
+    let Object::String(bytes, _) = info.as_dict().ok()?.get(b"Title").ok()? else {
+        return None;
+    };
+    Some(decode_text_string(bytes))
+}
+
+/// A PDF text string: UTF-16BE after a byte order mark, otherwise
+/// PDFDocEncoding, which matches Latin-1 for printable text
+pub fn decode_text_string(bytes: &[u8]) -> String {
+    match bytes.strip_prefix(&[0xFE, 0xFF]) {
+        Some(utf16) => {
+            let units: Vec<u16> = utf16.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+            String::from_utf16_lossy(&units)
+        }
+        None => bytes.iter().map(|&b| b as char).collect(),
+    }
+}
+
+/// Text of every page from the PDF's own text layer, or None when there is
+/// none or it decodes to garbage (scans, CID fonts without a usable encoding)
+pub fn extract_text_layer(data: &[u8]) -> Option<Vec<String>> {
+    let doc = Document::load_mem(data).ok()?;
+    let texts: Vec<String> = (1..=doc.get_pages().len())
+        .map(|page_num| extract_page_text(&doc, page_num))
+        .collect();
+    is_text_valid(&texts.join("\n")).then_some(texts)
+}
+
+/// Pages kept by a sample run: the middle page of `count` equal slices, so
+/// the picks spread over the document and a cover page is rarely one of them
+pub fn sample_page_numbers(page_count: usize, count: usize) -> Vec<usize> {
+    if count >= page_count {
+        return (1..=page_count).collect();
+    }
+    let mut pages: Vec<usize> = (0..count).map(|i| (2 * i + 1) * page_count / (2 * count) + 1).collect();
+    pages.dedup();
+    pages
+}
+
+/// Cut the document down to `count` evenly spaced pages. Returns the reduced
+/// PDF, the original page count and the original numbers of the kept pages.
+pub fn extract_sample(data: &[u8], count: usize) -> Result<(Vec<u8>, usize, Vec<usize>), PdfError> {
+    keep_pages(data, |page_count| Ok(sample_page_numbers(page_count, count)))
+}
+
+/// Cut the document down to the selected pages; returns the same as `extract_sample`
+pub fn extract_page_ranges(data: &[u8], ranges: &PageRanges) -> Result<(Vec<u8>, usize, Vec<usize>), PdfError> {
+    keep_pages(data, |page_count| ranges.resolve(page_count))
+}
+
+fn keep_pages(data: &[u8], select: impl FnOnce(usize) -> Result<Vec<usize>, PdfError>) -> Result<(Vec<u8>, usize, Vec<usize>), PdfError> {
+    let mut doc = Document::load_mem(data)
+        .map_err(|e| PdfError::Parse(e.to_string()))?;
+    let page_count = doc.get_pages().len();
+    if page_count == 0 {
+        return Err(PdfError::NoPages);
+    }
+    let keep = select(page_count)?;
+    let drop: Vec<u32> = (1..=page_count as u32).filter(|n| !keep.contains(&(*n as usize))).collect();
+    doc.delete_pages(&drop);
+    doc.prune_objects();
+    let mut out = Vec::new();
+    doc.save_to(&mut out)
+        .map_err(PdfError::io("Failed to write reduced PDF"))?;
+    Ok((out, page_count, keep))
+}
+
+/// A page selection as typed by the user: `3-10,15`, with `20-` running to
+/// the last page. Pages are numbered from 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PageRanges(Vec<(usize, Option<usize>)>);
+
+impl PageRanges {
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut ranges = Vec::new();
+        for part in s.split([',', '，']).map(str::trim).filter(|p| !p.is_empty()) {
+            let range = match part.split_once('-') {
+                Some((start, "")) => (start.trim().parse().ok()?, None),
+                Some((start, end)) => (start.trim().parse().ok()?, Some(end.trim().parse().ok()?)),
+                None => {
+                    let page = part.parse().ok()?;
+                    (page, Some(page))
+                }
+            };
+            if range.0 == 0 || range.1.is_some_and(|end| end < range.0) {
+                return None;
             }
-            Ok(pages)
+            ranges.push(range);
         }
-        _ => {
-            Err("pdftoppm not found. Please install poppler-utils:\n  macOS: brew install poppler\n  Ubuntu: apt install poppler-utils".to_string())
+        (!ranges.is_empty()).then_some(PageRanges(ranges))
+    }
+
+    /// The selected page numbers in document order, each once
+    pub fn resolve(&self, page_count: usize) -> Result<Vec<usize>, PdfError> {
+        let mut pages = Vec::new();
+        for &(start, end) in &self.0 {
+            let end = end.unwrap_or(page_count);
+            if start > page_count || end > page_count {
+                return Err(PdfError::PageOutOfRange(page_count));
+            }
+            pages.extend(start..=end);
+        }
+        pages.sort_unstable();
+        pages.dedup();
+        Ok(pages)
+    }
+}
+
+/// Page numbers written compactly, e.g. `3-10、15`
+pub fn format_page_list(pages: &[usize]) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < pages.len() {
+        let start = pages[i];
+        while i + 1 < pages.len() && pages[i + 1] == pages[i] + 1 {
+            i += 1;
         }
+        parts.push(if pages[i] == start { start.to_string() } else { format!("{}-{}", start, pages[i]) });
+        i += 1;
     }
+    parts.join("、")
 }
 
 /// Extract text from a single page
-#[allow(dead_code)]
 fn extract_page_text(doc: &Document, page_num: usize) -> String {
     let page_id = match doc.get_pages().get(&(page_num as u32)) {
         Some(id) => *id,
@@ -87,7 +424,6 @@ fn extract_page_text(doc: &Document, page_num: usize) -> String {
 }
 
 /// Extract readable text from PDF content stream
-#[allow(dead_code)]
 fn extract_text_from_content(content: &[u8], doc: &Document) -> String {
     let content_str = String::from_utf8_lossy(content);
     let mut text = String::new();
@@ -125,28 +461,25 @@ fn extract_text_from_content(content: &[u8], doc: &Document) -> String {
 }
 
 /// Extract text from PDF text operators
-#[allow(dead_code)]
 fn extract_text_operator(line: &str, _doc: &Document) -> Option<String> {
     let line = line.trim();
     
     // Handle (text) Tj
-    if line.ends_with(" Tj") || line.ends_with(")Tj") {
-        if let Some(start) = line.find('(') {
-            if let Some(end) = line.rfind(')') {
-                let text = &line[start + 1..end];
-                return Some(decode_pdf_string(text));
-            }
-        }
+    if (line.ends_with(" Tj") || line.ends_with(")Tj"))
+        && let Some(start) = line.find('(')
+        && let Some(end) = line.rfind(')')
+    {
+        let text = &line[start + 1..end];
+        return Some(decode_pdf_string(text));
     }
     
     // Handle <hex> Tj
-    if line.ends_with(" Tj") || line.ends_with(">Tj") {
-        if let Some(start) = line.find('<') {
-            if let Some(end) = line.rfind('>') {
-                let hex = &line[start + 1..end];
-                return decode_hex_string(hex);
-            }
-        }
+    if (line.ends_with(" Tj") || line.ends_with(">Tj"))
+        && let Some(start) = line.find('<')
+        && let Some(end) = line.rfind('>')
+    {
+        let hex = &line[start + 1..end];
+        return decode_hex_string(hex);
     }
     
     // Handle [ ... ] TJ (array of strings)
@@ -194,7 +527,6 @@ fn extract_text_operator(line: &str, _doc: &Document) -> Option<String> {
 }
 
 /// Decode PDF string escapes
-#[allow(dead_code)]
 fn decode_pdf_string(s: &str) -> String {
     let mut result = String::new();
     let mut chars = s.chars().peekable();
@@ -220,17 +552,16 @@ fn decode_pdf_string(s: &str) -> String {
 }
 
 /// Decode hex string to text
-#[allow(dead_code)]
 fn decode_hex_string(hex: &str) -> Option<String> {
     let hex = hex.replace(" ", "");
-    if hex.len() % 4 == 0 {
+    if hex.len().is_multiple_of(4) {
         // Try UTF-16BE (common for CJK)
         let mut chars = Vec::new();
         for i in (0..hex.len()).step_by(4) {
-            if let Ok(code) = u16::from_str_radix(&hex[i..i+4], 16) {
-                if let Some(c) = char::from_u32(code as u32) {
-                    chars.push(c);
-                }
+            if let Ok(code) = u16::from_str_radix(&hex[i..i+4], 16)
+                && let Some(c) = char::from_u32(code as u32)
+            {
+                chars.push(c);
             }
         }
         if !chars.is_empty() {
@@ -248,7 +579,6 @@ fn decode_hex_string(hex: &str) -> Option<String> {
 }
 
 /// Check if extracted text is valid (not empty, not garbled)
-#[allow(dead_code)]
 fn is_text_valid(text: &str) -> bool {
     let text = text.trim();
     
@@ -296,12 +626,12 @@ fn is_text_valid(text: &str) -> bool {
     ratio > 0.8 && has_structure
 }
 
-fn find_page_image(dir: &std::path::Path, page_num: usize) -> Result<std::path::PathBuf, String> {
-    // Try different naming patterns (JPEG format)
+fn find_page_image(dir: &std::path::Path, page_num: usize, extension: &str) -> Result<std::path::PathBuf, PdfError> {
+    // Try different naming patterns (pdftoppm pads the number to the page count's width)
     let patterns = [
-        format!("page-{}.jpg", page_num),
-        format!("page-{:02}.jpg", page_num),
-        format!("page-{:03}.jpg", page_num),
+        format!("page-{}.{}", page_num, extension),
+        format!("page-{:02}.{}", page_num, extension),
+        format!("page-{:03}.{}", page_num, extension),
     ];
     
     for pattern in &patterns {
@@ -311,11 +641,569 @@ fn find_page_image(dir: &std::path::Path, page_num: usize) -> Result<std::path::
         }
     }
     
-    Err(format!("Image for page {} not found", page_num))
+    Err(PdfError::MissingPage(page_num))
+}
+
+/// What the output document contains
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputMode {
+    #[default]
+    Translated,
+    /// Original (OCR) text and translation in two columns, for checking fidelity
+    Bilingual,
+    /// Each source sentence directly followed by its translation, for study editions
+    Interlinear,
+}
+
+impl OutputMode {
+    pub const ALL: [OutputMode; 3] = [OutputMode::Translated, OutputMode::Bilingual, OutputMode::Interlinear];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "translated" | "translation" => Some(OutputMode::Translated),
+            "bilingual" | "side-by-side" | "parallel" => Some(OutputMode::Bilingual),
+            "interlinear" | "sentence" => Some(OutputMode::Interlinear),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutputMode::Translated => "translated",
+            OutputMode::Bilingual => "bilingual",
+            OutputMode::Interlinear => "interlinear",
+        }
+    }
+
+    /// Whether the output shows the OCR text next to the translation
+    pub fn needs_originals(&self) -> bool {
+        *self != OutputMode::Translated
+    }
+}
+
+/// Which text gets a romanized reading line (pinyin, romaji, ...) under each line
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Romanize {
+    #[default]
+    Off,
+    Translated,
+    /// The OCR text; only shown in bilingual and interlinear output
+    Original,
+}
+
+impl Romanize {
+    pub const ALL: [Romanize; 3] = [Romanize::Off, Romanize::Translated, Romanize::Original];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "off" | "none" | "0" => Some(Romanize::Off),
+            "translated" | "on" | "1" => Some(Romanize::Translated),
+            "original" => Some(Romanize::Original),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Romanize::Off => "off",
+            Romanize::Translated => "translated",
+            Romanize::Original => "original",
+        }
+    }
+}
+
+/// Size of romanization lines relative to the body text
+const ROMANIZED_SCALE: f64 = 0.75;
+
+/// Fill colour of translations in interlinear mode (dark blue)
+const TRANSLATION_COLOR: &str = "0.1 0.3 0.6 rg";
+
+/// How body text is laid out on the page
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Layout {
+    #[default]
+    Standard,
+    /// Fixed line spacing with line numbers in the margin, for legal/technical review
+    LineNumbered,
+    /// Two logical pages side by side on A4 landscape, for printing
+    TwoUp,
+    /// Vertical right-to-left columns (tategaki) for Japanese / Classical Chinese
+    Vertical,
+}
+
+impl Layout {
+    pub const ALL: [Layout; 4] = [Layout::Standard, Layout::LineNumbered, Layout::TwoUp, Layout::Vertical];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "standard" => Some(Layout::Standard),
+            "line-numbered" | "line_numbered" | "numbered" => Some(Layout::LineNumbered),
+            "two-up" | "two_up" | "2up" | "booklet" => Some(Layout::TwoUp),
+            "vertical" | "tategaki" => Some(Layout::Vertical),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Layout::Standard => "standard",
+            Layout::LineNumbered => "line-numbered",
+            Layout::TwoUp => "two-up",
+            Layout::Vertical => "vertical",
+        }
+    }
+
+    fn geometry(&self) -> PageGeometry {
+        match self {
+            // Vertical pages are built by prepare_vertical_pages; the frame is the column area
+            Layout::Standard | Layout::Vertical => PageGeometry {
+                width: A4_WIDTH,
+                height: A4_HEIGHT,
+                line_height: 16.0,
+                frames: vec![Frame { x: 50.0, top: A4_HEIGHT - 50.0, width: A4_WIDTH - 100.0, height: A4_HEIGHT - 100.0 }],
+            },
+            // Gutter on the left for the line numbers
+            Layout::LineNumbered => PageGeometry {
+                width: A4_WIDTH,
+                height: A4_HEIGHT,
+                line_height: 20.0,
+                frames: vec![Frame { x: 80.0, top: A4_HEIGHT - 50.0, width: A4_WIDTH - 130.0, height: A4_HEIGHT - 100.0 }],
+            },
+            // A4 landscape split into two A5-sized halves
+            Layout::TwoUp => {
+                let half = A4_HEIGHT / 2.0;
+                let margin = 36.0;
+                let frame = |x: f64| Frame { x, top: A4_WIDTH - margin, width: half - margin * 2.0, height: A4_WIDTH - margin * 2.0 };
+                PageGeometry {
+                    width: A4_HEIGHT,
+                    height: A4_WIDTH,
+                    line_height: 14.0,
+                    frames: vec![frame(margin), frame(half + margin)],
+                }
+            }
+        }
+    }
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    // Writing into a Vec cannot fail
+    encoder.write_all(data).and_then(|_| encoder.finish()).unwrap_or_default()
+}
+
+/// Write a FlateDecode stream object; `extra` holds further dictionary entries
+fn write_stream(output: &mut Vec<u8>, obj_offsets: &mut Vec<usize>, obj_num: usize, extra: &str, compressed: &[u8]) {
+    let extra = if extra.is_empty() { String::new() } else { format!(" {}", extra) };
+    obj_offsets.push(output.len());
+    output.extend_from_slice(format!(
+        "{} 0 obj\n<< /Length {} /Filter /FlateDecode{} >>\nstream\n",
+        obj_num, compressed.len(), extra
+    ).as_bytes());
+    output.extend_from_slice(compressed);
+    output.extend_from_slice(b"\nendstream\nendobj\n");
+}
+
+/// One source line after wrapping
+struct Paragraph {
+    heading: bool,
+    lines: Vec<Line>,
+}
+
+/// Fill frames of `max_lines` with paragraphs, avoiding a lone first line at
+/// the bottom of a frame (orphan) or a lone last line at the top of the next
+/// (widow), and keeping headings with the start of the following paragraph.
+fn paginate(paragraphs: &[Paragraph], max_lines: usize) -> Vec<Vec<Line>> {
+    let mut frames: Vec<Vec<Line>> = Vec::new();
+    let mut current: Vec<Line> = Vec::new();
+    
+    for (i, paragraph) in paragraphs.iter().enumerate() {
+        let blank = paragraph.lines.iter().all(|l| l.text.is_empty());
+        if blank {
+            // Spacing is dropped at the top of a frame
+            if !current.is_empty() && current.len() < max_lines {
+                current.push(Line { text: String::new(), elem: None, style: LineStyle::Normal });
+            }
+            continue;
+        }
+        
+        if paragraph.heading && !current.is_empty() {
+            let next_lines = paragraphs[i + 1..].iter()
+                .find(|p| p.lines.iter().any(|l| !l.text.is_empty()))
+                .map_or(0, |p| p.lines.len().min(2));
+            let needed = (paragraph.lines.len() + next_lines).min(max_lines);
+            if current.len() + needed > max_lines {
+                frames.push(std::mem::take(&mut current));
+            }
+        }
+        
+        let mut rest: &[Line] = &paragraph.lines;
+        while !rest.is_empty() {
+            let room = max_lines.saturating_sub(current.len());
+            if rest.len() <= room {
+                current.extend_from_slice(rest);
+                break;
+            }
+            // Leave at least two lines for the next frame, and put at least two
+            // here unless the frame would otherwise stay empty
+            let mut take = room.min(rest.len().saturating_sub(2));
+            if take < 2 && !current.is_empty() {
+                take = 0;
+            }
+            if take == 0 && current.is_empty() {
+                take = room.max(1);
+            }
+            current.extend_from_slice(&rest[..take]);
+            rest = &rest[take..];
+            frames.push(std::mem::take(&mut current));
+        }
+    }
+    
+    while current.last().is_some_and(|l| l.text.is_empty()) {
+        current.pop();
+    }
+    if !current.is_empty() || frames.is_empty() {
+        frames.push(current);
+    }
+    frames
+}
+
+/// "- item", "* item", "• item" or "1. item" / "1) item"
+pub fn is_list_item(line: &str) -> bool {
+    if line.starts_with("- ") || line.starts_with("* ") || line.starts_with("• ") {
+        return true;
+    }
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    digits > 0 && (line[digits..].starts_with(". ") || line[digits..].starts_with(") "))
+}
+
+/// Split a full line that ends inside a Latin word. Short word starts (< 3
+/// letters) move to the next line whole; otherwise the last letter moves
+/// down to make room for the hyphen. Returns (line, carried text).
+fn hyphenate_break(current: &str) -> (String, String) {
+    let word_start = current
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_ascii_alphabetic())
+        .last()
+        .map_or(current.len(), |(i, _)| i);
+    let word_len = current.len() - word_start;
+    
+    if word_len < 3 && word_start > 0 {
+        let line = current[..word_start].trim_end().to_string();
+        return (line, current[word_start..].to_string());
+    }
+    if word_len < 3 {
+        return (current.to_string(), String::new());
+    }
+    let split = current.len() - 1;
+    (format!("{}-", &current[..split]), current[split..].to_string())
+}
+
+/// A non-embedded Adobe CJK font that viewers supply themselves
+struct BuiltinFont {
+    base_font: &'static str,
+    /// Unicode CMap name without the -H/-V suffix
+    cmap: &'static str,
+    ordering: &'static str,
+    supplement: u8,
+    hangul: bool,
+}
+
+/// The CJK character collection matching the output language: Japanese and
+/// Korean need their own glyph sets (kana forms, Hangul), Traditional Chinese
+/// its own character shapes; everything else uses the Simplified Chinese font.
+fn builtin_font(lang: &str) -> BuiltinFont {
+    let lang = lang.to_ascii_lowercase();
+    if lang.starts_with("ja") {
+        BuiltinFont { base_font: "KozMinPr6N-Regular", cmap: "UniJIS-UTF16", ordering: "Japan1", supplement: 6, hangul: false }
+    } else if lang.starts_with("ko") {
+        BuiltinFont { base_font: "HYSMyeongJo-Medium", cmap: "UniKS-UTF16", ordering: "Korea1", supplement: 2, hangul: true }
+    } else if lang == "zh-tw" || lang == "zh-hk" || lang == "zh-hant" {
+        BuiltinFont { base_font: "MSung-Light", cmap: "UniCNS-UTF16", ordering: "CNS1", supplement: 4, hangul: false }
+    } else {
+        BuiltinFont { base_font: "STSong-Light", cmap: "UniGB-UTF16", ordering: "GB1", supplement: 5, hangul: false }
+    }
+}
+
+/// Scripts the built-in CJK font renders well: ASCII, CJK ideographs, kana,
+/// bopomofo, CJK/fullwidth punctuation and common symbols (plus Hangul for the
+/// Korean font). Everything else (Latin extended, Greek, Cyrillic, Arabic,
+/// emoji…) prefers a fallback font.
+fn is_builtin_script(c: char, hangul: bool) -> bool {
+    if hangul && matches!(c as u32, 0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF) {
+        return true;
+    }
+    matches!(c as u32,
+        0x00..=0x7F
+        | 0x2000..=0x206F     // General punctuation
+        | 0x2190..=0x21FF     // Arrows
+        | 0x2460..=0x24FF     // Enclosed alphanumerics
+        | 0x2500..=0x25FF     // Box drawing, geometric shapes
+        | 0x2E80..=0x2FDF     // CJK radicals
+        | 0x3000..=0x312F     // CJK punctuation, kana, bopomofo
+        | 0x31C0..=0x33FF     // Strokes, katakana ext., enclosed CJK, compatibility
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xF900..=0xFAFF
+        | 0xFE10..=0xFE1F     // Vertical forms
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFFEF     // Fullwidth forms
+        | 0x20000..=0x2FA1F)
+}
+
+/// Six uppercase letters identifying a font subset by its glyph set
+fn subset_tag(glyphs: &BTreeMap<u16, char>) -> String {
+    // FNV-1a, so the tag (and the output) is stable across builds
+    let hash = glyphs.keys().fold(0x811C_9DC5u32, |h, gid| (h ^ *gid as u32).wrapping_mul(0x0100_0193));
+    (0..6).map(|i| (b'A' + ((hash >> (i * 5)) % 26) as u8) as char).collect()
+}
+
+/// Map horizontal CJK punctuation to its vertical presentation form (U+FE10–FE4F)
+fn to_vertical_form(c: char) -> char {
+    match c {
+        '，' => '︐',
+        '、' => '︑',
+        '。' => '︒',
+        '：' => '︓',
+        '；' => '︔',
+        '！' => '︕',
+        '？' => '︖',
+        '…' => '︙',
+        '—' | 'ー' => '︱',
+        '（' | '(' => '︵',
+        '）' | ')' => '︶',
+        '｛' => '︷',
+        '｝' => '︸',
+        '〔' => '︹',
+        '〕' => '︺',
+        '【' => '︻',
+        '】' => '︼',
+        '《' => '︽',
+        '》' => '︾',
+        '〈' => '︿',
+        '〉' => '﹀',
+        '「' => '﹁',
+        '」' => '﹂',
+        '『' => '﹃',
+        '』' => '﹄',
+        _ => c,
+    }
+}
+
+const A4_WIDTH: f64 = 595.0;
+const A4_HEIGHT: f64 = 842.0;
+
+/// Physical page size plus the text frames laid out on it, in reading order
+struct PageGeometry {
+    width: f64,
+    height: f64,
+    line_height: f64,
+    frames: Vec<Frame>,
+}
+
+/// A rectangular text area; `top` is the baseline of its first line
+#[derive(Clone, Copy)]
+struct Frame {
+    x: f64,
+    top: f64,
+    width: f64,
+    height: f64,
+}
+
+/// Layout options for the generated PDF
+#[derive(Clone, Default)]
+pub struct OutputOptions {
+    pub layout: Layout,
+    /// Rendered cover page text, placed alone on the first page
+    pub cover_page: Option<String>,
+    /// Original images of pages that could not be translated
+    pub appendix: Vec<AppendixPage>,
+    /// Embedded font for the text itself (subset to the glyphs used); None
+    /// references the viewer's built-in CJK font without embedding anything
+    pub body_font: Option<Arc<FallbackFont>>,
+    /// Embedded fonts tried in order for characters outside the CJK font's scripts
+    pub fallback_fonts: Vec<Arc<FallbackFont>>,
+    /// Break long Latin words with a hyphen instead of at an arbitrary letter
+    pub hyphenate: bool,
+    /// BCP 47 language of the text, set as the document language for screen readers
+    pub lang: String,
+    /// Compressed streams kept from the previous generation of the same document
+    pub cache: Option<Arc<StreamCache>>,
+    /// PDF date string (D:YYYYMMDDHHmmSSZ) for /CreationDate; None keeps output byte-identical across runs
+    pub creation_date: Option<String>,
+    /// Document title for the PDF's metadata
+    pub title: Option<String>,
+    pub mode: OutputMode,
+    /// OCR text of each source page, shown next to the translation in bilingual mode
+    pub originals: Vec<String>,
+    pub romanize: Romanize,
+}
+
+/// Compressed stream data keyed by a hash of the uncompressed bytes.
+///
+/// After a page edit only the streams whose content changed (the edited page
+/// and whatever reflowed after it) are compressed again; unchanged pages, the
+/// ToUnicode maps and embedded font files are reused as-is. Entries not used
+/// by the latest generation are dropped so the cache tracks one document.
+#[derive(Default)]
+pub struct StreamCache {
+    streams: parking_lot::Mutex<HashMap<u64, Arc<Vec<u8>>>>,
+}
+
+impl StreamCache {
+    fn get_or_compress(&self, data: &[u8]) -> (u64, Arc<Vec<u8>>) {
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+        let key = hasher.finish();
+        if let Some(compressed) = self.streams.lock().get(&key) {
+            return (key, compressed.clone());
+        }
+        let compressed = Arc::new(deflate(data));
+        self.streams.lock().insert(key, compressed.clone());
+        (key, compressed)
+    }
+    
+    fn retain(&self, used: &HashSet<u64>) {
+        self.streams.lock().retain(|key, _| used.contains(key));
+    }
 }
 
-pub fn generate_pdf(pages: &[String]) -> Result<Vec<u8>, String> {
+#[derive(Clone)]
+pub struct AppendixPage {
+    pub page_num: usize,
+    pub image: PdfImage,
+}
+
+/// A JPEG embedded as-is (DCTDecode) as an image XObject
+#[derive(Clone)]
+pub struct PdfImage {
+    /// Shared so that pages showing the same image don't copy it
+    data: Arc<Vec<u8>>,
+    width: u32,
+    height: u32,
+    components: u8,
+}
+
+impl PdfImage {
+    pub fn from_jpeg(data: Vec<u8>) -> Option<Self> {
+        let (width, height, components) = jpeg_dimensions(&data)?;
+        Some(Self { data: Arc::new(data), width, height, components })
+    }
+}
+
+/// Read width, height and component count from the JPEG SOF marker
+fn jpeg_dimensions(data: &[u8]) -> Option<(u32, u32, u8)> {
+    if data.len() < 4 || data[0] != 0xFF || data[1] != 0xD8 {
+        return None;
+    }
+    let mut i = 2;
+    while i + 9 < data.len() {
+        if data[i] != 0xFF {
+            i += 1;
+            continue;
+        }
+        let marker = data[i + 1];
+        let len = u16::from_be_bytes([data[i + 2], data[i + 3]]) as usize;
+        let is_sof = (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC);
+        if is_sof {
+            let height = u16::from_be_bytes([data[i + 5], data[i + 6]]) as u32;
+            let width = u16::from_be_bytes([data[i + 7], data[i + 8]]) as u32;
+            let components = data[i + 9];
+            return Some((width, height, components));
+        }
+        i += 2 + len;
+    }
+    None
+}
+
+/// One output page: its content stream, the images it draws and its size
+struct PageStream {
+    content: String,
+    images: Vec<PdfImage>,
+    media_box: (f64, f64),
+    /// Structure element of each marked-content sequence, indexed by MCID
+    marks: Vec<usize>,
+}
+
+impl PageStream {
+    fn new(media_box: (f64, f64)) -> Self {
+        Self { content: String::new(), images: Vec::new(), media_box, marks: Vec::new() }
+    }
+}
+
+/// A wrapped output line and the structure element it belongs to
+#[derive(Clone)]
+struct Line {
+    text: String,
+    elem: Option<usize>,
+    style: LineStyle,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum LineStyle {
+    Normal,
+    /// Translation under its source sentence (interlinear mode), set in colour
+    Translation,
+    /// Reading aid under the previous line, set smaller and kept out of the logical text
+    Romanized,
+}
+
+/// Logical structure element of the tagged PDF
+struct StructElem {
+    role: &'static str,
+    parent: Option<usize>,
+    /// Alternate description (figures)
+    alt: Option<String>,
+    /// Marked-content sequences as (page index, MCID), filled in at render time
+    kids: Vec<(usize, usize)>,
+}
+
+/// Values substituted into the cover page template
+pub struct CoverInfo {
+    pub title: String,
+    pub filename: String,
+    pub source_lang: String,
+    pub target_lang: String,
+    pub date: String,
+    pub disclaimer: String,
+}
+
+pub const DEFAULT_COVER_TEMPLATE: &str = "\n\n\n\n\n\n\n\n\n\n{title}\n\n\n原文件: {filename}\n语言: {source_lang} → {target_lang}\n日期: {date}\n\n\n\n\n\n{disclaimer}";
+
+/// Fill {title}, {filename}, {source_lang}, {target_lang}, {date} and {disclaimer}
+pub fn render_cover(template: &str, info: &CoverInfo) -> String {
+    template
+        .replace("{title}", &info.title)
+        .replace("{filename}", &info.filename)
+        .replace("{source_lang}", &info.source_lang)
+        .replace("{target_lang}", &info.target_lang)
+        .replace("{date}", &info.date)
+        .replace("{disclaimer}", &info.disclaimer)
+}
+
+/// Output is deterministic: the same pages and options always produce the same
+/// bytes (objects are numbered in page order, no timestamps or random IDs unless
+/// `creation_date` is set).
+pub fn generate_pdf(pages: &[String], options: &OutputOptions) -> Result<Vec<u8>, PdfError> {
+    // The body font comes first so it wins wherever it has the glyph
+    let fonts: Vec<Arc<FallbackFont>> = options.body_font.iter().chain(&options.fallback_fonts).cloned().collect();
     let mut pdf = SimplePdf::new();
+    pdf.cover = options.cover_page.clone();
+    pdf.appendix = &options.appendix;
+    pdf.layout = options.layout;
+    pdf.fonts = &fonts;
+    pdf.body_font = options.body_font.is_some();
+    pdf.hyphenate = options.hyphenate;
+    pdf.lang = options.lang.clone();
+    pdf.cache = options.cache.as_deref();
+    pdf.creation_date = options.creation_date.clone();
+    pdf.title = options.title.clone();
+    pdf.romanize = options.romanize;
+    if options.mode.needs_originals() {
+        pdf.originals = Some(&options.originals);
+    }
+    pdf.mode = options.mode;
+    pdf.used_glyphs = RefCell::new(vec![BTreeMap::new(); fonts.len()]);
     
     for page_content in pages {
         pdf.add_content(page_content);
@@ -324,13 +1212,56 @@ pub fn generate_pdf(pages: &[String]) -> Result<Vec<u8>, String> {
     pdf.render()
 }
 
-struct SimplePdf {
+struct SimplePdf<'a> {
     content: String,
+    /// Translated text per source page (`content` is these joined)
+    page_texts: Vec<String>,
+    /// Set in bilingual and interlinear modes: OCR text per source page
+    originals: Option<&'a [String]>,
+    mode: OutputMode,
+    cover: Option<String>,
+    appendix: &'a [AppendixPage],
+    layout: Layout,
+    fonts: &'a [Arc<FallbackFont>],
+    /// `fonts[0]` is an embedded body font used before the built-in CJK font
+    body_font: bool,
+    /// Glyphs shown per fallback font (glyph id → character), for widths and ToUnicode
+    used_glyphs: RefCell<Vec<BTreeMap<u16, char>>>,
+    hyphenate: bool,
+    /// Logical structure collected while the pages are laid out
+    structure: RefCell<Vec<StructElem>>,
+    /// Value of the catalog's /Lang entry
+    lang: String,
+    cache: Option<&'a StreamCache>,
+    /// Cache keys used by this generation
+    cache_used: RefCell<HashSet<u64>>,
+    creation_date: Option<String>,
+    title: Option<String>,
+    romanize: Romanize,
 }
 
-impl SimplePdf {
+impl SimplePdf<'_> {
     fn new() -> Self {
-        Self { content: String::new() }
+        Self {
+            content: String::new(),
+            page_texts: Vec::new(),
+            originals: None,
+            mode: OutputMode::Translated,
+            cover: None,
+            appendix: &[],
+            layout: Layout::Standard,
+            fonts: &[],
+            body_font: false,
+            used_glyphs: RefCell::new(Vec::new()),
+            hyphenate: false,
+            structure: RefCell::new(Vec::new()),
+            lang: String::new(),
+            cache: None,
+            cache_used: RefCell::new(HashSet::new()),
+            creation_date: None,
+            title: None,
+            romanize: Romanize::Off,
+        }
     }
     
     fn add_content(&mut self, text: &str) {
@@ -338,22 +1269,78 @@ impl SimplePdf {
             self.content.push_str("\n\n");
         }
         self.content.push_str(text);
+        self.page_texts.push(text.to_string());
     }
     
-    fn render(&self) -> Result<Vec<u8>, String> {
+    fn render(&self) -> Result<Vec<u8>, PdfError> {
         let mut output: Vec<u8> = Vec::new();
         output.extend_from_slice(b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n");
         
         let mut obj_offsets: Vec<usize> = Vec::new();
-        let page_contents = self.prepare_pages();
-        let num_pages = page_contents.len();
+        let pages = self.prepare_pages();
+        let num_pages = pages.len();
+        
+        // Object layout: 1 catalog, 2 page tree, 3 font (4 vertical font), the fallback
+        // fonts that were actually used, the structure tree (root, Document, one object
+        // per element), then per page: page object, content stream, one object per
+        // embedded image
+        let vertical = self.layout == Layout::Vertical;
+        let mut next_obj = if vertical { 5 } else { 4 };
+        let mut font_dict = if vertical { "/F1 3 0 R /F2 4 0 R".to_string() } else { "/F1 3 0 R".to_string() };
+        
+        let used_glyphs = self.used_glyphs.borrow();
+        let mut fallback_objs: Vec<(usize, usize)> = Vec::new();
+        for (i, glyphs) in used_glyphs.iter().enumerate() {
+            if glyphs.is_empty() {
+                continue;
+            }
+            // Type0 (H), [Type0 (V)], CIDFont, descriptor, font file, ToUnicode
+            let count = if vertical { 6 } else { 5 };
+            font_dict.push_str(&format!(" /FB{} {} 0 R", i, next_obj));
+            if vertical {
+                font_dict.push_str(&format!(" /FV{} {} 0 R", i, next_obj + 1));
+            }
+            fallback_objs.push((i, next_obj));
+            next_obj += count;
+        }
+        
+        let mut structure = self.structure.borrow_mut();
+        let struct_root_obj = next_obj;
+        next_obj += 2 + structure.len();
+        for (page_index, page) in pages.iter().enumerate() {
+            for (mcid, elem) in page.marks.iter().enumerate() {
+                structure[*elem].kids.push((page_index, mcid));
+            }
+        }
+        
+        // Identical images share one XObject, written where first used
+        let mut image_objs: HashMap<&[u8], usize> = HashMap::new();
+        let mut layout: Vec<(usize, usize, Vec<usize>)> = Vec::with_capacity(num_pages);
+        for page in &pages {
+            let page_obj_num = next_obj;
+            let content_obj_num = next_obj + 1;
+            next_obj += 2;
+            let image_obj_nums: Vec<usize> = page.images.iter().map(|image| {
+                *image_objs.entry(image.data.as_slice()).or_insert_with(|| {
+                    next_obj += 1;
+                    next_obj - 1
+                })
+            }).collect();
+            layout.push((page_obj_num, content_obj_num, image_obj_nums));
+        }
         
         obj_offsets.push(output.len());
-        output.extend_from_slice(b"1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n");
+        let lang = if self.lang.is_empty() { String::new() } else { format!(" /Lang ({})", self.lang) };
+        let catalog = format!(
+            "1 0 obj\n<< /Type /Catalog /Pages 2 0 R /MarkInfo << /Marked true >> \
+             /StructTreeRoot {} 0 R{} >>\nendobj\n",
+            struct_root_obj, lang
+        );
+        output.extend_from_slice(catalog.as_bytes());
         
         obj_offsets.push(output.len());
-        let page_refs: String = (0..num_pages)
-            .map(|i| format!("{} 0 R", 4 + i * 2))
+        let page_refs: String = layout.iter()
+            .map(|(page_obj_num, _, _)| format!("{} 0 R", page_obj_num))
             .collect::<Vec<_>>()
             .join(" ");
         let pages_obj = format!(
@@ -362,36 +1349,81 @@ impl SimplePdf {
         );
         output.extend_from_slice(pages_obj.as_bytes());
         
-        // CJK Font
-        obj_offsets.push(output.len());
-        output.extend_from_slice(
-            b"3 0 obj\n<< /Type /Font /Subtype /Type0 /BaseFont /STSong-Light \
-              /Encoding /UniGB-UTF16-H \
-              /DescendantFonts [ << /Type /Font /Subtype /CIDFontType0 \
-              /BaseFont /STSong-Light /CIDSystemInfo << /Registry (Adobe) \
-              /Ordering (GB1) /Supplement 5 >> >> ] >>\nendobj\n"
-        );
+        // CJK Font, plus the same font with the vertical CMap (glyphs advance top-to-bottom)
+        let builtin = builtin_font(&self.lang);
+        let writing_modes: &[(usize, char)] = if vertical { &[(3, 'H'), (4, 'V')] } else { &[(3, 'H')] };
+        for &(obj_num, mode) in writing_modes {
+            obj_offsets.push(output.len());
+            output.extend_from_slice(format!(
+                "{} 0 obj\n<< /Type /Font /Subtype /Type0 /BaseFont /{} \
+                 /Encoding /{}-{} \
+                 /DescendantFonts [ << /Type /Font /Subtype /CIDFontType0 \
+                 /BaseFont /{} /CIDSystemInfo << /Registry (Adobe) \
+                 /Ordering ({}) /Supplement {} >> >> ] >>\nendobj\n",
+                obj_num, builtin.base_font, builtin.cmap, mode,
+                builtin.base_font, builtin.ordering, builtin.supplement
+            ).as_bytes());
+        }
+        
+        for &(i, first_obj) in &fallback_objs {
+            self.write_fallback_font(&mut output, &mut obj_offsets, &self.fonts[i], &used_glyphs[i], first_obj, vertical);
+        }
         
-        for (i, content_stream) in page_contents.iter().enumerate() {
-            let page_obj_num = 4 + i * 2;
-            let content_obj_num = 5 + i * 2;
+        self.write_structure(&mut output, &mut obj_offsets, &structure, &pages, &layout, struct_root_obj);
+        
+        for (page_index, (page, (page_obj_num, content_obj_num, image_obj_nums))) in pages.iter().zip(&layout).enumerate() {
+            let xobjects: String = image_obj_nums.iter().enumerate()
+                .map(|(i, n)| format!("/Im{} {} 0 R ", i, n))
+                .collect();
+            let xobject_dict = if xobjects.is_empty() {
+                String::new()
+            } else {
+                format!(" /XObject << {}>>", xobjects)
+            };
             
             obj_offsets.push(output.len());
             let page_obj = format!(
-                "{} 0 obj\n<< /Type /Page /Parent 2 0 R /MediaBox [0 0 595 842] \
-                 /Contents {} 0 R /Resources << /Font << /F1 3 0 R >> >> >>\nendobj\n",
-                page_obj_num, content_obj_num
+                "{} 0 obj\n<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /StructParents {} \
+                 /Contents {} 0 R /Resources << /Font << {} >>{} >> >>\nendobj\n",
+                page_obj_num, page.media_box.0, page.media_box.1, page_index, content_obj_num, font_dict, xobject_dict
             );
             output.extend_from_slice(page_obj.as_bytes());
             
-            obj_offsets.push(output.len());
-            let content_obj = format!(
-                "{} 0 obj\n<< /Length {} >>\nstream\n{}endstream\nendobj\n",
-                content_obj_num, content_stream.len(), content_stream
-            );
-            output.extend_from_slice(content_obj.as_bytes());
+            let compressed = self.compress(page.content.as_bytes());
+            write_stream(&mut output, &mut obj_offsets, *content_obj_num, "", &compressed);
+            
+            for (image, image_obj_num) in page.images.iter().zip(image_obj_nums) {
+                if *image_obj_num != obj_offsets.len() + 1 {
+                    // Already written for an earlier page
+                    continue;
+                }
+                obj_offsets.push(output.len());
+                let color_space = if image.components == 1 { "/DeviceGray" } else { "/DeviceRGB" };
+                let header = format!(
+                    "{} 0 obj\n<< /Type /XObject /Subtype /Image /Width {} /Height {} \
+                     /ColorSpace {} /BitsPerComponent 8 /Filter /DCTDecode /Length {} >>\nstream\n",
+                    image_obj_num, image.width, image.height, color_space, image.data.len()
+                );
+                output.extend_from_slice(header.as_bytes());
+                output.extend_from_slice(&image.data);
+                output.extend_from_slice(b"\nendstream\nendobj\n");
+            }
         }
         
+        // Document info last; no timestamp unless one was configured
+        let info_obj = obj_offsets.len() + 1;
+        let creation_date = self.creation_date.as_ref()
+            .map(|d| format!(" /CreationDate ({})", d))
+            .unwrap_or_default();
+        let title = self.title.as_ref()
+            .map(|t| format!(" /Title <{}>", self.to_utf16be_hex(t)))
+            .unwrap_or_default();
+        obj_offsets.push(output.len());
+        output.extend_from_slice(format!(
+            "{} 0 obj\n<< /Producer (pdftrans){}{} >>\nendobj\n",
+            info_obj, title, creation_date
+        ).as_bytes());
+        
         let xref_offset = output.len();
         let xref_header = format!("xref\n0 {}\n", obj_offsets.len() + 1);
         output.extend_from_slice(xref_header.as_bytes());
@@ -402,23 +1434,165 @@ impl SimplePdf {
         }
         
         let trailer = format!(
-            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            "trailer\n<< /Size {} /Root 1 0 R /Info {} 0 R >>\nstartxref\n{}\n%%EOF\n",
             obj_offsets.len() + 1,
+            info_obj,
             xref_offset
         );
         output.extend_from_slice(trailer.as_bytes());
         
+        if let Some(cache) = self.cache {
+            cache.retain(&self.cache_used.borrow());
+        }
+        
         Ok(output)
     }
     
-    fn prepare_pages(&self) -> Vec<String> {
+    fn compress(&self, data: &[u8]) -> Arc<Vec<u8>> {
+        match self.cache {
+            Some(cache) => {
+                let (key, compressed) = cache.get_or_compress(data);
+                self.cache_used.borrow_mut().insert(key);
+                compressed
+            }
+            None => Arc::new(deflate(data)),
+        }
+    }
+    
+    /// Structure tree root with its parent tree (page → element per MCID), the
+    /// Document element, and one StructElem per collected element
+    fn write_structure(&self, output: &mut Vec<u8>, obj_offsets: &mut Vec<usize>, structure: &[StructElem], pages: &[PageStream], layout: &[(usize, usize, Vec<usize>)], root_obj: usize) {
+        let document_obj = root_obj + 1;
+        let elem_obj = |elem: usize| root_obj + 2 + elem;
+        
+        let parent_tree: String = pages.iter().enumerate()
+            .map(|(i, page)| {
+                let refs: Vec<String> = page.marks.iter().map(|e| format!("{} 0 R", elem_obj(*e))).collect();
+                format!("{} [ {} ]", i, refs.join(" "))
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        obj_offsets.push(output.len());
+        output.extend_from_slice(format!(
+            "{} 0 obj\n<< /Type /StructTreeRoot /K {} 0 R /ParentTree << /Nums [ {} ] >> \
+             /ParentTreeNextKey {} >>\nendobj\n",
+            root_obj, document_obj, parent_tree, pages.len()
+        ).as_bytes());
+        
+        let top_level: Vec<String> = (0..structure.len())
+            .filter(|i| structure[*i].parent.is_none())
+            .map(|i| format!("{} 0 R", elem_obj(i)))
+            .collect();
+        obj_offsets.push(output.len());
+        output.extend_from_slice(format!(
+            "{} 0 obj\n<< /Type /StructElem /S /Document /P {} 0 R /K [ {} ] >>\nendobj\n",
+            document_obj, root_obj, top_level.join(" ")
+        ).as_bytes());
+        
+        for (i, elem) in structure.iter().enumerate() {
+            let parent = elem.parent.map_or(document_obj, elem_obj);
+            let mut kids: Vec<String> = (0..structure.len())
+                .filter(|k| structure[*k].parent == Some(i))
+                .map(|k| format!("{} 0 R", elem_obj(k)))
+                .collect();
+            kids.extend(elem.kids.iter().map(|(page, mcid)| {
+                format!("<< /Type /MCR /Pg {} 0 R /MCID {} >>", layout[*page].0, mcid)
+            }));
+            let alt = elem.alt.as_ref()
+                .map(|alt| format!(" /Alt <{}>", self.to_utf16be_hex(alt)))
+                .unwrap_or_default();
+            obj_offsets.push(output.len());
+            output.extend_from_slice(format!(
+                "{} 0 obj\n<< /Type /StructElem /S /{} /P {} 0 R /K [ {} ]{} >>\nendobj\n",
+                elem_obj(i), elem.role, parent, kids.join(" "), alt
+            ).as_bytes());
+        }
+    }
+    
+    /// Embed one font as a Type0 font with Identity encoding (glyph ids as
+    /// codes), restricted /W widths and a ToUnicode map for text extraction.
+    /// TrueType fonts are subset to the glyphs shown.
+    fn write_fallback_font(&self, output: &mut Vec<u8>, obj_offsets: &mut Vec<usize>, font: &FallbackFont, glyphs: &BTreeMap<u16, char>, first_obj: usize, vertical: bool) {
+        let subset = font.subset(glyphs.keys().copied());
+        let font_data = subset.as_deref().unwrap_or(&font.data);
+        // Subset fonts are named with a tag derived from their glyphs (PDF 9.6.4)
+        let font_name = match subset {
+            Some(_) => format!("{}+{}", subset_tag(glyphs), font.name),
+            None => font.name.clone(),
+        };
+        let mut obj = first_obj;
+        let type0_objs: Vec<usize> = (0..if vertical { 2 } else { 1 }).map(|k| obj + k).collect();
+        obj += type0_objs.len();
+        let (cid_obj, descriptor_obj, file_obj, to_unicode_obj) = (obj, obj + 1, obj + 2, obj + 3);
+        
+        for (type0_obj, encoding) in type0_objs.iter().zip(["Identity-H", "Identity-V"]) {
+            obj_offsets.push(output.len());
+            output.extend_from_slice(format!(
+                "{} 0 obj\n<< /Type /Font /Subtype /Type0 /BaseFont /{} /Encoding /{} \
+                 /DescendantFonts [ {} 0 R ] /ToUnicode {} 0 R >>\nendobj\n",
+                type0_obj, font_name, encoding, cid_obj, to_unicode_obj
+            ).as_bytes());
+        }
+        
+        let widths: String = glyphs.keys()
+            .map(|gid| format!("{} [{}]", gid, font.advance(*gid)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let (subtype, gid_map) = if font.cff { ("CIDFontType0", "") } else { ("CIDFontType2", " /CIDToGIDMap /Identity") };
+        obj_offsets.push(output.len());
+        output.extend_from_slice(format!(
+            "{} 0 obj\n<< /Type /Font /Subtype /{} /BaseFont /{} \
+             /CIDSystemInfo << /Registry (Adobe) /Ordering (Identity) /Supplement 0 >> \
+             /FontDescriptor {} 0 R /DW 1000 /W [ {} ]{} >>\nendobj\n",
+            cid_obj, subtype, font_name, descriptor_obj, widths, gid_map
+        ).as_bytes());
+        
+        let file_key = if font.cff { "FontFile3" } else { "FontFile2" };
+        obj_offsets.push(output.len());
+        output.extend_from_slice(format!(
+            "{} 0 obj\n<< /Type /FontDescriptor /FontName /{} /Flags 32 /FontBBox [{} {} {} {}] \
+             /ItalicAngle 0 /Ascent {} /Descent {} /CapHeight {} /StemV 80 /{} {} 0 R >>\nendobj\n",
+            descriptor_obj, font_name,
+            font.scale(font.bbox[0]), font.scale(font.bbox[1]), font.scale(font.bbox[2]), font.scale(font.bbox[3]),
+            font.scale(font.ascent), font.scale(font.descent), font.scale(font.ascent),
+            file_key, file_obj
+        ).as_bytes());
+        
+        let file_dict = if font.cff {
+            "/Subtype /OpenType".to_string()
+        } else {
+            format!("/Length1 {}", font_data.len())
+        };
+        write_stream(output, obj_offsets, file_obj, &file_dict, &self.compress(font_data));
+        
+        let mut cmap = String::from(
+            "/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n\
+             /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+             /CMapName /Adobe-Identity-UCS def\n/CMapType 2 def\n\
+             1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n"
+        );
+        let entries: Vec<(&u16, &char)> = glyphs.iter().collect();
+        // bfchar blocks are limited to 100 entries each
+        for block in entries.chunks(100) {
+            cmap.push_str(&format!("{} beginbfchar\n", block.len()));
+            for (gid, c) in block {
+                let unicode = self.to_utf16be_hex(&c.to_string());
+                cmap.push_str(&format!("<{:04X}> <{}>\n", gid, &unicode[4..]));
+            }
+            cmap.push_str("endbfchar\n");
+        }
+        cmap.push_str("endcmap\nCMapName currentdict /CMap defineresource pop\nend\nend\n");
+        write_stream(output, obj_offsets, to_unicode_obj, "", &self.compress(cmap.as_bytes()));
+    }
+    
+    fn prepare_pages(&self) -> Vec<PageStream> {
         let font_size = 11.0;
         let line_height = 16.0;
         let margin_left = 50.0;
         let margin_top = 50.0;
         let margin_bottom = 50.0;
-        let page_height = 842.0;
-        let page_width = 595.0;
+        let page_height = A4_HEIGHT;
+        let page_width = A4_WIDTH;
         let usable_height = page_height - margin_top - margin_bottom;
         let usable_width = page_width - margin_left * 2.0;
         
@@ -426,44 +1600,394 @@ impl SimplePdf {
         let max_chars = (usable_width / char_width) as usize;
         let max_lines_per_page = (usable_height / line_height) as usize;
         
-        let mut pages: Vec<String> = Vec::new();
-        let mut current_page_lines: Vec<String> = Vec::new();
+        let mut pages: Vec<PageStream> = Vec::new();
+        
+        if let Some(cover) = &self.cover {
+            let cover_lines: Vec<Line> = cover.lines()
+                .flat_map(|line| {
+                    let elem = (!line.trim().is_empty()).then(|| self.new_elem("P", None, None));
+                    self.wrap_text(line, max_chars).into_iter().map(move |text| Line { text, elem, style: LineStyle::Normal })
+                })
+                .take(max_lines_per_page)
+                .collect();
+            pages.push(self.create_page_stream(&cover_lines, font_size, line_height, margin_left, page_height - margin_top));
+        }
+        
+        pages.extend(self.prepare_body_pages(font_size, char_width));
         
-        for line in self.content.lines() {
-            let wrapped = self.wrap_text(line, max_chars);
-            for wrapped_line in wrapped {
-                if current_page_lines.len() >= max_lines_per_page {
-                    pages.push(self.create_page_stream(&current_page_lines, font_size, line_height, margin_left, page_height - margin_top));
-                    current_page_lines.clear();
+        if !self.appendix.is_empty() {
+            // Index page, then one page per original image
+            let first_image_page = pages.len() + 2;
+            let mut index_lines = vec![
+                ("H1", "附录：未翻译页面原图".to_string()),
+                ("", String::new()),
+                ("P", "以下页面未能完成翻译，附上原始页面图像以免内容遗漏。".to_string()),
+                ("", String::new()),
+            ];
+            for (i, page) in self.appendix.iter().enumerate() {
+                index_lines.push(("P", format!("原文第 {} 页 …… 见第 {} 页", page.page_num, first_image_page + i)));
+            }
+            let index_lines: Vec<Line> = index_lines.iter()
+                .flat_map(|(role, line)| {
+                    let elem = (!role.is_empty()).then(|| self.new_elem(role, None, None));
+                    self.wrap_text(line, max_chars).into_iter().map(move |text| Line { text, elem, style: LineStyle::Normal })
+                })
+                .take(max_lines_per_page)
+                .collect();
+            pages.push(self.create_page_stream(&index_lines, font_size, line_height, margin_left, page_height - margin_top));
+            
+            for page in self.appendix {
+                let caption = format!("原文第 {} 页 (未翻译)", page.page_num);
+                pages.push(self.create_image_page(&caption, page.page_num, &page.image, font_size, margin_left, margin_top, page_width, page_height));
+            }
+        }
+        
+        pages
+    }
+    
+    /// Create the structure element for every source line of the body (None
+    /// for blank lines). Markdown headings become H1–H6, consecutive list
+    /// items are grouped under one L, everything else is a P.
+    fn tag_paragraphs(&self) -> Vec<Option<usize>> {
+        let mut list: Option<usize> = None;
+        self.content.lines()
+            .map(|line| {
+                let trimmed = line.trim_start();
+                if trimmed.is_empty() {
+                    return None;
+                }
+                if is_list_item(trimmed) {
+                    let parent = *list.get_or_insert_with(|| self.new_elem("L", None, None));
+                    return Some(self.new_elem("LI", Some(parent), None));
                 }
-                current_page_lines.push(wrapped_line);
+                list = None;
+                let level = trimmed.chars().take_while(|c| *c == '#').count();
+                let role = match level {
+                    0 => "P",
+                    1 => "H1",
+                    2 => "H2",
+                    3 => "H3",
+                    4 => "H4",
+                    5 => "H5",
+                    _ => "H6",
+                };
+                Some(self.new_elem(role, None, None))
+            })
+            .collect()
+    }
+    
+    /// Flow the body text through the layout's frames: each frame is filled in turn,
+    /// and a new physical page starts once every frame on the current one is full.
+    fn prepare_body_pages(&self, font_size: f64, char_width: f64) -> Vec<PageStream> {
+        if let Some(originals) = self.originals
+            && self.mode == OutputMode::Bilingual
+        {
+            return self.prepare_bilingual_pages(originals, font_size, char_width);
+        }
+        let geometry = self.layout.geometry();
+        if self.layout == Layout::Vertical && self.originals.is_none() {
+            return self.prepare_vertical_pages(font_size, &geometry);
+        }
+        // All frames of a layout share the same size
+        let frame = geometry.frames[0];
+        let max_chars = (frame.width / char_width) as usize;
+        let max_lines = (frame.height / geometry.line_height) as usize;
+        
+        let paragraphs: Vec<Paragraph> = match self.originals {
+            Some(originals) => self.interlinear_paragraphs(originals, max_chars),
+            None => {
+                let elems = self.tag_paragraphs();
+                self.content.lines()
+                    .zip(elems)
+                    .map(|(line, elem)| Paragraph {
+                        heading: line.trim_start().starts_with('#'),
+                        lines: self.paragraph_lines(line, elem, max_chars, self.romanize == Romanize::Translated),
+                    })
+                    .collect()
+            }
+        };
+        let frame_lines = paginate(&paragraphs, max_lines);
+        
+        frame_lines
+            .chunks(geometry.frames.len())
+            .map(|chunk| {
+                let mut page = PageStream::new((geometry.width, geometry.height));
+                for (lines, frame) in chunk.iter().zip(&geometry.frames) {
+                    self.append_body_block(&mut page, lines, font_size, geometry.line_height, frame.x, frame.top);
+                }
+                page
+            })
+            .collect()
+    }
+    
+    /// Interlinear body: every aligned source passage followed by its
+    /// translation, kept together as one paragraph so pagination doesn't
+    /// separate them, with a blank line between passages.
+    fn interlinear_paragraphs(&self, originals: &[String], max_chars: usize) -> Vec<Paragraph> {
+        let blank = || Paragraph { heading: false, lines: vec![Line { text: String::new(), elem: None, style: LineStyle::Normal }] };
+        let mut paragraphs = Vec::new();
+        for (i, translated) in self.page_texts.iter().enumerate() {
+            let original = originals.get(i).map(String::as_str).unwrap_or_default();
+            for segment in crate::align::align(original, translated) {
+                let mut lines = Vec::new();
+                if !segment.source.is_empty() {
+                    let elem = self.new_elem("P", None, None);
+                    lines.extend(self.paragraph_lines(&segment.source, Some(elem), max_chars, self.romanize == Romanize::Original));
+                }
+                if !segment.target.is_empty() {
+                    let elem = self.new_elem("P", None, None);
+                    let start = lines.len();
+                    lines.extend(self.paragraph_lines(&segment.target, Some(elem), max_chars, self.romanize == Romanize::Translated));
+                    for line in &mut lines[start..] {
+                        if line.style == LineStyle::Normal {
+                            line.style = LineStyle::Translation;
+                        }
+                    }
+                }
+                paragraphs.push(Paragraph { heading: segment.source.starts_with('#'), lines });
+                paragraphs.push(blank());
+            }
+        }
+        paragraphs
+    }
+    
+    /// Original on the left, translation on the right, on A4 landscape. Each
+    /// source page starts a new row pair, so both sides stay aligned page by page.
+    fn prepare_bilingual_pages(&self, originals: &[String], font_size: f64, char_width: f64) -> Vec<PageStream> {
+        let (width, height) = (A4_HEIGHT, A4_WIDTH);
+        let margin = 40.0;
+        let gutter = 30.0;
+        let line_height = 16.0;
+        let column_width = (width - margin * 2.0 - gutter) / 2.0;
+        let max_chars = (column_width / char_width) as usize;
+        let max_rows = ((height - margin * 2.0) / line_height) as usize;
+        let right_x = margin + column_width + gutter;
+        let top = height - margin;
+        
+        let column = |text: &str, label: String, romanize: bool| -> Vec<Line> {
+            let mut lines = vec![Line { text: label, elem: Some(self.new_elem("H2", None, None)), style: LineStyle::Normal }];
+            for paragraph in text.lines() {
+                let elem = (!paragraph.trim().is_empty()).then(|| self.new_elem("P", None, None));
+                lines.extend(self.paragraph_lines(paragraph, elem, max_chars, romanize));
             }
+            lines
+        };
+        
+        let mut rows: Vec<(Line, Line)> = Vec::new();
+        let blank = || Line { text: String::new(), elem: None, style: LineStyle::Normal };
+        for (i, translated) in self.page_texts.iter().enumerate() {
+            let original = originals.get(i).map(String::as_str).unwrap_or_default();
+            let left = column(original, format!("【原文 第 {} 页】", i + 1), self.romanize == Romanize::Original);
+            let right = column(translated, format!("【译文 第 {} 页】", i + 1), self.romanize == Romanize::Translated);
+            let count = left.len().max(right.len());
+            let mut left = left.into_iter();
+            let mut right = right.into_iter();
+            for _ in 0..count {
+                rows.push((left.next().unwrap_or_else(blank), right.next().unwrap_or_else(blank)));
+            }
+            rows.push((blank(), blank()));
         }
         
-        if !current_page_lines.is_empty() || pages.is_empty() {
-            pages.push(self.create_page_stream(&current_page_lines, font_size, line_height, margin_left, page_height - margin_top));
+        rows.chunks(max_rows.max(1))
+            .map(|chunk| {
+                let mut page = PageStream::new((width, height));
+                let divider_x = margin + column_width + gutter / 2.0;
+                page.content.push_str(&format!(
+                    "/Artifact BMC 0.5 w {:.2} {:.2} m {:.2} {:.2} l S EMC\n",
+                    divider_x, margin, divider_x, top
+                ));
+                let left: Vec<Line> = chunk.iter().map(|(l, _)| l.clone()).collect();
+                let right: Vec<Line> = chunk.iter().map(|(_, r)| r.clone()).collect();
+                self.append_text_block(&mut page, &left, font_size, line_height, margin, top);
+                self.append_text_block(&mut page, &right, font_size, line_height, right_x, top);
+                page
+            })
+            .collect()
+    }
+    
+    /// Tategaki: each paragraph starts a new column; columns run right to left.
+    /// In vertical writing mode the text origin is the top centre of the column.
+    fn prepare_vertical_pages(&self, font_size: f64, geometry: &PageGeometry) -> Vec<PageStream> {
+        let frame = geometry.frames[0];
+        let column_pitch = font_size * 1.6;
+        let chars_per_column = ((frame.height / font_size) as usize).max(1);
+        let columns_per_page = ((frame.width / column_pitch) as usize).max(1);
+        let first_column_x = frame.x + frame.width - font_size / 2.0;
+        let column_top = frame.top + font_size;
+        
+        let elems = self.tag_paragraphs();
+        let mut columns: Vec<Line> = Vec::new();
+        for (line, elem) in self.content.lines().zip(elems) {
+            let chars: Vec<char> = line.trim_end().chars().map(to_vertical_form).collect();
+            if chars.is_empty() {
+                columns.push(Line { text: String::new(), elem: None, style: LineStyle::Normal });
+                continue;
+            }
+            for chunk in chars.chunks(chars_per_column) {
+                columns.push(Line { text: chunk.iter().collect(), elem, style: LineStyle::Normal });
+            }
+        }
+        if columns.is_empty() {
+            columns.push(Line { text: String::new(), elem: None, style: LineStyle::Normal });
         }
         
-        pages
+        columns
+            .chunks(columns_per_page)
+            .map(|page_columns| {
+                let mut page = PageStream::new((geometry.width, geometry.height));
+                page.content.push_str("BT\n");
+                page.content.push_str(&format!("/F2 {} Tf\n", font_size));
+                for (i, column) in page_columns.iter().enumerate() {
+                    if column.text.is_empty() {
+                        continue;
+                    }
+                    let x = first_column_x - i as f64 * column_pitch;
+                    page.content.push_str(&format!("1 0 0 1 {:.2} {:.2} Tm\n", x, column_top));
+                    let shown = self.show_text(&column.text, font_size, true);
+                    let marked = self.mark_content(&mut page, column.elem, &shown);
+                    page.content.push_str(&format!("{}\n", marked));
+                }
+                page.content.push_str("ET\n");
+                page
+            })
+            .collect()
+    }
+    
+    /// A page showing a caption line and one image scaled to fit the margins
+    #[allow(clippy::too_many_arguments)]
+    fn create_image_page(&self, caption: &str, page_num: usize, image: &PdfImage, font_size: f64, margin: f64, margin_top: f64, page_width: f64, page_height: f64) -> PageStream {
+        let caption_y = page_height - margin_top;
+        let box_width = page_width - margin * 2.0;
+        let box_height = caption_y - font_size * 2.0 - margin;
+        let scale = (box_width / image.width as f64).min(box_height / image.height as f64);
+        let draw_width = image.width as f64 * scale;
+        let draw_height = image.height as f64 * scale;
+        let x = margin + (box_width - draw_width) / 2.0;
+        let y = caption_y - font_size * 2.0 - draw_height;
+        
+        let caption_elem = self.new_elem("Caption", None, None);
+        let alt = format!("原文第 {} 页的页面图像（未翻译）", page_num);
+        let figure_elem = self.new_elem("Figure", None, Some(alt));
+        
+        let mut page = PageStream::new((page_width, page_height));
+        page.images.push(image.clone());
+        page.content.push_str("BT\n");
+        page.content.push_str(&format!("/F1 {} Tf\n", font_size));
+        page.content.push_str(&format!("1 0 0 1 {} {} Tm\n", margin, caption_y));
+        let shown = self.show_text(caption, font_size, false);
+        let marked = self.mark_content(&mut page, Some(caption_elem), &shown);
+        page.content.push_str(&format!("{}\n", marked));
+        page.content.push_str("ET\n");
+        let draw = format!("q {:.2} 0 0 {:.2} {:.2} {:.2} cm /Im0 Do Q", draw_width, draw_height, x, y);
+        let marked = self.mark_content(&mut page, Some(figure_elem), &draw);
+        page.content.push_str(&format!("{}\n", marked));
+        
+        page
     }
     
-    fn create_page_stream(&self, lines: &[String], font_size: f64, line_height: f64, margin_left: f64, start_y: f64) -> String {
-        let mut stream = String::new();
-        stream.push_str("BT\n");
-        stream.push_str(&format!("/F1 {} Tf\n", font_size));
-        stream.push_str(&format!("{} TL\n", line_height));
-        stream.push_str(&format!("1 0 0 1 {} {} Tm\n", margin_left, start_y));
+    fn append_body_block(&self, page: &mut PageStream, lines: &[Line], font_size: f64, line_height: f64, margin_left: f64, start_y: f64) {
+        match self.layout {
+            Layout::Standard | Layout::TwoUp | Layout::Vertical => self.append_text_block(page, lines, font_size, line_height, margin_left, start_y),
+            Layout::LineNumbered => self.append_line_numbered_block(page, lines, font_size, line_height, margin_left, start_y),
+        }
+    }
+    
+    /// Text block plus a right-aligned line number column in the left gutter, restarting at 1 on each page
+    fn append_line_numbered_block(&self, page: &mut PageStream, lines: &[Line], font_size: f64, line_height: f64, margin_left: f64, start_y: f64) {
+        self.append_text_block(page, lines, font_size, line_height, margin_left, start_y);
+        
+        let number_size = font_size * 0.8;
+        let digit_width = number_size * 0.5;
+        let number_right = margin_left - 16.0;
+        
+        // Line numbers are page furniture, not part of the logical text
+        let stream = &mut page.content;
+        stream.push_str("/Artifact BMC\nBT\n");
+        stream.push_str(&format!("/F1 {} Tf\n", number_size));
+        let mut number = 0;
+        for (i, line) in lines.iter().enumerate() {
+            // Romanization lines belong to the line above
+            if line.style == LineStyle::Romanized {
+                continue;
+            }
+            number += 1;
+            let label = number.to_string();
+            let x = number_right - label.len() as f64 * digit_width;
+            let y = start_y - i as f64 * line_height;
+            stream.push_str(&format!("1 0 0 1 {:.2} {:.2} Tm\n", x, y));
+            stream.push_str(&format!("{}\n", self.show_text(&label, number_size, false)));
+        }
+        stream.push_str("ET\nEMC\n");
+    }
+    
+    fn create_page_stream(&self, lines: &[Line], font_size: f64, line_height: f64, margin_left: f64, start_y: f64) -> PageStream {
+        let mut page = PageStream::new((A4_WIDTH, A4_HEIGHT));
+        self.append_text_block(&mut page, lines, font_size, line_height, margin_left, start_y);
+        page
+    }
+    
+    fn append_text_block(&self, page: &mut PageStream, lines: &[Line], font_size: f64, line_height: f64, margin_left: f64, start_y: f64) {
+        page.content.push_str("BT\n");
+        page.content.push_str(&format!("/F1 {} Tf\n", font_size));
+        page.content.push_str(&format!("{} TL\n", line_height));
+        page.content.push_str(&format!("1 0 0 1 {} {} Tm\n", margin_left, start_y));
         
         for line in lines {
-            if line.is_empty() {
-                stream.push_str("T*\n");
+            if line.text.is_empty() {
+                page.content.push_str("T*\n");
+            } else if line.style == LineStyle::Romanized {
+                let size = font_size * ROMANIZED_SCALE;
+                let shown = format!("/F1 {} Tf {} /F1 {} Tf", size, self.show_text(&line.text, size, false), font_size);
+                let marked = self.mark_content(page, None, &shown);
+                page.content.push_str(&format!("{} T*\n", marked));
+            } else if line.style == LineStyle::Translation {
+                let shown = format!("{} {} 0 g", TRANSLATION_COLOR, self.show_text(&line.text, font_size, false));
+                let marked = self.mark_content(page, line.elem, &shown);
+                page.content.push_str(&format!("{} T*\n", marked));
             } else {
-                stream.push_str(&format!("<{}> Tj T*\n", self.to_utf16be_hex(line)));
+                let shown = self.show_text(&line.text, font_size, false);
+                let marked = self.mark_content(page, line.elem, &shown);
+                page.content.push_str(&format!("{} T*\n", marked));
             }
         }
         
-        stream.push_str("ET\n");
-        stream
+        page.content.push_str("ET\n");
+    }
+    
+    /// Wrap content operators in a marked-content sequence for `elem`, or mark
+    /// them as an artifact when they belong to no structure element
+    fn mark_content(&self, page: &mut PageStream, elem: Option<usize>, ops: &str) -> String {
+        match elem {
+            Some(elem) => {
+                let role = self.structure.borrow()[elem].role;
+                let mcid = page.marks.len();
+                page.marks.push(elem);
+                format!("/{} << /MCID {} >> BDC {} EMC", role, mcid, ops)
+            }
+            None => format!("/Artifact BMC {} EMC", ops),
+        }
+    }
+    
+    fn new_elem(&self, role: &'static str, parent: Option<usize>, alt: Option<String>) -> usize {
+        let mut structure = self.structure.borrow_mut();
+        structure.push(StructElem { role, parent, alt, kids: Vec::new() });
+        structure.len() - 1
+    }
+    
+    /// Wrapped lines of one source paragraph, each followed by its romanization
+    /// when `romanize` is set and the line contains non-Latin script
+    fn paragraph_lines(&self, text: &str, elem: Option<usize>, max_chars: usize, romanize: bool) -> Vec<Line> {
+        let mut lines = Vec::new();
+        for wrapped in self.wrap_text(text, max_chars) {
+            let reading = if romanize { crate::lang::romanize(wrapped.trim_start_matches('#')) } else { None };
+            lines.push(Line { text: wrapped, elem, style: LineStyle::Normal });
+            if let Some(reading) = reading {
+                let reading_chars = (max_chars as f64 / ROMANIZED_SCALE) as usize;
+                lines.extend(self.wrap_text(&reading, reading_chars).into_iter()
+                    .map(|text| Line { text, elem: None, style: LineStyle::Romanized }));
+            }
+        }
+        lines
     }
     
     fn wrap_text(&self, text: &str, max_chars: usize) -> Vec<String> {
@@ -478,9 +2002,16 @@ impl SimplePdf {
         for c in text.chars() {
             let char_width = if c.is_ascii() { 1 } else { 2 };
             if count + char_width > max_chars && !current.is_empty() {
-                lines.push(current);
-                current = String::new();
-                count = 0;
+                if self.hyphenate && c.is_ascii_alphabetic() && current.ends_with(|p: char| p.is_ascii_alphabetic()) {
+                    let (line, carry) = hyphenate_break(&current);
+                    lines.push(line);
+                    count = carry.len();
+                    current = carry;
+                } else {
+                    lines.push(current);
+                    current = String::new();
+                    count = 0;
+                }
             }
             current.push(c);
             count += char_width;
@@ -497,6 +2028,65 @@ impl SimplePdf {
         lines
     }
     
+    /// Text-showing operators for one line. Runs the built-in CJK font cannot
+    /// render switch to the first fallback font that has the glyphs; the base
+    /// font is selected again at the end so following lines are unaffected.
+    fn show_text(&self, text: &str, font_size: f64, vertical: bool) -> String {
+        let runs = self.font_runs(text);
+        if runs.iter().all(|(font, _)| font.is_none()) {
+            return format!("<{}> Tj", self.to_utf16be_hex(text));
+        }
+        
+        let (base, prefix) = if vertical { ("F2", "FV") } else { ("F1", "FB") };
+        let mut ops = String::new();
+        for (font, run) in &runs {
+            match font {
+                None => ops.push_str(&format!("/{} {} Tf <{}> Tj ", base, font_size, self.to_utf16be_hex(run))),
+                Some(i) => ops.push_str(&format!("/{}{} {} Tf <{}> Tj ", prefix, i, font_size, self.glyph_hex(*i, run))),
+            }
+        }
+        ops.push_str(&format!("/{} {} Tf", base, font_size));
+        ops
+    }
+    
+    /// Split text into runs by font: `None` is the built-in CJK font, `Some(i)` a fallback font
+    fn font_runs(&self, text: &str) -> Vec<(Option<usize>, String)> {
+        let mut runs: Vec<(Option<usize>, String)> = Vec::new();
+        for c in text.chars() {
+            let current = runs.last().and_then(|(font, _)| *font);
+            // Spaces and ASCII punctuation stay in the current fallback run when it can show them
+            let font = match current {
+                Some(i) if c.is_ascii() && !c.is_ascii_alphanumeric() && self.fonts[i].glyph(c).is_some() => Some(i),
+                _ => self.font_for(c),
+            };
+            match runs.last_mut() {
+                Some((last, run)) if *last == font => run.push(c),
+                _ => runs.push((font, c.to_string())),
+            }
+        }
+        runs
+    }
+    
+    fn font_for(&self, c: char) -> Option<usize> {
+        if !self.body_font && is_builtin_script(c, builtin_font(&self.lang).hangul) {
+            return None;
+        }
+        // Characters no font covers fall back to the CJK font (blank glyph, but still extractable)
+        self.fonts.iter().position(|font| font.glyph(c).is_some())
+    }
+    
+    fn glyph_hex(&self, font_index: usize, run: &str) -> String {
+        let font = &self.fonts[font_index];
+        let mut used = self.used_glyphs.borrow_mut();
+        let mut hex = String::with_capacity(run.len() * 4);
+        for c in run.chars() {
+            let gid = font.glyph(c).unwrap_or(0);
+            used[font_index].insert(gid, c);
+            hex.push_str(&format!("{:04X}", gid));
+        }
+        hex
+    }
+    
     fn to_utf16be_hex(&self, text: &str) -> String {
         let mut hex = String::with_capacity(text.len() * 4 + 4);
         hex.push_str("FEFF");
@@ -515,3 +2105,69 @@ impl SimplePdf {
         hex
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_pdfs_are_byte_identical_across_runs() {
+        let pages = ["# 第一章\n\n正文第一段，含 English words 和数字 2024。".to_string(), "第二页\n\n| 列 | 值 |\n|---|---|\n| a | 1 |".to_string()];
+        let generate = |options: &OutputOptions| generate_pdf(&pages, options).unwrap();
+        let contains = |pdf: &[u8], needle: &str| pdf.windows(needle.len()).any(|w| w == needle.as_bytes());
+
+        let options = OutputOptions { title: Some("测试文档".to_string()), ..Default::default() };
+        let first = generate(&options);
+        assert!(first == generate(&options));
+        assert!(!contains(&first, "/CreationDate"));
+        // Streams reused from a previous generation come out the same
+        let cached = OutputOptions { cache: Some(Arc::default()), ..options.clone() };
+        generate(&cached);
+        assert!(first == generate(&cached));
+
+        let dated = OutputOptions { creation_date: Some("D:20240102030405Z".to_string()), ..options };
+        let stamped = generate(&dated);
+        assert!(stamped == generate(&dated));
+        assert!(contains(&stamped, "/CreationDate (D:20240102030405Z)"));
+    }
+
+    #[test]
+    fn fallback_fonts_cover_only_what_they_have_glyphs_for() {
+        let fonts = [Arc::new(FallbackFont::covering("Greek", "αβ ")), Arc::new(FallbackFont::covering("Cyrillic", "дa"))];
+        let mut pdf = SimplePdf::new();
+        pdf.fonts = &fonts;
+        pdf.used_glyphs = RefCell::new(vec![BTreeMap::new(); fonts.len()]);
+
+        let runs = pdf.font_runs("Hi αβ д☃");
+        let expected = [(None, "Hi "), (Some(0), "αβ "), (Some(1), "д"), (None, "☃")];
+        assert_eq!(runs, expected.map(|(font, run)| (font, run.to_string())));
+
+        // ASCII stays with the built-in font even when a fallback covers it;
+        // a character no font covers goes back to it, blank but extractable
+        let shown = pdf.show_text("aд☃", 11.0, false);
+        assert_eq!(shown, "/F1 11 Tf <FEFF0061> Tj /FB1 11 Tf <0001> Tj /F1 11 Tf <FEFF2603> Tj /F1 11 Tf");
+        assert_eq!(pdf.used_glyphs.borrow()[1].get(&1), Some(&'д'));
+        assert!(pdf.used_glyphs.borrow()[0].is_empty());
+        // Nothing needs a fallback: one plain run
+        assert_eq!(pdf.show_text("中文 text", 11.0, false), format!("<{}> Tj", pdf.to_utf16be_hex("中文 text")));
+    }
+
+    #[test]
+    fn hyphenation_leaves_words_it_cannot_split() {
+        let pair = |line: &str, carry: &str| (line.to_string(), carry.to_string());
+        // A long Latin word gives up its last letter for the hyphen
+        assert_eq!(hyphenate_break("a hyphenation"), pair("a hyphenatio-", "n"));
+        // Too short to split: the start moves to the next line whole
+        assert_eq!(hyphenate_break("see it"), pair("see", "it"));
+        assert_eq!(hyphenate_break("naïve"), pair("naï", "ve"));
+        // Nothing before it to leave behind: the line is kept as it is
+        assert_eq!(hyphenate_break("ab"), pair("ab", ""));
+
+        let mut pdf = SimplePdf::new();
+        pdf.hyphenate = true;
+        assert_eq!(pdf.wrap_text("see itself", 6), ["see", "itself"]);
+        // Only ASCII letters are hyphenated; other scripts break plainly
+        assert_eq!(pdf.wrap_text("Привет", 6), ["При", "вет"]);
+        assert_eq!(pdf.wrap_text("2024年12345", 4), ["2024", "年12", "345"]);
+    }
+}
@@ -0,0 +1,142 @@
+use regex::Regex;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::provider::{self, ChatRequest, Message, MessageContent};
+
+/// What happens to personal data found in the OCR text before translation
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum PiiPolicy {
+    /// Text goes to the translation API as recognized
+    #[default]
+    Off,
+    /// Sent masked, and left masked in the output
+    Mask,
+    /// Sent masked, and put back into the translation afterwards
+    Restore,
+}
+
+impl PiiPolicy {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "off" | "0" | "false" => Some(PiiPolicy::Off),
+            "mask" | "1" | "true" => Some(PiiPolicy::Mask),
+            "restore" => Some(PiiPolicy::Restore),
+            _ => None,
+        }
+    }
+}
+
+/// Start of every placeholder; the translation prompt asks for them to be kept
+pub const PLACEHOLDER_PREFIX: &str = "[[PII-";
+
+/// Shown in the output for masked data
+const MASK: &str = "████";
+
+/// Placeholders put into a page's text, with what they stand for
+#[derive(Default)]
+pub struct Redaction {
+    items: Vec<(String, String)>,
+}
+
+impl Redaction {
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Replace the placeholders in a translation: the original values with
+    /// `Restore`, a mask otherwise
+    pub fn apply(&self, text: &str, policy: PiiPolicy) -> String {
+        let mut out = text.to_string();
+        for (placeholder, original) in &self.items {
+            let value = if policy == PiiPolicy::Restore { original.as_str() } else { MASK };
+            out = out.replace(placeholder.as_str(), value);
+        }
+        out
+    }
+}
+
+fn patterns() -> &'static [Regex] {
+    static PATTERNS: OnceLock<Vec<Regex>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            // Mainland resident ID, US SSN
+            r"(?-u:\b)\d{17}[\dXx](?-u:\b)",
+            r"(?-u:\b)\d{3}-\d{2}-\d{4}(?-u:\b)",
+            // Email
+            r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}",
+            // Mainland mobile, international and separated local numbers
+            r"(?-u:\b)1[3-9]\d{9}(?-u:\b)",
+            r"\+\d{1,3}[\s-]?\(?\d{1,4}\)?(?:[\s-]?\d{2,4}){2,4}",
+            r"\(?\d{2,4}\)?[\s-]\d{3,4}[\s-]?\d{4}(?-u:\b)",
+        ]
+        .iter()
+        .map(|p| Regex::new(p).expect("valid PII pattern"))
+        .collect()
+    })
+}
+
+/// Mask emails, phone and ID numbers, and the given names, in `text`. The
+/// same value always gets the same placeholder.
+pub fn redact(text: &str, names: &[String]) -> (String, Redaction) {
+    let mut spans: Vec<(usize, usize)> = patterns().iter()
+        .flat_map(|re| re.find_iter(text).map(|m| (m.start(), m.end())))
+        .collect();
+    for name in names.iter().filter(|n| n.chars().count() >= 2) {
+        spans.extend(text.match_indices(name.as_str()).map(|(start, m)| (start, start + m.len())));
+    }
+    // Earliest first, the longer of two starting together; drop overlaps
+    spans.sort_by_key(|&(start, end)| (start, std::cmp::Reverse(end)));
+
+    let mut redaction = Redaction::default();
+    let mut out = String::with_capacity(text.len());
+    let mut pos = 0;
+    for (start, end) in spans {
+        if start < pos {
+            continue;
+        }
+        let original = &text[start..end];
+        let placeholder = match redaction.items.iter().find(|(_, o)| o == original) {
+            Some((placeholder, _)) => placeholder.clone(),
+            None => {
+                let placeholder = format!("{}{}]]", PLACEHOLDER_PREFIX, redaction.items.len() + 1);
+                redaction.items.push((placeholder.clone(), original.to_string()));
+                placeholder
+            }
+        };
+        out.push_str(&text[pos..start]);
+        out.push_str(&placeholder);
+        pos = end;
+    }
+    out.push_str(&text[pos..]);
+    (out, redaction)
+}
+
+/// Ask `model` for the person names in a page, for masking along with the
+/// pattern matches. Meant for a local model: the page goes to it unmasked.
+pub async fn detect_names(config: &Config, model: &str, text: &str) -> Result<Vec<String>, String> {
+    if text.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let prompt = format!(
+r#"找出以下文本中出现的所有人名，按原文写法输出为 JSON 字符串数组，例如 ["张三", "John Smith"]；没有人名时输出 []。只输出 JSON。
+
+文本：
+{}"#, text);
+    let request = ChatRequest {
+        model,
+        messages: vec![Message { role: "user".to_string(), content: MessageContent::Text(prompt) }],
+        max_tokens: Some(1024),
+        stream: false,
+    };
+    let reply = provider::chat(config, &request, Duration::from_secs(config.translate_timeout_secs)).await
+        .map_err(|e| format!("人名识别失败: {}", e))?;
+    // Models like to wrap JSON in a code fence
+    let json = reply.text.trim().trim_start_matches("```json").trim_start_matches("```").trim_end_matches("```").trim();
+    serde_json::from_str(json).map_err(|e| format!("人名识别结果无效: {}", e))
+}
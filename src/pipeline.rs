@@ -0,0 +1,1385 @@
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::annotate::{self, Annotation};
+use crate::destination::{Destination, PublishedFile};
+use crate::error::{AppError, OcrError, PdfError, PolicyError, TranslateError};
+use crate::export::{self, TextFormat};
+use crate::hooks::{self, HookPoint};
+use crate::integrity::{self, Artifact};
+use crate::pii::{self, PiiPolicy};
+use crate::policy::PolicyAction;
+use crate::state::{self, AppState, TaskMode};
+use crate::translate::{self, ApiError, Completion, ModelFallbackState};
+use crate::usage::Usage;
+use crate::{check, config, deadletter, filename, lang, pdf, resources, watchdog, workers};
+
+pub async fn process_pdf_parallel(state: Arc<AppState>, task_id: String, data: Vec<u8>) {
+    // Ensure we release the slot when done
+    let options = state.get_options(&task_id).unwrap_or_default();
+    let _guard = TaskGuard { state: state.clone(), background: options.background };
+    
+    // A text layer already in the target language needs no OCR or translation;
+    // an OCR-only task wants the recognized text regardless
+    let policy = match options.mode {
+        TaskMode::Translate => state.config.already_translated,
+        TaskMode::Ocr => config::AlreadyTranslated::Off,
+    };
+    let data = Arc::new(data);
+    let title = {
+        let data = data.clone();
+        workers::run(move || pdf::document_title(&data)).await
+    };
+    state.suggest_title(&task_id, title.as_deref().and_then(filename::clean_title));
+    if policy != config::AlreadyTranslated::Off
+        && let Some(texts) = {
+            let data = data.clone();
+            workers::run(move || pdf::extract_text_layer(&data)).await
+        }
+        && let Some(message) = already_in_target(&state, &task_id, &texts.join("\n"))
+    {
+        if policy == config::AlreadyTranslated::Retypeset {
+            state.record_checksum(&task_id, Artifact::Input, &data);
+            retypeset_text_layer(&state, &task_id, &texts).await;
+        } else {
+            state.set_skipped(&task_id, message);
+        }
+        return;
+    }
+    
+    // Step 1: Render PDF to images
+    let pages = match render_pages(&state, &task_id, data).await {
+        Ok(p) => p,
+        Err(e) => {
+            state.set_error(&task_id, format!("PDF 处理失败: {}", e));
+            return;
+        }
+    };
+    
+    let total_pages = pages.len();
+    if total_pages == 0 {
+        state.set_error(&task_id, "PDF 没有页面".to_string());
+        return;
+    }
+    
+    state.set_rendering(&task_id, total_pages);
+    state.set_processing(&task_id);
+    
+    // Create fallback state for this task
+    let fallback_state = Arc::new(ModelFallbackState::default());
+    
+    // Step 2: Process all pages in parallel (OCR + Translate per page)
+    let detect = policy == config::AlreadyTranslated::Detect;
+    let results = process_pages_parallel(&state, &task_id, pages, fallback_state, detect).await;
+    
+    // Check if cancelled (or stopped because there is nothing to translate)
+    if state.is_cancelled(&task_id) || state.is_skipped(&task_id) {
+        return;
+    }
+    
+    if !check_page_results(&state, &task_id, results) {
+        return;
+    }
+    
+    // Step 3: Generate PDF
+    finish_pages(&state, &task_id, total_pages).await;
+}
+
+/// With every page through, generate the output, or for a task whose OCR text
+/// is to be reviewed first stop there until it is approved
+async fn finish_pages(state: &Arc<AppState>, task_id: &str, total_pages: usize) {
+    if state.awaits_review(task_id) {
+        state.set_awaiting_review(task_id);
+        return;
+    }
+    generate_output(state, task_id, total_pages).await;
+}
+
+/// "Nothing to translate" message when `text` is already in every target
+/// language of the task; too little text is never treated as a match
+fn already_in_target(state: &AppState, task_id: &str, text: &str) -> Option<String> {
+    let langs = state.get_options(task_id).unwrap_or_default().all_langs();
+    let text = text.trim();
+    if text.chars().filter(|c| !c.is_whitespace()).count() < 20 || !langs.iter().all(|l| l.is_mostly_target(text)) {
+        return None;
+    }
+    let names: Vec<&str> = langs.iter().map(|l| l.name()).collect();
+    Some(format!("文档已是{}，无需翻译", names.join("、")))
+}
+
+/// Typeset the text layer as-is, in place of OCR and translation
+async fn retypeset_text_layer(state: &Arc<AppState>, task_id: &str, texts: &[String]) {
+    let options = state.get_options(task_id).unwrap_or_default();
+    state.set_rendering(task_id, texts.len());
+    state.set_processing(task_id);
+    state.add_log(task_id, "文档已是目标语言，直接使用文本层重新排版".to_string());
+    for (i, text) in texts.iter().enumerate() {
+        let page_num = i + 1;
+        let preview = state.text_preview(text);
+        let _ = state::save_page_ocr(task_id, page_num, text);
+        state.record_checksum(task_id, Artifact::Ocr(page_num), text.as_bytes());
+        state.finish_page_ocr(task_id, page_num, text.chars().count(), preview.clone(), Usage::default(), "");
+        for lang in options.all_langs() {
+            let _ = state::save_page_translation(task_id, page_num, options.lang_suffix(lang), text);
+            state.record_checksum(task_id, Artifact::Translation(page_num, lang), text.as_bytes());
+        }
+        state.start_page_translate(task_id, page_num);
+        state.finish_page_translate(task_id, page_num, text.chars().count(), preview, Usage::default(), "");
+    }
+    if let Some(first) = texts.first() {
+        state.suggest_title(task_id, filename::heading_title(first));
+    }
+    generate_output(state, task_id, texts.len()).await;
+}
+
+/// Returns whether generation should proceed. Page errors fail the task unless
+/// best-effort mode is on, in which case failed pages go into the appendix.
+fn check_page_results(state: &Arc<AppState>, task_id: &str, results: Vec<Result<(usize, String), AppError>>) -> bool {
+    let mut failed = 0;
+    for result in results {
+        if let Err(e) = result {
+            if !state.config.best_effort {
+                state.set_error(task_id, e.to_string());
+                return false;
+            }
+            failed += 1;
+        }
+    }
+    
+    if state.is_cancelled(task_id) {
+        return false;
+    }
+    if failed > 0 {
+        state.add_log(task_id, format!("尽力模式：{} 页未能翻译，将以原图附在文末", failed));
+    }
+    true
+}
+
+/// Assemble one output PDF per target language from the translated pages on
+/// disk (more reliable than in-memory)
+pub async fn generate_output(state: &Arc<AppState>, task_id: &str, total_pages: usize) {
+    assemble_output(state, task_id, total_pages, true).await;
+}
+
+/// `generate_output` without any model request, for when only the assembly
+/// failed: comments and form fields are filled in from stored translations
+/// only. The result is still pushed to the task's destination, which never
+/// got it.
+pub async fn retypeset_output(state: &Arc<AppState>, task_id: &str, total_pages: usize) {
+    assemble_output(state, task_id, total_pages, false).await;
+}
+
+async fn assemble_output(state: &Arc<AppState>, task_id: &str, total_pages: usize, translate: bool) {
+    state.set_generating(task_id);
+    
+    let task_options = state.get_options(task_id).unwrap_or_default();
+    let mut outputs = HashMap::new();
+    for lang in task_options.all_langs() {
+        let suffix = task_options.lang_suffix(lang);
+        let texts = state::load_all_output_pages(task_id, total_pages, &task_options, lang);
+        let generated = match hooks::document_pages(&state.config, task_id, lang, texts).await {
+            Ok(texts) => {
+                let (state, task_id) = (state.clone(), task_id.to_string());
+                workers::run(move || generate_lang_output(&state, &task_id, texts, lang, suffix)).await
+            }
+            Err(e) => Err(e),
+        };
+        match generated {
+            Ok(pdf_data) => {
+                outputs.insert(lang, pdf_data);
+            }
+            Err(e) => {
+                state.set_error(task_id, format!("生成 PDF 失败 ({}): {}", lang.code(), e));
+                return;
+            }
+        }
+    }
+    state.note_memory(task_id, outputs.values().map(|pdf| pdf.len() as u64).sum());
+    if state.config.translate_annotations && task_options.mode == TaskMode::Translate {
+        translate_annotations(state, task_id, &task_options, translate).await;
+    }
+    if state.config.preview_pages > 0 {
+        render_previews(state, task_id, &task_options, &outputs).await;
+    }
+    if let Some(destination) = &task_options.destination {
+        publish(state, task_id, destination, &outputs).await;
+    }
+    state.set_complete(task_id, outputs);
+}
+
+/// Translate the source PDF's comments and text field values into each
+/// target language and save a copy of it with them filled in. Translations
+/// are kept, so regenerating after an edit makes no new requests; without
+/// `translate`, a language with none stored is skipped. Failures are logged;
+/// the task completes without the annotated copy.
+async fn translate_annotations(state: &Arc<AppState>, task_id: &str, options: &state::TaskOptions, translate: bool) {
+    let Ok(data) = state::load_input_pdf(task_id) else { return };
+    if pdf::is_image(&data) {
+        return;
+    }
+    let data = Arc::new(data);
+    let found = {
+        let data = data.clone();
+        workers::run(move || annotate::extract(&data)).await
+    };
+    let found = match found {
+        Ok(found) if !found.is_empty() => found,
+        Ok(_) => return,
+        Err(e) => {
+            state.add_log(task_id, format!("读取批注与表单失败: {}", e));
+            return;
+        }
+    };
+    for lang in options.all_langs() {
+        let translated = match state::load_annotations(task_id, lang) {
+            Some(translated) => translated,
+            None if !translate => {
+                state.add_log(task_id, format!("批注与表单尚未翻译，本次不生成批注译文 PDF ({})", lang.code()));
+                continue;
+            }
+            None => match translate_annotation_texts(state, task_id, &found, options, lang).await {
+                Ok(translated) => {
+                    if let Err(e) = state::save_annotations(task_id, lang, &translated) {
+                        state.add_log(task_id, format!("保存批注译文失败: {}", e));
+                    }
+                    state.add_log(task_id, format!("已翻译 {} 条批注与表单内容 ({})", translated.len(), lang.code()));
+                    translated
+                }
+                Err(e) => {
+                    state.add_log(task_id, format!("批注与表单翻译失败 ({}): {}", lang.code(), e));
+                    continue;
+                }
+            },
+        };
+        let annotated = {
+            let data = data.clone();
+            workers::run(move || annotate::apply(&data, &translated)).await
+        };
+        let saved = annotated.map_err(|e| e.to_string())
+            .and_then(|pdf| state::save_annotated(task_id, options.lang_suffix(lang), &pdf).map_err(|e| e.to_string()));
+        if let Err(e) = saved {
+            state.add_log(task_id, format!("生成批注译文 PDF 失败 ({}): {}", lang.code(), e));
+        }
+    }
+}
+
+/// Each distinct text once, through the same policy and PII handling as
+/// pages; the scheduler bounds how many requests run at a time
+async fn translate_annotation_texts(
+    state: &Arc<AppState>,
+    task_id: &str,
+    found: &[Annotation],
+    options: &state::TaskOptions,
+    lang: lang::TargetLang,
+) -> Result<Vec<Annotation>, String> {
+    let options = state::TaskOptions { target_lang: lang, extra_langs: Vec::new(), ..options.clone() };
+    let texts: BTreeSet<&str> = found.iter().map(|a| a.text.as_str()).collect();
+    let mut requests = tokio::task::JoinSet::new();
+    for text in texts {
+        let (state, task_id, options, text) = (state.clone(), task_id.to_string(), options.clone(), text.to_string());
+        requests.spawn(async move {
+            let chunks = translate_plain_text(&state, &task_id, &text, &options).await.map_err(|e| e.to_string())?;
+            let mut usage = Usage::default();
+            for chunk in &chunks {
+                usage.add(&chunk.completion.usage);
+            }
+            state.add_usage(&task_id, &usage);
+            let translated: Vec<&str> = chunks.iter().map(|c| c.completion.text.trim()).collect();
+            Ok::<_, String>((text, translated.join("\n\n")))
+        });
+    }
+    let mut translations = HashMap::new();
+    while let Some(joined) = requests.join_next().await {
+        let (text, translated) = joined.map_err(|e| e.to_string())??;
+        translations.insert(text, translated);
+    }
+    Ok(found.iter()
+        .map(|a| Annotation { text: translations.get(&a.text).cloned().unwrap_or_else(|| a.text.clone()), ..a.clone() })
+        .collect())
+}
+
+/// Rasterize the first pages of each output PDF for the preview endpoint.
+/// Failures (e.g. no renderer installed) are logged and leave the task
+/// without previews; it still completes.
+async fn render_previews(state: &Arc<AppState>, task_id: &str, options: &state::TaskOptions, outputs: &HashMap<lang::TargetLang, Vec<u8>>) {
+    // A regenerated document may be shorter than the one previewed before
+    state::clear_previews(task_id);
+    let mut rendered = 0;
+    for lang in options.all_langs() {
+        let Some(pdf_data) = outputs.get(&lang).cloned() else { continue };
+        let count = state.config.preview_pages;
+        let suffix = options.lang_suffix(lang);
+        let saved = workers::run(move || pdf::render_previews(&pdf_data, count)).await
+            .map_err(|e| e.to_string())
+            .and_then(|images| {
+                for (i, image) in images.iter().enumerate() {
+                    state::save_preview(task_id, i + 1, suffix, image).map_err(|e| e.to_string())?;
+                }
+                let bytes: usize = images.iter().map(Vec::len).sum();
+                state.add_rendered(task_id, bytes as u64, 0);
+                Ok(images.len())
+            });
+        match saved {
+            // Reported for the primary language; others may run shorter
+            Ok(count) if suffix.is_none() => rendered = count,
+            Ok(_) => {}
+            Err(e) => {
+                state.add_log(task_id, format!("生成预览图失败 ({}): {}", lang.code(), e));
+                rendered = 0;
+                break;
+            }
+        }
+    }
+    state.set_preview_pages(task_id, rendered);
+}
+
+/// Push every output PDF and the Markdown export to the task's destination.
+/// Upload failures are logged; the results stay downloadable either way.
+async fn publish(state: &Arc<AppState>, task_id: &str, destination: &Destination, outputs: &HashMap<lang::TargetLang, Vec<u8>>) {
+    let Some(progress) = state.get_progress(task_id) else { return };
+    let options = state.get_options(task_id).unwrap_or_default();
+    let mut files = Vec::new();
+    for lang in options.all_langs() {
+        let Some(pdf_data) = outputs.get(&lang) else { continue };
+        let text = export::export_text(task_id, &progress, &options, lang, TextFormat::Markdown);
+        let tag = options.output_tag(lang);
+        let uploads = [
+            (state.output_name(task_id, tag, "pdf"), pdf_data.clone(), "application/pdf"),
+            (state.output_name(task_id, tag, TextFormat::Markdown.extension()), text.into_bytes(), TextFormat::Markdown.content_type()),
+        ];
+        for (name, data, content_type) in uploads {
+            match destination.put(&state.config, &name, data, content_type).await {
+                Ok(url) => files.push(PublishedFile { name, url }),
+                Err(e) => state.add_log(task_id, format!("上传 {} 失败: {}", name, e)),
+            }
+        }
+    }
+    state.set_published(task_id, files);
+}
+
+/// Render the pages to images for OCR on the worker pool, keeping each page
+/// image and recording the checksums of the input and of the images
+async fn render_pages(state: &AppState, task_id: &str, data: Arc<Vec<u8>>) -> Result<Vec<pdf::PdfPage>, PdfError> {
+    let (format, quality) = (state.config.ocr_image_format, state.config.ocr_image_quality);
+    let id = task_id.to_string();
+    let (pages, checksums, rendered, held, save_error) = workers::run(move || {
+        let pages = pdf::process_pdf_pages(&data, format, quality)?;
+        let mut checksums = vec![(Artifact::Input, integrity::sha256_hex(&data))];
+        let (mut rendered, mut held, mut save_error) = (0, data.len(), None);
+        for page in &pages {
+            if let Some(image) = &page.image_base64 {
+                held += image.len();
+                let image = BASE64.decode(image).unwrap_or_default();
+                rendered += image.len();
+                checksums.push((Artifact::Image(page.page_num), integrity::sha256_hex(&image)));
+                if let Err(e) = state::save_page_image(&id, page.page_num, format, &image) {
+                    save_error = Some(e);
+                }
+            }
+        }
+        Ok::<_, PdfError>((pages, checksums, rendered, held, save_error))
+    }).await?;
+    state.record_checksums(task_id, checksums);
+    // Only the image endpoint needs them; OCR goes on without
+    if let Some(e) = save_error {
+        state.add_log(task_id, format!("保存页面图片失败: {}", e));
+    }
+    // Every page image stays in memory, base64-encoded, until its OCR is done
+    state.add_rendered(task_id, rendered as u64, held as u64);
+    Ok(pages)
+}
+
+/// Typeset one language's PDF; CPU-bound, run on the worker pool
+fn generate_lang_output(
+    state: &Arc<AppState>,
+    task_id: &str,
+    mut texts: Vec<String>,
+    lang: lang::TargetLang,
+    suffix: Option<&str>,
+) -> Result<Vec<u8>, String> {
+    let total_pages = texts.len();
+    let mut options = output_options(state, task_id, &texts, lang);
+    // The render cache tracks a single document, the one edits apply to
+    if suffix.is_some() {
+        options.cache = None;
+    }
+    
+    // Pages without a translation (best-effort failures) get a placeholder and an appendix image
+    let task_options = state.get_options(task_id).unwrap_or_default();
+    let missing: Vec<usize> = (1..=total_pages)
+        .filter(|n| state::load_page_output(task_id, *n, &task_options, lang).is_none())
+        .collect();
+    let mut input = None;
+    for page_num in missing {
+        texts[page_num - 1] = format!("【第 {} 页未能翻译，原始页面见文末附录】", page_num);
+        append_original(state, task_id, &mut input, page_num, &mut options);
+    }
+
+    if options.mode.needs_originals() {
+        options.originals = (1..=total_pages)
+            .map(|n| state::load_page_ocr(task_id, n).unwrap_or_default())
+            .collect();
+    }
+
+    let error = match typeset(&texts, &options) {
+        Ok(pdf_data) => return Ok(pdf_data),
+        Err(e) => e,
+    };
+    // Typeset each page alone to find the ones that break the layout, then
+    // try again with their original image in the appendix instead, rather
+    // than losing every page's translation to one of them
+    let broken = broken_pages(&texts, &options, typeset);
+    if broken.is_empty() {
+        return Err(error);
+    }
+    state.add_log(task_id, format!("生成 PDF 失败 ({})，第 {} 页排版出错，改以原图附在文末后重试", error, pdf::format_page_list(&broken)));
+    for &page_num in &broken {
+        texts[page_num - 1] = format!("【第 {} 页排版失败，原始页面见文末附录】", page_num);
+        if let Some(original) = options.originals.get_mut(page_num - 1) {
+            original.clear();
+        }
+        append_original(state, task_id, &mut input, page_num, &mut options);
+    }
+    options.appendix.sort_by_key(|page| page.page_num);
+    typeset(&texts, &options)
+}
+
+/// Put a source page's image in the appendix; the source PDF is read on first use
+fn append_original(state: &AppState, task_id: &str, input: &mut Option<Vec<u8>>, page_num: usize, options: &mut pdf::OutputOptions) {
+    let input = input.get_or_insert_with(|| state::load_input_pdf(task_id).unwrap_or_default());
+    match pdf::render_page_jpeg(input, page_num, 0).map(pdf::PdfImage::from_jpeg) {
+        Ok(Some(image)) => options.appendix.push(pdf::AppendixPage { page_num, image }),
+        Ok(None) => state.add_log(task_id, format!("第 {} 页原图无法解析，未加入附录", page_num)),
+        Err(e) => state.add_log(task_id, format!("第 {} 页原图渲染失败: {}", page_num, e)),
+    }
+}
+
+/// Pages (1-based) that `typeset` fails on when each is set alone
+pub(crate) fn broken_pages(
+    texts: &[String],
+    options: &pdf::OutputOptions,
+    typeset: impl Fn(&[String], &pdf::OutputOptions) -> Result<Vec<u8>, String>,
+) -> Vec<usize> {
+    (1..=texts.len())
+        .filter(|&n| {
+            let page = pdf::OutputOptions {
+                cover_page: None,
+                appendix: Vec::new(),
+                cache: None,
+                originals: options.originals.get(n - 1).cloned().into_iter().collect(),
+                ..options.clone()
+            };
+            typeset(&texts[n - 1..n], &page).is_err()
+        })
+        .collect()
+}
+
+/// generate_pdf, with a panic in the layout code turned into an error
+fn typeset(texts: &[String], options: &pdf::OutputOptions) -> Result<Vec<u8>, String> {
+    catch_layout_panic(|| pdf::generate_pdf(texts, options))
+}
+
+/// Run layout code, turning a panic into an error. Relies on the release
+/// profile unwinding (see Cargo.toml).
+pub(crate) fn catch_layout_panic(generate: impl FnOnce() -> Result<Vec<u8>, PdfError>) -> Result<Vec<u8>, String> {
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(generate)) {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(panic) => Err(panic.downcast_ref::<&str>().map(|s| s.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "排版时发生内部错误".to_string())),
+    }
+}
+
+fn output_options(state: &Arc<AppState>, task_id: &str, texts: &[String], lang: lang::TargetLang) -> pdf::OutputOptions {
+    let config = &state.config;
+    let options = state.get_options(task_id).unwrap_or_default();
+    let progress = state.get_progress(task_id);
+    let source = progress.as_ref().map(|p| p.filename.clone()).unwrap_or_default();
+    // The task's title, else the first heading of the translation
+    let title = progress.and_then(|p| p.title)
+        .or_else(|| texts.iter().find_map(|t| filename::heading_title(t)));
+    let cover_page = config.cover_template.as_ref().map(|template| {
+        pdf::render_cover(template, &pdf::CoverInfo {
+            title: title.clone().unwrap_or_else(|| source.clone()),
+            filename: source.clone(),
+            source_lang: "auto".to_string(),
+            target_lang: lang.name().to_string(),
+            date: config.output_time().format("%Y-%m-%d").to_string(),
+            disclaimer: config.cover_disclaimer.clone().unwrap_or_default(),
+        })
+    });
+    
+    pdf::OutputOptions {
+        cover_page,
+        title,
+        cache: state.get_render_cache(task_id),
+        ..pdf_options(config, &options, lang)
+    }
+}
+
+/// Typesetting options of a document in `lang`, without a cover page
+pub fn pdf_options(config: &config::Config, options: &state::TaskOptions, lang: lang::TargetLang) -> pdf::OutputOptions {
+    pdf::OutputOptions {
+        layout: options.layout,
+        mode: options.output_mode,
+        romanize: options.romanize,
+        body_font: config.body_fonts.get(&lang).cloned(),
+        fallback_fonts: config.fallback_fonts.clone(),
+        hyphenate: config.hyphenation,
+        lang: lang.code().to_string(),
+        creation_date: config.pdf_timestamp
+            .then(|| config.output_time().format("D:%Y%m%d%H%M%SZ").to_string()),
+        ..Default::default()
+    }
+}
+
+/// Pages recognized before translation starts on a scanned document, to
+/// check its language
+const DETECT_SAMPLE_PAGES: usize = 3;
+
+/// What a task's page workers share
+struct PageContext {
+    state: Arc<AppState>,
+    task_id: String,
+    fallback: Arc<ModelFallbackState>,
+    options: state::TaskOptions,
+    /// The server config with the task's model choices
+    config: Arc<config::Config>,
+    /// Earlier version of the document, to reuse unchanged pages' translations
+    previous: Option<Arc<PreviousVersion>>,
+    /// The OCR text was reviewed: pages are translated from the saved text
+    reviewed: bool,
+}
+
+/// A page leaving the OCR stage
+struct Recognized {
+    page_num: usize,
+    result: Result<String, AppError>,
+}
+
+/// OCR and translation run as two concurrent stages: recognized pages flow
+/// over a channel to the translation stage while later pages are still being
+/// recognized, so neither stage waits for a whole batch of the other.
+async fn process_pages_parallel(
+    state: &Arc<AppState>,
+    task_id: &str,
+    pages: Vec<pdf::PdfPage>,
+    fallback_state: Arc<ModelFallbackState>,
+    detect_target: bool,
+) -> Vec<Result<(usize, String), AppError>> {
+    use std::collections::VecDeque;
+    use tokio::sync::{mpsc, oneshot};
+    use tokio::task::JoinSet;
+    
+    let best_effort = state.config.best_effort;
+    let task_options = state.get_options(task_id).unwrap_or_default();
+    let previous = task_options.previous_task.as_deref()
+        .and_then(|id| PreviousVersion::load(state, id))
+        .map(Arc::new);
+    if let Some(previous) = &previous {
+        state.add_log(task_id, format!("对照上一版本 (任务 {})，未变化的页面沿用其译文", previous.task_id));
+    }
+    let reviewed = state.get_progress(task_id).is_some_and(|p| p.ocr_reviewed);
+    // Pages stop after OCR when their text is to be reviewed first
+    let review_pending = task_options.review_ocr && !reviewed;
+    let context = Arc::new(PageContext {
+        state: state.clone(),
+        task_id: task_id.to_string(),
+        fallback: fallback_state,
+        options: task_options.clone(),
+        config: Arc::new(task_options.config(&state.config)),
+        previous,
+        reviewed,
+    });
+    
+    // Pages in document order, for translating in order when context is carried over
+    let mut order: VecDeque<usize> = pages.iter().map(|p| p.page_num).collect();
+    order.make_contiguous().sort_unstable();
+    // Scanned documents have no text layer to check up front: translation
+    // waits for the first few pages, which end the task if they're already
+    // in the target language
+    let mut sample_size = if detect_target { DETECT_SAMPLE_PAGES.min(pages.len()) } else { 0 };
+    let mut sample: Vec<(usize, String)> = Vec::new();
+    
+    let (sender, mut receiver) = mpsc::channel(state.config.page_concurrency);
+    let producer = tokio::spawn(recognize_pages(context.clone(), pages, sender));
+    let _stop_ocr = AbortOnDrop(producer);
+    
+    let mut all_results = Vec::new();
+    let mut translate_set: JoinSet<Result<(usize, String), AppError>> = JoinSet::new();
+    // With context carried over, each page waits for the one before it;
+    // a failed page releases the next one all the same
+    let in_order = state.config.translate_context > 0;
+    let mut previous_done: Option<oneshot::Receiver<()>> = None;
+    // Recognized pages waiting for an earlier page (None if their OCR failed)
+    let mut ready: HashMap<usize, Option<String>> = HashMap::new();
+    let mut ocr_open = true;
+    
+    loop {
+        let recognized = tokio::select! {
+            recognized = receiver.recv(), if ocr_open => match recognized {
+                Some(recognized) => recognized,
+                None => {
+                    ocr_open = false;
+                    continue;
+                }
+            },
+            Some(joined) = translate_set.join_next() => {
+                let result = joined.unwrap_or_else(|e| Err(TranslateError::Join(e.to_string()).into()));
+                match result {
+                    Ok(r) => all_results.push(Ok(r)),
+                    // Refused content stops the whole document, not just its page
+                    Err(e) if best_effort && !matches!(e, AppError::Policy(PolicyError::Denied { .. }) | AppError::Translate(TranslateError::Join(_))) => {
+                        state.add_log(task_id, e.to_string());
+                        all_results.push(Err(e));
+                    }
+                    Err(e) => {
+                        translate_set.abort_all();
+                        all_results.push(Err(e));
+                        break;
+                    }
+                }
+                if state.is_cancelled(task_id) {
+                    translate_set.abort_all();
+                    all_results.push(Err(AppError::Cancelled));
+                    break;
+                }
+                continue;
+            },
+            else => break,
+        };
+        
+        if state.is_cancelled(task_id) {
+            translate_set.abort_all();
+            all_results.push(Err(AppError::Cancelled));
+            break;
+        }
+        let Recognized { page_num, result } = recognized;
+        let text = match result {
+            Ok(text) => Some(text),
+            Err(e) if best_effort && !matches!(e, AppError::Ocr(OcrError::Join(_))) => {
+                // Best-effort: record the failure and keep going with the other pages
+                state.add_log(task_id, e.to_string());
+                all_results.push(Err(e));
+                None
+            }
+            Err(e) => {
+                translate_set.abort_all();
+                all_results.push(Err(e));
+                break;
+            }
+        };
+        
+        if review_pending {
+            if let Some(text) = text {
+                all_results.push(Ok((page_num, text)));
+            }
+            continue;
+        }
+        if task_options.mode == TaskMode::Ocr {
+            if let Some(text) = text {
+                if page_num == 1 {
+                    state.suggest_title(task_id, filename::heading_title(&text));
+                }
+                state.finish_page_untranslated(task_id, page_num);
+                all_results.push(Ok((page_num, text)));
+            }
+            continue;
+        }
+        
+        ready.insert(page_num, text.clone());
+        if sample_size > 0 {
+            sample_size -= 1;
+            if let Some(text) = text {
+                sample.push((page_num, text));
+            }
+            if sample_size > 0 {
+                continue;
+            }
+            let texts: Vec<&str> = sample.iter().map(|(_, text)| text.as_str()).collect();
+            if let Some(message) = already_in_target(state, task_id, &texts.join("\n")) {
+                state.set_skipped(task_id, message);
+                break;
+            }
+        }
+        
+        // Hand every page whose turn has come to the translation stage
+        let mut due = Vec::new();
+        if in_order {
+            while let Some(text) = order.front().and_then(|n| ready.remove(n)) {
+                let page_num = order.pop_front().unwrap_or_default();
+                if let Some(text) = text {
+                    due.push((page_num, text));
+                }
+            }
+        } else {
+            due.extend(ready.drain().filter_map(|(n, text)| text.map(|t| (n, t))));
+        }
+        for (page_num, text) in due {
+            let (done, next) = oneshot::channel::<()>();
+            let wait = if in_order { previous_done.replace(next) } else { None };
+            translate_set.spawn(translate_page(context.clone(), page_num, text, wait, done));
+        }
+    }
+    
+    all_results
+}
+
+/// Aborts the task when dropped, so a stage stops with the function driving it
+struct AbortOnDrop<T>(tokio::task::JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// The OCR stage: recognize up to PAGE_CONCURRENCY pages at a time (within
+/// the shared API_CONCURRENCY) and send each one on as it finishes. Stops once the translation stage stops listening.
+async fn recognize_pages(context: Arc<PageContext>, pages: Vec<pdf::PdfPage>, sender: tokio::sync::mpsc::Sender<Recognized>) {
+    let mut pages = pages.into_iter();
+    let mut running = tokio::task::JoinSet::new();
+    let mut page_of = HashMap::new();
+    loop {
+        while running.len() < context.state.config.page_concurrency
+            && let Some(page) = pages.next()
+        {
+            let page_num = page.page_num;
+            let handle = running.spawn(ocr_page(context.clone(), page));
+            page_of.insert(handle.id(), page_num);
+        }
+        let recognized = match running.join_next_with_id().await {
+            None => break,
+            Some(Ok((id, result))) => Recognized { page_num: page_of.remove(&id).unwrap_or_default(), result },
+            Some(Err(e)) => Recognized {
+                page_num: page_of.remove(&e.id()).unwrap_or_default(),
+                result: Err(OcrError::Join(e.to_string()).into()),
+            },
+        };
+        if sender.send(recognized).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Recognize one page (or take its text layer), after waiting for this task's
+/// turn at the shared API concurrency
+async fn ocr_page(context: Arc<PageContext>, page: pdf::PdfPage) -> Result<String, AppError> {
+    let PageContext { state, task_id, fallback, options, config, reviewed, .. } = &*context;
+    if *reviewed {
+        return Ok(state::load_page_ocr(task_id, page.page_num).unwrap_or_default());
+    }
+    let _permit = state.scheduler.acquire(task_id, options.background).await;
+    if state.is_cancelled(task_id) {
+        return Err(AppError::Cancelled);
+    }
+    
+    let page_num = page.page_num;
+    state.start_page_ocr(task_id, page_num);
+    let page_task_id = format!("{}-p{}", task_id, page_num);
+    
+    let text = if let Some(ref image_base64) = page.image_base64 {
+        let ocr = watch_page(state, task_id, page_num, "OCR", config.ocr_max_retries, || async {
+            recognize_with_downgrade(state, config, task_id, page_num, image_base64, &page_task_id, fallback).await
+                .map_err(|e| e.to_string())
+        }).await;
+        match ocr {
+            Ok(completion) => {
+                let t = post_ocr(state, task_id, page_num, completion.text).await?;
+                let _ = state::save_page_ocr(task_id, page_num, &t);
+                state.record_checksum(task_id, Artifact::Ocr(page_num), t.as_bytes());
+                let preview = state.text_preview(&t);
+                state.finish_page_ocr(task_id, page_num, t.chars().count(), preview, completion.usage, &completion.model);
+                state.note_continuations(task_id, page_num, "OCR", completion.continuations, completion.truncated);
+                state.add_log(task_id, format!("第 {} 页 OCR 完成 ({} 字符)", page_num, t.chars().count()));
+                t
+            }
+            Err(e) => {
+                let request = request_params(config, None, image_base64.len());
+                state.record_dead_letter(task_id, page_num, "ocr", &e, request);
+                state.set_page_error(task_id, page_num, e.to_string());
+                return Err(OcrError::Page { page: page_num, message: e }.into());
+            }
+        }
+    } else if let Some(extracted) = page.extracted_text {
+        let extracted = post_ocr(state, task_id, page_num, extracted).await?;
+        let _ = state::save_page_ocr(task_id, page_num, &extracted);
+        state.record_checksum(task_id, Artifact::Ocr(page_num), extracted.as_bytes());
+        let preview = state.text_preview(&extracted);
+        state.finish_page_ocr(task_id, page_num, extracted.chars().count(), preview, Usage::default(), "");
+        extracted
+    } else {
+        state.finish_page_ocr(task_id, page_num, 0, String::new(), Usage::default(), "");
+        String::new()
+    };
+    
+    Ok(text)
+}
+
+/// Translate one recognized page into every language of the task. With
+/// `wait`, the page starts once the page before it is done; dropping `done`
+/// releases the page after it.
+async fn translate_page(
+    context: Arc<PageContext>,
+    page_num: usize,
+    text: String,
+    wait: Option<tokio::sync::oneshot::Receiver<()>>,
+    done: tokio::sync::oneshot::Sender<()>,
+) -> Result<(usize, String), AppError> {
+    let _done = done;
+    if let Some(wait) = wait {
+        let _ = wait.await;
+    }
+    let PageContext { state, task_id, fallback, options: task_options, config, previous, .. } = &*context;
+    let _permit = state.scheduler.acquire(task_id, task_options.background).await;
+    if state.is_cancelled(task_id) {
+        return Err(AppError::Cancelled);
+    }
+    
+    state.start_page_translate(task_id, page_num);
+    let route = match content_route(state, task_id, page_num, &text).await {
+        Ok(route) => route,
+        Err(e) => {
+            state.set_page_error(task_id, page_num, e.to_string());
+            return Err(e.into());
+        }
+    };
+    let page_config = routed_config(config, route.as_deref());
+    
+    // Languages are translated one after another so each page still
+    // holds a single request slot; the primary language goes last so
+    // its file on disk marks the page as fully translated for retries
+    let mut usage = Usage::default();
+    let mut models = String::new();
+    let (mut continuations, mut truncated) = (0, false);
+    let mut primary = String::new();
+    let mut reused_from = None;
+    let mut names = None;
+    for lang in task_options.extra_langs.iter().copied().chain([task_options.target_lang]) {
+        let suffix = task_options.lang_suffix(lang);
+        // Extra languages finished before a failed attempt are kept
+        if suffix.is_some() && state::load_page_translation(task_id, page_num, suffix).is_some() {
+            continue;
+        }
+        if let Some((previous_page, cached)) = previous.as_ref().and_then(|p| p.translation(&text, lang)) {
+            let _ = state::save_page_translation(task_id, page_num, suffix, &cached);
+            state.record_checksum(task_id, Artifact::Translation(page_num, lang), cached.as_bytes());
+            reused_from = Some(previous_page);
+            primary = cached;
+            continue;
+        }
+        let source = match hooks::page_text(&state.config, HookPoint::PreTranslate, task_id, page_num, Some(lang), text.clone()).await {
+            Ok(source) => source,
+            Err(e) => {
+                state.set_page_error(task_id, page_num, e.clone());
+                return Err(TranslateError::Page { page: page_num, lang: lang.code(), message: e }.into());
+            }
+        };
+        let policy = state.config.pii_redaction;
+        let (source, redaction) = if policy == PiiPolicy::Off {
+            (source, pii::Redaction::default())
+        } else {
+            let first = names.is_none();
+            match redact_page(state, &source, &mut names).await {
+                Ok((masked, redaction)) => {
+                    if first && !redaction.is_empty() {
+                        state.add_log(task_id, format!("第 {} 页翻译前隐藏了 {} 处个人信息", page_num, redaction.len()));
+                    }
+                    (masked, redaction)
+                }
+                Err(e) => {
+                    state.set_page_error(task_id, page_num, e.clone());
+                    return Err(TranslateError::Page { page: page_num, lang: lang.code(), message: e }.into());
+                }
+            }
+        };
+        let translated = watch_page(state, task_id, page_num, "翻译", page_config.translate_max_retries, || {
+            translate_checked(state, &page_config, task_id, page_num, &source, lang, fallback)
+        }).await;
+        match translated {
+            Ok(completion) => {
+                usage.add(&completion.usage);
+                translate::note_model(&mut models, &completion.model);
+                continuations += completion.continuations;
+                truncated |= completion.truncated;
+                let translation = redaction.apply(&completion.text, policy);
+                let translation = hooks::page_text(&state.config, HookPoint::PostTranslate, task_id, page_num, Some(lang), translation).await;
+                let translation = match translation {
+                    Ok(translation) => translation,
+                    Err(e) => {
+                        state.set_page_error(task_id, page_num, e.clone());
+                        return Err(TranslateError::Page { page: page_num, lang: lang.code(), message: e }.into());
+                    }
+                };
+                let _ = state::save_page_translation(task_id, page_num, suffix, &translation);
+                state.record_checksum(task_id, Artifact::Translation(page_num, lang), translation.as_bytes());
+                primary = translation;
+            }
+            Err(e) => {
+                let request = request_params(config, Some(lang), text.chars().count());
+                state.record_dead_letter(task_id, page_num, "translate", &e, request);
+                state.set_page_error(task_id, page_num, e.clone());
+                return Err(TranslateError::Page { page: page_num, lang: lang.code(), message: e }.into());
+            }
+        }
+    }
+    
+    let char_count = primary.chars().count();
+    let preview = state.text_preview(&primary);
+    state.finish_page_translate(task_id, page_num, char_count, preview, usage, &models);
+    state.note_continuations(task_id, page_num, "翻译", continuations, truncated);
+    if page_num == 1 {
+        state.suggest_title(task_id, filename::heading_title(&primary));
+    }
+    if let Some(previous_page) = reused_from {
+        state.add_log(task_id, format!("第 {} 页与上一版本第 {} 页相同，沿用其译文", page_num, previous_page));
+    }
+    state.add_log(task_id, format!("第 {} 页翻译完成 ({} 字符)", page_num, char_count));
+    Ok((page_num, primary))
+}
+
+/// Longest piece of a text input sent in one translation request
+const TEXT_CHUNK_CHARS: usize = 4000;
+
+/// One piece of a text input with its translation
+pub struct TextChunk {
+    pub source: String,
+    pub completion: Completion,
+}
+
+/// Translate text submitted without a document: it is split at paragraph
+/// breaks into chunks, each going through the content policy and PII masking
+/// like a page would. `job_id` only labels the requests in the server log.
+pub async fn translate_plain_text(state: &Arc<AppState>, job_id: &str, text: &str, options: &state::TaskOptions) -> Result<Vec<TextChunk>, AppError> {
+    let lang = options.target_lang;
+    let config = options.config(&state.config);
+    let fallback = ModelFallbackState::default();
+    let policy = state.config.pii_redaction;
+    let mut chunks = Vec::new();
+    for (i, source) in translate::split_text(text, TEXT_CHUNK_CHARS).into_iter().enumerate() {
+        let chunk_num = i + 1;
+        let _permit = state.scheduler.acquire(job_id, false).await;
+        let route = content_route(state, job_id, chunk_num, &source).await?;
+        let (masked, redaction) = if policy == PiiPolicy::Off {
+            (source.clone(), pii::Redaction::default())
+        } else {
+            redact_page(state, &source, &mut None).await
+                .map_err(|message| TranslateError::Page { page: chunk_num, lang: lang.code(), message })?
+        };
+        let mut completion = translate_checked(state, &routed_config(&config, route.as_deref()), job_id, chunk_num, &masked, lang, &fallback).await
+            .map_err(|message| TranslateError::Page { page: chunk_num, lang: lang.code(), message })?;
+        completion.text = redaction.apply(&completion.text, policy);
+        state.stats.record_usage(&state.config, &completion.usage);
+        chunks.push(TextChunk { source, completion });
+    }
+    Ok(chunks)
+}
+
+/// An earlier version of the document: its pages by OCR text, so unchanged
+/// pages of a new version can take over their translations
+struct PreviousVersion {
+    task_id: String,
+    options: state::TaskOptions,
+    pages: HashMap<String, usize>,
+}
+
+impl PreviousVersion {
+    fn load(state: &AppState, task_id: &str) -> Option<Self> {
+        let options = state.get_options(task_id)?;
+        let pages = (1..=state.get_total_pages(task_id))
+            .filter_map(|n| state::load_page_ocr(task_id, n).map(|text| (normalize_text(&text), n)))
+            .filter(|(text, _)| !text.is_empty())
+            .collect();
+        Some(Self { task_id: task_id.to_string(), options, pages })
+    }
+
+    /// The earlier page with the same text and its translation into `lang`
+    fn translation(&self, text: &str, lang: lang::TargetLang) -> Option<(usize, String)> {
+        if !self.options.all_langs().contains(&lang) {
+            return None;
+        }
+        let page_num = *self.pages.get(&normalize_text(text))?;
+        state::load_page_translation(&self.task_id, page_num, self.options.lang_suffix(lang))
+            .map(|translation| (page_num, translation))
+    }
+}
+
+/// OCR output compared across versions, ignoring whitespace differences
+fn normalize_text(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Classify a page against CONTENT_POLICY before it goes out for translation:
+/// a denied category fails the page, a routed one names the model to use
+async fn content_route(state: &AppState, task_id: &str, page_num: usize, text: &str) -> Result<Option<String>, PolicyError> {
+    let Some(policy) = &state.config.content_policy else {
+        return Ok(None);
+    };
+    let categories = policy.classify(&state.config, text).await
+        .map_err(|message| PolicyError::Classify { page: page_num, message })?;
+    let Some(rule) = policy.decide(&categories) else {
+        return Ok(None);
+    };
+    state.add_log(task_id, format!("第 {} 页内容分类: {} → {}", page_num, categories.join("、"), rule.action.describe()));
+    match &rule.action {
+        PolicyAction::Allow => Ok(None),
+        PolicyAction::Deny => Err(PolicyError::Denied { page: page_num, category: rule.category.clone() }),
+        PolicyAction::Route(model) => Ok(Some(model.clone())),
+    }
+}
+
+/// Mask personal data in a page's text before it goes to the translation API;
+/// names are looked up once per page and reused for the other languages
+async fn redact_page(state: &AppState, text: &str, names: &mut Option<Vec<String>>) -> Result<(String, pii::Redaction), String> {
+    if names.is_none() {
+        let found = match &state.config.pii_name_model {
+            Some(model) => pii::detect_names(&state.config, model, text).await?,
+            None => Vec::new(),
+        };
+        *names = Some(found);
+    }
+    Ok(pii::redact(text, names.as_deref().unwrap_or_default()))
+}
+
+/// Run the post-OCR hook on a page's text; a failure fails the page
+async fn post_ocr(state: &AppState, task_id: &str, page_num: usize, text: String) -> Result<String, OcrError> {
+    hooks::page_text(&state.config, HookPoint::PostOcr, task_id, page_num, None, text).await
+        .map_err(|e| {
+            state.set_page_error(task_id, page_num, e.clone());
+            OcrError::Page { page: page_num, message: e }
+        })
+}
+
+/// Pre-warm the API connection while the PDF is being rendered
+pub fn spawn_warm_up(state: &Arc<AppState>) {
+    if !state.config.api_warmup {
+        return;
+    }
+    let state = state.clone();
+    tokio::spawn(async move {
+        if let Err(e) = translate::warm_up(&state.config, &state.scheduler, false).await {
+            eprintln!("[warmup] {}", e);
+        }
+    });
+}
+
+/// A page routed by the content policy goes to that model only, with no fallback
+fn routed_config<'a>(config: &'a config::Config, model: Option<&str>) -> Cow<'a, config::Config> {
+    match model {
+        Some(model) => Cow::Owned(config::Config {
+            translate_model: model.to_string(),
+            translate_model_fallback: None,
+            ..config.clone()
+        }),
+        None => Cow::Borrowed(config),
+    }
+}
+
+/// Translate a page and run the post-checks on the result; a failing
+/// translation is redone once, and if that fails too the page is flagged
+async fn translate_checked(
+    state: &Arc<AppState>,
+    config: &config::Config,
+    task_id: &str,
+    page_num: usize,
+    text: &str,
+    lang: lang::TargetLang,
+    fallback: &ModelFallbackState,
+) -> Result<Completion, String> {
+    let page_task_id = &format!("{}-p{}", task_id, page_num);
+    let context = state.translation_context(task_id, page_num, lang);
+    let mut completion = translate::translate_text(config, text, lang, context.as_deref(), page_task_id, fallback).await?;
+    // Text passed through without a request is the source itself
+    let Some(rules) = config.post_check.filter(|_| !completion.model.is_empty()) else {
+        return Ok(completion);
+    };
+    let Some(problem) = check::check_translation(&completion.text, lang, &rules) else {
+        return Ok(completion);
+    };
+
+    state.add_log(task_id, format!("第 {} 页译文 ({}) 检查未通过，重新翻译: {}", page_num, lang.code(), problem));
+    let retry = match translate::translate_text(config, text, lang, context.as_deref(), page_task_id, fallback).await {
+        Ok(retry) => retry,
+        Err(e) => {
+            // Keep the first translation; it is usable, just suspect
+            state.flag_page(task_id, page_num, format!("({}) {}；重新翻译失败: {}", lang.code(), problem, e));
+            return Ok(completion);
+        }
+    };
+    if let Some(problem) = check::check_translation(&retry.text, lang, &rules) {
+        state.flag_page(task_id, page_num, format!("({}) {}", lang.code(), problem));
+    }
+    completion.absorb(&retry);
+    completion.truncated = retry.truncated;
+    completion.text = retry.text;
+    Ok(completion)
+}
+
+/// OCR a page; if it keeps timing out, re-render it smaller and try again,
+/// since payload size is the usual culprit.
+async fn recognize_with_downgrade(
+    state: &Arc<AppState>,
+    config: &config::Config,
+    task_id: &str,
+    page_num: usize,
+    image_base64: &str,
+    page_task_id: &str,
+    fallback: &ModelFallbackState,
+) -> Result<Completion, ApiError> {
+    let mut result = translate::recognize_text(config, image_base64, page_task_id, fallback).await;
+    
+    for level in 1..pdf::RENDER_LEVELS.len() {
+        match &result {
+            Err(e) if e.is_timeout() && !state.is_cancelled(task_id) => {}
+            _ => break,
+        }
+        
+        let pdf_bytes = match state::load_input_pdf(task_id) {
+            Ok(b) => b,
+            Err(_) => break,
+        };
+        let (format, quality) = (config.ocr_image_format, config.ocr_image_quality);
+        let smaller = match workers::run(move || pdf::render_page_downgraded(&pdf_bytes, page_num, level, format, quality)).await {
+            Ok(img) => img,
+            Err(e) => {
+                state.add_log(task_id, format!("第 {} 页降级渲染失败: {}", page_num, e));
+                break;
+            }
+        };
+        let image = BASE64.decode(&smaller).unwrap_or_default();
+        state.record_checksum(task_id, Artifact::Image(page_num), &image);
+        if let Err(e) = state::save_page_image(task_id, page_num, format, &image) {
+            state.add_log(task_id, format!("保存第 {} 页图片失败: {}", page_num, e));
+        }
+        state.add_rendered(task_id, image.len() as u64, 0);
+        
+        let render = pdf::RENDER_LEVELS[level].limit(config.ocr_image_quality);
+        state.add_log(task_id, format!(
+            "第 {} 页 OCR 多次超时，降级图片后重试 ({}px, 质量 {})",
+            page_num, render.scale_to, render.quality
+        ));
+        result = translate::recognize_text(config, &smaller, page_task_id, fallback).await;
+    }
+    
+    result
+}
+
+/// Run one step of a page under the stall watchdog. A step that goes quiet
+/// for too long is aborted and started over once; if that stalls as well the
+/// page fails with a "stalled" reason instead of hanging the task.
+///
+/// The API traffic of the step, retries included, counts toward the task's
+/// resource usage, and the page shows how much of its retry budget is spent
+/// and, with streaming, the text received so far.
+async fn watch_page<T, F, Fut>(
+    state: &Arc<AppState>,
+    task_id: &str,
+    page_num: usize,
+    stage: &str,
+    max_retries: u32,
+    step: F,
+) -> Result<T, String>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, String>>,
+{
+    state.start_page_retries(task_id, page_num, stage, max_retries);
+    let observer: Arc<dyn translate::PageObserver> = Arc::new(PageWatch {
+        state: state.clone(),
+        task_id: task_id.to_string(),
+        page_num,
+        stage: stage.to_string(),
+    });
+    let traffic = Arc::new(resources::Traffic::default());
+    let result = watch_stalls(state, task_id, page_num, stage, || {
+        resources::metered(traffic.clone(), translate::observe(observer.clone(), step()))
+    }).await;
+    let (sent, received) = traffic.totals();
+    state.add_api_traffic(task_id, sent, received);
+    result
+}
+
+/// Passes what the API layer reports about a page's requests on to its summary
+struct PageWatch {
+    state: Arc<AppState>,
+    task_id: String,
+    page_num: usize,
+    stage: String,
+}
+
+impl translate::PageObserver for PageWatch {
+    fn retry(&self, notice: &translate::RetryNotice) {
+        self.state.note_page_retry(&self.task_id, self.page_num, notice);
+    }
+
+    fn partial(&self, text: &str) {
+        self.state.set_page_partial(&self.task_id, self.page_num, &self.stage, text);
+    }
+}
+
+async fn watch_stalls<T, F, Fut>(
+    state: &AppState,
+    task_id: &str,
+    page_num: usize,
+    stage: &str,
+    step: F,
+) -> Result<T, String>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, String>>,
+{
+    if state.config.stall_timeout_secs == 0 {
+        return step().await;
+    }
+    let limit = std::time::Duration::from_secs(state.config.stall_timeout_secs);
+    let mut stalled = false;
+    loop {
+        let stall = match watchdog::watch(limit, step()).await {
+            Ok(result) => return result,
+            Err(stall) => stall,
+        };
+        eprintln!(
+            "[{}] 第 {} 页{}停滞: {} 秒无进展，最后活动: {}",
+            task_id, page_num, stage, stall.idle.as_secs(), stall.activity
+        );
+        if std::mem::replace(&mut stalled, true) || state.is_cancelled(task_id) {
+            return Err(format!("处理停滞 ({} 秒无进展，最后活动: {})", stall.idle.as_secs(), stall.activity));
+        }
+        state.add_log(task_id, format!(
+            "第 {} 页{}停滞 {} 秒 (最后活动: {})，已中止并重试",
+            page_num, stage, stall.idle.as_secs(), stall.activity
+        ));
+    }
+}
+
+/// Request settings for a dead letter: OCR when there is no target language
+fn request_params(config: &config::Config, lang: Option<lang::TargetLang>, input_len: usize) -> deadletter::RequestParams {
+    let (model, fallback_model, timeout_secs, max_retries) = match lang {
+        None => (&config.ocr_model, &config.ocr_model_fallback, config.ocr_timeout_secs, config.ocr_max_retries),
+        Some(_) => (&config.translate_model, &config.translate_model_fallback, config.translate_timeout_secs, config.translate_max_retries),
+    };
+    deadletter::RequestParams {
+        model: model.clone(),
+        fallback_model: fallback_model.clone(),
+        target_lang: lang.map(|l| l.code().to_string()),
+        timeout_secs,
+        max_retries,
+        stream: config.api_stream,
+        input_len,
+    }
+}
+
+// Guard to release task slot on drop
+struct TaskGuard {
+    state: Arc<AppState>,
+    background: bool,
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        self.state.release_task_slot(self.background);
+    }
+}
+
+pub async fn process_retry(state: Arc<AppState>, task_id: String, pdf_bytes: Vec<u8>) {
+    let background = state.get_options(&task_id).unwrap_or_default().background;
+    let _guard = TaskGuard { state: state.clone(), background };
+    
+    // Re-render pages
+    let pages = match render_pages(&state, &task_id, Arc::new(pdf_bytes)).await {
+        Ok(p) => p,
+        Err(e) => {
+            state.set_error(&task_id, format!("PDF 处理失败: {}", e));
+            state.finish_retry(&task_id);
+            return;
+        }
+    };
+    
+    let total_pages = pages.len();
+    if total_pages == 0 {
+        state.set_error(&task_id, "PDF 没有页面".to_string());
+        state.finish_retry(&task_id);
+        return;
+    }
+    
+    // Filter pending pages (check if the translated file, or for OCR-only
+    // tasks the OCR text, exists)
+    let options = state.get_options(&task_id).unwrap_or_default();
+    let pending_pages: Vec<_> = pages.into_iter()
+        .filter(|p| state::load_page_output(&task_id, p.page_num, &options, options.target_lang).is_none())
+        .collect();
+    let completed_count = total_pages - pending_pages.len();
+    
+    if pending_pages.is_empty() {
+        // All pages done, generate PDF from disk
+        generate_output(&state, &task_id, total_pages).await;
+        state.finish_retry(&task_id);
+        return;
+    }
+    
+    // Initialize progress
+    state.init_retry_progress(&task_id, completed_count, total_pages);
+    state.add_log(&task_id, format!("继续处理，已完成 {}/{} 页", completed_count, total_pages));
+    
+    // Create fallback state for this task
+    let fallback_state = Arc::new(ModelFallbackState::default());
+    
+    // Process pending pages
+    let results = process_pages_parallel(&state, &task_id, pending_pages, fallback_state, false).await;
+    
+    // Check if cancelled
+    if state.is_cancelled(&task_id) {
+        state.finish_retry(&task_id);
+        return;
+    }
+    
+    // Results are already saved to disk in process_pages_parallel
+    if !check_page_results(&state, &task_id, results) {
+        state.finish_retry(&task_id);
+        return;
+    }
+    
+    // Generate PDF from disk
+    finish_pages(&state, &task_id, total_pages).await;
+    state.finish_retry(&task_id);
+}
+
+/// Translate a task whose reviewed OCR text was approved, from the text as
+/// saved; no page is rendered or recognized again
+pub async fn process_approved(state: Arc<AppState>, task_id: String) {
+    let background = state.get_options(&task_id).unwrap_or_default().background;
+    let _guard = TaskGuard { state: state.clone(), background };
+    
+    let total_pages = state.get_total_pages(&task_id);
+    let pages = (1..=total_pages)
+        .map(|page_num| pdf::PdfPage { page_num, image_base64: None, extracted_text: None })
+        .collect();
+    let fallback_state = Arc::new(ModelFallbackState::default());
+    let results = process_pages_parallel(&state, &task_id, pages, fallback_state, false).await;
+    
+    if state.is_cancelled(&task_id) || !check_page_results(&state, &task_id, results) {
+        return;
+    }
+    generate_output(&state, &task_id, total_pages).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_that_break_the_layout_are_found() {
+        let texts: Vec<String> = ["第一页", "BREAK", "第三页"].map(String::from).into();
+        let options = pdf::OutputOptions::default();
+        // Layout code that panics on one page, as a layout bug would
+        let typeset = |texts: &[String], options: &pdf::OutputOptions| catch_layout_panic(|| {
+            assert!(!texts.iter().any(|t| t == "BREAK"), "line overflows the frame");
+            pdf::generate_pdf(texts, options)
+        });
+        assert!(typeset(&texts, &options).is_err_and(|e| e.contains("overflows")));
+        assert_eq!(broken_pages(&texts, &options, typeset), [2]);
+    }
+}
@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::provider::{self, ChatRequest, Message, MessageContent};
+
+/// What happens to a page that falls into a category
+#[derive(Clone, Debug, PartialEq)]
+pub enum PolicyAction {
+    Allow,
+    /// The task fails without the page being sent for translation
+    Deny,
+    /// Translated by this model instead of MODEL
+    Route(String),
+}
+
+impl PolicyAction {
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        match s.to_ascii_lowercase().as_str() {
+            "allow" => return Some(PolicyAction::Allow),
+            "deny" => return Some(PolicyAction::Deny),
+            _ => {}
+        }
+        // Model names may contain ':' themselves (qwen2.5:14b)
+        let (action, model) = s.split_once(':')?;
+        let model = model.trim();
+        (action.trim().eq_ignore_ascii_case("route") && !model.is_empty()).then(|| PolicyAction::Route(model.to_string()))
+    }
+
+    /// For the task log
+    pub fn describe(&self) -> String {
+        match self {
+            PolicyAction::Allow => "允许".to_string(),
+            PolicyAction::Deny => "拒绝".to_string(),
+            PolicyAction::Route(model) => format!("改用模型 {}", model),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ContentRule {
+    /// Described in plain words, since the classifier model sees the name
+    pub category: String,
+    pub action: PolicyAction,
+}
+
+/// Categories a page is checked against after OCR, with what to do about each
+/// (CONTENT_POLICY). Pages outside every category are translated as usual.
+#[derive(Clone, Debug)]
+pub struct ContentPolicy {
+    pub rules: Vec<ContentRule>,
+    /// Model doing the classification; MODEL when unset
+    pub model: Option<String>,
+}
+
+impl ContentPolicy {
+    /// `category=action` rules separated by `;`, e.g.
+    /// `medical records=deny;legal contracts=route:qwen2.5:14b`
+    pub fn parse_rules(s: &str) -> Result<Vec<ContentRule>, String> {
+        s.split(';')
+            .map(str::trim)
+            .filter(|r| !r.is_empty())
+            .map(|rule| {
+                let (category, action) = rule.split_once('=').ok_or_else(|| rule.to_string())?;
+                let category = category.trim();
+                let action = PolicyAction::parse(action).ok_or_else(|| rule.to_string())?;
+                if category.is_empty() {
+                    return Err(rule.to_string());
+                }
+                Ok(ContentRule { category: category.to_string(), action })
+            })
+            .collect()
+    }
+
+    /// The rule that decides for a page in `categories`: a denial over a
+    /// route over an allowance, the earlier rule among equals
+    pub fn decide(&self, categories: &[String]) -> Option<&ContentRule> {
+        let matched = || self.rules.iter().filter(|r| categories.contains(&r.category));
+        matched().find(|r| r.action == PolicyAction::Deny)
+            .or_else(|| matched().find(|r| matches!(r.action, PolicyAction::Route(_))))
+            .or_else(|| matched().next())
+    }
+
+    /// Ask the classifier which of the configured categories a page falls into
+    pub async fn classify(&self, config: &Config, text: &str) -> Result<Vec<String>, String> {
+        if text.trim().is_empty() || self.rules.is_empty() {
+            return Ok(Vec::new());
+        }
+        let categories: Vec<String> = self.rules.iter().map(|r| format!("- {}", r.category)).collect();
+        let prompt = format!(
+r#"判断以下文本属于下列哪些类别，按列表中的原样写法输出为 JSON 字符串数组，例如 ["{}"]；都不属于时输出 []。只输出 JSON。
+
+类别：
+{}
+
+文本：
+{}"#, self.rules[0].category, categories.join("\n"), text);
+        let request = ChatRequest {
+            model: self.model.as_deref().unwrap_or(&config.translate_model),
+            messages: vec![Message { role: "user".to_string(), content: MessageContent::Text(prompt) }],
+            max_tokens: Some(256),
+            stream: false,
+        };
+        let reply = provider::chat(config, &request, Duration::from_secs(config.translate_timeout_secs)).await
+            .map_err(|e| e.to_string())?;
+        // Models like to wrap JSON in a code fence
+        let json = reply.text.trim().trim_start_matches("```json").trim_start_matches("```").trim_end_matches("```").trim();
+        let found: Vec<String> = serde_json::from_str(json).map_err(|e| format!("分类结果无效: {}", e))?;
+        // Only names from the list count; anything else the model made up is dropped
+        Ok(found.into_iter()
+            .filter_map(|c| self.rules.iter().find(|r| r.category.eq_ignore_ascii_case(c.trim())).map(|r| r.category.clone()))
+            .collect())
+    }
+}
@@ -0,0 +1,53 @@
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::fs;
+
+use crate::state;
+
+/// Upload form fields a user can save as defaults
+pub const PREFERENCE_FIELDS: &[&str] = &["target_lang", "layout", "output", "romanize"];
+
+/// Saved upload defaults per user name, as form fields; saved as a whole on
+/// every change
+pub struct PreferencesStore {
+    users: Mutex<BTreeMap<String, Vec<(String, String)>>>,
+}
+
+impl PreferencesStore {
+    pub fn load() -> Self {
+        let users = fs::read_to_string(state::data_dir().join("preferences.json"))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        Self { users: Mutex::new(users) }
+    }
+
+    fn save(users: &BTreeMap<String, Vec<(String, String)>>) {
+        let path = state::data_dir().join("preferences.json");
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let tmp_path = path.with_extension("json.tmp");
+        if let Ok(json) = serde_json::to_string_pretty(users)
+            && fs::write(&tmp_path, json).is_ok()
+        {
+            let _ = fs::rename(tmp_path, path);
+        }
+    }
+
+    /// The user's saved fields; empty when nothing is saved
+    pub fn get(&self, user: &str) -> Vec<(String, String)> {
+        self.users.lock().get(user).cloned().unwrap_or_default()
+    }
+
+    /// Replace the user's fields; no fields clears them
+    pub fn set(&self, user: &str, fields: Vec<(String, String)>) {
+        let mut users = self.users.lock();
+        if fields.is_empty() {
+            users.remove(user);
+        } else {
+            users.insert(user.to_string(), fields);
+        }
+        Self::save(&users);
+    }
+}
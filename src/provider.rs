@@ -0,0 +1,674 @@
+use reqwest::StatusCode;
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::scheduler::PageScheduler;
+use crate::translate::ApiError;
+use crate::usage::{self, Usage};
+
+/// Which API the configured endpoint speaks
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ProviderKind {
+    /// OpenAI-compatible /v1/chat/completions (OpenAI, most gateways)
+    #[default]
+    OpenAi,
+    /// Anthropic Messages API
+    Anthropic,
+    /// Local Ollama server
+    Ollama,
+}
+
+impl ProviderKind {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "openai" | "openai-compatible" => Some(ProviderKind::OpenAi),
+            "anthropic" | "claude" => Some(ProviderKind::Anthropic),
+            "ollama" => Some(ProviderKind::Ollama),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProviderKind::OpenAi => "openai-compatible",
+            ProviderKind::Anthropic => "anthropic",
+            ProviderKind::Ollama => "ollama",
+        }
+    }
+
+    /// Endpoint used when BASE_URL is not set; OpenAI-compatible gateways have none
+    pub fn default_base_url(&self) -> Option<&'static str> {
+        match self {
+            ProviderKind::OpenAi => None,
+            ProviderKind::Anthropic => Some("https://api.anthropic.com"),
+            ProviderKind::Ollama => Some("http://localhost:11434"),
+        }
+    }
+
+    /// Only the OpenAI-compatible streaming format is implemented
+    pub fn supports_stream(&self) -> bool {
+        *self == ProviderKind::OpenAi
+    }
+}
+
+/// A chat request in provider-neutral form
+#[derive(Clone)]
+pub struct ChatRequest<'a> {
+    pub model: &'a str,
+    pub messages: Vec<Message>,
+    pub max_tokens: Option<u32>,
+    pub stream: bool,
+}
+
+#[derive(Clone)]
+pub struct Message {
+    pub role: String,
+    pub content: MessageContent,
+}
+
+#[derive(Clone)]
+pub enum MessageContent {
+    Text(String),
+    Multimodal(Vec<ContentPart>),
+}
+
+#[derive(Clone)]
+pub enum ContentPart {
+    Text { text: String },
+    /// Base64-encoded image
+    Image { mime_type: &'static str, data: String },
+}
+
+impl ChatRequest<'_> {
+    fn has_images(&self) -> bool {
+        self.messages.iter().any(|m| match &m.content {
+            MessageContent::Text(_) => false,
+            MessageContent::Multimodal(parts) => parts.iter().any(|p| matches!(p, ContentPart::Image { .. })),
+        })
+    }
+
+    /// Local estimate of prompt tokens, used when the provider reports no usage
+    pub fn estimate_prompt_tokens(&self) -> u64 {
+        self.messages.iter().map(|m| match &m.content {
+            MessageContent::Text(t) => usage::estimate_tokens(t),
+            MessageContent::Multimodal(parts) => parts.iter().map(|p| match p {
+                ContentPart::Text { text } => usage::estimate_tokens(text),
+                ContentPart::Image { .. } => usage::IMAGE_TOKEN_ESTIMATE,
+            }).sum(),
+        }).sum()
+    }
+}
+
+/// Reply text and the usage the provider reported, if any
+pub struct Reply {
+    pub text: String,
+    pub usage: Option<Usage>,
+    /// The model stopped at max_tokens rather than at the end of its answer
+    pub truncated: bool,
+}
+
+/// One chat API. OCR requests carry the page image and go through
+/// `chat_vision`; translation requests are text only.
+pub trait Provider {
+    fn chat_vision(&self, config: &Config, request: &ChatRequest<'_>, timeout: Duration) -> impl Future<Output = Result<Reply, ApiError>> + Send;
+    fn chat_text(&self, config: &Config, request: &ChatRequest<'_>, timeout: Duration) -> impl Future<Output = Result<Reply, ApiError>> + Send;
+    /// Open (or refresh) a pooled connection with a request that costs no tokens
+    fn warm_up(&self, config: &Config) -> impl Future<Output = Result<(), String>> + Send;
+    /// Names of the models the endpoint offers
+    fn list_models(&self, config: &Config) -> impl Future<Output = Result<Vec<String>, String>> + Send;
+}
+
+/// Send a request (without streaming) through the configured provider
+pub async fn chat(config: &Config, request: &ChatRequest<'_>, timeout: Duration) -> Result<Reply, ApiError> {
+    match config.provider {
+        ProviderKind::OpenAi => dispatch(&OpenAi, config, request, timeout).await,
+        ProviderKind::Anthropic => dispatch(&Anthropic, config, request, timeout).await,
+        ProviderKind::Ollama => dispatch(&Ollama, config, request, timeout).await,
+    }
+}
+
+async fn dispatch<P: Provider>(provider: &P, config: &Config, request: &ChatRequest<'_>, timeout: Duration) -> Result<Reply, ApiError> {
+    if request.has_images() {
+        provider.chat_vision(config, request, timeout).await
+    } else {
+        provider.chat_text(config, request, timeout).await
+    }
+}
+
+pub async fn warm_up(config: &Config) -> Result<(), String> {
+    match config.provider {
+        ProviderKind::OpenAi => OpenAi.warm_up(config).await,
+        ProviderKind::Anthropic => Anthropic.warm_up(config).await,
+        ProviderKind::Ollama => Ollama.warm_up(config).await,
+    }
+}
+
+pub async fn list_models(config: &Config) -> Result<Vec<String>, String> {
+    match config.provider {
+        ProviderKind::OpenAi => OpenAi.list_models(config).await,
+        ProviderKind::Anthropic => Anthropic.list_models(config).await,
+        ProviderKind::Ollama => Ollama.list_models(config).await,
+    }
+}
+
+/// How long a fetched model list is served before asking the provider again
+const MODEL_LIST_TTL: Duration = Duration::from_secs(600);
+
+/// The provider's model list as last fetched
+#[derive(Clone)]
+pub struct ModelList {
+    pub models: Vec<String>,
+    /// Unix time in milliseconds
+    pub fetched_at: u64,
+    /// The provider could not be reached and this is an older list
+    pub stale: bool,
+}
+
+/// The provider's model list, fetched at most once per MODEL_LIST_TTL
+#[derive(Default)]
+pub struct ModelListCache {
+    cached: tokio::sync::Mutex<Option<(Instant, ModelList)>>,
+}
+
+impl ModelListCache {
+    /// The cached list while fresh, else a new one; when the provider can't
+    /// be reached the last list is served, marked stale. A fetch holds an
+    /// API permit like any other upstream call.
+    pub async fn get(&self, config: &Config, scheduler: &Arc<PageScheduler>) -> Result<ModelList, String> {
+        // Held across the fetch so concurrent requests wait for one listing
+        let mut cached = self.cached.lock().await;
+        if let Some((at, list)) = cached.as_ref()
+            && at.elapsed() < MODEL_LIST_TTL
+        {
+            return Ok(list.clone());
+        }
+        let permit = scheduler.acquire("models", false).await;
+        let listed = list_models(config).await;
+        drop(permit);
+        match listed {
+            Ok(models) => {
+                let fetched_at = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)
+                    .map_or(0, |d| d.as_millis() as u64);
+                let list = ModelList { models, fetched_at, stale: false };
+                *cached = Some((Instant::now(), list.clone()));
+                Ok(list)
+            }
+            Err(e) => match cached.as_ref() {
+                Some((_, list)) => Ok(ModelList { stale: true, ..list.clone() }),
+                None => Err(e),
+            },
+        }
+    }
+}
+
+static HTTP_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+pub fn get_client() -> &'static reqwest::Client {
+    HTTP_CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(300))
+            .connect_timeout(Duration::from_secs(10))
+            .pool_max_idle_per_host(2)
+            .build()
+            .expect("Failed to create HTTP client")
+    })
+}
+
+fn endpoint(config: &Config, path: &str) -> String {
+    format!("{}{}", config.base_url.trim_end_matches('/'), path)
+}
+
+pub fn classify_reqwest_error(e: &reqwest::Error) -> ApiError {
+    if e.is_timeout() {
+        ApiError::Timeout(format!("请求超时: {}", e))
+    } else if e.is_connect() {
+        ApiError::Retryable(format!("网络错误: {}", e))
+    } else {
+        ApiError::NonRetryable(format!("请求失败: {}", e))
+    }
+}
+
+/// Longest wait a rate-limited response can ask for before the next attempt
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(120);
+
+pub fn classify_http_status(status: reqwest::StatusCode, headers: &HeaderMap, body: &str) -> ApiError {
+    let message = format!("API 错误 {}: {}", status, body);
+    if matches!(status, StatusCode::TOO_MANY_REQUESTS | StatusCode::REQUEST_TIMEOUT) {
+        ApiError::RateLimited { message, retry_after: retry_after(headers) }
+    } else if status.is_server_error() {
+        ApiError::Retryable(message)
+    } else {
+        ApiError::NonRetryable(message)
+    }
+}
+
+/// How long the provider asks to wait: `retry-after-ms`, then the standard
+/// `Retry-After` (seconds or an HTTP date), then the latest of OpenAI's
+/// `x-ratelimit-reset-*` (durations like `6m0s`) and Anthropic's
+/// `anthropic-ratelimit-*-reset` (RFC 3339 times)
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+    let until = |time: chrono::DateTime<chrono::FixedOffset>| {
+        (time.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().unwrap_or_default()
+    };
+    let delay = header("retry-after-ms")
+        .and_then(|v| v.parse::<f64>().ok())
+        .map(|ms| Duration::from_secs_f64(ms.max(0.0) / 1000.0))
+        .or_else(|| header("retry-after").and_then(|v| {
+            v.parse::<u64>().ok().map(Duration::from_secs)
+                .or_else(|| chrono::DateTime::parse_from_rfc2822(v).ok().map(until))
+        }))
+        .or_else(|| {
+            let openai = ["x-ratelimit-reset-requests", "x-ratelimit-reset-tokens"].into_iter()
+                .filter_map(|name| header(name).and_then(parse_go_duration));
+            let anthropic = ["anthropic-ratelimit-requests-reset", "anthropic-ratelimit-tokens-reset"].into_iter()
+                .filter_map(|name| header(name).and_then(|v| chrono::DateTime::parse_from_rfc3339(v).ok()).map(until));
+            openai.chain(anthropic).max()
+        })?;
+    Some(delay.min(MAX_RETRY_AFTER))
+}
+
+/// Go-style durations as OpenAI sends them: "1s", "6m0s", "20ms", "1.5s"
+fn parse_go_duration(s: &str) -> Option<Duration> {
+    let mut rest = s;
+    let mut secs = 0.0;
+    if rest.is_empty() {
+        return None;
+    }
+    while !rest.is_empty() {
+        let number_end = rest.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+        let value: f64 = rest[..number_end].parse().ok()?;
+        rest = &rest[number_end..];
+        let unit_end = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+        secs += value * match &rest[..unit_end] {
+            "h" => 3600.0,
+            "m" => 60.0,
+            "s" => 1.0,
+            "ms" => 0.001,
+            _ => return None,
+        };
+        rest = &rest[unit_end..];
+    }
+    Some(Duration::from_secs_f64(secs))
+}
+
+/// POST a JSON body and return the response body of a successful request
+async fn post_json(request: reqwest::RequestBuilder, body: &serde_json::Value, timeout: Duration) -> Result<String, ApiError> {
+    let response = json_body(request, body)
+        .timeout(timeout)
+        .send()
+        .await
+        .map_err(|e| classify_reqwest_error(&e))?;
+
+    let status = response.status();
+    crate::watchdog::beat(|| format!("收到响应头 (HTTP {})", status));
+    let headers = response.headers().clone();
+    let body = response.text().await.unwrap_or_default();
+    crate::resources::count_received(body.len());
+    if !status.is_success() {
+        return Err(classify_http_status(status, &headers, &body));
+    }
+    Ok(body)
+}
+
+/// Attach a JSON body, counting its size toward the task's API traffic
+pub fn json_body(request: reqwest::RequestBuilder, body: &serde_json::Value) -> reqwest::RequestBuilder {
+    let bytes = body.to_string().into_bytes();
+    crate::resources::count_sent(bytes.len());
+    request.header(reqwest::header::CONTENT_TYPE, "application/json").body(bytes)
+}
+
+fn parse_reply<'a, T: Deserialize<'a>>(body: &'a str) -> Result<T, ApiError> {
+    serde_json::from_str(body)
+        .map_err(|e| ApiError::NonRetryable(format!("解析失败: {} - 响应: {}", e, &body[..body.len().min(500)])))
+}
+
+/// GET a model listing: `list` holds one object per model, named by `name`
+async fn fetch_models(request: reqwest::RequestBuilder, list: &str, name: &str) -> Result<Vec<String>, String> {
+    let response = request
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await
+        .map_err(|e| format!("请求失败: {}", e))?;
+    let status = response.status();
+    let body = response.text().await.map_err(|e| format!("读取响应失败: {}", e))?;
+    if !status.is_success() {
+        return Err(format!("API 错误 {}: {}", status, body.chars().take(200).collect::<String>()));
+    }
+    let value: serde_json::Value = serde_json::from_str(&body).map_err(|e| format!("解析失败: {}", e))?;
+    let mut models: Vec<String> = value[list].as_array()
+        .map(|models| models.iter().filter_map(|m| m[name].as_str().map(String::from)).collect())
+        .unwrap_or_default();
+    models.sort();
+    models.dedup();
+    Ok(models)
+}
+
+async fn ping(request: reqwest::RequestBuilder) -> Result<(), String> {
+    let response = request
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await
+        .map_err(|e| format!("预热失败: {}", e))?;
+    // Drain the body so the connection goes back to the pool
+    let _ = response.bytes().await;
+    Ok(())
+}
+
+/// OpenAI-compatible chat completions
+pub struct OpenAi;
+
+impl OpenAi {
+    /// Request body; also used by the streaming path
+    pub fn body(request: &ChatRequest<'_>, stream: bool) -> serde_json::Value {
+        let messages: Vec<serde_json::Value> = request.messages.iter().map(|m| {
+            let content = match &m.content {
+                MessageContent::Text(text) => serde_json::json!(text),
+                MessageContent::Multimodal(parts) => parts.iter().map(|p| match p {
+                    ContentPart::Text { text } => serde_json::json!({ "type": "text", "text": text }),
+                    ContentPart::Image { mime_type, data } => serde_json::json!({
+                        "type": "image_url",
+                        "image_url": { "url": format!("data:{};base64,{}", mime_type, data) },
+                    }),
+                }).collect(),
+            };
+            serde_json::json!({ "role": m.role, "content": content })
+        }).collect();
+        let mut body = serde_json::json!({ "model": request.model, "messages": messages });
+        if let Some(max_tokens) = request.max_tokens {
+            body["max_tokens"] = max_tokens.into();
+        }
+        if stream {
+            body["stream"] = true.into();
+            // Without this the stream carries no usage chunk
+            body["stream_options"] = serde_json::json!({ "include_usage": true });
+        }
+        body
+    }
+
+    pub fn request(config: &Config) -> reqwest::RequestBuilder {
+        get_client()
+            .post(endpoint(config, "/v1/chat/completions"))
+            .header("Authorization", format!("Bearer {}", config.api_key))
+    }
+
+    async fn chat(&self, config: &Config, request: &ChatRequest<'_>, timeout: Duration) -> Result<Reply, ApiError> {
+        #[derive(Deserialize)]
+        struct ChatResponse {
+            choices: Vec<Choice>,
+            #[serde(default)]
+            usage: Option<serde_json::Value>,
+        }
+        #[derive(Deserialize)]
+        struct Choice {
+            message: ResponseMessage,
+            #[serde(default)]
+            finish_reason: Option<String>,
+        }
+        #[derive(Deserialize)]
+        struct ResponseMessage {
+            content: String,
+        }
+
+        let body = post_json(Self::request(config), &Self::body(request, false), timeout).await?;
+        let response: ChatResponse = parse_reply(&body)?;
+        let reported = response.usage.as_ref().and_then(usage::normalize);
+        response.choices
+            .into_iter()
+            .next()
+            .map(|c| Reply {
+                text: c.message.content,
+                usage: reported,
+                truncated: c.finish_reason.as_deref() == Some("length"),
+            })
+            .ok_or_else(|| ApiError::NonRetryable("空响应".to_string()))
+    }
+}
+
+impl Provider for OpenAi {
+    async fn chat_vision(&self, config: &Config, request: &ChatRequest<'_>, timeout: Duration) -> Result<Reply, ApiError> {
+        self.chat(config, request, timeout).await
+    }
+
+    async fn chat_text(&self, config: &Config, request: &ChatRequest<'_>, timeout: Duration) -> Result<Reply, ApiError> {
+        self.chat(config, request, timeout).await
+    }
+
+    /// GET /v1/models, which costs no tokens on OpenAI-compatible providers
+    async fn warm_up(&self, config: &Config) -> Result<(), String> {
+        ping(get_client()
+            .get(endpoint(config, "/v1/models"))
+            .header("Authorization", format!("Bearer {}", config.api_key))).await
+    }
+
+    async fn list_models(&self, config: &Config) -> Result<Vec<String>, String> {
+        fetch_models(get_client()
+            .get(endpoint(config, "/v1/models"))
+            .header("Authorization", format!("Bearer {}", config.api_key)), "data", "id").await
+    }
+}
+
+/// Anthropic Messages API
+pub struct Anthropic;
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+impl Anthropic {
+    fn body(request: &ChatRequest<'_>) -> serde_json::Value {
+        let messages: Vec<serde_json::Value> = request.messages.iter().map(|m| {
+            let content = match &m.content {
+                MessageContent::Text(text) => serde_json::json!(text),
+                MessageContent::Multimodal(parts) => parts.iter().map(|p| match p {
+                    ContentPart::Text { text } => serde_json::json!({ "type": "text", "text": text }),
+                    ContentPart::Image { mime_type, data } => serde_json::json!({
+                        "type": "image",
+                        "source": { "type": "base64", "media_type": mime_type, "data": data },
+                    }),
+                }).collect(),
+            };
+            serde_json::json!({ "role": m.role, "content": content })
+        }).collect();
+        // max_tokens is required by this API
+        serde_json::json!({
+            "model": request.model,
+            "max_tokens": request.max_tokens.unwrap_or(8192),
+            "messages": messages,
+        })
+    }
+
+    fn with_auth(config: &Config, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        request
+            .header("x-api-key", &config.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+    }
+
+    async fn chat(&self, config: &Config, request: &ChatRequest<'_>, timeout: Duration) -> Result<Reply, ApiError> {
+        #[derive(Deserialize)]
+        struct MessagesResponse {
+            content: Vec<Block>,
+            #[serde(default)]
+            usage: Option<serde_json::Value>,
+            #[serde(default)]
+            stop_reason: Option<String>,
+        }
+        #[derive(Deserialize)]
+        struct Block {
+            #[serde(default)]
+            text: Option<String>,
+        }
+
+        let http = Self::with_auth(config, get_client().post(endpoint(config, "/v1/messages")));
+        let body = post_json(http, &Self::body(request), timeout).await?;
+        let response: MessagesResponse = parse_reply(&body)?;
+        let text: String = response.content.into_iter().filter_map(|b| b.text).collect();
+        if text.is_empty() {
+            return Err(ApiError::NonRetryable("空响应".to_string()));
+        }
+        Ok(Reply {
+            text,
+            usage: response.usage.as_ref().and_then(usage::normalize),
+            truncated: response.stop_reason.as_deref() == Some("max_tokens"),
+        })
+    }
+}
+
+impl Provider for Anthropic {
+    async fn chat_vision(&self, config: &Config, request: &ChatRequest<'_>, timeout: Duration) -> Result<Reply, ApiError> {
+        self.chat(config, request, timeout).await
+    }
+
+    async fn chat_text(&self, config: &Config, request: &ChatRequest<'_>, timeout: Duration) -> Result<Reply, ApiError> {
+        self.chat(config, request, timeout).await
+    }
+
+    async fn warm_up(&self, config: &Config) -> Result<(), String> {
+        ping(Self::with_auth(config, get_client().get(endpoint(config, "/v1/models")))).await
+    }
+
+    async fn list_models(&self, config: &Config) -> Result<Vec<String>, String> {
+        fetch_models(Self::with_auth(config, get_client().get(endpoint(config, "/v1/models?limit=1000"))), "data", "id").await
+    }
+}
+
+/// Local Ollama server (/api/chat); images are attached to the message as plain base64
+pub struct Ollama;
+
+#[derive(Serialize)]
+struct OllamaMessage<'a> {
+    role: &'a str,
+    content: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    images: Vec<&'a str>,
+}
+
+impl Ollama {
+    fn body(request: &ChatRequest<'_>) -> serde_json::Value {
+        let messages: Vec<OllamaMessage> = request.messages.iter().map(|m| {
+            let (content, images) = match &m.content {
+                MessageContent::Text(text) => (text.clone(), Vec::new()),
+                MessageContent::Multimodal(parts) => {
+                    let mut text = String::new();
+                    let mut images = Vec::new();
+                    for part in parts {
+                        match part {
+                            ContentPart::Text { text: t } => text.push_str(t),
+                            ContentPart::Image { data, .. } => images.push(data.as_str()),
+                        }
+                    }
+                    (text, images)
+                }
+            };
+            OllamaMessage { role: &m.role, content, images }
+        }).collect();
+        let mut body = serde_json::json!({ "model": request.model, "messages": messages, "stream": false });
+        if let Some(max_tokens) = request.max_tokens {
+            body["options"] = serde_json::json!({ "num_predict": max_tokens });
+        }
+        body
+    }
+
+    async fn chat(&self, config: &Config, request: &ChatRequest<'_>, timeout: Duration) -> Result<Reply, ApiError> {
+        #[derive(Deserialize)]
+        struct ChatResponse {
+            message: ResponseMessage,
+            #[serde(default)]
+            prompt_eval_count: Option<u64>,
+            #[serde(default)]
+            eval_count: Option<u64>,
+            #[serde(default)]
+            done_reason: Option<String>,
+        }
+        #[derive(Deserialize)]
+        struct ResponseMessage {
+            content: String,
+        }
+
+        let mut http = get_client().post(endpoint(config, "/api/chat"));
+        if !config.api_key.is_empty() {
+            // For servers behind an authenticating proxy
+            http = http.header("Authorization", format!("Bearer {}", config.api_key));
+        }
+        let body = post_json(http, &Self::body(request), timeout).await?;
+        let response: ChatResponse = parse_reply(&body)?;
+        if response.message.content.is_empty() {
+            return Err(ApiError::NonRetryable("空响应".to_string()));
+        }
+        let reported = (response.prompt_eval_count.is_some() || response.eval_count.is_some()).then(|| Usage {
+            prompt_tokens: response.prompt_eval_count.unwrap_or(0),
+            completion_tokens: response.eval_count.unwrap_or(0),
+            estimated: false,
+        });
+        Ok(Reply {
+            text: response.message.content,
+            usage: reported,
+            truncated: response.done_reason.as_deref() == Some("length"),
+        })
+    }
+}
+
+impl Provider for Ollama {
+    async fn chat_vision(&self, config: &Config, request: &ChatRequest<'_>, timeout: Duration) -> Result<Reply, ApiError> {
+        self.chat(config, request, timeout).await
+    }
+
+    async fn chat_text(&self, config: &Config, request: &ChatRequest<'_>, timeout: Duration) -> Result<Reply, ApiError> {
+        self.chat(config, request, timeout).await
+    }
+
+    /// GET /api/tags lists local models without loading any
+    async fn warm_up(&self, config: &Config) -> Result<(), String> {
+        ping(get_client().get(endpoint(config, "/api/tags"))).await
+    }
+
+    async fn list_models(&self, config: &Config) -> Result<Vec<String>, String> {
+        let mut http = get_client().get(endpoint(config, "/api/tags"));
+        if !config.api_key.is_empty() {
+            http = http.header("Authorization", format!("Bearer {}", config.api_key));
+        }
+        fetch_models(http, "models", "name").await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        pairs.iter().map(|(name, value)| (reqwest::header::HeaderName::from_static(name), value.parse().unwrap())).collect()
+    }
+
+    #[test]
+    fn retry_after_takes_seconds_and_http_dates() {
+        assert_eq!(retry_after(&headers(&[("retry-after", "7")])), Some(Duration::from_secs(7)));
+        assert_eq!(retry_after(&headers(&[("retry-after", "0")])), Some(Duration::ZERO));
+        assert_eq!(retry_after(&headers(&[("retry-after", "86400")])), Some(MAX_RETRY_AFTER));
+        assert_eq!(retry_after(&headers(&[("retry-after-ms", "1500"), ("retry-after", "7")])), Some(Duration::from_millis(1500)));
+
+        let date = (chrono::Utc::now() + chrono::Duration::seconds(30)).to_rfc2822();
+        let wait = retry_after(&headers(&[("retry-after", &date)])).unwrap();
+        assert!(wait > Duration::from_secs(25) && wait <= Duration::from_secs(30), "{:?}", wait);
+        // A date already past means no wait
+        let past = "Wed, 21 Oct 2015 07:28:00 GMT";
+        assert_eq!(retry_after(&headers(&[("retry-after", past)])), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn malformed_retry_after_is_ignored() {
+        for value in ["soon", "-5", "1.5", "", "Wed, 32 Oct 2015 07:28:00 GMT"] {
+            assert_eq!(retry_after(&headers(&[("retry-after", value)])), None, "{}", value);
+        }
+        // The provider's own reset headers still count
+        let reset = headers(&[("retry-after", "soon"), ("x-ratelimit-reset-requests", "1s"), ("x-ratelimit-reset-tokens", "6m0s")]);
+        assert_eq!(retry_after(&reset), Some(Duration::from_secs(120)));
+        let reset = headers(&[("x-ratelimit-reset-tokens", "1.5s"), ("x-ratelimit-reset-requests", "20ms")]);
+        assert_eq!(retry_after(&reset), Some(Duration::from_millis(1500)));
+        assert_eq!(retry_after(&headers(&[("x-ratelimit-reset-tokens", "6 minutes")])), None);
+
+        let error = classify_http_status(StatusCode::TOO_MANY_REQUESTS, &headers(&[("retry-after", "soon")]), "slow down");
+        assert!(matches!(error, ApiError::RateLimited { retry_after: None, .. }));
+    }
+}
@@ -0,0 +1,172 @@
+use pdfium_render::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::error::PdfError;
+use crate::pdf::ImageFormat;
+
+/// Pdfium bound once at startup; None when the library could not be loaded,
+/// in which case rendering falls back to the pdftoppm subprocess
+static PDFIUM: OnceLock<Option<Pdfium>> = OnceLock::new();
+
+/// Built with the `static-pdfium` feature: pdfium is linked into the binary
+const EMBEDDED: bool = cfg!(feature = "static-pdfium");
+
+/// Which renderer pages go through (RENDERER)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Renderer {
+    /// Pdfium when it can be loaded, pdftoppm otherwise
+    #[default]
+    Auto,
+    /// Pdfium only; startup fails without it
+    Pdfium,
+    /// The pdftoppm subprocess, even when pdfium is available
+    Pdftoppm,
+}
+
+impl Renderer {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "auto" => Some(Renderer::Auto),
+            "pdfium" => Some(Renderer::Pdfium),
+            "pdftoppm" | "poppler" => Some(Renderer::Pdftoppm),
+            _ => None,
+        }
+    }
+}
+
+/// Load the pdfium library: built in with the `static-pdfium` feature,
+/// otherwise PDFIUM_PATH (the library file or its directory), then next to
+/// the executable, then the system library path. RENDERER=pdftoppm skips it.
+/// Returns a short description of the renderer in use.
+pub fn init(path: Option<&str>, renderer: Renderer) -> String {
+    if renderer == Renderer::Pdftoppm {
+        let _ = PDFIUM.set(None);
+        return "pdftoppm (RENDERER=pdftoppm)".to_string();
+    }
+    let pdfium = PDFIUM.get_or_init(|| bind(path).map(Pdfium::new));
+    match pdfium {
+        Some(_) if EMBEDDED => "pdfium (embedded)".to_string(),
+        Some(_) => "pdfium (in-process)".to_string(),
+        None if renderer == Renderer::Pdfium => panic!("RENDERER=pdfium but the pdfium library could not be loaded"),
+        None => "pdftoppm (pdfium library not found)".to_string(),
+    }
+}
+
+#[cfg(feature = "static-pdfium")]
+fn bind(path: Option<&str>) -> Option<Box<dyn PdfiumLibraryBindings>> {
+    if path.is_some() {
+        eprintln!("PDFIUM_PATH ignored: pdfium is built into this binary");
+    }
+    Pdfium::bind_to_statically_linked_library().ok()
+}
+
+#[cfg(not(feature = "static-pdfium"))]
+fn bind(path: Option<&str>) -> Option<Box<dyn PdfiumLibraryBindings>> {
+    if let Some(path) = path {
+        let path = Path::new(path);
+        let library = if path.is_dir() {
+            Pdfium::pdfium_platform_library_name_at_path(path)
+        } else {
+            path.to_path_buf()
+        };
+        return match Pdfium::bind_to_library(&library) {
+            Ok(bindings) => Some(bindings),
+            Err(e) => panic!("Failed to load PDFIUM_PATH {}: {}", library.display(), e),
+        };
+    }
+    let beside_exe = std::env::current_exe().ok()
+        .and_then(|exe| exe.parent().map(Pdfium::pdfium_platform_library_name_at_path));
+    beside_exe
+        .and_then(|library| Pdfium::bind_to_library(library).ok())
+        .or_else(|| Pdfium::bind_to_system_library().ok())
+}
+
+pub fn is_available() -> bool {
+    PDFIUM.get().is_some_and(Option::is_some)
+}
+
+/// The pdftoppm executable (PDFTOPPM_PATH); looked up on PATH when unset
+static PDFTOPPM: OnceLock<PathBuf> = OnceLock::new();
+
+/// Use this pdftoppm: the executable itself or the directory holding it, as
+/// in an unpacked poppler release for Windows. Called once at startup.
+pub fn set_pdftoppm(path: &Path) -> Result<PathBuf, String> {
+    let exe = if path.is_dir() {
+        path.join(format!("pdftoppm{}", std::env::consts::EXE_SUFFIX))
+    } else {
+        path.to_path_buf()
+    };
+    if !exe.is_file() {
+        return Err(format!("{} not found", exe.display()));
+    }
+    let _ = PDFTOPPM.set(exe.clone());
+    Ok(exe)
+}
+
+pub fn pdftoppm() -> PathBuf {
+    PDFTOPPM.get().cloned().unwrap_or_else(|| PathBuf::from("pdftoppm"))
+}
+
+/// Where pdftoppm writes the pages it renders (RENDER_TEMP_DIR)
+static TEMP_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Use `dir` for rendering instead of the system temp directory, creating it
+/// if needed. Called once at startup.
+pub fn set_temp_dir(dir: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let _ = TEMP_DIR.set(dir.to_path_buf());
+    Ok(())
+}
+
+pub fn temp_dir() -> PathBuf {
+    TEMP_DIR.get().cloned().unwrap_or_else(std::env::temp_dir)
+}
+
+/// Bytes available to this process on the filesystem holding `dir`; None
+/// where that can't be told (on Windows the space check is skipped)
+#[cfg(unix)]
+pub fn free_space(dir: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` is NUL-terminated and `stat` is a valid place to write to
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    // The field widths differ between platforms
+    #[allow(clippy::unnecessary_cast)]
+    Some((stat.f_bavail as u64).saturating_mul(stat.f_frsize as u64))
+}
+
+#[cfg(not(unix))]
+pub fn free_space(_dir: &Path) -> Option<u64> {
+    None
+}
+
+/// Render pages one at a time as images whose longer side is `scale_to` pixels;
+/// `only_page` limits rendering to a single (1-based) page
+pub fn render_pages(data: &[u8], only_page: Option<usize>, scale_to: u32, format: ImageFormat, quality: u8) -> Result<Vec<(usize, Vec<u8>)>, PdfError> {
+    let pdfium = PDFIUM.get().and_then(Option::as_ref).ok_or(PdfError::NoRenderer)?;
+    let document = pdfium.load_pdf_from_byte_slice(data, None)
+        .map_err(|e| PdfError::Parse(e.to_string()))?;
+    let pages = document.pages();
+    let page_nums: Vec<usize> = match only_page {
+        Some(n) => vec![n],
+        None => (1..=pages.len() as usize).collect(),
+    };
+    let config = PdfRenderConfig::new()
+        .set_maximum_width(scale_to as Pixels)
+        .set_maximum_height(scale_to as Pixels);
+
+    let mut images = Vec::with_capacity(page_nums.len());
+    for page_num in page_nums {
+        let page = pages.get((page_num - 1) as PdfPageIndex)
+            .map_err(|e| PdfError::Render { page: page_num, message: e.to_string() })?;
+        let bitmap = page.render_with_config(&config)
+            .map_err(|e| PdfError::Render { page: page_num, message: e.to_string() })?;
+        let image = crate::pdf::encode_image(&bitmap.as_image(), format, quality)?;
+        images.push((page_num, image));
+    }
+    Ok(images)
+}
@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// What a task cost the server, for correlating heavy documents with load
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    /// Estimate of the most the task held in memory at once: the input with
+    /// its rendered page images, or the generated output PDFs
+    pub peak_memory_bytes: u64,
+    /// Images rendered from the document: pages for OCR (downgraded
+    /// re-renders included) and output previews
+    pub rendered_bytes: u64,
+    /// Request bodies of the page OCR and translation calls
+    pub api_sent_bytes: u64,
+    /// Response bodies of the same calls
+    pub api_received_bytes: u64,
+    /// Size of the task directory when it finished
+    pub disk_bytes: u64,
+}
+
+impl ResourceUsage {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn add_rendered(&mut self, bytes: u64, memory: u64) {
+        self.rendered_bytes += bytes;
+        self.note_memory(memory);
+    }
+
+    pub fn note_memory(&mut self, bytes: u64) {
+        self.peak_memory_bytes = self.peak_memory_bytes.max(bytes);
+    }
+}
+
+/// Bytes moved by the API calls of one piece of work
+#[derive(Default)]
+pub struct Traffic {
+    sent: AtomicU64,
+    received: AtomicU64,
+}
+
+impl Traffic {
+    /// (sent, received)
+    pub fn totals(&self) -> (u64, u64) {
+        (self.sent.load(Ordering::Relaxed), self.received.load(Ordering::Relaxed))
+    }
+}
+
+tokio::task_local! {
+    /// Traffic of the page work running on this task; the API layer counts
+    /// into it the same way it reports heartbeats
+    static TRAFFIC: Arc<Traffic>;
+}
+
+/// Count a request body. Does nothing outside `metered`.
+pub fn count_sent(bytes: usize) {
+    let _ = TRAFFIC.try_with(|t| t.sent.fetch_add(bytes as u64, Ordering::Relaxed));
+}
+
+/// Count a response body or stream chunk. Does nothing outside `metered`.
+pub fn count_received(bytes: usize) {
+    let _ = TRAFFIC.try_with(|t| t.received.fetch_add(bytes as u64, Ordering::Relaxed));
+}
+
+/// Run work with its API traffic counted into `traffic`, which stays
+/// readable even if the work is dropped halfway
+pub async fn metered<F: Future>(traffic: Arc<Traffic>, work: F) -> F::Output {
+    TRAFFIC.scope(traffic, work).await
+}
+
+/// Total size of the files under a directory
+pub fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else { return 0 };
+    entries.flatten().map(|entry| match entry.metadata() {
+        Ok(meta) if meta.is_dir() => dir_size(&entry.path()),
+        Ok(meta) => meta.len(),
+        Err(_) => 0,
+    }).sum()
+}
+
+/// Bytes as megabytes for logs and messages
+pub fn format_size(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / (1 << 20) as f64)
+}
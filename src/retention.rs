@@ -0,0 +1,38 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::resources::format_size;
+use crate::state::AppState;
+
+/// How often expired tasks are looked for
+const SWEEP_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Remove orphaned task directories; before the server accepts uploads,
+/// whose directories have no record yet for a moment
+pub fn sweep_orphans(state: &AppState) {
+    let (removed, reclaimed) = state.sweep_orphans();
+    if removed > 0 {
+        println!("[cleanup] 删除 {} 个无任务记录的目录，释放 {}", removed, format_size(reclaimed));
+    }
+}
+
+/// Delete expired tasks and empty the trash of tasks past TRASH_HOURS for the
+/// life of the server; returns right away when there is nothing to expire
+pub async fn run_cleanup(state: Arc<AppState>) {
+    let config = &state.config;
+    if config.retain_complete_hours.is_none() && config.retain_failed_hours.is_none() && config.trash_hours == 0 {
+        return;
+    }
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        interval.tick().await;
+        let (removed, reclaimed) = state.cleanup_expired();
+        if removed > 0 {
+            println!("[cleanup] 删除 {} 个过期任务，释放 {}", removed, format_size(reclaimed));
+        }
+        let (removed, reclaimed) = state.purge_trash();
+        if removed > 0 {
+            println!("[cleanup] 清除回收站中 {} 个到期任务，释放 {}", removed, format_size(reclaimed));
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use axum::{Json, extract::State};
+use std::sync::Arc;
+
+use super::MAX_FILE_SIZE;
+use super::extract::{Admin, WithinQuota};
+use super::tasks::start_retry;
+use crate::error::AppError;
+use crate::state::{self, AppState};
+use crate::{deadletter, lang, pdf, stats, workers};
+
+pub async fn quota(
+    State(state): State<Arc<AppState>>,
+) -> Json<stats::QuotaStatus> {
+    Json(state.stats.quota_status(&state.config))
+}
+
+/// 描述当前实例支持的能力，供通用客户端自动适配
+pub async fn capabilities(
+    State(state): State<Arc<AppState>>,
+) -> Json<serde_json::Value> {
+    let config = &state.config;
+    Json(serde_json::json!({
+        "output_formats": ["pdf"],
+        "modes": state::TaskMode::ALL.iter().map(|m| m.as_str()).collect::<Vec<_>>(),
+        "layouts": pdf::Layout::ALL.iter().map(|l| l.as_str()).collect::<Vec<_>>(),
+        "output_modes": pdf::OutputMode::ALL.iter().map(|m| m.as_str()).collect::<Vec<_>>(),
+        "romanization": pdf::Romanize::ALL.iter().map(|r| r.as_str()).collect::<Vec<_>>(),
+        "sample_pages": config.sample_pages,
+        "preview_pages": config.preview_pages,
+        "input_formats": ["application/pdf", "image/jpeg", "image/png"],
+        "url_upload": config.url_upload,
+        "remote_sources": config.remote_sources.iter().map(|s| s.as_str()).collect::<Vec<_>>(),
+        "local_only": config.local_only,
+        "privacy_mode": config.privacy_mode,
+        "translate_annotations": config.translate_annotations,
+        "remote_destinations": config.remote_destinations.iter().map(|d| d.as_str()).collect::<Vec<_>>(),
+        "providers": [{
+            "kind": config.provider.as_str(),
+            "ocr_model": config.ocr_model,
+            "ocr_model_fallback": config.ocr_model_fallback,
+            "translate_model": config.translate_model,
+            "translate_model_fallback": config.translate_model_fallback,
+            "allowed_models": config.allowed_models,
+        }],
+        "max_file_size": MAX_FILE_SIZE,
+        "max_pages": serde_json::Value::Null,
+        "max_concurrent_tasks": config.max_concurrent_tasks,
+        "max_background_tasks": config.max_background_tasks,
+        "api_concurrency": config.api_concurrency,
+        "page_concurrency": config.page_concurrency,
+        "target_languages": lang::TargetLang::ALL.iter().map(|l| l.code()).collect::<Vec<_>>(),
+        "default_target_language": config.target_lang.code(),
+        "auth": { "required": false, "admin_header": "X-Admin-Token" },
+        "quota": state.stats.quota_status(config),
+    }))
+}
+
+/// The provider's models (cached), each marked with the role it is configured
+/// for and whether an upload may pick it (ALLOWED_MODELS), for model pickers
+pub async fn models(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let config = &state.config;
+    let list = state.models.get(config, &state.scheduler).await
+        .map_err(|e| AppError::Upstream(format!("获取模型列表失败: {}", e)))?;
+    // Configured models are shown even when the provider doesn't list them
+    let configured = [Some(&config.ocr_model), config.ocr_model_fallback.as_ref(), Some(&config.translate_model), config.translate_model_fallback.as_ref()];
+    let mut ids: Vec<&String> = list.models.iter()
+        .chain(configured.into_iter().flatten())
+        .chain(&config.allowed_models)
+        .collect();
+    ids.sort();
+    ids.dedup();
+    let models: Vec<serde_json::Value> = ids.into_iter().map(|id| serde_json::json!({
+        "id": id,
+        "listed": list.models.contains(id),
+        "ocr": *id == config.ocr_model || config.ocr_model_fallback.as_ref() == Some(id),
+        "translate": *id == config.translate_model || config.translate_model_fallback.as_ref() == Some(id),
+        "selectable": config.allowed_models.contains(id),
+    })).collect();
+    Ok(Json(serde_json::json!({
+        "models": models,
+        "fetched_at": list.fetched_at,
+        "stale": list.stale,
+    })))
+}
+
+/// Current load, for monitoring
+pub async fn metrics(
+    State(state): State<Arc<AppState>>,
+) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "active_tasks": state.active_task_count(),
+        "background_tasks": state.background_task_count(),
+        "cpu_pool": workers::stats(),
+        "storage": state.storage_status(),
+    }))
+}
+
+pub async fn list_dead_letters(
+    State(state): State<Arc<AppState>>,
+    _admin: Admin,
+) -> Json<Vec<deadletter::DeadLetter>> {
+    Json(state.dead_letters.list())
+}
+
+#[derive(serde::Deserialize, Default)]
+pub struct RedriveRequest {
+    /// Only these tasks; all tasks with dead letters when omitted
+    task_ids: Option<Vec<String>>,
+}
+
+/// Retry every task holding dead letters, e.g. after a new API key is set.
+/// Tasks that can't start now (no free slot, no longer failed, input gone)
+/// are reported and keep their entries for a later re-drive.
+pub async fn redrive_dead_letters(
+    State(state): State<Arc<AppState>>,
+    _admin: Admin,
+    _quota: WithinQuota,
+    body: Option<Json<RedriveRequest>>,
+) -> Json<serde_json::Value> {
+    let request = body.map(|Json(r)| r).unwrap_or_default();
+    let mut task_ids = state.dead_letters.task_ids();
+    if let Some(only) = &request.task_ids {
+        task_ids.retain(|id| only.contains(id));
+    }
+    
+    let mut redriven = Vec::new();
+    let mut skipped = Vec::new();
+    for task_id in task_ids {
+        match start_retry(&state, &task_id) {
+            Ok(()) => redriven.push(task_id),
+            Err(e) => skipped.push(serde_json::json!({ "task_id": task_id, "reason": e.to_string() })),
+        }
+    }
+    Json(serde_json::json!({ "redriven": redriven, "skipped": skipped }))
+}
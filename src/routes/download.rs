@@ -0,0 +1,228 @@
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{StatusCode, header},
+    response::Response,
+};
+use std::sync::Arc;
+use tokio_util::io::ReaderStream;
+
+use crate::archive::{ArchiveEntry, stream_zip};
+use crate::error::AppError;
+use crate::state::{self, AppState, TaskMode};
+use crate::export::{TextFormat, export_text};
+use crate::{filename, lang};
+
+#[derive(serde::Deserialize)]
+pub struct DownloadQuery {
+    /// One of the task's target languages; the primary one when absent
+    lang: Option<String>,
+    /// pdf (default), md, txt, or zip for everything the task produced
+    format: Option<String>,
+}
+
+pub async fn download(
+    State(state): State<Arc<AppState>>,
+    Path(task_id): Path<String>,
+    Query(query): Query<DownloadQuery>,
+) -> Result<Response, AppError> {
+    let requested = requested_lang(query.lang.as_deref())?;
+    let format = match query.format.as_deref().map(|f| f.trim().to_ascii_lowercase()).as_deref() {
+        None | Some("" | "pdf") => None,
+        Some("md" | "markdown") => Some(TextFormat::Markdown),
+        Some("txt" | "text") => Some(TextFormat::Plain),
+        Some("zip") => return bundle(&state, &task_id),
+        Some("annotated") => return annotated(&state, &task_id, requested).await,
+        Some(other) => return Err(AppError::BadRequest(format!("不支持的下载格式: {}", other))),
+    };
+    
+    if let Some(format) = format {
+        let options = state.get_options(&task_id).unwrap_or_default();
+        let lang = requested.unwrap_or(options.target_lang);
+        if let Some(progress) = state.get_progress(&task_id)
+            && progress.status == state::TaskStatus::Complete
+            && options.all_langs().contains(&lang)
+        {
+            let text = export_text(&task_id, &progress, &options, lang, format);
+            let name = state.output_name(&task_id, options.output_tag(lang), format.extension());
+            return Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, format.content_type())
+                .header(header::CONTENT_DISPOSITION, filename::content_disposition(&name))
+                .body(Body::from(text))
+                .unwrap());
+        }
+    } else if let Some(path) = state.get_output_path(&task_id, requested)
+        && let Ok(file) = tokio::fs::File::open(&path).await
+    {
+        let size = file.metadata().await.map(|m| m.len()).ok();
+        let options = state.get_options(&task_id).unwrap_or_default();
+        let lang = requested.unwrap_or(options.target_lang);
+        let name = state.output_name(&task_id, options.output_tag(lang), "pdf");
+        let mut response = Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/pdf")
+            .header(header::CONTENT_DISPOSITION, filename::content_disposition(&name));
+        if let Some(size) = size {
+            response = response.header(header::CONTENT_LENGTH, size);
+        }
+        // Streamed from disk, so large documents are never held in memory
+        return Ok(response
+            .body(Body::from_stream(ReaderStream::new(file)))
+            .unwrap());
+    }
+    
+    Err(not_found())
+}
+
+/// `?lang=` of a download; None for the task's primary language
+fn requested_lang(code: Option<&str>) -> Result<Option<lang::TargetLang>, AppError> {
+    match code.filter(|l| !l.is_empty()) {
+        Some(code) => lang::TargetLang::parse(code)
+            .map(Some)
+            .ok_or_else(|| AppError::BadRequest(format!("不支持的目标语言: {}", code))),
+        None => Ok(None),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct PreviewQuery {
+    lang: Option<String>,
+}
+
+/// PNG of page `n` of a finished task's output PDF, rendered on completion
+/// for the first PREVIEW_PAGES pages
+pub async fn output_preview(
+    State(state): State<Arc<AppState>>,
+    Path((task_id, page_num)): Path<(String, usize)>,
+    Query(query): Query<PreviewQuery>,
+) -> Result<Response, AppError> {
+    let requested = requested_lang(query.lang.as_deref())?;
+    let (Some(progress), Some(options)) = (state.get_progress(&task_id), state.get_options(&task_id)) else {
+        return Err(not_found());
+    };
+    let lang = requested.unwrap_or(options.target_lang);
+    if progress.status != state::TaskStatus::Complete || !options.all_langs().contains(&lang) {
+        return Err(not_found());
+    }
+    let path = state::preview_path(&task_id, page_num, options.lang_suffix(lang));
+    let image = tokio::fs::read(&path).await
+        .map_err(|_| AppError::NotFound(format!("第 {} 页没有预览图", page_num)))?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "image/png")
+        .body(Body::from(image))
+        .unwrap())
+}
+
+/// The image page `n` was recognized from, to show the original next to
+/// its text; available once the task's pages are rendered
+pub async fn page_image(
+    State(state): State<Arc<AppState>>,
+    Path((task_id, page_num)): Path<(String, usize)>,
+) -> Result<Response, AppError> {
+    if state.get_progress(&task_id).is_none() {
+        return Err(not_found());
+    }
+    let (path, format) = state::find_page_image(&task_id, page_num)
+        .ok_or_else(|| AppError::NotFound(format!("第 {} 页没有图片", page_num)))?;
+    let image = tokio::fs::read(&path).await
+        .map_err(|_| AppError::NotFound(format!("第 {} 页没有图片", page_num)))?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, format.mime_type())
+        .body(Body::from(image))
+        .unwrap())
+}
+
+/// The source PDF with its comments and text form fields translated, made
+/// with TRANSLATE_ANNOTATIONS when it has any
+async fn annotated(state: &AppState, task_id: &str, requested: Option<lang::TargetLang>) -> Result<Response, AppError> {
+    let (Some(progress), Some(options)) = (state.get_progress(task_id), state.get_options(task_id)) else {
+        return Err(not_found());
+    };
+    let lang = requested.unwrap_or(options.target_lang);
+    if progress.status != state::TaskStatus::Complete || !options.all_langs().contains(&lang) {
+        return Err(not_found());
+    }
+    let pdf_data = tokio::fs::read(state::annotated_path(task_id, options.lang_suffix(lang))).await
+        .map_err(|_| AppError::NotFound("该任务没有批注或表单译文".to_string()))?;
+    let name = state.output_name(task_id, &annotated_tag(&options, lang), "pdf");
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/pdf")
+        .header(header::CONTENT_DISPOSITION, filename::content_disposition(&name))
+        .body(Body::from(pdf_data))
+        .unwrap())
+}
+
+fn annotated_tag(options: &state::TaskOptions, lang: lang::TargetLang) -> String {
+    format!("{}_annotated", options.output_tag(lang))
+}
+
+/// All of a finished task in one ZIP: the processed source document, each
+/// language's PDF and Markdown, the OCR and translated text of every page and
+/// the checksum manifest
+fn bundle(state: &AppState, task_id: &str) -> Result<Response, AppError> {
+    let (Some(progress), Some(options)) = (state.get_progress(task_id), state.get_options(task_id)) else {
+        return Err(not_found());
+    };
+    if progress.status != state::TaskStatus::Complete {
+        return Err(not_found());
+    }
+    let langs = options.all_langs();
+    let mut entries = vec![ArchiveEntry::File { name: progress.filename.clone(), path: state::input_path(task_id) }];
+    for &lang in &langs {
+        if let Some(path) = state.get_output_path(task_id, Some(lang)) {
+            entries.push(ArchiveEntry::File { name: state.output_name(task_id, options.output_tag(lang), "pdf"), path });
+        }
+        let annotated = state::annotated_path(task_id, options.lang_suffix(lang));
+        if annotated.exists() {
+            entries.push(ArchiveEntry::File { name: state.output_name(task_id, &annotated_tag(&options, lang), "pdf"), path: annotated });
+        }
+        let format = TextFormat::Markdown;
+        entries.push(ArchiveEntry::Text {
+            name: state.output_name(task_id, options.output_tag(lang), format.extension()),
+            text: export_text(task_id, &progress, &options, lang, format),
+        });
+    }
+    // Pages are named by their number in the uploaded file
+    let pattern = &state.config.page_file_pattern;
+    let stem = filename::stem(&progress.filename);
+    for page_num in 1..=progress.total_pages {
+        let source_page = progress.source_page(page_num);
+        entries.push(ArchiveEntry::File {
+            name: format!("pages/{}", pattern.render(source_page, stem, "ocr")),
+            path: state::page_ocr_path(task_id, page_num),
+        });
+        if options.mode == TaskMode::Ocr {
+            continue;
+        }
+        for &lang in &langs {
+            entries.push(ArchiveEntry::File {
+                name: format!("pages/{}", pattern.render(source_page, stem, lang.code())),
+                path: state::page_translation_path(task_id, page_num, options.lang_suffix(lang)),
+            });
+        }
+    }
+
+    // Checksums of everything above, to check the bundle against
+    if let Some(manifest) = state.get_manifest(task_id)
+        && let Ok(text) = serde_json::to_string_pretty(&manifest)
+    {
+        entries.push(ArchiveEntry::Text { name: "manifest.json".to_string(), text });
+    }
+
+    let codes: Vec<&str> = langs.iter().map(|l| options.output_tag(*l)).collect();
+    let name = state.output_name(task_id, &codes.join("_"), "zip");
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/zip")
+        .header(header::CONTENT_DISPOSITION, filename::content_disposition(&name))
+        .body(stream_zip(entries))
+        .unwrap())
+}
+
+fn not_found() -> AppError {
+    AppError::NotFound("Not found".to_string())
+}
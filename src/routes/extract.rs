@@ -0,0 +1,88 @@
+use axum::extract::{FromRequestParts, OptionalFromRequestParts};
+use axum::http::{HeaderMap, header, request::Parts};
+use std::sync::Arc;
+use subtle::ConstantTimeEq;
+
+use crate::error::AppError;
+use crate::state::AppState;
+
+/// Guard for routes that start API work: rejects with 429 once the monthly
+/// quota is used up. Requests carrying the configured X-Admin-Token pass anyway.
+pub struct WithinQuota;
+
+impl FromRequestParts<Arc<AppState>> for WithinQuota {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        let status = state.stats.quota_status(&state.config);
+        if status.exhausted && !is_admin(state, &parts.headers) {
+            return Err(AppError::QuotaExceeded(format!(
+                "本月用量配额已用完 ({} tokens)，将于 {} 重置", status.tokens_used, status.resets_on,
+            )));
+        }
+        Ok(WithinQuota)
+    }
+}
+
+/// Guard for admin-only routes: the request must carry the configured
+/// X-Admin-Token. Without ADMIN_TOKEN set these routes are closed.
+pub struct Admin;
+
+impl FromRequestParts<Arc<AppState>> for Admin {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        if state.config.admin_token.is_none() {
+            return Err(AppError::Forbidden("未配置 ADMIN_TOKEN，管理接口不可用".to_string()));
+        }
+        if !is_admin(state, &parts.headers) {
+            return Err(AppError::Unauthorized("需要管理员令牌 (X-Admin-Token)".to_string()));
+        }
+        Ok(Admin)
+    }
+}
+
+/// Compared in constant time, so response timing doesn't reveal how much of a
+/// guessed token was right
+fn is_admin(state: &AppState, headers: &HeaderMap) -> bool {
+    match (&state.config.admin_token, headers.get("x-admin-token")) {
+        (Some(token), Some(value)) => value.as_bytes().ct_eq(token.as_bytes()).into(),
+        _ => false,
+    }
+}
+
+/// The user a request acts as, named by its `Authorization: Bearer <token>`
+/// header (USER_TOKENS). As `Option<User>` an anonymous request passes too,
+/// but a token that matches no user is still rejected.
+pub struct User(pub String);
+
+impl FromRequestParts<Arc<AppState>> for User {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        user_of(state, &parts.headers)?
+            .ok_or_else(|| AppError::Unauthorized("需要用户令牌 (Authorization: Bearer ...)".to_string()))
+    }
+}
+
+impl OptionalFromRequestParts<Arc<AppState>> for User {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<AppState>) -> Result<Option<Self>, Self::Rejection> {
+        user_of(state, &parts.headers)
+    }
+}
+
+pub fn user_of(state: &AppState, headers: &HeaderMap) -> Result<Option<User>, AppError> {
+    let Some(value) = headers.get(header::AUTHORIZATION) else {
+        return Ok(None);
+    };
+    let token = value.to_str().ok()
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim)
+        .unwrap_or_default();
+    state.config.user_tokens.iter()
+        .find(|(_, t)| !token.is_empty() && t.as_bytes().ct_eq(token.as_bytes()).into())
+        .map(|(name, _)| Some(User(name.clone())))
+        .ok_or_else(|| AppError::Unauthorized("无效的用户令牌".to_string()))
+}
@@ -0,0 +1,286 @@
+use axum::{
+    Json,
+    extract::{Multipart, Path, State},
+    http::{StatusCode, header},
+    response::Response,
+};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::MAX_FILE_SIZE;
+use super::extract::{User, WithinQuota};
+use super::upload::{UploadForm, accept_form, apply_preferences, read_text_field, start_task, task_options};
+use crate::archive::{ArchiveEntry, stream_zip};
+use crate::error::{AppError, StorageError};
+use crate::filename;
+use crate::job::{self, Job, JobFile};
+use crate::state::{self, AppState, TaskStatus};
+
+/// How often the runner looks for queued files it can start
+const TICK_SECS: u64 = 3;
+
+/// Upload a ZIP archive of PDFs and images with the upload form's option
+/// fields. Every document becomes a task with those options, started as
+/// task slots free up.
+pub async fn create_job(
+    State(state): State<Arc<AppState>>,
+    _quota: WithinQuota,
+    user: Option<User>,
+    mut multipart: Multipart,
+) -> Result<Json<JobStatus>, AppError> {
+    // Refused before the archive is read when the disk budget is already spent
+    state.ensure_storage()?;
+    let mut archive = None;
+    let mut fields = Vec::new();
+    while let Some(mut field) = multipart.next_field().await
+        .map_err(|e| AppError::BadRequest(format!("Multipart error: {}", e)))?
+    {
+        match field.name() {
+            Some("file") => {
+                let name = filename::sanitize(field.file_name().unwrap_or_default());
+                let mut data = Vec::new();
+                while let Some(chunk) = field.chunk().await
+                    .map_err(|e| AppError::BadRequest(format!("Read error: {}", e)))?
+                {
+                    if data.len() + chunk.len() > MAX_FILE_SIZE {
+                        return Err(AppError::TooLarge);
+                    }
+                    data.extend_from_slice(&chunk);
+                }
+                archive = Some((name, data));
+            }
+            Some(name) if UploadForm::TEXT_FIELDS.contains(&name) => {
+                let name = name.to_string();
+                fields.push((name, read_text_field(field).await?));
+            }
+            _ => {}
+        }
+    }
+    let (archive_name, data) = archive.ok_or_else(|| AppError::BadRequest("No file uploaded".to_string()))?;
+    if !data.starts_with(b"PK\x03\x04") {
+        return Err(AppError::BadRequest("无效的文件，批量任务仅支持 ZIP".to_string()));
+    }
+    let mut form = UploadForm::from_fields(&fields);
+    apply_preferences(&state, &mut form, user.as_ref());
+    if form.source.as_deref().is_some_and(|s| !s.is_empty()) {
+        return Err(AppError::BadRequest("批量任务的文件取自 ZIP，不支持 source".to_string()));
+    }
+    task_options(&state, &form).map_err(AppError::BadRequest)?;
+    form.page_ranges().map_err(AppError::BadRequest)?;
+
+    let job_id = uuid::Uuid::new_v4().to_string();
+    let (names, skipped) = match job::extract_documents(data, &job_id, MAX_FILE_SIZE).await {
+        Ok((names, _)) if names.is_empty() => Err("ZIP 中没有 PDF 或图片文件".to_string()),
+        result => result,
+    }
+    .map_err(|e| {
+        job::remove_queued_files(&job_id);
+        AppError::BadRequest(e)
+    })?;
+    // The unpacked documents count toward the budget
+    state.ensure_storage().inspect_err(|_| job::remove_queued_files(&job_id))?;
+    let job = Job {
+        id: job_id,
+        filename: archive_name,
+        fields,
+        user: user.map(|User(name)| name),
+        created_at: state::now_ms(),
+        files: names.into_iter()
+            .map(|name| JobFile { name, task_id: uuid::Uuid::new_v4().to_string(), started: false, error: None })
+            .collect(),
+        skipped,
+    };
+    state.jobs.add(job.clone());
+    Ok(Json(job_status(&state, &job)))
+}
+
+pub async fn get_job(
+    State(state): State<Arc<AppState>>,
+    Path(job_id): Path<String>,
+) -> Result<Json<JobStatus>, AppError> {
+    let job = state.jobs.get(&job_id)
+        .ok_or_else(|| AppError::NotFound("批量任务不存在".to_string()))?;
+    Ok(Json(job_status(&state, &job)))
+}
+
+/// The translated PDFs of the job's completed tasks, every target language,
+/// in one ZIP
+pub async fn download_job(
+    State(state): State<Arc<AppState>>,
+    Path(job_id): Path<String>,
+) -> Result<Response, AppError> {
+    let job = state.jobs.get(&job_id)
+        .ok_or_else(|| AppError::NotFound("批量任务不存在".to_string()))?;
+    let mut used = HashSet::new();
+    let mut entries = Vec::new();
+    for file in job.files.iter().filter(|f| f.started) {
+        let (Some(progress), Some(options)) = (state.get_progress(&file.task_id), state.get_options(&file.task_id)) else { continue };
+        if progress.status != TaskStatus::Complete {
+            continue;
+        }
+        for lang in options.all_langs() {
+            let Some(path) = state.get_output_path(&file.task_id, Some(lang)) else { continue };
+            let name = unique_name(&mut used, state.output_name(&file.task_id, options.output_tag(lang), "pdf"));
+            entries.push(ArchiveEntry::File { name, path });
+        }
+    }
+    if entries.is_empty() {
+        return Err(AppError::NotFound("批量任务还没有已完成的文件".to_string()));
+    }
+    let stem = job.filename.strip_suffix(".zip").unwrap_or(&job.filename);
+    let name = format!("{}_translated.zip", stem);
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/zip")
+        .header(header::CONTENT_DISPOSITION, filename::content_disposition(&name))
+        .body(stream_zip(entries))
+        .unwrap())
+}
+
+/// `name`, or `name (2)` etc. when an earlier file already took it
+fn unique_name(used: &mut HashSet<String>, name: String) -> String {
+    let mut candidate = name.clone();
+    let mut n = 1;
+    while !used.insert(candidate.clone()) {
+        n += 1;
+        candidate = match name.rsplit_once('.') {
+            Some((stem, ext)) => format!("{} ({}).{}", stem, n, ext),
+            None => format!("{} ({})", name, n),
+        };
+    }
+    candidate
+}
+
+#[derive(Serialize)]
+pub struct JobStatus {
+    pub job_id: String,
+    pub filename: String,
+    pub created_at: u64,
+    /// `Processing` while any task is queued or running, then `Complete`, or
+    /// `Error` when no task completed
+    pub status: &'static str,
+    /// Average over the tasks; finished ones count as 100
+    pub overall_percent: u8,
+    pub total: usize,
+    pub queued: usize,
+    pub running: usize,
+    pub complete: usize,
+    pub failed: usize,
+    /// Archive entries that are not a PDF or an image
+    pub skipped: Vec<String>,
+    pub fields: Vec<(String, String)>,
+    pub tasks: Vec<JobTaskStatus>,
+}
+
+#[derive(Serialize)]
+pub struct JobTaskStatus {
+    pub name: String,
+    pub task_id: String,
+    /// The task's status, or `Queued` until it starts
+    pub status: String,
+    pub overall_percent: u8,
+    pub message: String,
+}
+
+fn job_status(state: &AppState, job: &Job) -> JobStatus {
+    let tasks: Vec<JobTaskStatus> = job.files.iter().map(|file| {
+        let (status, overall_percent, message) = match (&file.error, file.started) {
+            (Some(error), _) => ("Error".to_string(), 100, error.clone()),
+            (None, false) => ("Queued".to_string(), 0, "等待空闲的任务槽".to_string()),
+            (None, true) => match state.get_progress(&file.task_id) {
+                Some(p) if p.is_done() => (format!("{:?}", p.status), 100, p.message),
+                Some(p) => (format!("{:?}", p.status), p.overall_percent, p.message),
+                None => ("Error".to_string(), 100, "任务已删除".to_string()),
+            },
+        };
+        JobTaskStatus { name: file.name.clone(), task_id: file.task_id.clone(), status, overall_percent, message }
+    }).collect();
+
+    let count = |status: &str| tasks.iter().filter(|t| t.status == status).count();
+    let (queued, complete) = (count("Queued"), count("Complete") + count("Skipped"));
+    let failed = count("Error");
+    let running = tasks.len() - queued - complete - failed;
+    let status = if queued + running > 0 {
+        "Processing"
+    } else if complete == 0 {
+        "Error"
+    } else {
+        "Complete"
+    };
+    let overall_percent = (tasks.iter().map(|t| t.overall_percent as usize).sum::<usize>() / tasks.len().max(1)) as u8;
+    let job = job.redacted();
+    JobStatus {
+        job_id: job.id,
+        filename: job.filename,
+        created_at: job.created_at,
+        status,
+        overall_percent,
+        total: tasks.len(),
+        queued,
+        running,
+        complete,
+        failed,
+        skipped: job.skipped,
+        fields: job.fields,
+        tasks,
+    }
+}
+
+/// Start queued files of batch jobs as task slots free up, oldest job first.
+/// The only place job files are started, so none starts twice.
+pub async fn run_jobs(state: Arc<AppState>) {
+    loop {
+        start_queued(&state).await;
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(TICK_SECS)) => {}
+            _ = state.jobs.added() => {}
+        }
+    }
+}
+
+async fn start_queued(state: &Arc<AppState>) {
+    for job in state.jobs.queued() {
+        let mut form = UploadForm::from_fields(&job.fields);
+        apply_preferences(state, &mut form, job.user.clone().map(User).as_ref());
+        let background = task_options(state, &form).is_ok_and(|o| o.background);
+        for (index, file) in job.files.iter().enumerate() {
+            if file.started || file.error.is_some() {
+                continue;
+            }
+            if !state.has_free_slot(background) {
+                break;
+            }
+            let path = job::queued_file_path(&job.id, index);
+            let saved = std::fs::read(&path)
+                .map_err(StorageError::ReadInput)
+                .and_then(|data| state::save_input_pdf(&file.task_id, &data).map_err(StorageError::SaveInput));
+            form.file = Some(filename::sanitize(&file.name));
+            let accepted = match saved {
+                Ok(()) => accept_form(state, &file.task_id, &form).await,
+                Err(e) => Err(e.into()),
+            };
+            match accepted {
+                Ok(task) => {
+                    let task_id = start_task(state, task);
+                    state.add_log(&task_id, format!("由批量任务 {} 创建", job.id));
+                    state.jobs.update_file(&job.id, index, |f| f.started = true);
+                }
+                // Another upload took the slot first; try again on the next tick
+                Err(AppError::Busy(_)) => {
+                    state::cleanup_task_files(&file.task_id);
+                    break;
+                }
+                Err(e) => {
+                    state::cleanup_task_files(&file.task_id);
+                    state.jobs.update_file(&job.id, index, |f| f.error = Some(e.to_string()));
+                }
+            }
+            let _ = std::fs::remove_file(path);
+        }
+        if !state.jobs.queued().iter().any(|j| j.id == job.id) {
+            job::remove_queued_files(&job.id);
+        }
+    }
+}
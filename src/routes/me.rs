@@ -0,0 +1,49 @@
+use axum::{Json, extract::State};
+use serde_json::{Map, Value};
+use std::sync::Arc;
+
+use super::extract::User;
+use super::upload::{UploadForm, json_fields, task_options};
+use crate::error::AppError;
+use crate::preferences::PREFERENCE_FIELDS;
+use crate::state::AppState;
+
+pub async fn get_preferences(
+    State(state): State<Arc<AppState>>,
+    User(user): User,
+) -> Json<Value> {
+    let fields = state.preferences.get(&user);
+    Json(preferences_json(&user, &fields))
+}
+
+/// Replace the user's upload defaults with the JSON body's `target_lang`,
+/// `layout`, `output` and `romanize`; an empty body clears them
+pub async fn put_preferences(
+    State(state): State<Arc<AppState>>,
+    User(user): User,
+    Json(body): Json<Map<String, Value>>,
+) -> Result<Json<Value>, AppError> {
+    if let Some(name) = body.keys().find(|k| !PREFERENCE_FIELDS.contains(&k.as_str())) {
+        return Err(AppError::BadRequest(format!("不支持的偏好设置: {}", name)));
+    }
+    let mut fields = json_fields(&body, &[]).map_err(AppError::BadRequest)?;
+    fields.retain(|(_, value)| !value.is_empty());
+    task_options(&state, &UploadForm::from_fields(&fields)).map_err(AppError::BadRequest)?;
+    state.preferences.set(&user, fields.clone());
+    Ok(Json(preferences_json(&user, &fields)))
+}
+
+/// Fields as a JSON object, a repeated field (several languages) as a list
+fn preferences_json(user: &str, fields: &[(String, String)]) -> Value {
+    let mut preferences = Map::new();
+    for (name, value) in fields {
+        match preferences.get_mut(name) {
+            Some(Value::Array(values)) => values.push(value.clone().into()),
+            Some(first) => *first = Value::Array(vec![first.clone(), value.clone().into()]),
+            None => {
+                preferences.insert(name.clone(), value.clone().into());
+            }
+        }
+    }
+    serde_json::json!({ "user": user, "preferences": preferences })
+}
@@ -0,0 +1,140 @@
+//! HTTP API: `app()` builds the router, one module per group of routes.
+//! JSON endpoints live under /api/v1; their old unversioned paths remain as
+//! deprecated aliases.
+
+mod admin;
+mod download;
+mod extract;
+mod jobs;
+mod me;
+mod progress;
+mod schedules;
+mod tasks;
+mod text;
+mod upload;
+
+use axum::{
+    Router,
+    body::Body,
+    extract::{DefaultBodyLimit, Request, State},
+    http::{HeaderValue, StatusCode, header},
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Response},
+    routing::{delete, get, post, put},
+};
+use std::sync::Arc;
+use tower_http::cors::CorsLayer;
+
+use crate::error::AppError;
+use crate::state::AppState;
+
+pub use jobs::run_jobs;
+pub use schedules::run_schedules;
+
+pub const MAX_FILE_SIZE: usize = 50 * 1024 * 1024;
+/// Room for the other form fields and multipart boundaries
+const UPLOAD_FORM_SLACK: usize = 1024 * 1024;
+
+/// Version of the JSON API, reported in every response
+pub const API_VERSION: &str = "v1";
+
+/// The full router; the index page is only served when the built-in UI is enabled
+pub fn app(state: Arc<AppState>) -> Router {
+    let mut app = Router::new()
+        .route("/status/{token}", get(tasks::public_status_page));
+    if state.config.index_page.is_some() {
+        app = app.route("/", get(index));
+    }
+    app
+        .nest("/api/v1", api())
+        .merge(api().layer(middleware::from_fn(deprecated_alias)))
+        .layer(CorsLayer::very_permissive())
+        .with_state(state)
+}
+
+/// JSON endpoints, relative to the API root
+fn api() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/upload", post(upload::upload).layer(DefaultBodyLimit::max(MAX_FILE_SIZE + UPLOAD_FORM_SLACK)))
+        .route("/upload-url", post(upload::upload_url))
+        .route("/translate-text", post(text::translate_text))
+        .route("/progress/{task_id}", get(progress::progress))
+        .route("/cancel/{task_id}", post(tasks::cancel))
+        .route("/retry/{task_id}", post(tasks::retry_task))
+        .route("/tasks/{task_id}/approve", post(tasks::approve_task))
+        .route("/tasks/{task_id}/regenerate", post(tasks::regenerate))
+        .route("/tasks/{task_id}/regenerate-output", post(tasks::regenerate_output))
+        .route("/download/{task_id}", get(download::download))
+        .route("/tasks", get(tasks::list_tasks))
+        .route("/tasks/trash", get(tasks::list_trash))
+        .route("/tasks/{task_id}", delete(tasks::delete_task))
+        .route("/tasks/{task_id}/restore", post(tasks::restore_task))
+        .route("/tasks/{task_id}/pages/{page_num}", get(tasks::get_page_detail).put(tasks::edit_page))
+        .route("/tasks/{task_id}/pages/{page_num}/ocr", put(tasks::edit_page_ocr))
+        .route("/tasks/{task_id}/pages/{page_num}/image", get(download::page_image))
+        .route("/tasks/{task_id}/share", post(tasks::share_task).delete(tasks::unshare_task))
+        .route("/tasks/{task_id}/output/preview/{page_num}", get(download::output_preview))
+        .route("/tasks/{task_id}/verify", get(tasks::verify_task))
+        .route("/status/{token}/data", get(tasks::public_status))
+        .route("/me/preferences", get(me::get_preferences).put(me::put_preferences))
+        .route("/capabilities", get(admin::capabilities))
+        .route("/models", get(admin::models))
+        .route("/quota", get(admin::quota))
+        .route("/metrics", get(admin::metrics))
+        .route("/dead-letters", get(admin::list_dead_letters))
+        .route("/dead-letters/redrive", post(admin::redrive_dead_letters))
+        .route("/schedules", get(schedules::list_schedules).post(schedules::create_schedule))
+        .route("/schedules/{id}", delete(schedules::delete_schedule))
+        .route("/jobs", post(jobs::create_job).layer(DefaultBodyLimit::max(MAX_FILE_SIZE + UPLOAD_FORM_SLACK)))
+        .route("/jobs/{job_id}", get(jobs::get_job))
+        .route("/jobs/{job_id}/download", get(jobs::download_job))
+        .layer(middleware::from_fn(tag_version))
+}
+
+/// Add `api_version` to JSON object responses, and an `Api-Version` header to
+/// every response (arrays and streams can't carry the field)
+async fn tag_version(request: Request, next: Next) -> Response {
+    let mut response = next.run(request).await;
+    response.headers_mut().insert("api-version", HeaderValue::from_static(API_VERSION));
+    let is_json = response.headers().get(header::CONTENT_TYPE)
+        .is_some_and(|v| v.as_bytes().starts_with(b"application/json"));
+    if !is_json {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, usize::MAX).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    let body = match serde_json::from_slice::<serde_json::Value>(&bytes) {
+        Ok(serde_json::Value::Object(mut object)) => {
+            object.insert("api_version".to_string(), API_VERSION.into());
+            serde_json::to_vec(&object).map(Body::from).unwrap_or_else(|_| Body::from(bytes))
+        }
+        _ => Body::from(bytes),
+    };
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, body)
+}
+
+/// Mark a request to an unversioned path as deprecated and point at its successor
+async fn deprecated_alias(request: Request, next: Next) -> Response {
+    let successor = format!("</api/{}{}>; rel=\"successor-version\"", API_VERSION, request.uri().path());
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert("deprecation", HeaderValue::from_static("true"));
+    if let Ok(link) = HeaderValue::from_str(&successor) {
+        headers.insert(header::LINK, link);
+    }
+    response
+}
+
+async fn index(State(state): State<Arc<AppState>>) -> Html<String> {
+    Html(state.config.index_page.clone().unwrap_or_default())
+}
+
+fn busy_error(state: &AppState, background: bool) -> AppError {
+    AppError::Busy(state.busy_message(background))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,52 @@
+use axum::{
+    extract::{Path, State},
+    response::Sse,
+};
+use serde_json::Value;
+use std::sync::Arc;
+
+use crate::state::{AppState, TaskProgress};
+use crate::translator::ProgressEvent;
+
+pub async fn progress(
+    State(state): State<Arc<AppState>>,
+    Path(task_id): Path<String>,
+) -> Sse<impl tokio_stream::Stream<Item = Result<axum::response::sse::Event, std::convert::Infallible>>> {
+    let stream = async_stream::stream! {
+        let Some(mut updates) = state.subscribe(&task_id) else {
+            let event = axum::response::sse::Event::default()
+                .data(r#"{"status":"Error","message":"任务不存在"}"#);
+            yield Ok(event);
+            return;
+        };
+        // Current state first, then one event per change; changes made while
+        // an event is being sent are coalesced into the next one
+        let mut previous: Option<TaskProgress> = None;
+        while let Some(progress) = state.get_progress(&task_id) {
+            let is_done = progress.is_done();
+            for (name, data) in typed_events(previous.as_ref(), &progress) {
+                yield Ok(axum::response::sse::Event::default().event(name).data(data.to_string()));
+            }
+            let event = axum::response::sse::Event::default()
+                .data(serde_json::to_string(&progress).unwrap_or_default());
+            yield Ok(event);
+            previous = Some(progress);
+
+            if is_done || updates.changed().await.is_err() {
+                break;
+            }
+        }
+    };
+
+    // Comment lines keep proxies from closing a quiet stream
+    Sse::new(stream).keep_alive(axum::response::sse::KeepAlive::default())
+}
+
+/// Named events for what changed since the previous snapshot, so clients can
+/// react to a failed page without diffing snapshots themselves. The first
+/// snapshot is the baseline and only gets a `status` event.
+pub fn typed_events(previous: Option<&TaskProgress>, current: &TaskProgress) -> Vec<(&'static str, Value)> {
+    ProgressEvent::changes(previous, current).iter()
+        .map(|event| (event.name(), event.data()))
+        .collect()
+}
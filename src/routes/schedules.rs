@@ -0,0 +1,196 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::extract::WithinQuota;
+use super::upload::{UploadForm, accept_form, json_fields, remote_source, start_task, task_options};
+use crate::connector::{self, SourceKind};
+use crate::error::AppError;
+use crate::schedule::{Cron, ScheduledJob};
+use crate::state::{self, AppState, TaskOptions};
+
+/// How often the runner looks for due jobs
+const TICK_SECS: u64 = 20;
+
+pub async fn list_schedules(
+    State(state): State<Arc<AppState>>,
+) -> Json<Vec<ScheduledJob>> {
+    Json(state.schedules.list().iter().map(ScheduledJob::redacted).collect())
+}
+
+/// Register a job from `cron`, an optional `incremental` flag and the upload
+/// form fields a run submits (`source` is required); a field given as a list
+/// repeats
+pub async fn create_schedule(
+    State(state): State<Arc<AppState>>,
+    _quota: WithinQuota,
+    Json(body): Json<serde_json::Map<String, Value>>,
+) -> Result<Json<ScheduledJob>, AppError> {
+    let expr = body.get("cron").and_then(Value::as_str).map(str::trim).unwrap_or_default();
+    if expr.is_empty() {
+        return Err(AppError::BadRequest("缺少 cron 表达式".to_string()));
+    }
+    let cron = Cron::parse(expr).ok_or_else(|| AppError::BadRequest(format!("无效的 cron 表达式: {}", expr)))?;
+
+    let incremental = match body.get("incremental") {
+        None | Some(Value::Null) => false,
+        Some(Value::Bool(b)) => *b,
+        Some(_) => return Err(AppError::BadRequest("字段 incremental 的值无效".to_string())),
+    };
+
+    let fields = json_fields(&body, &["cron", "incremental"]).map_err(AppError::BadRequest)?;
+    let form = UploadForm::from_fields(&fields);
+    let (kind, options) = validate(&state, &form).map_err(AppError::BadRequest)?;
+    // Every run checks these again, as the addresses may resolve differently by then
+    connector::check_source(&remote_source(&form, kind)).await.map_err(AppError::Forbidden)?;
+    if let Some(destination) = &options.destination {
+        destination.check_public().await.map_err(AppError::Forbidden)?;
+    }
+
+    let now = state::now_ms();
+    let next_run = cron.next_after(now)
+        .ok_or_else(|| AppError::BadRequest(format!("cron 表达式不会触发: {}", expr)))?;
+    let job = ScheduledJob {
+        id: uuid::Uuid::new_v4().to_string(),
+        cron: expr.to_string(),
+        fields,
+        incremental,
+        created_at: now,
+        next_run,
+        last_run: None,
+        last_task_id: None,
+        last_hash: None,
+        last_result: None,
+    };
+    state.schedules.add(job.clone());
+    Ok(Json(job.redacted()))
+}
+
+pub async fn delete_schedule(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, AppError> {
+    if state.schedules.remove(&id) {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::NotFound("定时任务不存在".to_string()))
+    }
+}
+
+/// Catch what would fail every run before the job is stored; returns the
+/// source kind and the task options
+fn validate(state: &AppState, form: &UploadForm) -> Result<(SourceKind, TaskOptions), String> {
+    let options = task_options(state, form)?;
+    form.page_ranges()?;
+    let source = form.source.as_deref().filter(|s| !s.is_empty())
+        .ok_or_else(|| "定时任务需要用 source 指定远程来源".to_string())?;
+    let kind = SourceKind::parse(source)
+        .filter(|k| state.config.remote_sources.contains(k))
+        .ok_or_else(|| format!("不支持或未启用的远程来源: {}", source))?;
+    Ok((kind, options))
+}
+
+/// Start due jobs for the life of the server
+pub async fn run_schedules(state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(TICK_SECS));
+    loop {
+        interval.tick().await;
+        for job in state.schedules.due(state::now_ms()) {
+            run_job(&state, job).await;
+        }
+    }
+}
+
+/// Fetch the job's document and translate it, unless it is unchanged since
+/// the last run. The upload path refuses a source or destination that now
+/// leads into the server's network. A run that finds no free task slot
+/// stays due.
+async fn run_job(state: &Arc<AppState>, job: ScheduledJob) {
+    let now = state::now_ms();
+    let task_id = uuid::Uuid::new_v4().to_string();
+    let mut form = UploadForm::from_fields(&job.fields);
+    if job.incremental
+        && let Some(last) = &job.last_task_id
+        && state.get_options(last).is_some()
+    {
+        form.set_field("previous_task", last.clone());
+    }
+    let mut started = None;
+    let mut hash = job.last_hash.clone();
+    let mut due = false;
+    let result = match accept_form(state, &task_id, &form).await {
+        Ok(task) => {
+            let digest = format!("{:x}", Sha256::digest(&task.data));
+            if hash.as_deref() == Some(digest.as_str()) {
+                state.release_task_slot(task.options.background);
+                state::cleanup_task_files(&task_id);
+                "内容未变化，已跳过".to_string()
+            } else {
+                hash = Some(digest);
+                let task_id = start_task(state, task);
+                state.add_log(&task_id, format!("由定时任务 {} 创建", job.id));
+                started = Some(task_id.clone());
+                format!("已创建任务 {}", task_id)
+            }
+        }
+        Err(e) => {
+            state::cleanup_task_files(&task_id);
+            due = e.status() == StatusCode::TOO_MANY_REQUESTS;
+            e.to_string()
+        }
+    };
+    println!("[schedule] {}: {}", job.id, result);
+
+    let next_run = if due {
+        job.next_run
+    } else {
+        Cron::parse(&job.cron).and_then(|c| c.next_after(now)).unwrap_or(u64::MAX)
+    };
+    state.schedules.update(&job.id, |job| {
+        job.next_run = next_run;
+        job.last_run = Some(now);
+        job.last_hash = hash;
+        job.last_result = Some(result);
+        if started.is_some() {
+            job.last_task_id = started;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::testing::config;
+
+    #[tokio::test]
+    async fn runs_refuse_internal_addresses() {
+        // Stored before the checks existed, or pointing at a host that has
+        // since moved into the network
+        let state = Arc::new(AppState::new(Config { remote_sources: vec![SourceKind::S3], ..config() }));
+        let job = ScheduledJob {
+            id: uuid::Uuid::new_v4().to_string(),
+            cron: "0 8 * * 1".to_string(),
+            fields: vec![("source".to_string(), "s3".to_string()), ("url".to_string(), "http://169.254.169.254/latest/meta-data/".to_string())],
+            incremental: false,
+            created_at: 0,
+            next_run: 0,
+            last_run: None,
+            last_task_id: None,
+            last_hash: None,
+            last_result: None,
+        };
+        state.schedules.add(job.clone());
+        run_job(&state, job.clone()).await;
+        let stored = state.schedules.list().into_iter().find(|j| j.id == job.id).unwrap();
+        state.schedules.remove(&job.id);
+        assert!(stored.last_result.as_deref().is_some_and(|r| r.contains("不允许访问内网地址")), "{:?}", stored.last_result);
+        assert!(stored.last_task_id.is_none());
+    }
+}
@@ -0,0 +1,295 @@
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse},
+};
+use std::sync::Arc;
+
+use super::busy_error;
+use super::extract::WithinQuota;
+use crate::error::{AppError, StorageError};
+use crate::pipeline::{generate_output, process_approved, process_retry, retypeset_output, spawn_warm_up};
+use crate::pdf;
+use crate::workers;
+use crate::integrity::{Artifact, Manifest, Verification};
+use crate::state::{self, AppState, PageDetail};
+
+pub async fn cancel(
+    State(state): State<Arc<AppState>>,
+    Path(task_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    if state.cancel_task(&task_id) {
+        Ok((StatusCode::OK, "cancelled"))
+    } else {
+        Err(AppError::NotFound("not found or already done".to_string()))
+    }
+}
+
+/// Opt in to a read-only status link for this task
+pub async fn share_task(
+    State(state): State<Arc<AppState>>,
+    Path(task_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let token = state.share_task(&task_id)
+        .ok_or_else(|| AppError::NotFound("任务不存在".to_string()))?;
+    Ok(Json(serde_json::json!({ "share_token": token, "url": format!("/status/{}", token) })))
+}
+
+pub async fn unshare_task(
+    State(state): State<Arc<AppState>>,
+    Path(task_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    if state.unshare_task(&task_id) {
+        Ok((StatusCode::OK, "unshared"))
+    } else {
+        Err(AppError::NotFound("not found or not shared".to_string()))
+    }
+}
+
+pub async fn public_status_page(
+    State(state): State<Arc<AppState>>,
+    Path(token): Path<String>,
+) -> Result<Html<&'static str>, AppError> {
+    state.get_public_status(&token)
+        .map(|_| Html(include_str!("../status.html")))
+        .ok_or_else(|| AppError::NotFound("链接无效或已取消分享".to_string()))
+}
+
+pub async fn public_status(
+    State(state): State<Arc<AppState>>,
+    Path(token): Path<String>,
+) -> Result<Json<state::PublicStatus>, AppError> {
+    state.get_public_status(&token)
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound("链接无效或已取消分享".to_string()))
+}
+
+pub async fn retry_task(
+    State(state): State<Arc<AppState>>,
+    Path(task_id): Path<String>,
+    _quota: WithinQuota,
+) -> Result<impl IntoResponse, AppError> {
+    start_retry(&state, &task_id)?;
+    Ok(Json(serde_json::json!({ "status": "retrying" })))
+}
+
+/// Resume a failed task from its completed pages
+pub fn start_retry(state: &Arc<AppState>, task_id: &str) -> Result<(), AppError> {
+    // 先检查文件是否存在（在改变状态之前）
+    let pdf_bytes = match state::load_input_pdf(task_id) {
+        Ok(bytes) => bytes,
+        Err(_) => {
+            return Err(StorageError::InputGone.into());
+        }
+    };
+    
+    // 尝试获取并发槽位（在改变状态之前）
+    let background = state.get_options(task_id).is_some_and(|o| o.background);
+    if !state.try_acquire_task_slot(background) {
+        return Err(busy_error(state, background));
+    }
+    
+    // 所有前置检查通过后，才改变任务状态
+    if let Err(e) = state.try_start_retry(task_id) {
+        state.release_task_slot(background);
+        return Err(e);
+    }
+    // Pages that fail again are recorded afresh
+    state.dead_letters.remove_task(task_id);
+    
+    spawn_warm_up(state);
+    
+    let state_clone = state.clone();
+    let task_id_clone = task_id.to_string();
+    
+    tokio::spawn(async move {
+        process_retry(state_clone, task_id_clone, pdf_bytes).await;
+    });
+    
+    Ok(())
+}
+
+/// Start translating a task whose OCR text awaits review, from the text as
+/// it now stands
+pub async fn approve_task(
+    State(state): State<Arc<AppState>>,
+    Path(task_id): Path<String>,
+    _quota: WithinQuota,
+) -> Result<impl IntoResponse, AppError> {
+    let background = state.get_options(&task_id).is_some_and(|o| o.background);
+    if !state.try_acquire_task_slot(background) {
+        return Err(busy_error(&state, background));
+    }
+    if let Err(e) = state.try_approve_review(&task_id) {
+        state.release_task_slot(background);
+        return Err(e);
+    }
+    spawn_warm_up(&state);
+    tokio::spawn(process_approved(state.clone(), task_id));
+    Ok(Json(serde_json::json!({ "status": "approved" })))
+}
+
+pub async fn list_tasks(
+    State(state): State<Arc<AppState>>,
+) -> Json<Vec<state::TaskSummary>> {
+    Json(state.get_all_tasks())
+}
+
+pub async fn list_trash(
+    State(state): State<Arc<AppState>>,
+) -> Json<Vec<state::TaskSummary>> {
+    Json(state.get_trash())
+}
+
+/// Move a task to the trash; deleting it from the trash removes it for good
+pub async fn delete_task(
+    State(state): State<Arc<AppState>>,
+    Path(task_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let status = if state.delete_task(&task_id)? { "trashed" } else { "deleted" };
+    Ok(Json(serde_json::json!({ "status": status })))
+}
+
+pub async fn restore_task(
+    State(state): State<Arc<AppState>>,
+    Path(task_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    state.restore_task(&task_id)?;
+    Ok(Json(serde_json::json!({ "status": "restored" })))
+}
+
+pub async fn get_page_detail(
+    Path((task_id, page_num)): Path<(String, usize)>,
+) -> Result<Json<PageDetail>, AppError> {
+    state::load_page_detail(&task_id, page_num)
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound("页面不存在或未处理".to_string()))
+}
+
+#[derive(serde::Deserialize)]
+pub struct EditPageRequest {
+    translated_text: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct EditPageQuery {
+    /// false to save the text only, when several pages are edited before one
+    /// regenerate
+    regenerate: Option<bool>,
+}
+
+/// Replace one page's translation and regenerate the PDF; unchanged pages
+/// reuse their cached compressed streams
+pub async fn edit_page(
+    State(state): State<Arc<AppState>>,
+    Path((task_id, page_num)): Path<(String, usize)>,
+    Query(query): Query<EditPageQuery>,
+    Json(req): Json<EditPageRequest>,
+) -> Result<Json<PageDetail>, AppError> {
+    let regenerate = query.regenerate.unwrap_or(true);
+    let preview = state.text_preview(&req.translated_text);
+    state.start_page_edit(&task_id, page_num, req.translated_text.chars().count(), preview, regenerate)?;
+    
+    if let Err(e) = state::save_page_translated(&task_id, page_num, &req.translated_text) {
+        let error = StorageError::SavePage { page: page_num, source: e };
+        state.set_error(&task_id, error.to_string());
+        return Err(error.into());
+    }
+    let lang = state.get_options(&task_id).unwrap_or_default().target_lang;
+    state.record_checksum(&task_id, Artifact::Translation(page_num, lang), req.translated_text.as_bytes());
+    
+    if regenerate {
+        let total_pages = state.get_total_pages(&task_id);
+        generate_output(&state, &task_id, total_pages).await;
+    }
+    
+    state::load_page_detail(&task_id, page_num)
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound("页面不存在或未处理".to_string()))
+}
+
+/// Rebuild a completed task's PDFs from the translations on disk, after
+/// edits saved with `?regenerate=false`
+pub async fn regenerate(
+    State(state): State<Arc<AppState>>,
+    Path(task_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let total_pages = state.start_regenerate(&task_id)?;
+    generate_output(&state, &task_id, total_pages).await;
+    match state.get_progress(&task_id) {
+        Some(progress) if progress.status == state::TaskStatus::Error => Err(AppError::Storage(StorageError::Generate(progress.message))),
+        _ => Ok(Json(serde_json::json!({ "status": "regenerated" }))),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct RegenerateOutputQuery {
+    layout: Option<String>,
+    output: Option<String>,
+    romanize: Option<String>,
+}
+
+/// Assemble the output again from the stored page texts, optionally with
+/// other typesetting options, when only the final step failed. Makes no
+/// model requests (no OCR, translation or annotation translation) and takes
+/// no processing slot.
+pub async fn regenerate_output(
+    State(state): State<Arc<AppState>>,
+    Path(task_id): Path<String>,
+    Query(query): Query<RegenerateOutputQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let given = |value: &Option<String>| value.clone().filter(|v| !v.is_empty());
+    let layout = given(&query.layout)
+        .map(|l| pdf::Layout::parse(&l).ok_or_else(|| AppError::BadRequest(format!("不支持的排版方式: {}", l))))
+        .transpose()?;
+    let output_mode = given(&query.output)
+        .map(|o| pdf::OutputMode::parse(&o).ok_or_else(|| AppError::BadRequest(format!("不支持的输出模式: {}", o))))
+        .transpose()?;
+    let romanize = given(&query.romanize)
+        .map(|r| pdf::Romanize::parse(&r).ok_or_else(|| AppError::BadRequest(format!("不支持的注音选项: {}", r))))
+        .transpose()?;
+    let total_pages = state.start_regenerate_output(&task_id, layout, output_mode, romanize)?;
+    retypeset_output(&state, &task_id, total_pages).await;
+    match state.get_progress(&task_id) {
+        Some(progress) if progress.status == state::TaskStatus::Error => Err(AppError::Storage(StorageError::Generate(progress.message))),
+        _ => Ok(Json(serde_json::json!({ "status": "regenerated" }))),
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct EditOcrRequest {
+    ocr_text: String,
+}
+
+/// Correct one page's OCR text while the task awaits review
+pub async fn edit_page_ocr(
+    State(state): State<Arc<AppState>>,
+    Path((task_id, page_num)): Path<(String, usize)>,
+    Json(req): Json<EditOcrRequest>,
+) -> Result<Json<PageDetail>, AppError> {
+    state.edit_page_ocr(&task_id, page_num, &req.ocr_text)?;
+    state::load_page_detail(&task_id, page_num)
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound("页面不存在或未处理".to_string()))
+}
+
+#[derive(serde::Serialize)]
+pub struct VerifyResponse {
+    #[serde(flatten)]
+    verification: Verification,
+    manifest: Manifest,
+}
+
+/// Re-hash the task's stored files against the checksums recorded while it ran
+pub async fn verify_task(
+    State(state): State<Arc<AppState>>,
+    Path(task_id): Path<String>,
+) -> Result<Json<VerifyResponse>, AppError> {
+    let verified = {
+        let (state, task_id) = (state.clone(), task_id.clone());
+        workers::run(move || state.verify_task(&task_id)).await
+    };
+    let (manifest, verification) = verified.ok_or_else(|| AppError::NotFound("任务不存在".to_string()))?;
+    Ok(Json(VerifyResponse { verification, manifest }))
+}
@@ -0,0 +1,586 @@
+use async_zip::base::write::ZipFileWriter;
+use async_zip::{Compression, ZipEntryBuilder};
+use axum::http::StatusCode;
+use axum_test::TestServer;
+use axum_test::multipart::{MultipartForm, Part};
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::connector::SourceKind;
+use crate::destination::{DestinationKind, PublishedFile};
+use crate::job;
+use crate::resources::ResourceUsage;
+use crate::state::{AppState, LogEntry, PageRange, PageRetries, PageSummary, SampleInfo, TaskProgress, TaskStatus, TaskSummary};
+use crate::testing::config;
+use crate::usage::Usage;
+
+/// Server over a fresh state; nothing here reaches the API or writes task files
+fn server() -> TestServer {
+    let state = Arc::new(AppState::new(config()));
+    TestServer::new(super::app(state)).unwrap()
+}
+
+#[tokio::test]
+async fn capabilities_describe_instance() {
+    let response = server().get("/api/v1/capabilities").await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["max_file_size"], super::MAX_FILE_SIZE);
+    assert_eq!(body["providers"][0]["kind"], "openai-compatible");
+    assert!(body["target_languages"].as_array().is_some_and(|l| !l.is_empty()));
+}
+
+#[tokio::test]
+async fn model_listing_reports_unreachable_provider() {
+    let response = server().get("/api/v1/models").await;
+    response.assert_status(StatusCode::BAD_GATEWAY);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["code"], "upstream_failed");
+}
+
+#[tokio::test]
+async fn json_responses_carry_api_version() {
+    let response = server().get("/api/v1/quota").await;
+    response.assert_status_ok();
+    response.assert_header("api-version", super::API_VERSION);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["api_version"], super::API_VERSION);
+    assert!(response.maybe_header("deprecation").is_none());
+}
+
+#[tokio::test]
+async fn metrics_report_worker_pool() {
+    let response = server().get("/api/v1/metrics").await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert!(body["cpu_pool"]["size"].as_u64().is_some_and(|n| n > 0));
+    assert_eq!(body["cpu_pool"]["queued"], 0);
+    assert_eq!(body["active_tasks"], 0);
+}
+
+#[tokio::test]
+async fn unversioned_paths_are_deprecated_aliases() {
+    let response = server().get("/capabilities").await;
+    response.assert_status_ok();
+    response.assert_header("deprecation", "true");
+    response.assert_header("link", "</api/v1/capabilities>; rel=\"successor-version\"");
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["api_version"], super::API_VERSION);
+}
+
+#[tokio::test]
+async fn upload_without_file_is_rejected() {
+    let form = MultipartForm::new().add_text("layout", "");
+    let response = server().post("/api/v1/upload").multipart(form).await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["code"], "invalid_request");
+    assert_eq!(body["message"], "No file uploaded");
+}
+
+#[tokio::test]
+async fn upload_rejects_unknown_options() {
+    let server = server();
+    let response = server.post("/api/v1/upload")
+        .multipart(MultipartForm::new().add_text("layout", "sideways"))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("不支持的排版方式");
+
+    let response = server.post("/api/v1/upload")
+        .multipart(MultipartForm::new().add_text("priority", "urgent"))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("不支持的优先级");
+
+    let response = server.post("/api/v1/upload")
+        .multipart(MultipartForm::new().add_text("mode", "summarize"))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("不支持的处理模式");
+
+    let response = server.post("/api/v1/upload")
+        .multipart(MultipartForm::new().add_text("mode", "ocr").add_text("output", "bilingual"))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("仅识别模式不支持对照输出");
+
+    // Models outside ALLOWED_MODELS (unset here) can't be picked
+    let response = server.post("/api/v1/upload")
+        .multipart(MultipartForm::new().add_text("translate_model", "gpt-4.1-mini"))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("本服务不支持指定模型");
+
+    let response = server.post("/api/v1/upload")
+        .multipart(MultipartForm::new().add_text("retries", "99"))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("无效的重试次数");
+}
+
+#[tokio::test]
+async fn upload_rejects_malformed_page_ranges() {
+    let server = server();
+    for pages in ["-3", "0", "10-3", "a-b", "1,,x"] {
+        let response = server.post("/api/v1/upload")
+            .multipart(MultipartForm::new().add_text("pages", pages))
+            .await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        response.assert_text_contains("无效的页码范围");
+    }
+}
+
+#[tokio::test]
+async fn translate_text_validates_input() {
+    let server = server();
+    let response = server.post("/api/v1/translate-text").json(&serde_json::json!({ "text": "  " })).await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("缺少要翻译的文本");
+
+    let response = server.post("/api/v1/translate-text")
+        .json(&serde_json::json!({ "text": "Hello", "format": "docx" }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("不支持的结果格式");
+
+    let response = server.post("/api/v1/translate-text")
+        .json(&serde_json::json!({ "text": "Hello", "target_lang": "en,ja" }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("只能指定一种目标语言");
+}
+
+#[tokio::test]
+async fn preferences_require_a_user_token() {
+    let server = server();
+    let response = server.get("/api/v1/me/preferences").await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["code"], "unauthorized");
+
+    let response = server.put("/api/v1/me/preferences")
+        .authorization_bearer("wrong")
+        .json(&serde_json::json!({ "target_lang": "ja" }))
+        .await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+
+    // Validated like upload options before anything is saved
+    let response = server.put("/api/v1/me/preferences")
+        .authorization_bearer("secret")
+        .json(&serde_json::json!({ "layout": "sideways" }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("不支持的排版方式");
+
+    let response = server.put("/api/v1/me/preferences")
+        .authorization_bearer("secret")
+        .json(&serde_json::json!({ "pages": "1-3" }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("不支持的偏好设置");
+}
+
+#[tokio::test]
+async fn upload_url_is_validated_before_download() {
+    let server = server();
+    let response = server.post("/api/v1/upload-url").json(&serde_json::json!({ "target_lang": "en" })).await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("缺少文件地址");
+
+    let response = server.post("/api/v1/upload-url").json(&serde_json::json!({ "url": "ftp://example.com/a.pdf" })).await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("不支持的地址协议");
+
+    // Options are checked before anything is fetched
+    let response = server.post("/api/v1/upload-url")
+        .json(&serde_json::json!({ "url": "http://127.0.0.1:9/a.pdf", "layout": "sideways" }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("不支持的排版方式");
+
+    let response = server.post("/api/v1/upload-url")
+        .json(&serde_json::json!({ "url": "http://127.0.0.1:9/a.pdf", "source": "s3" }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("只能二选一");
+}
+
+#[tokio::test]
+async fn upload_requires_multipart() {
+    let response = server().post("/api/v1/upload").text("%PDF-1.4").await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn original_romanization_needs_bilingual_output() {
+    let form = MultipartForm::new()
+        .add_text("romanize", "original")
+        .add_text("output", "translated");
+    let response = server().post("/api/v1/upload").multipart(form).await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("原文注音");
+}
+
+#[tokio::test]
+async fn progress_of_unknown_task_reports_error() {
+    let response = server().get("/api/v1/progress/missing").await;
+    response.assert_status_ok();
+    response.assert_text_contains("任务不存在");
+}
+
+#[tokio::test]
+async fn unknown_task_routes_return_not_found() {
+    let server = server();
+    server.post("/api/v1/cancel/missing").await.assert_status_not_found();
+    server.get("/api/v1/download/missing").await.assert_status_not_found();
+    server.get("/api/v1/tasks/missing/pages/1").await.assert_status_not_found();
+    server.get("/api/v1/tasks/missing/pages/1/image").await.assert_status_not_found();
+    server.post("/api/v1/tasks/missing/share").await.assert_status_not_found();
+    server.get("/api/v1/tasks/missing/verify").await.assert_status_not_found();
+    server.post("/api/v1/tasks/missing/regenerate").await.assert_status_not_found();
+    server.post("/api/v1/tasks/missing/regenerate-output").await.assert_status_not_found();
+    server.delete("/api/v1/tasks/missing").await.assert_status_not_found();
+    server.post("/api/v1/tasks/missing/restore").await.assert_status_not_found();
+    server.get("/status/missing").await.assert_status_not_found();
+}
+
+#[tokio::test]
+async fn download_validates_query() {
+    let server = server();
+    let response = server.get("/api/v1/download/missing").add_query_param("format", "docx").await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("不支持的下载格式");
+
+    let response = server.get("/api/v1/download/missing").add_query_param("lang", "xx-nope").await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("不支持的目标语言");
+
+    server.get("/api/v1/download/missing").add_query_param("format", "zip").await.assert_status_not_found();
+}
+
+#[tokio::test]
+async fn output_regeneration_validates_query() {
+    let server = server();
+    let response = server.post("/api/v1/tasks/missing/regenerate-output").add_query_param("layout", "diagonal").await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("不支持的排版方式");
+
+    let response = server.post("/api/v1/tasks/missing/regenerate-output").add_query_param("output", "summary").await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("不支持的输出模式");
+
+    server.post("/api/v1/tasks/missing/regenerate-output").await.assert_status_not_found();
+}
+
+#[tokio::test]
+async fn retry_of_unknown_task_is_gone() {
+    let response = server().post("/api/v1/retry/missing").await;
+    response.assert_status(StatusCode::GONE);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["code"], "input_gone");
+}
+
+#[tokio::test]
+async fn errors_have_code_and_message() {
+    let server = server();
+    let response = server.get("/api/v1/tasks/missing/pages/1").await;
+    response.assert_status_not_found();
+    response.assert_header("content-type", "application/json");
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["code"], "not_found");
+    assert_eq!(body["message"], "页面不存在或未处理");
+
+    let response = server.post("/api/v1/upload").text("%PDF-1.4").await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["code"], "invalid_multipart");
+}
+
+#[tokio::test]
+async fn schedule_creation_is_validated() {
+    let server = server();
+    let response = server.post("/api/v1/schedules").json(&serde_json::json!({ "source": "s3" })).await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("缺少 cron");
+
+    let response = server.post("/api/v1/schedules").json(&serde_json::json!({ "cron": "61 * * * *" })).await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("无效的 cron");
+
+    let response = server.post("/api/v1/schedules")
+        .json(&serde_json::json!({ "cron": "0 8 * * 1", "file": "report.pdf" }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("不支持的字段");
+
+    let response = server.post("/api/v1/schedules").json(&serde_json::json!({ "cron": "0 8 * * 1" })).await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("source");
+
+    // No remote sources are enabled in tests
+    let response = server.post("/api/v1/schedules")
+        .json(&serde_json::json!({ "cron": "0 8 * * 1", "source": "s3", "url": "https://example.com/a.pdf" }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("未启用的远程来源");
+}
+
+#[tokio::test]
+async fn schedules_refuse_internal_addresses() {
+    let config = Config {
+        remote_sources: vec![SourceKind::S3],
+        remote_destinations: vec![DestinationKind::WebDav],
+        ..config()
+    };
+    let server = TestServer::new(super::app(Arc::new(AppState::new(config)))).unwrap();
+    for body in [
+        serde_json::json!({ "cron": "0 8 * * 1", "source": "s3", "url": "http://169.254.169.254/latest/meta-data/" }),
+        serde_json::json!({ "cron": "0 8 * * 1", "source": "s3", "url": "https://127.0.0.1/a.pdf" }),
+        serde_json::json!({ "cron": "0 8 * * 1", "source": "s3", "url": "https://203.0.113.5/a.pdf", "dest": "http://localhost:9/dav" }),
+    ] {
+        let response = server.post("/api/v1/schedules").json(&body).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        response.assert_text_contains("不允许访问内网地址");
+    }
+    assert!(server.get("/api/v1/schedules").await.json::<Vec<serde_json::Value>>().is_empty());
+}
+
+#[tokio::test]
+async fn deleting_unknown_schedule_is_not_found() {
+    server().delete("/api/v1/schedules/missing").await.assert_status_not_found();
+}
+
+#[tokio::test]
+async fn upload_rejects_unknown_previous_version() {
+    let form = MultipartForm::new().add_text("previous_task", "missing");
+    let response = server().post("/api/v1/upload").multipart(form).await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("上一版本任务不存在");
+}
+
+/// Compare `value` with `src/routes/snapshots/{name}.json`. These lock down
+/// what API clients parse: a renamed, removed or added field fails here
+/// until the snapshot is regenerated with `UPDATE_SNAPSHOTS=1 cargo test`.
+fn assert_snapshot(name: &str, value: serde_json::Value) {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src/routes/snapshots")
+        .join(format!("{}.json", name));
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::write(&path, serde_json::to_string_pretty(&value).unwrap() + "\n").unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("missing snapshot {}: {}", path.display(), e));
+    let expected: serde_json::Value = serde_json::from_str(&expected).unwrap();
+    assert_eq!(value, expected, "serialized shape of {} changed", name);
+}
+
+fn usage() -> Usage {
+    Usage { prompt_tokens: 1200, completion_tokens: 800, estimated: false }
+}
+
+fn page_summary() -> PageSummary {
+    PageSummary {
+        page_num: 1,
+        ocr_started: Some(1_700_000_000_000),
+        ocr_duration_ms: Some(4200),
+        ocr_chars: Some(1834),
+        ocr_text_preview: Some("Chapter 1".to_string()),
+        ocr_usage: Some(usage()),
+        ocr_model: Some("ocr-model".to_string()),
+        translate_started: Some(1_700_000_004_200),
+        translate_duration_ms: Some(3100),
+        translated_chars: Some(1102),
+        translated_text_preview: Some("第一章".to_string()),
+        translate_usage: Some(usage()),
+        translate_model: Some("translate-model".to_string()),
+        check_warning: Some("(en) 译文中残留其他文字".to_string()),
+        continuations: Some(1),
+        retries: Some(PageRetries {
+            stage: "翻译".to_string(),
+            max_retries: 3,
+            used: 1,
+            remaining: 2,
+            last_delay_ms: Some(1000),
+            last_error: Some("请求超时".to_string()),
+        }),
+        status: "done".to_string(),
+        error: None,
+    }
+}
+
+fn resources() -> ResourceUsage {
+    ResourceUsage {
+        peak_memory_bytes: 48_000_000,
+        rendered_bytes: 31_000_000,
+        api_sent_bytes: 42_000_000,
+        api_received_bytes: 96_000,
+        disk_bytes: 2_400_000,
+    }
+}
+
+fn task_progress() -> TaskProgress {
+    TaskProgress {
+        status: TaskStatus::Complete,
+        total_pages: 2,
+        ocr_done: 2,
+        translate_done: 2,
+        message: "完成！用时 12 秒".to_string(),
+        overall_percent: 100,
+        filename: "report.pdf".to_string(),
+        title: Some("Annual Report 2024".to_string()),
+        logs: vec![LogEntry { ts: 1_700_000_000_000, msg: "任务开始".to_string() }],
+        page_summaries: vec![page_summary(), PageSummary { page_num: 2, status: "pending".to_string(), ..Default::default() }],
+        usage: usage(),
+        sample: Some(SampleInfo { source_pages: 40, pages: vec![1, 20], projected_tokens: Some(40_000), projected_cost: Some(0.4) }),
+        page_range: Some(PageRange { source_pages: 40, pages: vec![1, 20] }),
+        published: vec![PublishedFile { name: "report_en.pdf".to_string(), url: "s3://bucket/report_en.pdf".to_string() }],
+        local_only: true,
+        preview_pages: Some(2),
+        ocr_reviewed: true,
+        resources: resources(),
+        pages_per_minute: Some(9.5),
+        eta_seconds: Some(42),
+    }
+}
+
+#[test]
+fn task_progress_shape_is_stable() {
+    let progress = task_progress();
+    assert_snapshot("task_progress", serde_json::to_value(&progress).unwrap());
+
+    // Optional parts are left out rather than sent as null
+    let minimal = TaskProgress { title: None, sample: None, page_range: None, published: Vec::new(), local_only: false, preview_pages: None, ocr_reviewed: false, resources: ResourceUsage::default(), ..progress };
+    let value = serde_json::to_value(&minimal).unwrap();
+    for field in ["title", "sample", "page_range", "published", "local_only", "preview_pages", "ocr_reviewed", "resources"] {
+        assert!(value.get(field).is_none(), "{} should be omitted", field);
+    }
+}
+
+#[test]
+fn progress_events_name_what_changed() {
+    let names = |events: Vec<(&'static str, serde_json::Value)>| events.into_iter().map(|(name, _)| name).collect::<Vec<_>>();
+    let before = task_progress();
+    // The first snapshot is the baseline
+    assert_eq!(names(super::progress::typed_events(None, &before)), ["status"]);
+    assert!(super::progress::typed_events(Some(&before), &before).is_empty());
+
+    let mut after = task_progress();
+    after.page_summaries[1].status = "error".to_string();
+    after.page_summaries[1].error = Some("第 2 页 OCR 失败: timeout".to_string());
+    after.logs.push(LogEntry { ts: 1_700_000_001_000, msg: "第 2 页 OCR 失败".to_string() });
+    let events = super::progress::typed_events(Some(&before), &after);
+    assert_eq!(names(events.clone()), ["page_error", "log"]);
+    assert_eq!(events[0].1["page_num"], 2);
+
+    let mut done = after.clone();
+    done.page_summaries[1] = PageSummary { page_num: 2, status: "done".to_string(), ..Default::default() };
+    done.status = TaskStatus::Error;
+    assert_eq!(names(super::progress::typed_events(Some(&after), &done)), ["status", "page_done"]);
+}
+
+#[test]
+fn page_summary_shape_is_stable() {
+    assert_snapshot("page_summary", serde_json::to_value(page_summary()).unwrap());
+}
+
+#[test]
+fn task_summary_shape_is_stable() {
+    let summary = TaskSummary {
+        task_id: "0f8fad5b-d9cb-469f-a165-70867728950e".to_string(),
+        filename: "report.pdf".to_string(),
+        title: Some("Annual Report 2024".to_string()),
+        status: TaskStatus::Processing,
+        overall_percent: 40,
+        ocr_done: 8,
+        translate_done: 6,
+        total_pages: 20,
+        usage: usage(),
+        resources: resources(),
+        background: false,
+        deleted_at: None,
+    };
+    assert_snapshot("task_summary", serde_json::to_value(&summary).unwrap());
+}
+
+#[tokio::test]
+async fn progress_event_shape_is_stable() {
+    // Events carry TaskProgress as their data (locked down above); a task
+    // that doesn't exist gets a single error event
+    let response = server().get("/api/v1/progress/missing").await;
+    response.assert_header("content-type", "text/event-stream");
+    let text = response.text();
+    let data = text.lines().find_map(|line| line.strip_prefix("data: ")).expect("an SSE data line");
+    assert_snapshot("progress_event_missing_task", serde_json::from_str(data).unwrap());
+}
+
+#[tokio::test]
+async fn jobs_take_only_zip_archives() {
+    let server = server();
+    let form = MultipartForm::new()
+        .add_part("file", Part::bytes(b"%PDF-1.4".to_vec()).file_name("paper.pdf"));
+    let response = server.post("/api/v1/jobs").multipart(form).await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    response.assert_text_contains("仅支持 ZIP");
+
+    server.get("/api/v1/jobs/missing").await.assert_status_not_found();
+    server.get("/api/v1/jobs/missing/download").await.assert_status_not_found();
+}
+
+#[tokio::test]
+async fn upload_url_refuses_internal_addresses() {
+    let server = server();
+    for url in [
+        "http://127.0.0.1:9/a.pdf",
+        "http://169.254.169.254/latest/meta-data/",
+        "http://10.0.0.8/a.pdf",
+        "http://[::ffff:192.168.1.1]/a.pdf",
+        "http://localhost:9/a.pdf",
+    ] {
+        let response = server.post("/api/v1/upload-url").json(&serde_json::json!({ "url": url })).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        response.assert_text_contains("不允许访问内网地址");
+    }
+}
+
+#[tokio::test]
+async fn remote_sources_refuse_internal_addresses() {
+    let config = Config { remote_sources: vec![SourceKind::S3, SourceKind::WebDav], ..config() };
+    let server = TestServer::new(super::app(Arc::new(AppState::new(config)))).unwrap();
+    for (source, url) in [("s3", "http://127.0.0.1:9/a.pdf"), ("webdav", "http://169.254.169.254/latest/meta-data/"), ("s3", "http://localhost:9/a.pdf")] {
+        let form = MultipartForm::new().add_text("source", source).add_text("url", url);
+        let response = server.post("/api/v1/upload").multipart(form).await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        response.assert_text_contains("不允许访问内网地址");
+    }
+}
+
+#[tokio::test]
+async fn jobs_count_toward_the_storage_budget() {
+    let state = Arc::new(AppState::new(Config { data_max_bytes: Some(1), ..config() }));
+    let server = TestServer::new(super::app(state)).unwrap();
+    let mut zip = ZipFileWriter::with_tokio(Vec::new());
+    zip.write_entry_whole(ZipEntryBuilder::new("paper.pdf".to_string().into(), Compression::Stored), b"%PDF-1.4").await.unwrap();
+    let archive = zip.close().await.unwrap().into_inner();
+
+    let form = MultipartForm::new().add_part("file", Part::bytes(archive).file_name("papers.zip"));
+    let response = server.post("/api/v1/jobs").multipart(form).await;
+    response.assert_status(StatusCode::INSUFFICIENT_STORAGE);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["code"], "storage_full");
+    // The unpacked documents were removed again
+    assert_eq!(std::fs::read_dir(job::files_dir()).map_or(0, |entries| entries.count()), 0);
+}
+
+#[tokio::test]
+async fn dead_letters_need_the_admin_token() {
+    let server = server();
+    server.get("/api/v1/dead-letters").await.assert_status(StatusCode::UNAUTHORIZED);
+    server.get("/api/v1/dead-letters").add_header("x-admin-token", "admin-secreT").await
+        .assert_status(StatusCode::UNAUTHORIZED);
+    server.post("/api/v1/dead-letters/redrive").await.assert_status(StatusCode::UNAUTHORIZED);
+
+    server.get("/api/v1/dead-letters").add_header("x-admin-token", "admin-secret").await.assert_status_ok();
+    let response = server.post("/api/v1/dead-letters/redrive").add_header("x-admin-token", "admin-secret").await;
+    response.assert_status_ok();
+}
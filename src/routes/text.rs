@@ -0,0 +1,90 @@
+use axum::{
+    Json,
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use serde_json::{Map, Value};
+use std::sync::Arc;
+
+use super::extract::{User, WithinQuota};
+use super::upload::{UploadForm, apply_preferences, json_fields, task_options};
+use crate::error::AppError;
+use crate::pipeline::{self, TextChunk};
+use crate::state::{AppState, TaskMode};
+use crate::usage::Usage;
+use crate::{translate, workers};
+
+/// Longest text accepted in one request, in characters
+const MAX_TEXT_CHARS: usize = 200_000;
+
+/// Translate plain text or Markdown directly, without a PDF or a task. The
+/// JSON body holds `text`, `format` (`json` or `pdf`) and the upload options
+/// that apply to text: `target_lang`, `layout`, `output` and `romanize`.
+pub async fn translate_text(
+    State(state): State<Arc<AppState>>,
+    _quota: WithinQuota,
+    user: Option<User>,
+    Json(body): Json<Map<String, Value>>,
+) -> Result<Response, AppError> {
+    let text = body.get("text").and_then(Value::as_str).unwrap_or_default();
+    if text.trim().is_empty() {
+        return Err(AppError::BadRequest("缺少要翻译的文本 (text)".to_string()));
+    }
+    if text.chars().count() > MAX_TEXT_CHARS {
+        return Err(AppError::BadRequest(format!("文本过长，最多 {} 字符", MAX_TEXT_CHARS)));
+    }
+    let as_pdf = match body.get("format").and_then(Value::as_str).map(|f| f.trim().to_ascii_lowercase()).as_deref() {
+        None | Some("" | "json") => false,
+        Some("pdf") => true,
+        Some(other) => return Err(AppError::BadRequest(format!("不支持的结果格式: {}", other))),
+    };
+    let mut form = UploadForm::from_fields(&json_fields(&body, &["text", "format"]).map_err(AppError::BadRequest)?);
+    apply_preferences(&state, &mut form, user.as_ref());
+    let options = task_options(&state, &form).map_err(AppError::BadRequest)?;
+    if options.mode == TaskMode::Ocr {
+        return Err(AppError::BadRequest("文本翻译不支持仅识别模式".to_string()));
+    }
+    if !options.extra_langs.is_empty() {
+        return Err(AppError::BadRequest("文本翻译只能指定一种目标语言".to_string()));
+    }
+    if options.review_ocr {
+        return Err(AppError::BadRequest("文本翻译没有识别文本可审阅".to_string()));
+    }
+
+    let lang = options.target_lang;
+    let job_id = format!("text-{}", uuid::Uuid::new_v4().simple());
+    let chunks = pipeline::translate_plain_text(&state, &job_id, text, &options).await?;
+
+    if as_pdf {
+        let mut pdf_options = pipeline::pdf_options(&state.config, &options, lang);
+        // Side by side the chunks keep their originals next to them; otherwise
+        // the translation flows as one document
+        let pages: Vec<String> = if options.output_mode.needs_originals() {
+            pdf_options.originals = chunks.iter().map(|c| c.source.clone()).collect();
+            chunks.iter().map(|c| c.completion.text.clone()).collect()
+        } else {
+            vec![joined(&chunks)]
+        };
+        let pdf = workers::run(move || crate::pdf::generate_pdf(&pages, &pdf_options)).await?;
+        return Ok(([(header::CONTENT_TYPE, "application/pdf")], pdf).into_response());
+    }
+
+    let mut usage = Usage::default();
+    let mut models = String::new();
+    for chunk in &chunks {
+        usage.add(&chunk.completion.usage);
+        translate::note_model(&mut models, &chunk.completion.model);
+    }
+    Ok(Json(serde_json::json!({
+        "text": joined(&chunks),
+        "target_lang": lang.code(),
+        "chunks": chunks.len(),
+        "model": models,
+        "usage": usage,
+    })).into_response())
+}
+
+fn joined(chunks: &[TextChunk]) -> String {
+    chunks.iter().map(|c| c.completion.text.trim()).collect::<Vec<_>>().join("\n\n")
+}
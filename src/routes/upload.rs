@@ -0,0 +1,636 @@
+use axum::{
+    Json,
+    extract::{FromRequest, Multipart, Request, State},
+    response::IntoResponse,
+};
+use serde_json::{Map, Value};
+use std::sync::Arc;
+
+use super::extract::{User, WithinQuota, user_of};
+use super::{MAX_FILE_SIZE, busy_error};
+use crate::destination::Destination;
+use crate::error::{AppError, PdfError, StorageError};
+use crate::pipeline::{process_pdf_parallel, spawn_warm_up};
+use crate::preferences::PREFERENCE_FIELDS;
+use crate::state::{self, AppState, TaskMode};
+use crate::{config, connector, filename, lang, pdf};
+
+/// Most retries per request an upload may ask for
+const MAX_TASK_RETRIES: u32 = 10;
+
+pub async fn upload(
+    State(state): State<Arc<AppState>>,
+    _quota: WithinQuota,
+    upload: NewTask,
+) -> Result<impl IntoResponse, AppError> {
+    let task_id = start_task(&state, upload);
+    Ok(Json(serde_json::json!({ "task_id": task_id })))
+}
+
+/// Download the document at `url` and start a task for it; the other keys of
+/// the JSON body are upload form fields
+pub async fn upload_url(
+    State(state): State<Arc<AppState>>,
+    _quota: WithinQuota,
+    user: Option<User>,
+    Json(body): Json<Map<String, Value>>,
+) -> Result<impl IntoResponse, AppError> {
+    let task_id = uuid::Uuid::new_v4().to_string();
+    let upload = accept_url(&state, &task_id, &body, user.as_ref()).await.inspect_err(|_| {
+        state::cleanup_task_files(&task_id);
+    })?;
+    let task_id = start_task(&state, upload);
+    Ok(Json(serde_json::json!({ "task_id": task_id })))
+}
+
+async fn accept_url(
+    state: &AppState,
+    task_id: &str,
+    body: &Map<String, Value>,
+    user: Option<&User>,
+) -> Result<NewTask, AppError> {
+    if !state.config.url_upload {
+        return Err(AppError::Forbidden("未启用按链接上传".to_string()));
+    }
+    state.ensure_storage()?;
+    let url = body.get("url").and_then(Value::as_str).map(str::trim).unwrap_or_default();
+    if url.is_empty() {
+        return Err(AppError::BadRequest("缺少文件地址 (url)".to_string()));
+    }
+    let url = reqwest::Url::parse(url).map_err(|e| AppError::BadRequest(format!("无效的文件地址: {}", e)))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(AppError::BadRequest(format!("不支持的地址协议: {}", url.scheme())));
+    }
+    if state.config.local_only && !config::is_local_url(url.as_str(), &state.config.local_hosts) {
+        return Err(AppError::Forbidden(format!("本地模式下只能从本地地址下载: {}", url)));
+    }
+
+    let mut form = UploadForm::from_fields(&json_fields(body, &["url"]).map_err(AppError::BadRequest)?);
+    if form.source.is_some() {
+        return Err(AppError::BadRequest("url 与 source 只能二选一".to_string()));
+    }
+    apply_preferences(state, &mut form, user);
+    // Catch bad options before downloading anything
+    task_options(state, &form).map_err(AppError::BadRequest)?;
+    form.page_ranges().map_err(AppError::BadRequest)?;
+
+    if !state.config.local_only {
+        connector::check_public_url(&url).await.map_err(AppError::Forbidden)?;
+    }
+    let file = connector::open_url(&state.config, url).await
+        .map_err(AppError::Upstream)?;
+    form.file = Some(save_remote(task_id, file).await?);
+    accept_form(state, task_id, &form).await
+}
+
+/// Register an accepted upload and start processing it; returns the task id
+pub fn start_task(state: &Arc<AppState>, upload: NewTask) -> String {
+    let NewTask { task_id, filename, options, page_range, sample, data } = upload;
+    let background = options.background;
+    
+    state.create_task(&task_id, &filename, options);
+    state.stats.record_task();
+    if background {
+        state.add_log(&task_id, "后台任务：仅使用空闲的 API 并发".to_string());
+    }
+    if let Some(range) = page_range {
+        state.set_page_range(&task_id, range);
+    }
+    if let Some(info) = sample {
+        state.set_sample(&task_id, info);
+    }
+    
+    spawn_warm_up(state);
+    
+    let state_clone = state.clone();
+    let task_id_clone = task_id.clone();
+    
+    tokio::spawn(async move {
+        process_pdf_parallel(state_clone, task_id_clone, data).await;
+    });
+    
+    task_id
+}
+
+/// An accepted upload: the PDF is stored under a new task id, the form's
+/// options are validated and a task slot is held for it
+pub struct NewTask {
+    pub task_id: String,
+    pub filename: String,
+    pub options: state::TaskOptions,
+    pub page_range: Option<state::PageRange>,
+    pub sample: Option<state::SampleInfo>,
+    /// The PDF to process (only the selected or sampled pages)
+    pub data: Vec<u8>,
+}
+
+impl FromRequest<Arc<AppState>> for NewTask {
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        let user = user_of(state, req.headers())?;
+        // Refused before the file is read when the disk budget is already spent
+        state.ensure_storage()?;
+        let mut multipart = Multipart::from_request(req, state).await?;
+        // The file is written straight to data/tasks/{id}/input.pdf while it arrives
+        let task_id = uuid::Uuid::new_v4().to_string();
+        accept_upload(state, &task_id, &mut multipart, user.as_ref()).await.inspect_err(|_| {
+            state::cleanup_task_files(&task_id);
+        })
+    }
+}
+
+/// Read the form, streaming the PDF to disk, and accept it
+async fn accept_upload(
+    state: &AppState,
+    task_id: &str,
+    multipart: &mut Multipart,
+    user: Option<&User>,
+) -> Result<NewTask, AppError> {
+    let mut form = read_upload_form(multipart, task_id).await?;
+    apply_preferences(state, &mut form, user);
+    accept_form(state, task_id, &form).await
+}
+
+/// Fill the options the user left out from their saved preferences
+pub fn apply_preferences(state: &AppState, form: &mut UploadForm, user: Option<&User>) {
+    if let Some(User(name)) = user {
+        form.apply_defaults(&state.preferences.get(name));
+    }
+}
+
+/// Validate the options, fetch a remote document and take a task slot. A page
+/// selection or sample run replaces the stored input with just the pages to
+/// process.
+pub async fn accept_form(
+    state: &AppState,
+    task_id: &str,
+    form: &UploadForm,
+) -> Result<NewTask, AppError> {
+    let options = task_options(state, form).map_err(AppError::BadRequest)?;
+    let ranges = form.page_ranges().map_err(AppError::BadRequest)?;
+    if let Some(destination) = &options.destination {
+        destination.check_public().await.map_err(AppError::Forbidden)?;
+    }
+    let filename = match (&form.file, form.source.as_deref().filter(|s| !s.is_empty())) {
+        (Some(_), Some(_)) => {
+            return Err(AppError::BadRequest("file 与 source 只能二选一".to_string()));
+        }
+        (Some(filename), None) => filename.clone(),
+        (None, Some(source)) => fetch_remote(state, task_id, form, source).await?,
+        (None, None) => return Err(AppError::BadRequest("No file uploaded".to_string())),
+    };
+    // The document is on disk now and counts toward the budget
+    state.ensure_storage()?;
+    
+    // Check task limit (background tasks have their own)
+    let background = options.background;
+    if !state.try_acquire_task_slot(background) {
+        return Err(busy_error(state, background));
+    }
+    
+    let prepared = state::load_input_pdf(task_id)
+        .map_err(|e| StorageError::ReadInput(e).into())
+        .and_then(|data| {
+            // Work on a cut-down copy holding only the selected pages, and
+            // of those only the sampled ones for a sample run
+            let (page_range, data) = match range_upload(ranges.as_ref(), &data)? {
+                Some((reduced, range)) => (Some(range), reduced),
+                None => (None, data),
+            };
+            let (sample, data) = match sample_upload(state, &form.sample, &data)? {
+                Some((reduced, mut info)) => {
+                    // Sampled from the selection, so numbered within it
+                    if let Some(range) = &page_range {
+                        info.pages = info.pages.iter().map(|p| range.pages[p - 1]).collect();
+                    }
+                    (Some(info), reduced)
+                }
+                None => (None, data),
+            };
+            if page_range.is_some() || sample.is_some() {
+                state::save_input_pdf(task_id, &data).map_err(StorageError::SaveInput)?;
+            }
+            Ok((page_range, sample, data))
+        });
+    match prepared {
+        Ok((page_range, sample, data)) => Ok(NewTask { task_id: task_id.to_string(), filename, options, page_range, sample, data }),
+        Err(e) => {
+            state.release_task_slot(background);
+            Err(e)
+        }
+    }
+}
+
+/// Multipart upload: the PDF plus optional per-task option fields
+#[derive(Default)]
+pub struct UploadForm {
+    /// Sanitized filename; the content is already in the task directory
+    pub file: Option<String>,
+    /// `ocr` to only recognize the text, without translating it
+    pub mode: Option<String>,
+    pub layout: Option<String>,
+    pub output: Option<String>,
+    /// Each entry may itself be a comma-separated list; the field may also repeat
+    pub target_langs: Vec<String>,
+    pub romanize: Option<String>,
+    /// Sample run: a page count, or a true value for the configured default
+    pub sample: Option<String>,
+    /// `background` for a low-priority task
+    pub priority: Option<String>,
+    /// Only these pages, e.g. `3-10,15`
+    pub pages: Option<String>,
+    /// Cloud storage connector to pull the file from instead of `file`
+    pub source: Option<String>,
+    pub url: Option<String>,
+    pub file_id: Option<String>,
+    pub token: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Where to push the results on completion: `s3://bucket/prefix` or a WebDAV URL
+    pub dest: Option<String>,
+    pub dest_username: Option<String>,
+    pub dest_password: Option<String>,
+    /// Task of an earlier version of the document, to reuse unchanged pages' translations
+    pub previous_task: Option<String>,
+    /// A true value to stop after OCR until the text is reviewed and approved
+    pub review: Option<String>,
+    /// Models from ALLOWED_MODELS to use instead of OCR_MODEL and MODEL
+    pub ocr_model: Option<String>,
+    pub translate_model: Option<String>,
+    /// Retries per request instead of OCR_MAX_RETRIES / TRANSLATE_MAX_RETRIES
+    pub retries: Option<String>,
+}
+
+impl UploadForm {
+    pub const TEXT_FIELDS: &[&str] = &[
+        "mode", "layout", "output", "target_lang", "romanize", "sample", "priority", "pages",
+        "source", "url", "file_id", "token", "username", "password",
+        "dest", "dest_username", "dest_password", "previous_task", "review", "ocr_model", "translate_model", "retries",
+    ];
+
+    /// The `pages` selection, if any
+    pub fn page_ranges(&self) -> Result<Option<pdf::PageRanges>, String> {
+        match self.pages.as_deref().filter(|p| !p.is_empty()) {
+            Some(pages) => pdf::PageRanges::parse(pages)
+                .map(Some)
+                .ok_or_else(|| format!("无效的页码范围: {}", pages)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn from_fields(fields: &[(String, String)]) -> Self {
+        let mut form = Self::default();
+        for (name, value) in fields {
+            form.set_field(name, value.clone());
+        }
+        form
+    }
+
+    /// Whether a saved-preference field was given a value
+    fn has_field(&self, name: &str) -> bool {
+        let given = |value: &Option<String>| value.as_deref().is_some_and(|v| !v.trim().is_empty());
+        match name {
+            "target_lang" => self.target_langs.iter().any(|v| !v.trim().is_empty()),
+            "layout" => given(&self.layout),
+            "output" => given(&self.output),
+            "romanize" => given(&self.romanize),
+            _ => false,
+        }
+    }
+
+    /// Fill the fields left blank from saved defaults. An OCR-only upload
+    /// takes none: its language names the document's, and it has one output.
+    pub fn apply_defaults(&mut self, fields: &[(String, String)]) {
+        if self.mode.as_deref().and_then(TaskMode::parse) == Some(TaskMode::Ocr) {
+            return;
+        }
+        let blank: Vec<&str> = PREFERENCE_FIELDS.iter().copied().filter(|name| !self.has_field(name)).collect();
+        for (name, value) in fields {
+            if blank.contains(&name.as_str()) {
+                self.set_field(name, value.clone());
+            }
+        }
+    }
+
+    /// Set a text field by its form name; unknown names are ignored
+    pub fn set_field(&mut self, name: &str, value: String) {
+        match name {
+            "mode" => self.mode = Some(value),
+            "layout" => self.layout = Some(value),
+            "output" => self.output = Some(value),
+            "target_lang" => self.target_langs.push(value),
+            "romanize" => self.romanize = Some(value),
+            "sample" => self.sample = Some(value),
+            "priority" => self.priority = Some(value),
+            "pages" => self.pages = Some(value),
+            "source" => self.source = Some(value),
+            "url" => self.url = Some(value),
+            "file_id" => self.file_id = Some(value),
+            "token" => self.token = Some(value),
+            "username" => self.username = Some(value),
+            "password" => self.password = Some(value),
+            "dest" => self.dest = Some(value),
+            "dest_username" => self.dest_username = Some(value),
+            "dest_password" => self.dest_password = Some(value),
+            "previous_task" => self.previous_task = Some(value),
+            "review" => self.review = Some(value),
+            "ocr_model" => self.ocr_model = Some(value),
+            "translate_model" => self.translate_model = Some(value),
+            "retries" => self.retries = Some(value),
+            _ => {}
+        }
+    }
+}
+
+/// Form fields from a JSON body, leaving out the keys in `skip`; a list value
+/// repeats the field
+pub fn json_fields(body: &Map<String, Value>, skip: &[&str]) -> Result<Vec<(String, String)>, String> {
+    let mut fields = Vec::new();
+    for (name, value) in body {
+        if skip.contains(&name.as_str()) {
+            continue;
+        }
+        if !UploadForm::TEXT_FIELDS.contains(&name.as_str()) {
+            return Err(format!("不支持的字段: {}", name));
+        }
+        let values = match value {
+            Value::Array(items) => items.clone(),
+            other => vec![other.clone()],
+        };
+        for value in values {
+            let text = match value {
+                Value::String(s) => s.trim().to_string(),
+                Value::Number(n) => n.to_string(),
+                Value::Bool(b) => b.to_string(),
+                _ => return Err(format!("字段 {} 的值无效", name)),
+            };
+            fields.push((name.clone(), text));
+        }
+    }
+    Ok(fields)
+}
+
+async fn read_upload_form(multipart: &mut Multipart, task_id: &str) -> Result<UploadForm, AppError> {
+    let mut form = UploadForm::default();
+    
+    while let Some(field) = multipart.next_field().await
+        .map_err(|e| AppError::BadRequest(format!("Multipart error: {}", e)))?
+    {
+        match field.name() {
+            Some("file") => {
+                let filename = filename::sanitize(field.file_name().unwrap_or_default());
+                save_upload(field, task_id).await?;
+                form.file = Some(filename);
+            }
+            Some(name) if UploadForm::TEXT_FIELDS.contains(&name) => {
+                let name = name.to_string();
+                let value = read_text_field(field).await?;
+                form.set_field(&name, value);
+            }
+            _ => {}
+        }
+    }
+    
+    Ok(form)
+}
+
+/// Stream the uploaded file to disk chunk by chunk
+async fn save_upload(mut field: axum::extract::multipart::Field<'_>, task_id: &str) -> Result<(), AppError> {
+    let mut sink = PdfSink::create(task_id)?;
+    while let Some(chunk) = field.chunk().await
+        .map_err(|e| AppError::BadRequest(format!("Read error: {}", e)))?
+    {
+        sink.push(&chunk)?;
+    }
+    sink.finish()
+}
+
+/// Pull the document from a cloud storage connector into the task directory;
+/// returns its filename
+async fn fetch_remote(state: &AppState, task_id: &str, form: &UploadForm, source: &str) -> Result<String, AppError> {
+    let kind = connector::SourceKind::parse(source)
+        .filter(|k| state.config.remote_sources.contains(k))
+        .ok_or_else(|| AppError::BadRequest(format!("不支持或未启用的远程来源: {}", source)))?;
+    let remote = remote_source(form, kind);
+    connector::check_source(&remote).await.map_err(AppError::Forbidden)?;
+    let file = connector::open(&remote, state.config.remote_timeout_secs).await
+        .map_err(AppError::Upstream)?;
+    save_remote(task_id, file).await
+}
+
+/// The remote document an upload form names
+pub fn remote_source(form: &UploadForm, kind: connector::SourceKind) -> connector::RemoteSource {
+    connector::RemoteSource {
+        kind,
+        url: form.url.clone(),
+        file_id: form.file_id.clone(),
+        token: form.token.clone(),
+        username: form.username.clone(),
+        password: form.password.clone(),
+    }
+}
+
+/// Stream a download into the task directory; returns its filename
+async fn save_remote(task_id: &str, mut file: connector::RemoteFile) -> Result<String, AppError> {
+    if file.size.is_some_and(|size| size > MAX_FILE_SIZE as u64) {
+        return Err(AppError::TooLarge);
+    }
+    
+    let mut sink = PdfSink::create(task_id)?;
+    while let Some(chunk) = file.response.chunk().await
+        .map_err(|e| AppError::Upstream(format!("下载远程文件失败: {}", e)))?
+    {
+        sink.push(&chunk)?;
+    }
+    sink.finish()?;
+    Ok(filename::sanitize(&file.filename))
+}
+
+/// Writes an incoming PDF (or image) to disk, stopping as soon as it exceeds
+/// the size limit or doesn't start like a supported file
+struct PdfSink {
+    writer: state::InputWriter,
+    head: Vec<u8>,
+    size: usize,
+}
+
+impl PdfSink {
+    fn create(task_id: &str) -> Result<Self, AppError> {
+        let writer = state::InputWriter::create(task_id).map_err(StorageError::SaveInput)?;
+        Ok(Self { writer, head: Vec::with_capacity(4), size: 0 })
+    }
+
+    fn push(&mut self, chunk: &[u8]) -> Result<(), AppError> {
+        self.size += chunk.len();
+        if self.size > MAX_FILE_SIZE {
+            return Err(AppError::TooLarge);
+        }
+        // Chunks can be tiny, so collect the magic bytes across them
+        if self.head.len() < 4 {
+            self.head.extend(chunk.iter().take(4 - self.head.len()));
+            if self.head.len() == 4 && !is_supported(&self.head) {
+                return Err(AppError::UnsupportedFile);
+            }
+        }
+        Ok(self.writer.write(chunk).map_err(StorageError::SaveInput)?)
+    }
+
+    fn finish(self) -> Result<(), AppError> {
+        if self.head.len() < 4 || !is_supported(&self.head) {
+            return Err(AppError::UnsupportedFile);
+        }
+        Ok(self.writer.finish().map_err(StorageError::SaveInput)?)
+    }
+}
+
+/// PDF, or an image translated as a one-page document
+fn is_supported(head: &[u8]) -> bool {
+    head == b"%PDF" || pdf::is_image(head)
+}
+
+pub async fn read_text_field(field: axum::extract::multipart::Field<'_>) -> Result<String, AppError> {
+    field.text().await
+        .map(|t| t.trim().to_string())
+        .map_err(|e| AppError::BadRequest(format!("Read error: {}", e)))
+}
+
+/// Per-task options: config defaults overridden by upload form fields
+pub fn task_options(state: &AppState, form: &UploadForm) -> Result<state::TaskOptions, String> {
+    let mut options = state::TaskOptions {
+        mode: TaskMode::default(),
+        layout: state.config.output_layout,
+        output_mode: state.config.output_mode,
+        target_lang: state.config.target_lang,
+        extra_langs: Vec::new(),
+        romanize: state.config.romanize,
+        background: false,
+        destination: None,
+        previous_task: None,
+        review_ocr: false,
+        ocr_model: None,
+        translate_model: None,
+        max_retries: None,
+    };
+    if let Some(mode) = form.mode.as_deref().filter(|m| !m.is_empty()) {
+        options.mode = TaskMode::parse(mode)
+            .ok_or_else(|| format!("不支持的处理模式: {}", mode))?;
+    }
+    if let Some(layout) = form.layout.as_deref().filter(|l| !l.is_empty()) {
+        options.layout = pdf::Layout::parse(layout)
+            .ok_or_else(|| format!("不支持的排版方式: {}", layout))?;
+    }
+    if let Some(output) = form.output.as_deref().filter(|o| !o.is_empty()) {
+        options.output_mode = pdf::OutputMode::parse(output)
+            .ok_or_else(|| format!("不支持的输出模式: {}", output))?;
+    }
+    let mut langs: Vec<lang::TargetLang> = Vec::new();
+    for code in form.target_langs.iter().flat_map(|l| l.split(',')).map(str::trim).filter(|l| !l.is_empty()) {
+        let lang = lang::TargetLang::parse(code)
+            .ok_or_else(|| format!("不支持的目标语言: {}", code))?;
+        if !langs.contains(&lang) {
+            langs.push(lang);
+        }
+    }
+    if let Some((first, rest)) = langs.split_first() {
+        options.target_lang = *first;
+        options.extra_langs = rest.to_vec();
+    }
+    // Without a translation there is nothing to set beside the recognized
+    // text; target_lang then only names the document's language
+    if options.mode == TaskMode::Ocr {
+        if form.output.as_deref().is_some_and(|o| !o.is_empty()) && options.output_mode.needs_originals() {
+            return Err("仅识别模式不支持对照输出".to_string());
+        }
+        if !options.extra_langs.is_empty() {
+            return Err("仅识别模式只能指定一种语言".to_string());
+        }
+        options.output_mode = pdf::OutputMode::Translated;
+    }
+    if let Some(romanize) = form.romanize.as_deref().filter(|r| !r.is_empty()) {
+        options.romanize = pdf::Romanize::parse(romanize)
+            .ok_or_else(|| format!("不支持的注音选项: {}", romanize))?;
+    }
+    if options.romanize == pdf::Romanize::Original && !options.output_mode.needs_originals() {
+        return Err("原文注音需要对照或逐句对照输出模式 (output=bilingual / interlinear)".to_string());
+    }
+    options.background = match form.priority.as_deref().map(|p| p.to_ascii_lowercase()).as_deref() {
+        None | Some("" | "normal") => false,
+        Some("background" | "low") => true,
+        Some(other) => return Err(format!("不支持的优先级: {}", other)),
+    };
+    if options.background && state.config.max_background_tasks == 0 {
+        return Err("后台任务未启用".to_string());
+    }
+    if let Some(dest) = form.dest.as_deref().map(str::trim).filter(|d| !d.is_empty()) {
+        let username = form.dest_username.clone().filter(|u| !u.is_empty());
+        options.destination = Some(Destination::parse(&state.config, dest, username, form.dest_password.clone())?);
+    }
+    if let Some(previous) = form.previous_task.as_deref().filter(|p| !p.is_empty()) {
+        if state.get_options(previous).is_none() {
+            return Err(format!("上一版本任务不存在: {}", previous));
+        }
+        options.previous_task = Some(previous.to_string());
+    }
+    options.review_ocr = match form.review.as_deref().map(|r| r.trim().to_ascii_lowercase()).as_deref() {
+        None | Some("" | "0" | "false" | "off" | "no") => false,
+        Some("1" | "true" | "on" | "yes") => true,
+        Some(other) => return Err(format!("无效的审阅选项: {}", other)),
+    };
+    if options.review_ocr && options.mode == TaskMode::Ocr {
+        return Err("仅识别模式不支持翻译前审阅".to_string());
+    }
+    options.ocr_model = allowed_model(&state.config, form.ocr_model.as_deref(), &state.config.ocr_model)?;
+    options.translate_model = allowed_model(&state.config, form.translate_model.as_deref(), &state.config.translate_model)?;
+    if let Some(retries) = form.retries.as_deref().map(str::trim).filter(|r| !r.is_empty()) {
+        options.max_retries = Some(retries.parse().ok().filter(|n| *n <= MAX_TASK_RETRIES)
+            .ok_or_else(|| format!("无效的重试次数: {} (0-{})", retries, MAX_TASK_RETRIES))?);
+    }
+    Ok(options)
+}
+
+/// A model picked in the form, if it differs from the configured one; only
+/// ALLOWED_MODELS may be picked
+fn allowed_model(config: &config::Config, model: Option<&str>, configured: &str) -> Result<Option<String>, String> {
+    let Some(model) = model.map(str::trim).filter(|m| !m.is_empty() && *m != configured) else {
+        return Ok(None);
+    };
+    if !config.allowed_models.iter().any(|m| m == model) {
+        if config.allowed_models.is_empty() {
+            return Err(format!("本服务不支持指定模型: {}", model));
+        }
+        return Err(format!("不允许的模型: {} (可选: {})", model, config.allowed_models.join(", ")));
+    }
+    Ok(Some(model.to_string()))
+}
+
+/// For a page selection, the reduced PDF and the selected page numbers
+fn range_upload(ranges: Option<&pdf::PageRanges>, data: &[u8]) -> Result<Option<(Vec<u8>, state::PageRange)>, PdfError> {
+    let Some(ranges) = ranges else {
+        return Ok(None);
+    };
+    // An image is a single page: the selection can only be all of it
+    if pdf::is_image(data) {
+        return ranges.resolve(1).map(|_| None);
+    }
+    let (reduced, source_pages, pages) = pdf::extract_page_ranges(data, ranges)?;
+    Ok(Some((reduced, state::PageRange { source_pages, pages })))
+}
+
+/// For a sample upload, the reduced PDF and the sample description
+fn sample_upload(state: &AppState, field: &Option<String>, data: &[u8]) -> Result<Option<(Vec<u8>, state::SampleInfo)>, AppError> {
+    let count = match field.as_deref().map(|s| s.to_ascii_lowercase()).as_deref() {
+        None | Some("" | "0" | "false" | "off" | "no") => return Ok(None),
+        Some("true" | "on" | "yes") => state.config.sample_pages,
+        Some(n) => n.parse::<usize>()
+            .ok()
+            .filter(|n| *n > 0)
+            .ok_or_else(|| AppError::BadRequest(format!("无效的试译页数: {}", n)))?,
+    };
+    if pdf::is_image(data) {
+        return Ok(None);
+    }
+    let (sample, source_pages, pages) = pdf::extract_sample(data, count)
+        .map_err(|e| AppError::BadRequest(format!("提取试译页面失败: {}", e)))?;
+    Ok(Some((sample, state::SampleInfo { source_pages, pages, projected_tokens: None, projected_cost: None })))
+}
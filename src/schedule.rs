@@ -0,0 +1,266 @@
+use chrono::{DateTime, Datelike, Duration, TimeZone, Timelike, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::state;
+
+/// Upload fields that are credentials: stored so the job can run, never listed
+pub const SECRET_FIELDS: &[&str] = &["token", "password", "dest_password"];
+
+/// A five-field cron expression (minute hour day-of-month month day-of-week),
+/// evaluated in UTC. Fields take `*`, numbers, ranges, lists and `/step`.
+#[derive(Clone, Debug)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Day-of-month and day-of-week both restricted: either may match, as in cron
+    any_day: bool,
+}
+
+impl Cron {
+    pub fn parse(expr: &str) -> Option<Self> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields.as_slice() else {
+            return None;
+        };
+        let mut weekdays = parse_field(weekday, 0, 7)?;
+        // 7 is Sunday too
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Some(Self {
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)?,
+            days: parse_field(day, 1, 31)?,
+            months: parse_field(month, 1, 12)?,
+            weekdays,
+            any_day: !day.starts_with('*') && !weekday.starts_with('*'),
+        })
+    }
+
+    fn matches(&self, t: &DateTime<Utc>) -> bool {
+        self.minutes & (1 << t.minute()) != 0
+            && self.hours & (1 << t.hour()) != 0
+            && self.months & (1 << t.month()) != 0
+            && self.matches_day(t)
+    }
+
+    /// First matching minute after `after_ms`, within four years
+    pub fn next_after(&self, after_ms: u64) -> Option<u64> {
+        let after = Utc.timestamp_millis_opt(after_ms as i64).single()?;
+        let mut t = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let end = t + Duration::days(4 * 366);
+        while t < end {
+            if self.months & (1 << t.month()) == 0 {
+                // Skip to the first day of the next month
+                let (year, month) = if t.month() == 12 { (t.year() + 1, 1) } else { (t.year(), t.month() + 1) };
+                t = Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).single()?;
+                continue;
+            }
+            if !self.matches_day(&t) {
+                t = (t + Duration::days(1)).with_hour(0)?.with_minute(0)?;
+                continue;
+            }
+            if self.hours & (1 << t.hour()) == 0 {
+                t = (t + Duration::hours(1)).with_minute(0)?;
+                continue;
+            }
+            if self.matches(&t) {
+                return Some(t.timestamp_millis() as u64);
+            }
+            t += Duration::minutes(1);
+        }
+        None
+    }
+
+    fn matches_day(&self, t: &DateTime<Utc>) -> bool {
+        let day = self.days & (1 << t.day()) != 0;
+        let weekday = self.weekdays & (1 << t.weekday().num_days_from_sunday()) != 0;
+        if self.any_day { day || weekday } else { day && weekday }
+    }
+}
+
+/// Bit set of the values a field allows
+fn parse_field(field: &str, min: u32, max: u32) -> Option<u64> {
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|s| *s > 0)?),
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (a.parse().ok()?, b.parse().ok()?)
+        } else {
+            let value: u32 = range.parse().ok()?;
+            // `5/15` runs from 5 to the end of the field
+            (value, if part.contains('/') { max } else { value })
+        };
+        if start < min || end > max || start > end {
+            return None;
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Some(bits)
+}
+
+/// A document fetched and translated on a cron schedule
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ScheduledJob {
+    pub id: String,
+    pub cron: String,
+    /// Upload form fields (source, url, target_lang, ...) each run is submitted with
+    pub fields: Vec<(String, String)>,
+    /// Each run only translates pages that changed since the last run's task
+    #[serde(default)]
+    pub incremental: bool,
+    pub created_at: u64,
+    /// Due time; a run that can't start yet (no free task slot) stays due
+    pub next_run: u64,
+    #[serde(default)]
+    pub last_run: Option<u64>,
+    /// Task started by the latest run that found new content
+    #[serde(default)]
+    pub last_task_id: Option<String>,
+    /// SHA-256 of the last document translated, to skip unchanged ones
+    #[serde(default)]
+    pub last_hash: Option<String>,
+    /// Outcome of the latest run
+    #[serde(default)]
+    pub last_result: Option<String>,
+}
+
+impl ScheduledJob {
+    /// The job without credential fields, for listing
+    pub fn redacted(&self) -> Self {
+        let mut job = self.clone();
+        job.fields.retain(|(name, _)| !SECRET_FIELDS.contains(&name.as_str()));
+        job
+    }
+}
+
+/// Persistent job list, saved as a whole on every change
+pub struct ScheduleStore {
+    jobs: Mutex<Vec<ScheduledJob>>,
+}
+
+impl ScheduleStore {
+    pub fn load() -> Self {
+        let jobs = fs::read_to_string(state::data_dir().join("schedules.json"))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        Self { jobs: Mutex::new(jobs) }
+    }
+
+    fn save(jobs: &[ScheduledJob]) {
+        let path = state::data_dir().join("schedules.json");
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let tmp_path = path.with_extension("json.tmp");
+        if let Ok(json) = serde_json::to_string_pretty(jobs)
+            && fs::write(&tmp_path, json).is_ok()
+        {
+            let _ = fs::rename(tmp_path, path);
+        }
+    }
+
+    pub fn add(&self, job: ScheduledJob) {
+        let mut jobs = self.jobs.lock();
+        jobs.push(job);
+        Self::save(&jobs);
+    }
+
+    pub fn remove(&self, id: &str) -> bool {
+        let mut jobs = self.jobs.lock();
+        let before = jobs.len();
+        jobs.retain(|j| j.id != id);
+        if jobs.len() == before {
+            return false;
+        }
+        Self::save(&jobs);
+        true
+    }
+
+    pub fn list(&self) -> Vec<ScheduledJob> {
+        self.jobs.lock().clone()
+    }
+
+    /// Jobs whose next run is at or before `now`
+    pub fn due(&self, now: u64) -> Vec<ScheduledJob> {
+        self.jobs.lock().iter().filter(|j| j.next_run <= now).cloned().collect()
+    }
+
+    /// Apply a run's outcome; a job deleted meanwhile is left deleted
+    pub fn update(&self, id: &str, apply: impl FnOnce(&mut ScheduledJob)) {
+        let mut jobs = self.jobs.lock();
+        if let Some(job) = jobs.iter_mut().find(|j| j.id == id) {
+            apply(job);
+            Self::save(&jobs);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Milliseconds since the epoch of a UTC time
+    fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> u64 {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0).unwrap().timestamp_millis() as u64
+    }
+
+    #[test]
+    fn fields_take_steps_ranges_and_lists() {
+        let values = |field, min, max| {
+            let bits = parse_field(field, min, max).unwrap();
+            (min..=max).filter(|v| bits & (1 << v) != 0).collect::<Vec<u32>>()
+        };
+        assert_eq!(values("*/15", 0, 59), [0, 15, 30, 45]);
+        assert_eq!(values("5/20", 0, 59), [5, 25, 45]);
+        assert_eq!(values("1-5", 0, 7), [1, 2, 3, 4, 5]);
+        assert_eq!(values("9-17/4", 0, 23), [9, 13, 17]);
+        assert_eq!(values("1,15,31", 1, 31), [1, 15, 31]);
+        assert_eq!(values("1-3,10-12/2", 1, 12), [1, 2, 3, 10, 12]);
+        // 7 and 0 are both Sunday
+        let cron = Cron::parse("0 0 * * 7").unwrap();
+        assert_eq!(cron.weekdays, 1);
+    }
+
+    #[test]
+    fn invalid_expressions_are_refused() {
+        for expr in [
+            "", "* * * *", "* * * * * *", "60 * * * *", "* 24 * * *", "* * 0 * *", "* * 32 * *",
+            "* * * 13 *", "* * * * 8", "*/0 * * * *", "5-1 * * * *", "a * * * *", "1-2-3 * * * *", "1,,2 * * * *",
+        ] {
+            assert!(Cron::parse(expr).is_none(), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn next_run_is_the_first_matching_minute_after() {
+        let next = |expr: &str, after: u64| Cron::parse(expr).unwrap().next_after(after);
+        // Always strictly later, on a whole minute
+        assert_eq!(next("* * * * *", at(2024, 5, 1, 10, 30) + 15_000), Some(at(2024, 5, 1, 10, 31)));
+        assert_eq!(next("*/15 * * * *", at(2024, 5, 1, 10, 30)), Some(at(2024, 5, 1, 10, 45)));
+        assert_eq!(next("0 8 * * *", at(2024, 5, 1, 8, 0)), Some(at(2024, 5, 2, 8, 0)));
+        // Across the end of a month and a year
+        assert_eq!(next("30 6 1 * *", at(2024, 12, 15, 0, 0)), Some(at(2025, 1, 1, 6, 30)));
+        // 2024-05-01 is a Wednesday; the next Monday is the 6th
+        assert_eq!(next("0 9 * * 1", at(2024, 5, 1, 12, 0)), Some(at(2024, 5, 6, 9, 0)));
+        // Day of month and day of week both given: either one matches
+        assert_eq!(next("0 0 15 * 1", at(2024, 5, 1, 12, 0)), Some(at(2024, 5, 6, 0, 0)));
+        // Leap day, three years ahead
+        assert_eq!(next("0 0 29 2 *", at(2024, 3, 1, 0, 0)), Some(at(2028, 2, 29, 0, 0)));
+        // Never due
+        assert_eq!(next("0 0 31 2 *", at(2024, 3, 1, 0, 0)), None);
+    }
+}
@@ -0,0 +1,241 @@
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use tokio::sync::oneshot;
+
+/// Shares the API concurrency limit between running tasks.
+///
+/// Every page-level API step (one page's OCR, one page's translation) holds a
+/// permit while it runs, and makes its requests (classification, name
+/// detection, retries, continuations) one at a time under it. Connection
+/// warm-up, keepalive pings and model listing take a permit as well, so the
+/// permit count is the cap on concurrent upstream calls across all tasks.
+/// When permits are scarce, waiting requests are served round-robin by task,
+/// so a task that queued its whole batch first can't starve one that arrived
+/// later.
+///
+/// Background tasks only get spare capacity: their requests are served when
+/// no interactive request is waiting, and with more than one permit they
+/// never hold the last, so an interactive page can always start as soon as
+/// it is queued. With a single permit they share it, or could never run; an
+/// interactive page then waits for the background request in flight.
+pub struct PageScheduler {
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    available: usize,
+    /// Most permits background tasks may hold at once
+    background_limit: usize,
+    background_running: usize,
+    interactive: Lane,
+    background: Lane,
+}
+
+/// Tasks with waiting requests, in service order; each with its waiters in arrival order
+#[derive(Default)]
+struct Lane(VecDeque<(String, VecDeque<oneshot::Sender<()>>)>);
+
+impl Lane {
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn push(&mut self, task_id: &str, sender: oneshot::Sender<()>) {
+        match self.0.iter_mut().find(|(task, _)| task == task_id) {
+            Some((_, queue)) => queue.push_back(sender),
+            None => self.0.push_back((task_id.to_string(), VecDeque::from([sender]))),
+        }
+    }
+
+    /// Hand a permit to the next task in turn; false if nobody took it
+    fn grant(&mut self) -> bool {
+        while let Some((task, mut queue)) = self.0.pop_front() {
+            let Some(sender) = queue.pop_front() else {
+                continue;
+            };
+            // The task goes to the back of the rotation if it is still waiting
+            if !queue.is_empty() {
+                self.0.push_back((task, queue));
+            }
+            // A waiter whose request was cancelled no longer needs it
+            if sender.send(()).is_ok() {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// Returned to the scheduler on drop
+pub struct PagePermit {
+    scheduler: Arc<PageScheduler>,
+    background: bool,
+}
+
+impl Drop for PagePermit {
+    fn drop(&mut self) {
+        self.scheduler.release(self.background);
+    }
+}
+
+impl PageScheduler {
+    pub fn new(permits: usize) -> Self {
+        let permits = permits.max(1);
+        Self {
+            inner: Mutex::new(Inner {
+                available: permits,
+                // All but the last permit, yet at least one so they can run
+                background_limit: (permits - 1).max(1),
+                background_running: 0,
+                interactive: Lane::default(),
+                background: Lane::default(),
+            }),
+        }
+    }
+
+    /// Wait for a permit on behalf of `task_id`
+    pub async fn acquire(self: &Arc<Self>, task_id: &str, background: bool) -> PagePermit {
+        let mut pending = {
+            let mut inner = self.inner.lock();
+            let free = inner.available > 0 && inner.interactive.is_empty();
+            if !background && free {
+                inner.available -= 1;
+                return PagePermit { scheduler: self.clone(), background };
+            }
+            if background && free && inner.background.is_empty() && inner.background_running < inner.background_limit {
+                inner.available -= 1;
+                inner.background_running += 1;
+                return PagePermit { scheduler: self.clone(), background };
+            }
+            let (sender, receiver) = oneshot::channel();
+            if background {
+                inner.background.push(task_id, sender);
+            } else {
+                inner.interactive.push(task_id, sender);
+            }
+            Pending { receiver: Some(receiver), scheduler: self.clone(), background }
+        };
+        if let Some(receiver) = pending.receiver.as_mut() {
+            // The sender is only dropped after handing over a permit, or never
+            let _ = receiver.await;
+        }
+        pending.receiver = None;
+        PagePermit { scheduler: self.clone(), background }
+    }
+
+    /// Hand the permit to the next waiter, interactive first, or put it back
+    fn release(&self, background: bool) {
+        let mut inner = self.inner.lock();
+        if background {
+            inner.background_running -= 1;
+        }
+        if inner.interactive.grant() {
+            return;
+        }
+        if inner.background_running < inner.background_limit && inner.background.grant() {
+            inner.background_running += 1;
+            return;
+        }
+        inner.available += 1;
+    }
+}
+
+/// A queued request; if it is dropped (the page was cancelled) after being
+/// handed a permit it never used, the permit goes back to the scheduler
+struct Pending {
+    receiver: Option<oneshot::Receiver<()>>,
+    scheduler: Arc<PageScheduler>,
+    background: bool,
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        if let Some(mut receiver) = self.receiver.take() {
+            receiver.close();
+            if receiver.try_recv().is_ok() {
+                self.scheduler.release(self.background);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    /// Queue a request for a permit that notes `label` once granted and then
+    /// returns the permit at once
+    async fn queue_request(scheduler: &Arc<PageScheduler>, order: &Arc<parking_lot::Mutex<Vec<&'static str>>>, task_id: &'static str, background: bool, label: &'static str) {
+        let (scheduler, order) = (scheduler.clone(), order.clone());
+        tokio::spawn(async move {
+            let _permit = scheduler.acquire(task_id, background).await;
+            order.lock().push(label);
+        });
+        // Let it reach the queue before the next one
+        tokio::task::yield_now().await;
+    }
+
+    #[tokio::test]
+    async fn scheduler_serves_waiting_tasks_round_robin() {
+        let scheduler = Arc::new(PageScheduler::new(1));
+        let order = Arc::default();
+        let held = scheduler.acquire("first", false).await;
+        queue_request(&scheduler, &order, "a", false, "a1").await;
+        queue_request(&scheduler, &order, "a", false, "a2").await;
+        queue_request(&scheduler, &order, "a", false, "a3").await;
+        queue_request(&scheduler, &order, "b", false, "b1").await;
+        drop(held);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        // b arrived after a had queued its whole batch, yet goes second
+        assert_eq!(*order.lock(), ["a1", "b1", "a2", "a3"]);
+    }
+
+    #[tokio::test]
+    async fn scheduler_background_yields_to_interactive() {
+        let scheduler = Arc::new(PageScheduler::new(1));
+        let order = Arc::default();
+        let held = scheduler.acquire("first", false).await;
+        queue_request(&scheduler, &order, "archive", true, "background").await;
+        queue_request(&scheduler, &order, "upload", false, "interactive").await;
+        drop(held);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(*order.lock(), ["interactive", "background"]);
+
+        // With more than one permit background work leaves the last one free
+        let scheduler = Arc::new(PageScheduler::new(2));
+        let _background = scheduler.acquire("archive", true).await;
+        let waiting = tokio::time::timeout(Duration::from_millis(20), scheduler.acquire("archive", true)).await;
+        assert!(waiting.is_err());
+        let interactive = tokio::time::timeout(Duration::from_millis(20), scheduler.acquire("upload", false)).await;
+        assert!(interactive.is_ok());
+    }
+
+    #[tokio::test]
+    async fn scheduler_takes_back_permits_of_dropped_waiters() {
+        let scheduler = Arc::new(PageScheduler::new(1));
+        let held = scheduler.acquire("first", false).await;
+        // Cancelled while queued
+        let queued = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move { drop(scheduler.acquire("a", false).await) }
+        });
+        tokio::task::yield_now().await;
+        queued.abort();
+        let _ = queued.await;
+        // Cancelled after being handed the permit, before using it
+        let granted = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move { drop(scheduler.acquire("b", false).await) }
+        });
+        tokio::task::yield_now().await;
+        drop(held);
+        granted.abort();
+        let _ = granted.await;
+
+        let next = tokio::time::timeout(Duration::from_millis(20), scheduler.acquire("c", false)).await;
+        assert!(next.is_ok(), "the permit was lost");
+    }
+}
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::state::{self, AppState};
+use crate::{render, retention, routes, translate, workers};
+
+/// Serve the HTTP API and the built-in UI on PORT (8080 by default) for the
+/// life of the process, after printing the effective settings. Panics on
+/// settings it can't start with, such as an unusable PDFTOPPM_PATH.
+pub async fn serve(config: Config) {
+    println!("PDF Translator V2 (Parallel) starting...");
+    println!("API: {} ({})", config.base_url, config.provider.as_str());
+    println!("OCR Model: {} (fallback: {:?})", config.ocr_model, config.ocr_model_fallback);
+    println!("OCR image: {} (quality {}, fallback model: {})", config.ocr_image_format.as_str(), config.ocr_image_quality,
+        config.ocr_image_format_fallback.unwrap_or(config.ocr_image_format).as_str());
+    println!("Translate Model: {} (fallback: {:?})", config.translate_model, config.translate_model_fallback);
+    if !config.allowed_models.is_empty() {
+        println!("Models uploads may pick: {}", config.allowed_models.join(", "));
+    }
+    println!("Max concurrent tasks: {} (+{} background, API concurrency: {}, pages per task: {})",
+        config.max_concurrent_tasks, config.max_background_tasks, config.api_concurrency, config.page_concurrency);
+    if let Some(path) = &config.pdftoppm_path {
+        let exe = render::set_pdftoppm(path).unwrap_or_else(|e| panic!("Invalid PDFTOPPM_PATH: {}", e));
+        println!("pdftoppm: {}", exe.display());
+    }
+    println!("Page renderer: {}", render::init(config.pdfium_path.as_deref(), config.renderer));
+    if let Some(dir) = &config.render_temp_dir {
+        render::set_temp_dir(dir).unwrap_or_else(|e| panic!("Invalid RENDER_TEMP_DIR {}: {}", dir.display(), e));
+        println!("Render temp dir: {}", dir.display());
+    }
+    println!("CPU pool: {}", workers::init(config.cpu_workers));
+    if config.privacy_mode {
+        println!("Privacy mode: previews and logs carry no document text");
+    }
+    if config.local_only {
+        println!("Local-only mode: every outbound endpoint is local");
+    }
+    if !config.remote_sources.is_empty() {
+        let sources: Vec<&str> = config.remote_sources.iter().map(|s| s.as_str()).collect();
+        println!("Remote sources: {}", sources.join(", "));
+    }
+    if !config.remote_destinations.is_empty() {
+        let destinations: Vec<&str> = config.remote_destinations.iter().map(|d| d.as_str()).collect();
+        println!("Remote destinations: {}", destinations.join(", "));
+    }
+    if let Some(policy) = &config.content_policy {
+        println!("Content policy: {} rules (classifier: {})", policy.rules.len(),
+            policy.model.as_deref().unwrap_or(&config.translate_model));
+    }
+    if !config.hooks.is_empty() {
+        let mut hooks: Vec<&str> = config.hooks.keys().map(|p| p.as_str()).collect();
+        hooks.sort();
+        println!("Pipeline hooks: {}", hooks.join(", "));
+    }
+    if !config.body_fonts.is_empty() {
+        let mut fonts: Vec<String> = config.body_fonts.iter().map(|(lang, font)| format!("{}={}", lang.code(), font.name)).collect();
+        fonts.sort();
+        println!("Body fonts: {}", fonts.join(", "));
+    }
+    println!("Timeouts: OCR {}s x{} retries, translate {}s x{} retries",
+        config.ocr_timeout_secs, config.ocr_max_retries,
+        config.translate_timeout_secs, config.translate_max_retries);
+    
+    if config.retain_complete_hours.is_some() || config.retain_failed_hours.is_some() {
+        let hours = |h: Option<u64>| h.map_or("forever".to_string(), |h| format!("{}h", h));
+        println!("Retention: complete {}, failed {}", hours(config.retain_complete_hours), hours(config.retain_failed_hours));
+    }
+    if config.trash_hours > 0 {
+        println!("Trash: deleted tasks kept {}h", config.trash_hours);
+    }
+    if let Some(secs) = config.progress_snapshot_secs {
+        println!("Progress snapshots: every {}s", secs);
+    }
+    if let Some(secs) = config.api_keepalive_secs {
+        println!("API keepalive: every {}s", secs);
+    }
+    
+    let state = Arc::new(AppState::new(config));
+    retention::sweep_orphans(&state);
+    if let Some(secs) = state.config.api_keepalive_secs {
+        tokio::spawn(translate::keepalive_loop(state.config.clone(), state.scheduler.clone(), secs));
+    }
+    
+    if state.config.index_page.is_none() {
+        println!("Built-in UI disabled (API only)");
+    }
+    tokio::spawn(routes::run_schedules(state.clone()));
+    tokio::spawn(routes::run_jobs(state.clone()));
+    tokio::spawn(retention::run_cleanup(state.clone()));
+    tokio::spawn(state::run_snapshots(state.clone()));
+    let app = routes::app(state);
+
+    let port = std::env::var("PORT").unwrap_or_else(|_| "8080".to_string());
+    let addr = format!("0.0.0.0:{}", port);
+    let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
+    println!("Server running at http://localhost:{}", port);
+    axum::serve(listener, app).await.unwrap();
+}
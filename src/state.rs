@@ -1,51 +1,100 @@
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fs;
 use std::io::Write;
+use tokio::sync::watch;
 
+use crate::annotate::Annotation;
 use crate::config::Config;
+use crate::deadletter::{DeadLetter, DeadLetterStore, RequestParams};
+use crate::destination::{Destination, PublishedFile};
+use crate::error::{AppError, StorageError};
+use crate::filename;
+use crate::integrity::{self, Artifact, Manifest, Verification};
+use crate::job::{self, JobStore};
+use crate::lang::TargetLang;
+use crate::migrate;
+use crate::pdf::{ImageFormat, Layout, OutputMode, Romanize, StreamCache, format_page_list};
+use crate::preferences::PreferencesStore;
+use crate::provider::ModelListCache;
+use crate::resources::{self, ResourceUsage};
+use crate::schedule::ScheduleStore;
+use crate::stats::StatsStore;
+use crate::scheduler::PageScheduler;
+use crate::usage::Usage;
+use crate::translate;
+use crate::workers;
 
-const DATA_DIR: &str = "data/tasks";
+static DATA_ROOT: OnceLock<PathBuf> = OnceLock::new();
 
-pub const MAX_CONCURRENT_TASKS: usize = 1;
 const MAX_LOGS: usize = 50;
+/// Longest carried-over translation context, in characters
+const MAX_CONTEXT_CHARS: usize = 1500;
 
-#[derive(Clone, Serialize, PartialEq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum TaskStatus {
     Rendering,
     Processing,  // Combined OCR + Translate (parallel)
+    /// OCR is done and its text waits to be reviewed and approved before
+    /// translation starts; the task holds no slot meanwhile
+    AwaitingReview,
     Generating,
     Complete,
+    /// Nothing to translate: the document is already in the target language
+    Skipped,
     Error,
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct LogEntry {
     pub ts: u64,
     pub msg: String,
 }
 
-#[derive(Clone, Serialize, Default)]
+#[derive(Clone, Serialize, Deserialize, Default)]
 pub struct PageSummary {
     pub page_num: usize,
     pub ocr_started: Option<u64>,
     pub ocr_duration_ms: Option<u64>,
     pub ocr_chars: Option<usize>,
     pub ocr_text_preview: Option<String>,      // OCR 识别的文本预览（前200字）
+    pub ocr_usage: Option<Usage>,
+    pub ocr_model: Option<String>,               // 产生识别结果的模型（备用模型接手时可见）
     pub translate_started: Option<u64>,
     pub translate_duration_ms: Option<u64>,
     pub translated_chars: Option<usize>,
     pub translated_text_preview: Option<String>, // 翻译结果预览（前200字）
-    pub status: String,  // "pending", "ocr", "translating", "done", "error"
+    pub translate_usage: Option<Usage>,
+    pub translate_model: Option<String>,
+    pub check_warning: Option<String>,           // 译文自动检查重译后仍未通过的原因
+    pub continuations: Option<u32>,              // 回复达到长度上限后自动续写的次数 (OCR 与翻译合计)
+    pub retries: Option<PageRetries>,            // 当前阶段的重试策略与已用次数
+    pub status: String,  // "pending", "ocr", "review", "translating", "done", "error"
     pub error: Option<String>,
 }
 
-#[derive(Clone, Serialize)]
+/// Retry budget of a page's current stage and how much of it is spent. The
+/// budget is per request: a continuation or a fallback model starts afresh.
+#[derive(Clone, Serialize, Deserialize, Default)]
+pub struct PageRetries {
+    /// `OCR` or `翻译`
+    pub stage: String,
+    pub max_retries: u32,
+    /// Retries of the latest request so far
+    pub used: u32,
+    pub remaining: u32,
+    /// Wait before the latest retry
+    pub last_delay_ms: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct TaskProgress {
     pub status: TaskStatus,
     pub total_pages: usize,
@@ -54,13 +103,75 @@ pub struct TaskProgress {
     pub message: String,
     pub overall_percent: u8,
     pub filename: String,
+    /// Taken from the document: its metadata title, or the first heading of
+    /// the first page's translation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
     pub logs: Vec<LogEntry>,
     pub page_summaries: Vec<PageSummary>,
+    pub usage: Usage,
+    /// Set for sample (preview) tasks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample: Option<SampleInfo>,
+    /// Set when only some pages of the upload are processed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_range: Option<PageRange>,
+    /// Results pushed to the task's destination, set before it completes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub published: Vec<PublishedFile>,
+    /// Processed with LOCAL_ONLY on: no request left the configured local endpoints
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub local_only: bool,
+    /// Output pages with a preview image, numbered from 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview_pages: Option<usize>,
+    /// The OCR text was reviewed and approved; translation works from it as saved
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ocr_reviewed: bool,
+    /// Memory, rendering, API traffic and disk the task has used so far
+    #[serde(default, skip_serializing_if = "ResourceUsage::is_empty")]
+    pub resources: ResourceUsage,
+    /// Pages finished per minute over the last THROUGHPUT_WINDOW pages; kept
+    /// once the task ends as the rate it ran at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pages_per_minute: Option<f64>,
+    /// Seconds until every page is processed at that rate, while pages are
+    /// processed; PDF generation is not included
+    #[serde(default, skip_serializing_if = "Option::is_none", skip_deserializing)]
+    pub eta_seconds: Option<u64>,
+}
+
+/// A preview run over a few evenly spaced pages, with the cost of the whole
+/// document extrapolated from it once the sample completes
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SampleInfo {
+    /// Page count of the uploaded document
+    pub source_pages: usize,
+    /// Original numbers of the sampled pages, in order
+    pub pages: Vec<usize>,
+    pub projected_tokens: Option<u64>,
+    pub projected_cost: Option<f64>,
+}
+
+/// Pages picked with the `pages` upload field
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PageRange {
+    /// Page count of the uploaded document
+    pub source_pages: usize,
+    /// Original numbers of the selected pages, in order
+    pub pages: Vec<usize>,
 }
 
 impl TaskProgress {
     pub fn is_done(&self) -> bool {
-        matches!(self.status, TaskStatus::Complete | TaskStatus::Error)
+        matches!(self.status, TaskStatus::Complete | TaskStatus::Skipped | TaskStatus::Error)
+    }
+
+    /// Number in the uploaded file of a processed page
+    pub fn source_page(&self, page_num: usize) -> usize {
+        let pages = self.sample.as_ref().map(|s| &s.pages)
+            .or(self.page_range.as_ref().map(|r| &r.pages));
+        pages.and_then(|p| p.get(page_num - 1).copied()).unwrap_or(page_num)
     }
 }
 
@@ -68,19 +179,192 @@ impl TaskProgress {
 pub struct TaskSummary {
     pub task_id: String,
     pub filename: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
     pub status: TaskStatus,
     pub overall_percent: u8,
     pub ocr_done: usize,
     pub translate_done: usize,
     pub total_pages: usize,
+    pub usage: Usage,
+    pub resources: ResourceUsage,
+    pub background: bool,
+    /// In the trash since; purged TRASH_HOURS later
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<u64>,
+}
+
+/// Space taken by task files and queued batch files against DATA_MAX_BYTES
+#[derive(Clone, Serialize)]
+pub struct StorageStatus {
+    pub used_bytes: u64,
+    pub max_bytes: Option<u64>,
+    pub full: bool,
+}
+
+/// Progress visible through a share link: counts and per-page states only, no text
+#[derive(Clone, Serialize)]
+pub struct PublicStatus {
+    pub status: TaskStatus,
+    pub overall_percent: u8,
+    pub total_pages: usize,
+    pub ocr_done: usize,
+    pub translate_done: usize,
+    pub page_states: Vec<String>,
+}
+
+/// What a task produces
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TaskMode {
+    #[default]
+    Translate,
+    /// Recognized text only: no translation, the output is typeset from the OCR text
+    Ocr,
+}
+
+impl TaskMode {
+    pub const ALL: [TaskMode; 2] = [TaskMode::Translate, TaskMode::Ocr];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "translate" | "translation" => Some(TaskMode::Translate),
+            "ocr" => Some(TaskMode::Ocr),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskMode::Translate => "translate",
+            TaskMode::Ocr => "ocr",
+        }
+    }
+}
+
+/// Options chosen at upload time, applied for the whole task (including retries)
+#[derive(Clone, Default)]
+pub struct TaskOptions {
+    pub mode: TaskMode,
+    pub layout: Layout,
+    pub output_mode: OutputMode,
+    pub target_lang: TargetLang,
+    /// Further languages translated from the same OCR text, each with its own PDF
+    pub extra_langs: Vec<TargetLang>,
+    pub romanize: Romanize,
+    /// Low priority: pages only use API capacity no regular task is waiting for
+    pub background: bool,
+    /// Cloud storage the results are pushed to on completion
+    pub destination: Option<Destination>,
+    /// Earlier version of the document; pages whose OCR text is unchanged
+    /// reuse its translations
+    pub previous_task: Option<String>,
+    /// Pause after OCR until the recognized text is reviewed and approved
+    pub review_ocr: bool,
+    /// Picked from ALLOWED_MODELS in place of OCR_MODEL
+    pub ocr_model: Option<String>,
+    /// Picked from ALLOWED_MODELS in place of MODEL
+    pub translate_model: Option<String>,
+    /// Replaces OCR_MAX_RETRIES and TRANSLATE_MAX_RETRIES, e.g. 0 to fail fast
+    pub max_retries: Option<u32>,
+}
+
+impl TaskOptions {
+    /// Primary language first, then the extra ones
+    pub fn all_langs(&self) -> Vec<TargetLang> {
+        std::iter::once(self.target_lang).chain(self.extra_langs.iter().copied()).collect()
+    }
+
+    /// Translation file suffix: the primary language keeps the plain name
+    pub fn lang_suffix(&self, lang: TargetLang) -> Option<&'static str> {
+        (lang != self.target_lang).then(|| lang.code())
+    }
+
+    /// Tag in the names of downloaded files: the language, or `ocr` for
+    /// untranslated text
+    pub fn output_tag(&self, lang: TargetLang) -> &'static str {
+        match self.mode {
+            TaskMode::Translate => lang.code(),
+            TaskMode::Ocr => TaskMode::Ocr.as_str(),
+        }
+    }
+
+    /// The server config with the models and retries picked for this task;
+    /// the fallback models still apply
+    pub fn config(&self, config: &Config) -> Config {
+        Config {
+            ocr_model: self.ocr_model.clone().unwrap_or_else(|| config.ocr_model.clone()),
+            translate_model: self.translate_model.clone().unwrap_or_else(|| config.translate_model.clone()),
+            ocr_max_retries: self.max_retries.unwrap_or(config.ocr_max_retries),
+            translate_max_retries: self.max_retries.unwrap_or(config.translate_max_retries),
+            ..config.clone()
+        }
+    }
 }
 
 pub struct TaskData {
     pub progress: TaskProgress,
-    pub pdf_data: Option<Arc<Vec<u8>>>,
+    pub options: TaskOptions,
+    /// Generated PDF per target language, saved under the task directory
+    pub outputs: HashMap<TargetLang, PathBuf>,
+    /// Checksums of everything the task produced, for integrity audits
+    pub manifest: Manifest,
     pub cancelled: bool,
     pub started_at: u64,
     pub is_retrying: bool,
+    /// Compressed output streams reused when the PDF is regenerated after an edit
+    pub render_cache: Arc<StreamCache>,
+    /// Set once the task is shared; grants read-only access to its progress
+    pub share_token: Option<String>,
+    /// When the task was moved to the trash
+    pub deleted_at: Option<u64>,
+    /// Ticks on every progress change; progress streams wait on it
+    pub updates: watch::Sender<()>,
+    /// When pages started being processed, then when each of the last
+    /// THROUGHPUT_WINDOW pages finished
+    pub page_finishes: VecDeque<u64>,
+    /// Progress changed since task.json was last written; the next snapshot
+    /// writes it
+    pub unsaved: AtomicBool,
+    /// Counts task.json writes. A snapshot written off the lock only lands
+    /// if no newer write was started since it was taken.
+    pub saves: Arc<Mutex<u64>>,
+}
+
+/// Pages the throughput is measured over: recent enough to follow a provider
+/// slowing down, enough to even out pages of different lengths
+const THROUGHPUT_WINDOW: usize = 10;
+
+impl TaskData {
+    /// Wake progress streams for a change that is only written to task.json
+    /// by the next save or snapshot
+    fn notify(&self) {
+        self.updates.send_replace(());
+        self.unsaved.store(true, Ordering::Relaxed);
+    }
+
+    /// Measure throughput from now, when pages start being processed
+    fn start_throughput(&mut self) {
+        self.page_finishes.clear();
+        self.page_finishes.push_back(now_ms());
+    }
+
+    /// Count a finished page toward the throughput and re-estimate the time left
+    fn note_page_finished(&mut self) {
+        let now = now_ms();
+        self.page_finishes.push_back(now);
+        if self.page_finishes.len() > THROUGHPUT_WINDOW + 1 {
+            self.page_finishes.pop_front();
+        }
+        let Some(&since) = self.page_finishes.front() else { return };
+        let pages = self.page_finishes.len() - 1;
+        if pages == 0 {
+            return;
+        }
+        let per_minute = pages as f64 * 60_000.0 / now.saturating_sub(since).max(1) as f64;
+        let remaining = self.progress.total_pages.saturating_sub(self.progress.translate_done);
+        self.progress.pages_per_minute = Some((per_minute * 10.0).round() / 10.0);
+        self.progress.eta_seconds = Some((remaining as f64 * 60.0 / per_minute).round() as u64);
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -90,18 +374,44 @@ pub struct PageDetail {
     pub translated_text: String,
 }
 
-fn now_ms() -> u64 {
+pub fn now_ms() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .map(|d| d.as_millis() as u64)
         .unwrap_or(0)
 }
 
-fn task_dir(task_id: &str) -> PathBuf {
-    PathBuf::from(DATA_DIR).join(task_id)
+/// Where tasks, jobs and the stores are kept (DATA_DIR). The first AppState
+/// fixes it for the process; tests get a directory of their own, so they
+/// never touch the data of a checkout they run in.
+pub fn data_dir() -> &'static Path {
+    DATA_ROOT.get_or_init(default_data_dir)
+}
+
+#[cfg(not(test))]
+fn default_data_dir() -> PathBuf {
+    PathBuf::from("data")
+}
+
+#[cfg(test)]
+fn default_data_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("pdftrans-test-{}", std::process::id()))
+}
+
+fn set_data_dir(dir: &Path) {
+    let root = DATA_ROOT.get_or_init(|| dir.to_path_buf());
+    assert_eq!(root, dir, "DATA_DIR can't change once tasks were loaded from it");
+}
+
+fn tasks_dir() -> PathBuf {
+    data_dir().join("tasks")
 }
 
-fn pages_dir(task_id: &str) -> PathBuf {
+pub fn task_dir(task_id: &str) -> PathBuf {
+    tasks_dir().join(task_id)
+}
+
+pub fn pages_dir(task_id: &str) -> PathBuf {
     task_dir(task_id).join("pages")
 }
 
@@ -117,39 +427,130 @@ pub fn save_input_pdf(task_id: &str, data: &[u8]) -> std::io::Result<()> {
     Ok(())
 }
 
+/// An upload being streamed into data/tasks/{id}/input.pdf; the file only
+/// appears under its final name once `finish` succeeds
+pub struct InputWriter {
+    file: fs::File,
+    dir: PathBuf,
+}
+
+impl InputWriter {
+    pub fn create(task_id: &str) -> std::io::Result<Self> {
+        let dir = task_dir(task_id);
+        fs::create_dir_all(&dir)?;
+        let file = fs::File::create(dir.join("input.pdf.tmp"))?;
+        Ok(Self { file, dir })
+    }
+
+    pub fn write(&mut self, chunk: &[u8]) -> std::io::Result<()> {
+        self.file.write_all(chunk)
+    }
+
+    pub fn finish(self) -> std::io::Result<()> {
+        self.file.sync_all()?;
+        fs::rename(self.dir.join("input.pdf.tmp"), self.dir.join("input.pdf"))
+    }
+}
+
+/// The document as processed: the upload, or just its selected or sampled pages
+pub fn input_path(task_id: &str) -> PathBuf {
+    task_dir(task_id).join("input.pdf")
+}
+
 pub fn load_input_pdf(task_id: &str) -> std::io::Result<Vec<u8>> {
-    let path = task_dir(task_id).join("input.pdf");
-    fs::read(path)
+    fs::read(input_path(task_id))
 }
 
 pub fn save_page_ocr(task_id: &str, page_num: usize, text: &str) -> std::io::Result<()> {
     let dir = pages_dir(task_id);
     fs::create_dir_all(&dir)?;
-    let path = dir.join(format!("{}.ocr.txt", page_num));
-    let tmp_path = dir.join(format!("{}.ocr.txt.tmp", page_num));
+    let name = ocr_file_name(page_num);
+    let path = dir.join(&name);
+    let tmp_path = dir.join(format!("{}.tmp", name));
     fs::write(&tmp_path, text)?;
     fs::rename(tmp_path, path)?;
     Ok(())
 }
 
 pub fn save_page_translated(task_id: &str, page_num: usize, text: &str) -> std::io::Result<()> {
+    save_page_translation(task_id, page_num, None, text)
+}
+
+/// Translation into one of the task's languages; `lang` is None for the primary
+/// language, other languages are stored as `{nnnn}.translated.{code}.txt`
+pub fn save_page_translation(task_id: &str, page_num: usize, lang: Option<&str>, text: &str) -> std::io::Result<()> {
     let dir = pages_dir(task_id);
     fs::create_dir_all(&dir)?;
-    let path = dir.join(format!("{}.translated.txt", page_num));
-    let tmp_path = dir.join(format!("{}.translated.txt.tmp", page_num));
+    let name = translated_file_name(page_num, lang);
+    let path = dir.join(&name);
+    let tmp_path = dir.join(format!("{}.tmp", name));
     fs::write(&tmp_path, text)?;
     fs::rename(tmp_path, path)?;
     Ok(())
 }
 
+/// Page files start with the page number zero-padded to this many digits, so
+/// they list in page order
+pub const PAGE_NUM_WIDTH: usize = 4;
+
+fn ocr_file_name(page_num: usize) -> String {
+    format!("{:0w$}.ocr.txt", page_num, w = PAGE_NUM_WIDTH)
+}
+
+fn translated_file_name(page_num: usize, lang: Option<&str>) -> String {
+    match lang {
+        Some(code) => format!("{:0w$}.translated.{}.txt", page_num, code, w = PAGE_NUM_WIDTH),
+        None => format!("{:0w$}.translated.txt", page_num, w = PAGE_NUM_WIDTH),
+    }
+}
+
+/// Image of a page as sent to OCR, in OCR_IMAGE_FORMAT
+pub fn page_image_path(task_id: &str, page_num: usize, format: ImageFormat) -> PathBuf {
+    pages_dir(task_id).join(format!("{:0w$}.image.{}", page_num, format.as_str(), w = PAGE_NUM_WIDTH))
+}
+
+/// Keep the image a page was recognized from; a downgraded render replaces
+/// the first one, and a copy in another format (OCR_IMAGE_FORMAT changed
+/// before a retry) is dropped
+pub fn save_page_image(task_id: &str, page_num: usize, format: ImageFormat, data: &[u8]) -> std::io::Result<()> {
+    let path = page_image_path(task_id, page_num, format);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, data)?;
+    for other in [ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::Webp] {
+        if other != format {
+            let _ = fs::remove_file(page_image_path(task_id, page_num, other));
+        }
+    }
+    Ok(())
+}
+
+/// The stored image of a page and its format
+pub fn find_page_image(task_id: &str, page_num: usize) -> Option<(PathBuf, ImageFormat)> {
+    [ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::Webp].into_iter()
+        .map(|format| (page_image_path(task_id, page_num, format), format))
+        .find(|(path, _)| path.exists())
+}
+
+pub fn page_ocr_path(task_id: &str, page_num: usize) -> PathBuf {
+    pages_dir(task_id).join(ocr_file_name(page_num))
+}
+
+pub fn page_translation_path(task_id: &str, page_num: usize, lang: Option<&str>) -> PathBuf {
+    pages_dir(task_id).join(translated_file_name(page_num, lang))
+}
+
 pub fn load_page_ocr(task_id: &str, page_num: usize) -> Option<String> {
-    let path = pages_dir(task_id).join(format!("{}.ocr.txt", page_num));
-    fs::read_to_string(path).ok()
+    fs::read_to_string(page_ocr_path(task_id, page_num)).ok()
 }
 
 pub fn load_page_translated(task_id: &str, page_num: usize) -> Option<String> {
-    let path = pages_dir(task_id).join(format!("{}.translated.txt", page_num));
-    fs::read_to_string(path).ok()
+    load_page_translation(task_id, page_num, None)
+}
+
+pub fn load_page_translation(task_id: &str, page_num: usize, lang: Option<&str>) -> Option<String> {
+    fs::read_to_string(page_translation_path(task_id, page_num, lang)).ok()
 }
 
 pub fn load_page_detail(task_id: &str, page_num: usize) -> Option<PageDetail> {
@@ -165,58 +566,416 @@ pub fn load_page_detail(task_id: &str, page_num: usize) -> Option<PageDetail> {
     })
 }
 
-pub fn load_all_translated_pages(task_id: &str, total_pages: usize) -> Vec<String> {
-    (1..=total_pages)
-        .map(|i| load_page_translated(task_id, i).unwrap_or_default())
-        .collect()
+/// The text a page contributes to the output in `lang`: its translation, or
+/// the OCR text of an OCR-only task. None until the page is done.
+pub fn load_page_output(task_id: &str, page_num: usize, options: &TaskOptions, lang: TargetLang) -> Option<String> {
+    match options.mode {
+        TaskMode::Translate => load_page_translation(task_id, page_num, options.lang_suffix(lang)),
+        TaskMode::Ocr => load_page_ocr(task_id, page_num),
+    }
 }
 
-pub fn get_completed_page_count(task_id: &str) -> usize {
-    let dir = pages_dir(task_id);
-    if !dir.exists() {
-        return 0;
-    }
-    fs::read_dir(dir)
-        .map(|entries| {
-            entries
-                .filter_map(|e| e.ok())
-                .filter(|e| e.path().extension().map(|ext| ext == "txt").unwrap_or(false))
-                .filter(|e| e.file_name().to_string_lossy().ends_with(".translated.txt"))
-                .count()
-        })
-        .unwrap_or(0)
+pub fn load_all_output_pages(task_id: &str, total_pages: usize, options: &TaskOptions, lang: TargetLang) -> Vec<String> {
+    (1..=total_pages)
+        .map(|i| load_page_output(task_id, i, options, lang).unwrap_or_default())
+        .collect()
 }
 
-fn cleanup_task_files(task_id: &str) {
+pub fn cleanup_task_files(task_id: &str) {
     let dir = task_dir(task_id);
     if dir.exists() {
         let _ = fs::remove_dir_all(dir);
     }
 }
 
+/// What survives a restart: everything except the in-memory output and render cache
+#[derive(Serialize, Deserialize)]
+struct TaskRecord {
+    /// migrate::TASK_SCHEMA_VERSION when written
+    #[serde(default)]
+    schema_version: u32,
+    progress: TaskProgress,
+    #[serde(default)]
+    mode: String,
+    layout: String,
+    output_mode: String,
+    /// Primary language first
+    target_langs: Vec<String>,
+    #[serde(default)]
+    romanize: String,
+    #[serde(default)]
+    background: bool,
+    #[serde(default)]
+    destination: Option<Destination>,
+    #[serde(default)]
+    previous_task: Option<String>,
+    #[serde(default)]
+    review_ocr: bool,
+    #[serde(default)]
+    ocr_model: Option<String>,
+    #[serde(default)]
+    translate_model: Option<String>,
+    #[serde(default)]
+    max_retries: Option<u32>,
+    cancelled: bool,
+    started_at: u64,
+    share_token: Option<String>,
+    #[serde(default)]
+    deleted_at: Option<u64>,
+}
+
+/// Write data/tasks/{id}/task.json and wake the task's progress streams;
+/// failures are logged, the task keeps running
+fn save_task(task_id: &str, task: &TaskData) {
+    task.updates.send_replace(());
+    write_task(task_id, task);
+}
+
+/// Snapshot running tasks' progress every PROGRESS_SNAPSHOT_SECS for the
+/// life of the server; returns right away when snapshots are off
+pub async fn run_snapshots(state: Arc<AppState>) {
+    let Some(secs) = state.config.progress_snapshot_secs else { return };
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(secs));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        state.snapshot_progress().await;
+    }
+}
+
+fn write_task(task_id: &str, task: &TaskData) {
+    let mut saves = task.saves.lock();
+    *saves += 1;
+    write_record(task_id, &task_record(task));
+}
+
+/// What task.json holds for a task; clears its unsaved flag
+fn task_record(task: &TaskData) -> TaskRecord {
+    task.unsaved.store(false, Ordering::Relaxed);
+    TaskRecord {
+        schema_version: migrate::TASK_SCHEMA_VERSION,
+        progress: task.progress.clone(),
+        mode: task.options.mode.as_str().to_string(),
+        layout: task.options.layout.as_str().to_string(),
+        output_mode: task.options.output_mode.as_str().to_string(),
+        target_langs: task.options.all_langs().iter().map(|l| l.code().to_string()).collect(),
+        romanize: task.options.romanize.as_str().to_string(),
+        background: task.options.background,
+        destination: task.options.destination.clone(),
+        previous_task: task.options.previous_task.clone(),
+        review_ocr: task.options.review_ocr,
+        ocr_model: task.options.ocr_model.clone(),
+        translate_model: task.options.translate_model.clone(),
+        max_retries: task.options.max_retries,
+        cancelled: task.cancelled,
+        started_at: task.started_at,
+        share_token: task.share_token.clone(),
+        deleted_at: task.deleted_at,
+    }
+}
+
+fn write_record(task_id: &str, record: &TaskRecord) {
+    let result = serde_json::to_vec(record)
+        .map_err(std::io::Error::other)
+        .and_then(|json| {
+            let dir = task_dir(task_id);
+            fs::create_dir_all(&dir)?;
+            let tmp_path = dir.join("task.json.tmp");
+            fs::write(&tmp_path, json)?;
+            fs::rename(tmp_path, dir.join("task.json"))
+        });
+    if let Err(e) = result {
+        eprintln!("[state] 保存任务 {} 失败: {}", task_id, e);
+    }
+}
+
+/// Reload every task.json under data/tasks, split into live tasks and the
+/// trash. Tasks that were still running when the server stopped become
+/// errors, so they can be retried.
+fn load_tasks() -> (HashMap<String, TaskData>, HashMap<String, TaskData>) {
+    let (mut tasks, mut trash) = (HashMap::new(), HashMap::new());
+    let Ok(entries) = fs::read_dir(tasks_dir()) else {
+        return (tasks, trash);
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let task_id = entry.file_name().to_string_lossy().to_string();
+        let Ok(json) = fs::read(entry.path().join("task.json")) else {
+            continue;
+        };
+        let mut record: serde_json::Value = match serde_json::from_slice(&json) {
+            Ok(record) => record,
+            Err(e) => {
+                eprintln!("[state] 任务 {} 记录无法解析: {}", task_id, e);
+                continue;
+            }
+        };
+        let migrated_from = match migrate::migrate_task(&task_id, &mut record) {
+            Ok(from) => from,
+            Err(e) => {
+                eprintln!("[state] 任务 {} 已跳过: {}", task_id, e);
+                continue;
+            }
+        };
+        let record: TaskRecord = match serde_json::from_value(record) {
+            Ok(record) => record,
+            Err(e) => {
+                eprintln!("[state] 任务 {} 记录无法解析: {}", task_id, e);
+                continue;
+            }
+        };
+        let mut langs = record.target_langs.iter().filter_map(|code| TargetLang::parse(code));
+        let options = TaskOptions {
+            mode: TaskMode::parse(&record.mode).unwrap_or_default(),
+            layout: Layout::parse(&record.layout).unwrap_or_default(),
+            output_mode: OutputMode::parse(&record.output_mode).unwrap_or_default(),
+            target_lang: langs.next().unwrap_or_default(),
+            extra_langs: langs.collect(),
+            romanize: Romanize::parse(&record.romanize).unwrap_or_default(),
+            background: record.background,
+            destination: record.destination,
+            previous_task: record.previous_task,
+            review_ocr: record.review_ocr,
+            ocr_model: record.ocr_model,
+            translate_model: record.translate_model,
+            max_retries: record.max_retries,
+        };
+        let mut task = TaskData {
+            progress: record.progress,
+            options,
+            outputs: HashMap::new(),
+            manifest: load_manifest(&task_id),
+            cancelled: record.cancelled,
+            started_at: record.started_at,
+            is_retrying: false,
+            render_cache: Arc::new(StreamCache::default()),
+            share_token: record.share_token,
+            deleted_at: record.deleted_at,
+            updates: watch::Sender::new(()),
+            page_finishes: VecDeque::new(),
+            unsaved: AtomicBool::new(false),
+            saves: Arc::default(),
+        };
+        if task.progress.status == TaskStatus::Complete {
+            for lang in task.options.all_langs() {
+                let path = output_path(&task_id, task.options.lang_suffix(lang));
+                if path.exists() {
+                    task.outputs.insert(lang, path);
+                }
+            }
+        }
+        if let Some(from) = migrated_from {
+            let msg = format!("任务数据从版本 {} 升级到 {}", from, migrate::TASK_SCHEMA_VERSION);
+            println!("[state] 任务 {} {}", task_id, msg);
+            task.progress.logs.push(LogEntry { ts: now_ms(), msg });
+            save_task(&task_id, &task);
+        }
+        // A task awaiting review has nothing running to interrupt
+        if !task.progress.is_done() && task.progress.status != TaskStatus::AwaitingReview {
+            task.progress.status = TaskStatus::Error;
+            task.progress.message = "服务重启，任务已中断，可重试继续".to_string();
+            task.progress.logs.push(LogEntry { ts: now_ms(), msg: "服务重启，任务中断".to_string() });
+            save_task(&task_id, &task);
+        }
+        if task.deleted_at.is_some() {
+            trash.insert(task_id, task);
+        } else {
+            tasks.insert(task_id, task);
+        }
+    }
+    (tasks, trash)
+}
+
+fn task_summary(task_id: &str, t: &TaskData) -> TaskSummary {
+    TaskSummary {
+        task_id: task_id.to_string(),
+        filename: t.progress.filename.clone(),
+        title: t.progress.title.clone(),
+        status: t.progress.status.clone(),
+        overall_percent: t.progress.overall_percent,
+        ocr_done: t.progress.ocr_done,
+        translate_done: t.progress.translate_done,
+        total_pages: t.progress.total_pages,
+        usage: t.progress.usage,
+        resources: t.progress.resources,
+        background: t.options.background,
+        deleted_at: t.deleted_at,
+    }
+}
+
+pub fn output_path(task_id: &str, lang: Option<&str>) -> PathBuf {
+    match lang {
+        Some(code) => task_dir(task_id).join(format!("output.{}.pdf", code)),
+        None => task_dir(task_id).join("output.pdf"),
+    }
+}
+
+fn preview_dir(task_id: &str) -> PathBuf {
+    task_dir(task_id).join("preview")
+}
+
+/// PNG of an output page; per language like the output PDF
+pub fn preview_path(task_id: &str, page_num: usize, lang: Option<&str>) -> PathBuf {
+    let dir = preview_dir(task_id);
+    match lang {
+        Some(code) => dir.join(format!("{:0w$}.{}.png", page_num, code, w = PAGE_NUM_WIDTH)),
+        None => dir.join(format!("{:0w$}.png", page_num, w = PAGE_NUM_WIDTH)),
+    }
+}
+
+pub fn clear_previews(task_id: &str) {
+    let _ = fs::remove_dir_all(preview_dir(task_id));
+}
+
+pub fn save_preview(task_id: &str, page_num: usize, lang: Option<&str>, data: &[u8]) -> std::io::Result<()> {
+    let path = preview_path(task_id, page_num, lang);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp_path = path.with_extension("png.tmp");
+    fs::write(&tmp_path, data)?;
+    fs::rename(tmp_path, path)
+}
+
+fn load_manifest(task_id: &str) -> Manifest {
+    fs::read(task_dir(task_id).join("manifest.json")).ok()
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Write data/tasks/{id}/manifest.json; failures are logged like task.json's
+pub fn save_manifest(task_id: &str, manifest: &Manifest) {
+    let result = serde_json::to_vec_pretty(manifest)
+        .map_err(std::io::Error::other)
+        .and_then(|json| {
+            let dir = task_dir(task_id);
+            fs::create_dir_all(&dir)?;
+            let tmp_path = dir.join("manifest.json.tmp");
+            fs::write(&tmp_path, json)?;
+            fs::rename(tmp_path, dir.join("manifest.json"))
+        });
+    if let Err(e) = result {
+        eprintln!("[state] 保存任务 {} 校验清单失败: {}", task_id, e);
+    }
+}
+
+/// Copy of the source PDF with its comments and form fields translated;
+/// per language like the output PDF
+pub fn annotated_path(task_id: &str, lang: Option<&str>) -> PathBuf {
+    match lang {
+        Some(code) => task_dir(task_id).join(format!("annotated.{}.pdf", code)),
+        None => task_dir(task_id).join("annotated.pdf"),
+    }
+}
+
+pub fn save_annotated(task_id: &str, lang: Option<&str>, data: &[u8]) -> std::io::Result<()> {
+    let path = annotated_path(task_id, lang);
+    let tmp_path = path.with_extension("pdf.tmp");
+    fs::write(&tmp_path, data)?;
+    fs::rename(tmp_path, path)
+}
+
+fn annotations_path(task_id: &str, lang: TargetLang) -> PathBuf {
+    task_dir(task_id).join(format!("annotations.{}.json", lang.code()))
+}
+
+/// Translated comments and field values, kept so regenerating the outputs
+/// does not translate them again
+pub fn load_annotations(task_id: &str, lang: TargetLang) -> Option<Vec<Annotation>> {
+    let json = fs::read(annotations_path(task_id, lang)).ok()?;
+    serde_json::from_slice(&json).ok()
+}
+
+pub fn save_annotations(task_id: &str, lang: TargetLang, annotations: &[Annotation]) -> std::io::Result<()> {
+    let json = serde_json::to_vec(annotations).map_err(std::io::Error::other)?;
+    fs::write(annotations_path(task_id, lang), json)
+}
+
+fn save_output(task_id: &str, lang: Option<&str>, data: &[u8]) -> std::io::Result<PathBuf> {
+    let path = output_path(task_id, lang);
+    let tmp_path = path.with_extension("pdf.tmp");
+    fs::write(&tmp_path, data)?;
+    fs::rename(tmp_path, &path)?;
+    Ok(path)
+}
+
 pub struct AppState {
     pub config: Config,
+    pub stats: Arc<StatsStore>,
+    pub dead_letters: DeadLetterStore,
+    pub schedules: ScheduleStore,
+    pub jobs: JobStore,
+    pub preferences: PreferencesStore,
     tasks: RwLock<HashMap<String, TaskData>>,
+    /// Deleted tasks kept for TRASH_HOURS; out of every other lookup
+    trash: RwLock<HashMap<String, TaskData>>,
     active_task_count: AtomicUsize,
+    background_task_count: AtomicUsize,
+    /// Page-level API permits shared round-robin between running tasks
+    pub scheduler: Arc<PageScheduler>,
+    pub models: ModelListCache,
 }
 
 impl AppState {
     pub fn new(config: Config) -> Self {
+        set_data_dir(&config.data_dir);
+        let (tasks, trash) = load_tasks();
         Self {
+            scheduler: Arc::new(PageScheduler::new(config.api_concurrency)),
             config,
-            tasks: RwLock::new(HashMap::new()),
+            stats: Arc::new(StatsStore::load()),
+            dead_letters: DeadLetterStore::load(),
+            schedules: ScheduleStore::load(),
+            jobs: JobStore::load(),
+            preferences: PreferencesStore::load(),
+            tasks: RwLock::new(tasks),
+            trash: RwLock::new(trash),
             active_task_count: AtomicUsize::new(0),
+            background_task_count: AtomicUsize::new(0),
+            models: ModelListCache::default(),
         }
     }
 
-    pub fn try_acquire_task_slot(&self) -> bool {
+    /// Current size of data/tasks and of the batch jobs' queued files in
+    /// data/jobs. Walks the directories, so it is measured per upload rather
+    /// than polled.
+    pub fn storage_status(&self) -> StorageStatus {
+        let used_bytes = [tasks_dir(), job::files_dir()].iter()
+            .map(|dir| resources::dir_size(dir))
+            .sum();
+        let max_bytes = self.config.data_max_bytes;
+        StorageStatus { used_bytes, max_bytes, full: max_bytes.is_some_and(|max| used_bytes >= max) }
+    }
+
+    /// Refuse new work once task files fill the storage budget
+    pub fn ensure_storage(&self) -> Result<(), AppError> {
+        if self.config.data_max_bytes.is_none() {
+            return Ok(());
+        }
+        let status = self.storage_status();
+        if !status.full {
+            return Ok(());
+        }
+        Err(AppError::StorageFull(format!(
+            "存储空间已满 (已用 {}，上限 {})，请稍后再试或清理旧任务",
+            resources::format_size(status.used_bytes), resources::format_size(status.max_bytes.unwrap_or_default()),
+        )))
+    }
+
+    /// Background tasks have their own slots, so a long archive job doesn't
+    /// keep regular uploads out
+    pub fn try_acquire_task_slot(&self, background: bool) -> bool {
+        let (count, max) = if background {
+            (&self.background_task_count, self.config.max_background_tasks)
+        } else {
+            (&self.active_task_count, self.config.max_concurrent_tasks)
+        };
         // Use CAS loop for atomic check-and-increment
         loop {
-            let current = self.active_task_count.load(Ordering::SeqCst);
-            if current >= MAX_CONCURRENT_TASKS {
+            let current = count.load(Ordering::SeqCst);
+            if current >= max {
                 return false;
             }
-            match self.active_task_count.compare_exchange(
+            match count.compare_exchange(
                 current,
                 current + 1,
                 Ordering::SeqCst,
@@ -228,16 +987,41 @@ impl AppState {
         }
     }
 
-    pub fn release_task_slot(&self) {
-        self.active_task_count.fetch_sub(1, Ordering::SeqCst);
+    pub fn release_task_slot(&self, background: bool) {
+        if background {
+            self.background_task_count.fetch_sub(1, Ordering::SeqCst);
+        } else {
+            self.active_task_count.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Why `try_acquire_task_slot` refused a task
+    pub fn busy_message(&self, background: bool) -> String {
+        if background {
+            format!("后台任务已满，当前已有 {} 个后台任务在处理，请稍后重试", self.config.max_background_tasks)
+        } else {
+            format!("服务繁忙，当前已有 {} 个任务在处理，请稍后重试", self.config.max_concurrent_tasks)
+        }
+    }
+
+    /// Whether a task could take a slot right now, without taking it
+    pub fn has_free_slot(&self, background: bool) -> bool {
+        if background {
+            self.background_task_count.load(Ordering::SeqCst) < self.config.max_background_tasks
+        } else {
+            self.active_task_count.load(Ordering::SeqCst) < self.config.max_concurrent_tasks
+        }
     }
 
-    #[allow(dead_code)]
     pub fn active_task_count(&self) -> usize {
         self.active_task_count.load(Ordering::SeqCst)
     }
 
-    pub fn create_task(&self, task_id: &str, filename: &str) {
+    pub fn background_task_count(&self) -> usize {
+        self.background_task_count.load(Ordering::SeqCst)
+    }
+
+    pub fn create_task(&self, task_id: &str, filename: &str, options: TaskOptions) {
         let now = now_ms();
         let task = TaskData {
             progress: TaskProgress {
@@ -248,26 +1032,56 @@ impl AppState {
                 message: "正在处理 PDF...".to_string(),
                 overall_percent: 0,
                 filename: filename.to_string(),
-                logs: vec![LogEntry { ts: now, msg: "任务开始".to_string() }],
+                title: None,
+                logs: if self.config.local_only {
+                    vec![
+                        LogEntry { ts: now, msg: "任务开始".to_string() },
+                        LogEntry { ts: now, msg: "本地模式：仅调用本地模型端点，不向外部服务发送数据".to_string() },
+                    ]
+                } else {
+                    vec![LogEntry { ts: now, msg: "任务开始".to_string() }]
+                },
                 page_summaries: Vec::new(),
+                usage: Usage::default(),
+                sample: None,
+                page_range: None,
+                published: Vec::new(),
+                local_only: self.config.local_only,
+                preview_pages: None,
+                ocr_reviewed: false,
+                resources: ResourceUsage::default(),
+                pages_per_minute: None,
+                eta_seconds: None,
             },
-            pdf_data: None,
+            options,
+            outputs: HashMap::new(),
+            manifest: Manifest::default(),
             cancelled: false,
             started_at: now,
             is_retrying: false,
+            render_cache: Arc::new(StreamCache::default()),
+            share_token: None,
+            deleted_at: None,
+            updates: watch::Sender::new(()),
+            page_finishes: VecDeque::new(),
+            unsaved: AtomicBool::new(false),
+            saves: Arc::default(),
         };
+        save_task(task_id, &task);
         self.tasks.write().insert(task_id.to_string(), task);
     }
 
     pub fn cancel_task(&self, task_id: &str) -> bool {
-        if let Some(task) = self.tasks.write().get_mut(task_id) {
-            if !task.progress.is_done() {
-                task.cancelled = true;
-                task.progress.status = TaskStatus::Error;
-                task.progress.message = "任务已取消".to_string();
-                task.progress.logs.push(LogEntry { ts: now_ms(), msg: "任务取消".to_string() });
-                return true;
-            }
+        if let Some(task) = self.tasks.write().get_mut(task_id)
+            && !task.progress.is_done()
+        {
+            task.cancelled = true;
+            task.progress.status = TaskStatus::Error;
+            task.progress.eta_seconds = None;
+            task.progress.message = "任务已取消".to_string();
+            task.progress.logs.push(LogEntry { ts: now_ms(), msg: "任务取消".to_string() });
+            save_task(task_id, task);
+            return true;
         }
         false
     }
@@ -291,6 +1105,7 @@ impl AppState {
                     ..Default::default()
                 })
                 .collect();
+            save_task(task_id, task);
         }
     }
 
@@ -298,7 +1113,9 @@ impl AppState {
         if let Some(task) = self.tasks.write().get_mut(task_id) {
             task.progress.status = TaskStatus::Processing;
             task.progress.message = "并行处理中...".to_string();
+            task.start_throughput();
             task.progress.logs.push(LogEntry { ts: now_ms(), msg: "开始并行 OCR + 翻译".to_string() });
+            save_task(task_id, task);
         }
     }
 
@@ -321,27 +1138,175 @@ impl AppState {
         if let Some(task) = self.tasks.write().get_mut(task_id) {
             task.progress.status = TaskStatus::Generating;
             task.progress.overall_percent = 95;
+            task.progress.eta_seconds = None;
             task.progress.message = "正在生成 PDF...".to_string();
             task.progress.logs.push(LogEntry { ts: now_ms(), msg: "开始生成 PDF".to_string() });
+            save_task(task_id, task);
         }
     }
 
-    pub fn set_complete(&self, task_id: &str, pdf_data: Vec<u8>) {
+    pub fn set_complete(&self, task_id: &str, outputs: HashMap<TargetLang, Vec<u8>>) {
+        let hashes: HashMap<TargetLang, String> = outputs.iter()
+            .map(|(lang, data)| (*lang, integrity::sha256_hex(data)))
+            .collect();
         if let Some(task) = self.tasks.write().get_mut(task_id) {
+            let mut paths = HashMap::new();
+            for (lang, data) in &outputs {
+                match save_output(task_id, task.options.lang_suffix(*lang), data) {
+                    Ok(path) => {
+                        paths.insert(*lang, path);
+                        task.manifest.record(Artifact::Output(*lang), hashes[lang].clone());
+                    }
+                    Err(e) => {
+                        let error = StorageError::SaveOutput(e).to_string();
+                        task.progress.status = TaskStatus::Error;
+                        task.progress.message = error.clone();
+                        task.progress.logs.push(LogEntry { ts: now_ms(), msg: format!("错误: {}", error) });
+                        save_task(task_id, task);
+                        return;
+                    }
+                }
+            }
+            task.outputs = paths;
+            save_manifest(task_id, &task.manifest);
+            task.progress.resources.disk_bytes = resources::dir_size(&task_dir(task_id));
             let elapsed = (now_ms() - task.started_at) / 1000;
             task.progress.status = TaskStatus::Complete;
             task.progress.overall_percent = 100;
             task.progress.message = format!("完成！用时 {} 秒", elapsed);
-            task.progress.logs.push(LogEntry { ts: now_ms(), msg: format!("完成，用时 {} 秒", elapsed) });
-            task.pdf_data = Some(Arc::new(pdf_data));
+            let usage = &task.progress.usage;
+            let msg = format!(
+                "完成，用时 {} 秒，消耗 {} tokens{}",
+                elapsed, usage.total(), if usage.estimated { " (含估算)" } else { "" }
+            );
+            task.progress.logs.push(LogEntry { ts: now_ms(), msg });
+            if let Some(sample) = task.progress.sample.as_mut()
+                && !sample.pages.is_empty()
+            {
+                let tokens = usage.total() * sample.source_pages as u64 / sample.pages.len() as u64;
+                let cost = self.config.token_price_per_million.map(|price| tokens as f64 * price / 1_000_000.0);
+                let msg = format!(
+                    "试译 {} 页，预计全文 {} 页消耗约 {} tokens{}",
+                    sample.pages.len(), sample.source_pages, tokens,
+                    cost.map(|c| format!("，费用约 {:.2}", c)).unwrap_or_default()
+                );
+                sample.projected_tokens = Some(tokens);
+                sample.projected_cost = cost;
+                task.progress.logs.push(LogEntry { ts: now_ms(), msg });
+            }
+            save_task(task_id, task);
+        }
+    }
+
+    pub fn set_sample(&self, task_id: &str, sample: SampleInfo) {
+        if let Some(task) = self.tasks.write().get_mut(task_id) {
+            let pages: Vec<String> = sample.pages.iter().map(|n| n.to_string()).collect();
+            let msg = format!("试译模式：处理原文第 {} 页 (共 {} 页)", pages.join("、"), sample.source_pages);
+            task.progress.logs.push(LogEntry { ts: now_ms(), msg });
+            task.progress.sample = Some(sample);
+            save_task(task_id, task);
+        }
+    }
+
+    pub fn set_published(&self, task_id: &str, files: Vec<PublishedFile>) {
+        if let Some(task) = self.tasks.write().get_mut(task_id) {
+            for file in &files {
+                task.progress.logs.push(LogEntry { ts: now_ms(), msg: format!("已上传 {}", file.url) });
+            }
+            task.progress.published = files;
+            save_task(task_id, task);
+        }
+    }
+
+    pub fn set_preview_pages(&self, task_id: &str, count: usize) {
+        if let Some(task) = self.tasks.write().get_mut(task_id) {
+            task.progress.preview_pages = Some(count).filter(|n| *n > 0);
+            save_task(task_id, task);
         }
     }
 
+    pub fn set_page_range(&self, task_id: &str, range: PageRange) {
+        if let Some(task) = self.tasks.write().get_mut(task_id) {
+            let msg = format!("仅处理原文第 {} 页 (共 {} 页)", format_page_list(&range.pages), range.source_pages);
+            task.progress.logs.push(LogEntry { ts: now_ms(), msg });
+            task.progress.page_range = Some(range);
+            save_task(task_id, task);
+        }
+    }
+
+    /// Count images rendered for a task; `memory` is what the task holds
+    /// in memory alongside them
+    pub fn add_rendered(&self, task_id: &str, bytes: u64, memory: u64) {
+        if let Some(task) = self.tasks.write().get_mut(task_id) {
+            task.progress.resources.add_rendered(bytes, memory);
+            task.unsaved.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn note_memory(&self, task_id: &str, bytes: u64) {
+        if let Some(task) = self.tasks.write().get_mut(task_id) {
+            task.progress.resources.note_memory(bytes);
+            task.unsaved.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Count a page step's API request and response bytes; saved with the
+    /// next progress change or snapshot
+    pub fn add_api_traffic(&self, task_id: &str, sent: u64, received: u64) {
+        if let Some(task) = self.tasks.write().get_mut(task_id) {
+            task.progress.resources.api_sent_bytes += sent;
+            task.progress.resources.api_received_bytes += received;
+            task.unsaved.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn set_skipped(&self, task_id: &str, message: String) {
+        if let Some(task) = self.tasks.write().get_mut(task_id) {
+            task.progress.resources.disk_bytes = resources::dir_size(&task_dir(task_id));
+            task.progress.status = TaskStatus::Skipped;
+            task.progress.overall_percent = 100;
+            task.progress.message = message.clone();
+            task.progress.logs.push(LogEntry { ts: now_ms(), msg: message });
+            save_task(task_id, task);
+        }
+    }
+
+    pub fn is_skipped(&self, task_id: &str) -> bool {
+        self.tasks.read().get(task_id).is_some_and(|t| t.progress.status == TaskStatus::Skipped)
+    }
+
     pub fn set_error(&self, task_id: &str, error: String) {
         if let Some(task) = self.tasks.write().get_mut(task_id) {
+            task.progress.resources.disk_bytes = resources::dir_size(&task_dir(task_id));
             task.progress.status = TaskStatus::Error;
             task.progress.message = error.clone();
+            task.progress.eta_seconds = None;
             task.progress.logs.push(LogEntry { ts: now_ms(), msg: format!("错误: {}", error) });
+            save_task(task_id, task);
+        }
+    }
+
+    /// Start of a page's text for progress reports; in privacy mode only its
+    /// length and a hash, so pages can be told apart without showing them
+    pub fn text_preview(&self, text: &str) -> String {
+        if self.config.privacy_mode {
+            let digest = format!("{:x}", Sha256::digest(text.as_bytes()));
+            return format!("[{} 字符 · sha256 {}]", text.chars().count(), &digest[..12]);
+        }
+        text.chars().take(300).collect()
+    }
+
+    /// Name the task after its document unless it already has a title. Not
+    /// in privacy mode: a title is document text.
+    pub fn suggest_title(&self, task_id: &str, title: Option<String>) {
+        let Some(title) = title.filter(|_| !self.config.privacy_mode) else {
+            return;
+        };
+        if let Some(task) = self.tasks.write().get_mut(task_id)
+            && task.progress.title.is_none()
+        {
+            task.progress.title = Some(title);
+            save_task(task_id, task);
         }
     }
 
@@ -351,121 +1316,630 @@ impl AppState {
             if task.progress.logs.len() > MAX_LOGS {
                 task.progress.logs.remove(0);
             }
+            task.notify();
         }
     }
 
     pub fn start_page_ocr(&self, task_id: &str, page_num: usize) {
-        if let Some(task) = self.tasks.write().get_mut(task_id) {
-            if let Some(ps) = task.progress.page_summaries.get_mut(page_num - 1) {
-                ps.ocr_started = Some(now_ms());
-                ps.status = "ocr".to_string();
-                ps.error = None; // 清除之前的错误
-            }
+        if let Some(task) = self.tasks.write().get_mut(task_id)
+            && let Some(ps) = task.progress.page_summaries.get_mut(page_num - 1)
+        {
+            ps.ocr_started = Some(now_ms());
+            ps.status = "ocr".to_string();
+            ps.error = None; // 清除之前的错误
+            task.notify();
         }
     }
 
-    pub fn finish_page_ocr(&self, task_id: &str, page_num: usize, char_count: usize, text_preview: String) {
+    pub fn finish_page_ocr(&self, task_id: &str, page_num: usize, char_count: usize, text_preview: String, usage: Usage, model: &str) {
         if let Some(task) = self.tasks.write().get_mut(task_id) {
             task.progress.ocr_done += 1;
+            task.progress.usage.add(&usage);
             if let Some(ps) = task.progress.page_summaries.get_mut(page_num - 1) {
                 if let Some(started) = ps.ocr_started {
                     ps.ocr_duration_ms = Some(now_ms() - started);
                 }
                 ps.ocr_chars = Some(char_count);
                 ps.ocr_text_preview = Some(text_preview);
+                ps.ocr_usage = Some(usage);
+                ps.ocr_model = (!model.is_empty()).then(|| model.to_string());
             }
             self.update_progress(task);
+            save_task(task_id, task);
+        }
+        self.stats.record_usage(&self.config, &usage);
+    }
+
+    /// Record follow-up requests made for a page's replies cut off at max_tokens
+    pub fn note_continuations(&self, task_id: &str, page_num: usize, stage: &str, continuations: u32, truncated: bool) {
+        if continuations == 0 {
+            return;
+        }
+        if let Some(task) = self.tasks.write().get_mut(task_id)
+            && let Some(ps) = task.progress.page_summaries.get_mut(page_num - 1)
+        {
+            *ps.continuations.get_or_insert(0) += continuations;
+            let msg = if truncated {
+                format!("⚠️ 第 {} 页{}回复续写 {} 次后仍超出长度上限，内容可能不完整", page_num, stage, continuations)
+            } else {
+                format!("第 {} 页{}回复达到长度上限，已自动续写 {} 次", page_num, stage, continuations)
+            };
+            task.progress.logs.push(LogEntry { ts: now_ms(), msg });
+            save_task(task_id, task);
         }
     }
 
     pub fn start_page_translate(&self, task_id: &str, page_num: usize) {
-        if let Some(task) = self.tasks.write().get_mut(task_id) {
-            if let Some(ps) = task.progress.page_summaries.get_mut(page_num - 1) {
-                ps.translate_started = Some(now_ms());
-                ps.status = "translating".to_string();
-            }
+        if let Some(task) = self.tasks.write().get_mut(task_id)
+            && let Some(ps) = task.progress.page_summaries.get_mut(page_num - 1)
+        {
+            ps.translate_started = Some(now_ms());
+            ps.status = "translating".to_string();
+            ps.check_warning = None;
+            task.notify();
         }
     }
 
-    pub fn finish_page_translate(&self, task_id: &str, page_num: usize, char_count: usize, text_preview: String) {
+    /// Mark a page whose translation still fails the post-checks after a re-translation
+    pub fn flag_page(&self, task_id: &str, page_num: usize, warning: String) {
+        if let Some(task) = self.tasks.write().get_mut(task_id)
+            && let Some(ps) = task.progress.page_summaries.get_mut(page_num - 1)
+        {
+            let msg = format!("⚠️ 第 {} 页译文检查未通过: {}", page_num, warning);
+            task.progress.logs.push(LogEntry { ts: now_ms(), msg });
+            ps.check_warning = Some(warning);
+            save_task(task_id, task);
+        }
+    }
+
+    pub fn finish_page_translate(&self, task_id: &str, page_num: usize, char_count: usize, text_preview: String, usage: Usage, model: &str) {
         if let Some(task) = self.tasks.write().get_mut(task_id) {
             task.progress.translate_done += 1;
+            task.progress.usage.add(&usage);
             if let Some(ps) = task.progress.page_summaries.get_mut(page_num - 1) {
                 if let Some(started) = ps.translate_started {
                     ps.translate_duration_ms = Some(now_ms() - started);
                 }
                 ps.translated_chars = Some(char_count);
                 ps.translated_text_preview = Some(text_preview);
+                ps.translate_usage = Some(usage);
+                ps.translate_model = (!model.is_empty()).then(|| model.to_string());
                 ps.status = "done".to_string();
                 ps.error = None; // 确保成功时清除错误
             }
+            task.note_page_finished();
             self.update_progress(task);
+            save_task(task_id, task);
         }
+        self.stats.record_usage(&self.config, &usage);
     }
 
-    pub fn set_page_error(&self, task_id: &str, page_num: usize, error: String) {
+    /// Count requests made for the task outside its pages
+    pub fn add_usage(&self, task_id: &str, usage: &Usage) {
+        if let Some(task) = self.tasks.write().get_mut(task_id) {
+            task.progress.usage.add(usage);
+            save_task(task_id, task);
+        }
+    }
+
+    /// A page of an OCR-only task is done once its text is recognized
+    pub fn finish_page_untranslated(&self, task_id: &str, page_num: usize) {
         if let Some(task) = self.tasks.write().get_mut(task_id) {
+            task.progress.translate_done += 1;
             if let Some(ps) = task.progress.page_summaries.get_mut(page_num - 1) {
-                ps.status = "error".to_string();
-                ps.error = Some(error);
+                ps.status = "done".to_string();
+                ps.error = None;
             }
+            task.note_page_finished();
+            self.update_progress(task);
+            save_task(task_id, task);
         }
     }
 
+    /// A page's API stage starts with its full retry budget
+    pub fn start_page_retries(&self, task_id: &str, page_num: usize, stage: &str, max_retries: u32) {
+        if let Some(task) = self.tasks.write().get_mut(task_id)
+            && let Some(ps) = task.progress.page_summaries.get_mut(page_num - 1)
+        {
+            ps.retries = Some(PageRetries { stage: stage.to_string(), max_retries, remaining: max_retries, ..Default::default() });
+            task.notify();
+        }
+    }
+
+    /// A request of the page failed and is about to be retried
+    pub fn note_page_retry(&self, task_id: &str, page_num: usize, retry: &translate::RetryNotice) {
+        if let Some(task) = self.tasks.write().get_mut(task_id)
+            && let Some(ps) = task.progress.page_summaries.get_mut(page_num - 1)
+            && let Some(retries) = ps.retries.as_mut()
+        {
+            retries.max_retries = retry.max_retries;
+            retries.used = retry.attempt;
+            retries.remaining = retry.max_retries.saturating_sub(retry.attempt);
+            retries.last_delay_ms = Some(retry.delay_ms);
+            retries.last_error = Some(retry.error.clone());
+            task.notify();
+        }
+    }
+
+    /// Show the text a streaming reply has produced so far as the page's
+    /// OCR or translation preview, until the stage finishes. Not in privacy
+    /// mode, whose previews are digests of the final text.
+    pub fn set_page_partial(&self, task_id: &str, page_num: usize, stage: &str, text: &str) {
+        if self.config.privacy_mode {
+            return;
+        }
+        let preview = self.text_preview(text);
+        if let Some(task) = self.tasks.write().get_mut(task_id)
+            && let Some(ps) = task.progress.page_summaries.get_mut(page_num - 1)
+        {
+            let field = match (stage, ps.status.as_str()) {
+                ("OCR", "ocr") => &mut ps.ocr_text_preview,
+                ("翻译", "translating") => &mut ps.translated_text_preview,
+                _ => return,
+            };
+            if field.as_deref() != Some(preview.as_str()) {
+                *field = Some(preview);
+                task.notify();
+            }
+        }
+    }
+
+    pub fn set_page_error(&self, task_id: &str, page_num: usize, error: String) {
+        if let Some(task) = self.tasks.write().get_mut(task_id)
+            && let Some(ps) = task.progress.page_summaries.get_mut(page_num - 1)
+        {
+            ps.status = "error".to_string();
+            ps.error = Some(error);
+            save_task(task_id, task);
+        }
+    }
+
+    /// Record a page that failed for good in the dead-letter list
+    pub fn record_dead_letter(&self, task_id: &str, page_num: usize, stage: &str, error: &str, request: RequestParams) {
+        let filename = self.tasks.read().get(task_id).map(|t| t.progress.filename.clone()).unwrap_or_default();
+        self.dead_letters.record(DeadLetter {
+            task_id: task_id.to_string(),
+            filename,
+            page_num,
+            stage: stage.to_string(),
+            error: error.to_string(),
+            failed_at: now_ms(),
+            request,
+        });
+    }
+
     pub fn get_progress(&self, task_id: &str) -> Option<TaskProgress> {
         self.tasks.read().get(task_id).map(|t| t.progress.clone())
     }
 
-    pub fn get_pdf_data(&self, task_id: &str) -> Option<Arc<Vec<u8>>> {
-        self.tasks.read().get(task_id).and_then(|t| t.pdf_data.clone())
+    /// Notified whenever the task's progress changes
+    pub fn subscribe(&self, task_id: &str) -> Option<watch::Receiver<()>> {
+        self.tasks.read().get(task_id).map(|t| t.updates.subscribe())
+    }
+
+    /// Last `TRANSLATE_CONTEXT` paragraphs of the previous page's translation
+    /// into `lang`, to keep terms and references consistent across pages
+    pub fn translation_context(&self, task_id: &str, page_num: usize, lang: TargetLang) -> Option<String> {
+        let paragraphs = self.config.translate_context;
+        if paragraphs == 0 || page_num < 2 {
+            return None;
+        }
+        let options = self.get_options(task_id)?;
+        let previous = load_page_translation(task_id, page_num - 1, options.lang_suffix(lang))?;
+        let all: Vec<&str> = previous.split("\n\n").map(str::trim).filter(|p| !p.is_empty()).collect();
+        let context = all[all.len().saturating_sub(paragraphs)..].join("\n\n");
+        // A page of one huge paragraph shouldn't double the prompt
+        let skip = context.chars().count().saturating_sub(MAX_CONTEXT_CHARS);
+        let context: String = context.chars().skip(skip).collect();
+        (!context.is_empty()).then_some(context)
+    }
+
+    pub fn get_options(&self, task_id: &str) -> Option<TaskOptions> {
+        self.tasks.read().get(task_id).map(|t| t.options.clone())
+    }
+
+    pub fn get_render_cache(&self, task_id: &str) -> Option<Arc<StreamCache>> {
+        self.tasks.read().get(task_id).map(|t| t.render_cache.clone())
+    }
+
+    /// Accept a manual edit of one translated page; only finished tasks can be edited
+    /// Record an edited translation; with `regenerate` the task goes back to
+    /// Generating, otherwise the PDF stays as it was until a later regenerate
+    pub fn start_page_edit(&self, task_id: &str, page_num: usize, char_count: usize, text_preview: String, regenerate: bool) -> Result<(), AppError> {
+        let mut tasks = self.tasks.write();
+        let task = tasks.get_mut(task_id).ok_or_else(|| AppError::NotFound("任务不存在".to_string()))?;
+        if task.progress.status != TaskStatus::Complete {
+            return Err(AppError::Conflict("只能修改已完成任务的页面".to_string()));
+        }
+        let ps = task.progress.page_summaries.get_mut(page_num.wrapping_sub(1))
+            .ok_or_else(|| AppError::NotFound("页码超出范围".to_string()))?;
+        ps.translated_chars = Some(char_count);
+        ps.translated_text_preview = Some(text_preview);
+        ps.check_warning = None;
+        if !regenerate {
+            task.progress.logs.push(LogEntry { ts: now_ms(), msg: format!("第 {} 页译文已手动修改，待重新生成 PDF", page_num) });
+            save_task(task_id, task);
+            return Ok(());
+        }
+        task.progress.logs.push(LogEntry { ts: now_ms(), msg: format!("第 {} 页译文已手动修改", page_num) });
+        // Blocks further edits until the regenerated PDF is in place
+        task.progress.status = TaskStatus::Generating;
+        save_task(task_id, task);
+        Ok(())
+    }
+
+    /// Claim a completed task for rebuilding its PDF from the page files
+    pub fn start_regenerate(&self, task_id: &str) -> Result<usize, AppError> {
+        let mut tasks = self.tasks.write();
+        let task = tasks.get_mut(task_id).ok_or_else(|| AppError::NotFound("任务不存在".to_string()))?;
+        if task.progress.status != TaskStatus::Complete {
+            return Err(AppError::Conflict("只能重新生成已完成任务的 PDF".to_string()));
+        }
+        task.progress.logs.push(LogEntry { ts: now_ms(), msg: "按当前译文重新生成 PDF".to_string() });
+        task.progress.status = TaskStatus::Generating;
+        save_task(task_id, task);
+        Ok(task.progress.total_pages)
+    }
+
+    /// Generate a task's output again, with new typesetting options, from the
+    /// pages on disk: a completed task, or one that failed after every page
+    /// was done, i.e. while its PDF was being assembled. Options that are
+    /// None stay as they were.
+    pub fn start_regenerate_output(
+        &self,
+        task_id: &str,
+        layout: Option<Layout>,
+        output_mode: Option<OutputMode>,
+        romanize: Option<Romanize>,
+    ) -> Result<usize, AppError> {
+        let mut tasks = self.tasks.write();
+        let task = tasks.get_mut(task_id).ok_or_else(|| AppError::NotFound("任务不存在".to_string()))?;
+        let progress = &task.progress;
+        let assembly_failed = progress.status == TaskStatus::Error && !task.cancelled
+            && progress.total_pages > 0 && progress.translate_done >= progress.total_pages;
+        if progress.status != TaskStatus::Complete && !assembly_failed {
+            return Err(AppError::Conflict("只能为已完成或生成 PDF 时失败的任务重新生成输出".to_string()));
+        }
+        let mut options = task.options.clone();
+        options.layout = layout.unwrap_or(options.layout);
+        options.output_mode = output_mode.unwrap_or(options.output_mode);
+        options.romanize = romanize.unwrap_or(options.romanize);
+        if options.mode == TaskMode::Ocr && options.output_mode.needs_originals() {
+            return Err(AppError::BadRequest("仅识别模式不支持对照输出".to_string()));
+        }
+        if options.romanize == Romanize::Original && !options.output_mode.needs_originals() {
+            return Err(AppError::BadRequest("原文注音需要对照或逐句对照输出模式 (output=bilingual / interlinear)".to_string()));
+        }
+        task.options = options;
+        task.progress.logs.push(LogEntry {
+            ts: now_ms(),
+            msg: format!("按当前译文重新生成输出 (排版 {}，输出 {})", task.options.layout.as_str(), task.options.output_mode.as_str()),
+        });
+        task.progress.status = TaskStatus::Generating;
+        save_task(task_id, task);
+        Ok(task.progress.total_pages)
+    }
+
+    /// Write task.json for running tasks whose progress changed without
+    /// being saved (logs, page starts, retries, streamed previews, resource
+    /// counters), so a crash loses at most one snapshot interval of it.
+    /// Returns how many were taken; they are written on the worker pool, off
+    /// the task lock.
+    pub async fn snapshot_progress(&self) -> usize {
+        let snapshots: Vec<(String, TaskRecord, Arc<Mutex<u64>>, u64)> = self.tasks.read().iter()
+            .filter(|(_, task)| task.unsaved.load(Ordering::Relaxed) && !task.progress.is_done())
+            .map(|(task_id, task)| {
+                let mut saves = task.saves.lock();
+                *saves += 1;
+                (task_id.clone(), task_record(task), task.saves.clone(), *saves)
+            })
+            .collect();
+        let taken = snapshots.len();
+        if taken > 0 {
+            workers::run(move || {
+                for (task_id, record, saves, taken_as) in snapshots {
+                    // A newer write has the newer progress; a task deleted
+                    // meanwhile has no directory to write to
+                    let saves = saves.lock();
+                    if *saves == taken_as && task_dir(&task_id).is_dir() {
+                        write_record(&task_id, &record);
+                    }
+                }
+            }).await;
+        }
+        taken
+    }
+
+    /// Record the checksum of an artifact a stage just wrote
+    pub fn record_checksum(&self, task_id: &str, artifact: Artifact, data: &[u8]) {
+        self.record_checksums(task_id, vec![(artifact, integrity::sha256_hex(data))]);
+    }
+
+    /// Record checksums computed elsewhere (on the worker pool, for large files)
+    pub fn record_checksums(&self, task_id: &str, checksums: Vec<(Artifact, String)>) {
+        if let Some(task) = self.tasks.write().get_mut(task_id) {
+            for (artifact, hash) in checksums {
+                task.manifest.record(artifact, hash);
+            }
+            save_manifest(task_id, &task.manifest);
+        }
+    }
+
+    /// Name of one of the task's output files, per OUTPUT_NAME_PATTERN
+    pub fn output_name(&self, task_id: &str, tag: &str, extension: &str) -> String {
+        let tasks = self.tasks.read();
+        let (source, title, started_at) = tasks.get(task_id)
+            .map(|t| (t.progress.filename.as_str(), t.progress.title.as_deref(), t.started_at))
+            .unwrap_or_default();
+        let name = filename::OutputName { source, title, tag, started_at };
+        self.config.output_name_pattern.render(&name, extension)
+    }
+
+    pub fn get_manifest(&self, task_id: &str) -> Option<Manifest> {
+        self.tasks.read().get(task_id).map(|t| t.manifest.clone())
+    }
+
+    /// Re-hash the task's files against its manifest; reads every file, so
+    /// run it off the async runtime
+    pub fn verify_task(&self, task_id: &str) -> Option<(Manifest, Verification)> {
+        let (manifest, options) = {
+            let tasks = self.tasks.read();
+            let task = tasks.get(task_id)?;
+            (task.manifest.clone(), task.options.clone())
+        };
+        let verification = manifest.verify(task_id, &options);
+        Some((manifest, verification))
+    }
+
+    /// File holding the output PDF in the given language, or in the primary
+    /// language when None
+    pub fn get_output_path(&self, task_id: &str, lang: Option<TargetLang>) -> Option<PathBuf> {
+        let tasks = self.tasks.read();
+        let task = tasks.get(task_id)?;
+        task.outputs.get(&lang.unwrap_or(task.options.target_lang)).cloned()
     }
 
     pub fn get_all_tasks(&self) -> Vec<TaskSummary> {
-        self.tasks.read().iter().map(|(id, t)| TaskSummary {
-            task_id: id.clone(),
-            filename: t.progress.filename.clone(),
-            status: t.progress.status.clone(),
-            overall_percent: t.progress.overall_percent,
-            ocr_done: t.progress.ocr_done,
-            translate_done: t.progress.translate_done,
-            total_pages: t.progress.total_pages,
-        }).collect()
-    }
-
-    #[allow(dead_code)]
-    pub fn cleanup_old_tasks(&self) {
+        self.tasks.read().iter().map(|(id, t)| task_summary(id, t)).collect()
+    }
+
+    pub fn get_trash(&self) -> Vec<TaskSummary> {
+        self.trash.read().iter().map(|(id, t)| task_summary(id, t)).collect()
+    }
+
+    /// Move a task that is not running to the trash, or delete it outright
+    /// when TRASH_HOURS is 0. A task already in the trash is deleted for
+    /// good. Returns whether it went to the trash.
+    pub fn delete_task(&self, task_id: &str) -> Result<bool, AppError> {
+        if self.trash.write().remove(task_id).is_some() {
+            cleanup_task_files(task_id);
+            self.dead_letters.remove_task(task_id);
+            return Ok(false);
+        }
+        let mut tasks = self.tasks.write();
+        let task = tasks.get(task_id).ok_or_else(|| AppError::NotFound("任务不存在".to_string()))?;
+        let idle = task.progress.is_done() || task.progress.status == TaskStatus::AwaitingReview;
+        if !idle || task.is_retrying {
+            return Err(AppError::Conflict("任务运行中，请先取消".to_string()));
+        }
+        let mut task = tasks.remove(task_id).expect("task looked up above");
+        drop(tasks);
+        // A deleted task can't be re-driven, trashed or not
+        self.dead_letters.remove_task(task_id);
+        if self.config.trash_hours == 0 {
+            cleanup_task_files(task_id);
+            return Ok(false);
+        }
+        task.deleted_at = Some(now_ms());
+        task.progress.logs.push(LogEntry { ts: now_ms(), msg: "任务已移入回收站".to_string() });
+        save_task(task_id, &task);
+        self.trash.write().insert(task_id.to_string(), task);
+        Ok(true)
+    }
+
+    /// Bring a task back from the trash as it was
+    pub fn restore_task(&self, task_id: &str) -> Result<(), AppError> {
+        let mut task = self.trash.write().remove(task_id)
+            .ok_or_else(|| AppError::NotFound("回收站中没有该任务".to_string()))?;
+        task.deleted_at = None;
+        task.progress.logs.push(LogEntry { ts: now_ms(), msg: "任务已从回收站恢复".to_string() });
+        save_task(task_id, &task);
+        self.tasks.write().insert(task_id.to_string(), task);
+        Ok(())
+    }
+
+    /// Delete tasks that have been in the trash for TRASH_HOURS. Returns the
+    /// number removed and the bytes reclaimed.
+    pub fn purge_trash(&self) -> (usize, u64) {
+        let ttl = self.config.trash_hours.saturating_mul(3_600_000);
         let now = now_ms();
+        let mut trash = self.trash.write();
+        let expired: Vec<String> = trash.iter()
+            .filter(|(_, t)| t.deleted_at.is_some_and(|at| now.saturating_sub(at) >= ttl))
+            .map(|(id, _)| id.clone())
+            .collect();
+        let mut reclaimed = 0;
+        for task_id in &expired {
+            reclaimed += resources::dir_size(&task_dir(task_id));
+            cleanup_task_files(task_id);
+            self.dead_letters.remove_task(task_id);
+            trash.remove(task_id);
+        }
+        (expired.len(), reclaimed)
+    }
+
+    /// Create (or return the existing) share token for a task
+    pub fn share_task(&self, task_id: &str) -> Option<String> {
         let mut tasks = self.tasks.write();
-        let to_cleanup: Vec<String> = tasks.iter()
-            .filter(|(_, t)| t.progress.is_done() && (now - t.started_at) >= 3600_000)
+        let task = tasks.get_mut(task_id)?;
+        let token = task.share_token
+            .get_or_insert_with(|| uuid::Uuid::new_v4().simple().to_string())
+            .clone();
+        save_task(task_id, task);
+        Some(token)
+    }
+
+    pub fn unshare_task(&self, task_id: &str) -> bool {
+        match self.tasks.write().get_mut(task_id) {
+            Some(task) => {
+                let was_shared = task.share_token.take().is_some();
+                save_task(task_id, task);
+                was_shared
+            }
+            None => false,
+        }
+    }
+
+    pub fn get_public_status(&self, token: &str) -> Option<PublicStatus> {
+        let tasks = self.tasks.read();
+        let task = tasks.values().find(|t| t.share_token.as_deref() == Some(token))?;
+        let p = &task.progress;
+        Some(PublicStatus {
+            status: p.status.clone(),
+            overall_percent: p.overall_percent,
+            total_pages: p.total_pages,
+            ocr_done: p.ocr_done,
+            translate_done: p.translate_done,
+            page_states: p.page_summaries.iter().map(|ps| ps.status.clone()).collect(),
+        })
+    }
+
+    /// Delete finished tasks kept longer than RETAIN_COMPLETE_HOURS (complete
+    /// or skipped) or RETAIN_FAILED_HOURS (failed), counted from their last
+    /// log entry. Returns the number removed and the bytes reclaimed.
+    pub fn cleanup_expired(&self) -> (usize, u64) {
+        let now = now_ms();
+        let hours_ms = |hours: Option<u64>| hours.map(|h| h.saturating_mul(3_600_000));
+        let (complete_ttl, failed_ttl) = (hours_ms(self.config.retain_complete_hours), hours_ms(self.config.retain_failed_hours));
+        let mut tasks = self.tasks.write();
+        let expired: Vec<String> = tasks.iter()
+            .filter(|(_, t)| !t.is_retrying)
+            .filter(|(_, t)| {
+                let ttl = match t.progress.status {
+                    TaskStatus::Complete | TaskStatus::Skipped => complete_ttl,
+                    TaskStatus::Error => failed_ttl,
+                    _ => None,
+                };
+                let finished = t.progress.logs.last().map_or(t.started_at, |l| l.ts);
+                ttl.is_some_and(|ttl| now.saturating_sub(finished) >= ttl)
+            })
             .map(|(id, _)| id.clone())
             .collect();
-        
-        for task_id in &to_cleanup {
+
+        let mut reclaimed = 0;
+        for task_id in &expired {
+            reclaimed += resources::dir_size(&task_dir(task_id));
             cleanup_task_files(task_id);
+            self.dead_letters.remove_task(task_id);
+            tasks.remove(task_id);
         }
-        
-        tasks.retain(|id, _| !to_cleanup.contains(id));
+        (expired.len(), reclaimed)
+    }
+
+    /// Remove task directories without a task record, left behind by uploads
+    /// interrupted before their first save. Only safe before any task is
+    /// created. Returns the number removed and the bytes reclaimed.
+    pub fn sweep_orphans(&self) -> (usize, u64) {
+        let Ok(entries) = fs::read_dir(tasks_dir()) else { return (0, 0) };
+        let tasks = self.tasks.read();
+        let (mut removed, mut reclaimed) = (0, 0);
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let task_id = entry.file_name().to_string_lossy().to_string();
+            // Unreadable records are kept for a look; only directories that
+            // never got one go
+            if !path.is_dir() || tasks.contains_key(&task_id) || path.join("task.json").exists() {
+                continue;
+            }
+            let size = resources::dir_size(&path);
+            if fs::remove_dir_all(&path).is_ok() {
+                removed += 1;
+                reclaimed += size;
+            }
+        }
+        (removed, reclaimed)
     }
 
-    pub fn try_start_retry(&self, task_id: &str) -> Result<(), String> {
+    pub fn try_start_retry(&self, task_id: &str) -> Result<(), AppError> {
         let mut tasks = self.tasks.write();
-        let task = tasks.get_mut(task_id).ok_or("任务不存在")?;
+        let task = tasks.get_mut(task_id).ok_or_else(|| AppError::NotFound("任务不存在".to_string()))?;
         
         if task.progress.status != TaskStatus::Error {
-            return Err("只能重试失败的任务".to_string());
+            return Err(AppError::Conflict("只能重试失败的任务".to_string()));
         }
         if task.cancelled {
-            return Err("已取消的任务不能重试".to_string());
+            return Err(AppError::Conflict("已取消的任务不能重试".to_string()));
         }
         if task.is_retrying {
-            return Err("任务正在重试中".to_string());
+            return Err(AppError::Conflict("任务正在重试中".to_string()));
         }
         
         task.is_retrying = true;
         task.progress.status = TaskStatus::Processing;
+        task.start_throughput();
         task.progress.message = "重试中...".to_string();
         task.progress.logs.push(LogEntry { ts: now_ms(), msg: "开始重试".to_string() });
+        save_task(task_id, task);
+        Ok(())
+    }
+
+    /// Whether the task stops after OCR for its text to be reviewed
+    pub fn awaits_review(&self, task_id: &str) -> bool {
+        self.tasks.read().get(task_id).is_some_and(|t| t.options.review_ocr && !t.progress.ocr_reviewed)
+    }
+
+    pub fn set_awaiting_review(&self, task_id: &str) {
+        if let Some(task) = self.tasks.write().get_mut(task_id) {
+            task.progress.status = TaskStatus::AwaitingReview;
+            task.progress.message = "识别完成，等待审阅识别文本后开始翻译".to_string();
+            task.progress.eta_seconds = None;
+            task.progress.logs.push(LogEntry { ts: now_ms(), msg: "OCR 完成，等待审阅".to_string() });
+            for ps in &mut task.progress.page_summaries {
+                if ps.status == "ocr" {
+                    ps.status = "review".to_string();
+                }
+            }
+            save_task(task_id, task);
+        }
+    }
+
+    /// Replace a page's OCR text while the task awaits review
+    pub fn edit_page_ocr(&self, task_id: &str, page_num: usize, text: &str) -> Result<(), AppError> {
+        let mut tasks = self.tasks.write();
+        let task = tasks.get_mut(task_id).ok_or_else(|| AppError::NotFound("任务不存在".to_string()))?;
+        if task.progress.status != TaskStatus::AwaitingReview {
+            return Err(AppError::Conflict("只能在任务等待审阅时修改识别文本".to_string()));
+        }
+        if page_num == 0 || page_num > task.progress.total_pages {
+            return Err(AppError::NotFound("页面不存在".to_string()));
+        }
+        save_page_ocr(task_id, page_num, text)
+            .map_err(|source| StorageError::SavePage { page: page_num, source })?;
+        task.manifest.record(Artifact::Ocr(page_num), integrity::sha256_hex(text.as_bytes()));
+        save_manifest(task_id, &task.manifest);
+        let preview = self.text_preview(text);
+        if let Some(ps) = task.progress.page_summaries.get_mut(page_num - 1) {
+            ps.ocr_chars = Some(text.chars().count());
+            ps.ocr_text_preview = Some(preview);
+        }
+        task.progress.logs.push(LogEntry { ts: now_ms(), msg: format!("第 {} 页识别文本已修改", page_num) });
+        save_task(task_id, task);
+        Ok(())
+    }
+
+    /// Mark the reviewed OCR text approved and the task running again
+    pub fn try_approve_review(&self, task_id: &str) -> Result<(), AppError> {
+        let mut tasks = self.tasks.write();
+        let task = tasks.get_mut(task_id).ok_or_else(|| AppError::NotFound("任务不存在".to_string()))?;
+        if task.progress.status != TaskStatus::AwaitingReview {
+            return Err(AppError::Conflict("任务不在等待审阅状态".to_string()));
+        }
+        task.progress.ocr_reviewed = true;
+        task.progress.status = TaskStatus::Processing;
+        task.start_throughput();
+        self.update_progress(task);
+        task.progress.logs.push(LogEntry { ts: now_ms(), msg: "识别文本已审阅，开始翻译".to_string() });
+        save_task(task_id, task);
         Ok(())
     }
 
@@ -478,16 +1952,65 @@ impl AppState {
     pub fn init_retry_progress(&self, task_id: &str, completed_count: usize, total_pages: usize) {
         if let Some(task) = self.tasks.write().get_mut(task_id) {
             task.progress.translate_done = completed_count;
-            task.progress.ocr_done = completed_count;
+            // Reviewed text is reused as saved, not recognized again
+            task.progress.ocr_done = if task.progress.ocr_reviewed { total_pages } else { completed_count };
             task.progress.total_pages = total_pages;
             self.update_progress(task);
+            save_task(task_id, task);
         }
     }
     
-    #[allow(dead_code)]
     pub fn get_total_pages(&self, task_id: &str) -> usize {
         self.tasks.read().get(task_id)
             .map(|t| t.progress.total_pages)
             .unwrap_or(0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deadletter::RequestParams;
+    use crate::testing::config;
+
+    #[tokio::test]
+    async fn snapshots_write_unsaved_progress_once() {
+        let state = AppState::new(config());
+        let task_id = uuid::Uuid::new_v4().to_string();
+        state.create_task(&task_id, "snapshot.pdf", TaskOptions::default());
+        let path = task_dir(&task_id).join("task.json");
+        let saved = std::fs::read_to_string(&path).unwrap();
+
+        state.add_log(&task_id, "第 1 页开始识别".to_string());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), saved);
+        let taken = state.snapshot_progress().await;
+        let snapshot = std::fs::read_to_string(&path).unwrap();
+        // Clears the flag: the next snapshot has nothing to write
+        let retaken = state.snapshot_progress().await;
+        std::fs::remove_dir_all(task_dir(&task_id)).unwrap();
+
+        assert!(taken >= 1);
+        assert!(!saved.contains("第 1 页开始识别") && snapshot.contains("第 1 页开始识别"));
+        assert_eq!(retaken, 0);
+    }
+
+    #[test]
+    fn deleting_a_task_drops_its_dead_letters() {
+        let state = AppState::new(config());
+        let task_id = uuid::Uuid::new_v4().to_string();
+        state.create_task(&task_id, "dead.pdf", TaskOptions::default());
+        let request = RequestParams {
+            model: "ocr".to_string(), fallback_model: None, target_lang: None,
+            timeout_secs: 60, max_retries: 3, stream: false, input_len: 1,
+        };
+        state.record_dead_letter(&task_id, 1, "ocr", "boom", request);
+        state.set_error(&task_id, "第 1 页失败".to_string());
+        assert!(state.dead_letters.task_ids().contains(&task_id));
+
+        state.delete_task(&task_id).unwrap();
+        let left = state.dead_letters.task_ids();
+        let _ = state.delete_task(&task_id);
+        let _ = std::fs::remove_dir_all(task_dir(&task_id));
+        assert!(!left.contains(&task_id));
+    }
+}
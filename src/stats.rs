@@ -0,0 +1,191 @@
+use chrono::{Datelike, NaiveDate, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::config::Config;
+use crate::state;
+use crate::usage::Usage;
+use crate::workers;
+
+
+/// Consumption for one calendar month (UTC)
+#[derive(Clone, Serialize, Deserialize, Default)]
+pub struct MonthlyStats {
+    pub month: String,
+    pub usage: Usage,
+    pub cost: f64,
+    pub tasks: u64,
+}
+
+#[derive(Clone, Serialize)]
+pub struct QuotaStatus {
+    pub month: String,
+    pub tokens_used: u64,
+    pub cost_used: f64,
+    pub token_limit: Option<u64>,
+    pub cost_limit: Option<f64>,
+    pub exhausted: bool,
+    pub resets_on: String,
+}
+
+/// Persistent usage counters, rolled over at the start of each month
+pub struct StatsStore {
+    path: PathBuf,
+    current: Mutex<MonthlyStats>,
+    /// Set while a write is waiting for a worker; changes made meanwhile go
+    /// out with it instead of queuing another
+    save_queued: AtomicBool,
+    /// Held while writing, so writes land in the order they read the counters
+    writing: Mutex<()>,
+}
+
+fn current_month() -> String {
+    Utc::now().format("%Y-%m").to_string()
+}
+
+/// First day of next month, when the quota resets
+fn next_reset_date() -> String {
+    let today = Utc::now().date_naive();
+    let (year, month) = if today.month() == 12 {
+        (today.year() + 1, 1)
+    } else {
+        (today.year(), today.month() + 1)
+    };
+    NaiveDate::from_ymd_opt(year, month, 1)
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .unwrap_or_default()
+}
+
+impl StatsStore {
+    pub fn load() -> Self {
+        Self::load_from(state::data_dir().join("stats.json"))
+    }
+
+    pub(crate) fn load_from(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let stats = fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| serde_json::from_str::<MonthlyStats>(&s).ok())
+            .filter(|s| s.month == current_month())
+            .unwrap_or_else(|| MonthlyStats { month: current_month(), ..Default::default() });
+        Self { path, current: Mutex::new(stats), save_queued: AtomicBool::new(false), writing: Mutex::new(()) }
+    }
+
+    fn roll_over(stats: &mut MonthlyStats) {
+        let month = current_month();
+        if stats.month != month {
+            *stats = MonthlyStats { month, ..Default::default() };
+        }
+    }
+
+    fn save(path: &Path, stats: &MonthlyStats) {
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let tmp_path = path.with_extension("json.tmp");
+        if let Ok(json) = serde_json::to_string_pretty(stats)
+            && fs::write(&tmp_path, json).is_ok()
+        {
+            let _ = fs::rename(tmp_path, path);
+        }
+    }
+
+    /// Write the counters on the CPU pool. Usage comes in once per page, so
+    /// a burst of finished pages costs a single write.
+    fn queue_save(self: &Arc<Self>) {
+        if self.save_queued.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let store = self.clone();
+        let save = move || {
+            let _writing = store.writing.lock();
+            store.save_queued.store(false, Ordering::SeqCst);
+            let stats = store.current.lock().clone();
+            Self::save(&store.path, &stats);
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => drop(runtime.spawn(workers::run(save))),
+            Err(_) => save(),
+        }
+    }
+
+    pub fn record_usage(self: &Arc<Self>, config: &Config, usage: &Usage) {
+        if usage.total() == 0 {
+            return;
+        }
+        let mut stats = self.current.lock();
+        Self::roll_over(&mut stats);
+        stats.usage.add(usage);
+        if let Some(price) = config.token_price_per_million {
+            stats.cost += usage.total() as f64 * price / 1_000_000.0;
+        }
+        drop(stats);
+        self.queue_save();
+    }
+
+    pub fn record_task(self: &Arc<Self>) {
+        let mut stats = self.current.lock();
+        Self::roll_over(&mut stats);
+        stats.tasks += 1;
+        drop(stats);
+        self.queue_save();
+    }
+
+    pub fn quota_status(&self, config: &Config) -> QuotaStatus {
+        let mut stats = self.current.lock();
+        Self::roll_over(&mut stats);
+        let tokens_used = stats.usage.total();
+        let exhausted = config.quota_monthly_tokens.is_some_and(|limit| tokens_used >= limit)
+            || config.quota_monthly_cost.is_some_and(|limit| stats.cost >= limit);
+        QuotaStatus {
+            month: stats.month.clone(),
+            tokens_used,
+            cost_used: stats.cost,
+            token_limit: config.quota_monthly_tokens,
+            cost_limit: config.quota_monthly_cost,
+            exhausted,
+            resets_on: next_reset_date(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::testing::config;
+
+    #[tokio::test]
+    async fn usage_is_saved_off_the_calling_thread() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stats.json");
+        let store = Arc::new(StatsStore::load_from(&path));
+        let config = config();
+        let usage = Usage { prompt_tokens: 10, completion_tokens: 5, estimated: false };
+        for _ in 0..20 {
+            store.record_usage(&config, &usage);
+        }
+        store.record_task();
+        // Nothing is written on the calling thread
+        assert!(!path.exists());
+
+        let saved = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(stats) = std::fs::read(&path).ok().and_then(|json| serde_json::from_slice::<serde_json::Value>(&json).ok()) {
+                    break stats;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        }).await.unwrap();
+        // The single queued write carries everything recorded before it ran
+        assert_eq!(saved["usage"]["prompt_tokens"], 200);
+        assert_eq!(saved["usage"]["completion_tokens"], 100);
+        assert_eq!(saved["tasks"], 1);
+        assert_eq!(StatsStore::load_from(&path).quota_status(&config).tokens_used, 300);
+    }
+}
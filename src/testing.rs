@@ -0,0 +1,20 @@
+use std::sync::Once;
+
+use crate::config::Config;
+use crate::state;
+
+/// Config whose API is unreachable, keeping its data in the tests' own directory
+pub fn config() -> Config {
+    static ENV: Once = Once::new();
+    ENV.call_once(|| {
+        // SAFETY: every test goes through this Once before anything reads the environment
+        unsafe {
+            std::env::set_var("BASE_URL", "http://127.0.0.1:9");
+            std::env::set_var("API_KEY", "test");
+            std::env::set_var("USER_TOKENS", "tester:secret");
+            std::env::set_var("ADMIN_TOKEN", "admin-secret");
+            std::env::set_var("DATA_DIR", state::data_dir());
+        }
+    });
+    Config::from_env()
+}
@@ -1,28 +1,31 @@
 use rand::Rng;
-use serde::{Deserialize, Serialize};
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use serde::Deserialize;
 use std::future::Future;
-use std::sync::OnceLock;
+use std::sync::Arc;
 use std::sync::atomic::{AtomicU32, AtomicBool, Ordering};
 use std::time::Duration;
 use tokio::time::sleep;
 
 use crate::config::Config;
+use crate::lang::TargetLang;
+use crate::error::PdfError;
+use crate::pdf;
+use crate::pii;
+use crate::provider::{self, classify_http_status, classify_reqwest_error, ChatRequest, ContentPart, Message, MessageContent, OpenAi, Reply};
+use crate::scheduler::PageScheduler;
+use crate::usage::{self, Usage};
+use crate::{resources, watchdog, workers};
 
 const FALLBACK_THRESHOLD: u32 = 3;
 
+#[derive(Default)]
 pub struct OpFallbackState {
     consecutive_failures: AtomicU32,
     using_fallback: AtomicBool,
 }
 
 impl OpFallbackState {
-    pub fn new() -> Self {
-        Self {
-            consecutive_failures: AtomicU32::new(0),
-            using_fallback: AtomicBool::new(false),
-        }
-    }
-    
     pub fn is_using_fallback(&self) -> bool {
         self.using_fallback.load(Ordering::Relaxed)
     }
@@ -31,13 +34,14 @@ impl OpFallbackState {
         self.consecutive_failures.store(0, Ordering::Relaxed);
     }
     
-    fn record_failure(&self, has_fallback: bool) -> bool {
+    /// Count a primary-model failure; true when this one switches the task over
+    fn record_failure(&self) -> bool {
         if self.using_fallback.load(Ordering::Relaxed) {
             return false;
         }
         
         let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
-        if failures >= FALLBACK_THRESHOLD && has_fallback {
+        if failures >= FALLBACK_THRESHOLD {
             self.using_fallback.store(true, Ordering::Relaxed);
             return true;
         }
@@ -45,82 +49,88 @@ impl OpFallbackState {
     }
 }
 
+#[derive(Default)]
 pub struct ModelFallbackState {
     pub ocr: OpFallbackState,
     pub translate: OpFallbackState,
 }
 
-impl ModelFallbackState {
-    pub fn new() -> Self {
-        Self {
-            ocr: OpFallbackState::new(),
-            translate: OpFallbackState::new(),
-        }
-    }
+/// Open (or refresh) a pooled connection to the API, holding an API permit
+/// like any other upstream call
+pub async fn warm_up(config: &Config, scheduler: &Arc<PageScheduler>, background: bool) -> Result<(), String> {
+    let _permit = scheduler.acquire("warm-up", background).await;
+    provider::warm_up(config).await
 }
 
-static HTTP_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
-
-fn get_client() -> &'static reqwest::Client {
-    HTTP_CLIENT.get_or_init(|| {
-        reqwest::Client::builder()
-            .timeout(Duration::from_secs(300))
-            .connect_timeout(Duration::from_secs(10))
-            .pool_max_idle_per_host(2)
-            .build()
-            .expect("Failed to create HTTP client")
-    })
-}
-
-#[derive(Serialize)]
-struct ChatRequest<'a> {
-    model: &'a str,
-    messages: Vec<Message>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    max_tokens: Option<u32>,
+/// Periodically ping the API so idle pooled connections stay warm; the pings
+/// only use spare API capacity
+pub async fn keepalive_loop(config: Config, scheduler: Arc<PageScheduler>, interval_secs: u64) {
+    let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+    loop {
+        interval.tick().await;
+        if let Err(e) = warm_up(&config, &scheduler, true).await {
+            eprintln!("[keepalive] {}", e);
+        }
+    }
 }
 
-#[derive(Serialize)]
-struct Message {
-    role: String,
-    content: MessageContent,
+#[derive(Deserialize)]
+struct StreamChunk {
+    #[serde(default)]
+    choices: Vec<StreamChoice>,
+    #[serde(default)]
+    usage: Option<serde_json::Value>,
 }
 
-#[derive(Serialize)]
-#[serde(untagged)]
-enum MessageContent {
-    Text(String),
-    Multimodal(Vec<ContentPart>),
+/// Model output together with normalized token usage
+#[derive(Debug, Clone, Default)]
+pub struct Completion {
+    pub text: String,
+    pub usage: Usage,
+    /// Model that produced the text; empty when no request was made
+    pub model: String,
+    /// Follow-up requests made because the model stopped at max_tokens
+    pub continuations: u32,
+    /// Still cut off after the last continuation
+    pub truncated: bool,
 }
 
-#[derive(Serialize)]
-#[serde(tag = "type")]
-enum ContentPart {
-    #[serde(rename = "text")]
-    Text { text: String },
-    #[serde(rename = "image_url")]
-    ImageUrl { image_url: ImageUrl },
+impl Completion {
+    /// Fold in the bookkeeping of another request that went into the same result
+    pub fn absorb(&mut self, other: &Completion) {
+        self.usage.add(&other.usage);
+        note_model(&mut self.model, &other.model);
+        self.continuations += other.continuations;
+        self.truncated |= other.truncated;
+    }
 }
 
-#[derive(Serialize)]
-struct ImageUrl {
-    url: String,
+/// Add a model name to a comma-separated list of the models behind a result
+pub fn note_model(models: &mut String, model: &str) {
+    if model.is_empty() || models.split(", ").any(|m| m == model) {
+        return;
+    }
+    if !models.is_empty() {
+        models.push_str(", ");
+    }
+    models.push_str(model);
 }
 
 #[derive(Deserialize)]
-struct ChatResponse {
-    choices: Vec<Choice>,
+struct StreamChoice {
+    #[serde(default)]
+    delta: Option<StreamDelta>,
+    #[serde(default)]
+    finish_reason: Option<String>,
 }
 
 #[derive(Deserialize)]
-struct Choice {
-    message: ResponseMessage,
+struct StreamDelta {
+    #[serde(default)]
+    content: Option<String>,
 }
 
-#[derive(Deserialize)]
-struct ResponseMessage {
-    content: String,
-}
+const CONTINUE_PROMPT: &str = "你上一条回复在中途被中断了。请从中断处继续输出，不要重复已经输出的内容，也不要添加任何说明。";
 
 /// Use vision model to recognize text from image (with fallback support)
 pub async fn recognize_text(
@@ -128,7 +138,7 @@ pub async fn recognize_text(
     image_base64: &str, 
     task_id: &str,
     fallback_state: &ModelFallbackState,
-) -> Result<String, String> {
+) -> Result<Completion, ApiError> {
     let prompt = r#"请仔细识别这张图片中的所有文本内容。
 
 要求：
@@ -141,175 +151,354 @@ pub async fn recognize_text(
 
 请开始识别："#;
 
-    let model = if fallback_state.ocr.is_using_fallback() {
-        config.ocr_model_fallback.as_deref().unwrap_or(&config.ocr_model)
-    } else {
-        &config.ocr_model
-    };
-
     let request = ChatRequest {
-        model,
+        model: &config.ocr_model,
         messages: vec![Message {
             role: "user".to_string(),
             content: MessageContent::Multimodal(vec![
                 ContentPart::Text { text: prompt.to_string() },
-                ContentPart::ImageUrl {
-                    image_url: ImageUrl {
-                        url: format!("data:image/jpeg;base64,{}", image_base64),
-                    },
+                ContentPart::Image {
+                    mime_type: config.ocr_image_format.mime_type(),
+                    data: image_base64.to_string(),
                 },
             ]),
         }],
         max_tokens: Some(8192),
+        stream: config.api_stream,
     };
 
-    let result = with_retry(|| call_api_inner(config, &request), 3, task_id).await;
-    
-    match &result {
-        Ok(_) => {
-            fallback_state.ocr.record_success();
-        }
-        Err(_) => {
-            let switched = fallback_state.ocr.record_failure(config.ocr_model_fallback.is_some());
-            if switched {
-                eprintln!("[{}] OCR 主模型连续失败 {} 次，切换到备用模型: {:?}", 
-                    task_id, FALLBACK_THRESHOLD, config.ocr_model_fallback);
-                // Retry immediately with fallback model
-                let fallback_model = config.ocr_model_fallback.as_deref().unwrap();
-                let fallback_request = ChatRequest {
-                    model: fallback_model,
-                    messages: request.messages,
-                    max_tokens: request.max_tokens,
-                };
-                return with_retry(|| call_api_inner(config, &fallback_request), 3, task_id).await;
-            }
-        }
-    }
-    
-    result
+    call_with_fallback(config, request, CallKind::Ocr, task_id, &fallback_state.ocr, config.ocr_model_fallback.as_deref()).await
 }
 
-/// Use translation model to translate text to Chinese (with fallback support)
+/// Use translation model to translate text into the target language (with fallback support)
 pub async fn translate_text(
     config: &Config, 
     text: &str, 
+    target: TargetLang,
+    context: Option<&str>,
     task_id: &str,
     fallback_state: &ModelFallbackState,
-) -> Result<String, String> {
+) -> Result<Completion, String> {
     let trimmed = text.trim();
     if trimmed.is_empty() {
-        return Ok(String::new());
+        return Ok(Completion::default());
     }
     
-    // If already mostly Chinese, skip translation
-    let chinese_ratio = count_chinese_chars(trimmed) as f32 / trimmed.chars().count().max(1) as f32;
-    if chinese_ratio > 0.7 {
-        return Ok(text.to_string());
+    // If already mostly in the target language, skip translation
+    if target.is_mostly_target(trimmed) {
+        return Ok(Completion { text: text.to_string(), ..Default::default() });
     }
     
+    // Mixed-language page: translate only the runs of blocks that aren't in
+    // the target language and pass the rest through untouched
+    let blocks = route_blocks(trimmed, target);
+    let runs = blocks.iter().filter(|(translate, _)| *translate).count();
+    if runs == 0 || blocks.len() == 1 {
+        return translate_chunk(config, trimmed, target, context, task_id, fallback_state, false).await;
+    }
+    if runs > MAX_ROUTED_RUNS {
+        // Too fragmented for one request per run; ask the model to keep those blocks itself
+        return translate_chunk(config, trimmed, target, context, task_id, fallback_state, true).await;
+    }
+    let mut output = Vec::with_capacity(blocks.len());
+    let mut combined = Completion::default();
+    for (translate, block) in blocks {
+        if translate {
+            let completion = translate_chunk(config, &block, target, context, task_id, fallback_state, false).await?;
+            combined.absorb(&completion);
+            output.push(completion.text.trim().to_string());
+        } else {
+            output.push(block);
+        }
+    }
+    combined.text = output.join("\n");
+    Ok(combined)
+}
+
+/// Split a long text into pieces of at most `max_chars` characters, at blank
+/// lines where possible, then at line breaks, and only as a last resort
+/// inside a line
+pub fn split_text(text: &str, max_chars: usize) -> Vec<String> {
+    let mut chunks: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut push = |piece: &str, separator: &str, chunks: &mut Vec<String>| {
+        if !current.is_empty() && current.chars().count() + separator.len() + piece.chars().count() > max_chars {
+            chunks.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push_str(separator);
+        }
+        current.push_str(piece);
+    };
+    for paragraph in text.trim().split("\n\n").filter(|p| !p.trim().is_empty()) {
+        if paragraph.chars().count() <= max_chars {
+            push(paragraph, "\n\n", &mut chunks);
+            continue;
+        }
+        for line in paragraph.lines() {
+            let chars: Vec<char> = line.chars().collect();
+            for piece in chars.chunks(max_chars.max(1)) {
+                push(&piece.iter().collect::<String>(), "\n", &mut chunks);
+            }
+        }
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Most separately translated runs on one page before falling back to a single request
+const MAX_ROUTED_RUNS: usize = 4;
+
+/// Blocks shorter than this (page numbers, labels, formulas) have no reliable
+/// language of their own and follow the block before them
+const MIN_ROUTED_BLOCK_CHARS: usize = 8;
+
+/// Whether a block needs translating, or None when it's too short to tell
+fn needs_translation(block: &str, target: TargetLang) -> Option<bool> {
+    if target.is_mostly_target(block) {
+        return Some(false);
+    }
+    let short = block.chars().filter(|c| !c.is_whitespace()).count() < MIN_ROUTED_BLOCK_CHARS;
+    let latin_target = target.is_latin();
+    if !latin_target {
+        return (!short).then_some(true);
+    }
+    // Latin languages are told apart by function words, which a heading or
+    // caption may not contain; only another script settles those
+    let letters: Vec<char> = block.chars().filter(|c| c.is_alphabetic()).collect();
+    let foreign = letters.iter().filter(|c| !c.is_ascii() && !('\u{C0}'..='\u{24F}').contains(*c)).count();
+    if foreign * 2 > letters.len() {
+        Some(true)
+    } else if short || block.split_whitespace().count() < 5 {
+        None
+    } else {
+        Some(true)
+    }
+}
+
+/// Split a page into runs of consecutive lines, marking which runs need
+/// translation (true) and which are already in the target language (false)
+fn route_blocks(text: &str, target: TargetLang) -> Vec<(bool, String)> {
+    let mut runs: Vec<(bool, String)> = Vec::new();
+    let mut previous: Option<bool> = None;
+    let mut pending: Vec<&str> = Vec::new();
+    for line in text.lines() {
+        let content = line.trim().trim_start_matches('#').trim();
+        let Some(translate) = needs_translation(content, target).or(previous) else {
+            // Nothing to follow yet; decided by the first block with a language
+            pending.push(line);
+            continue;
+        };
+        previous = Some(translate);
+        for line in pending.drain(..).chain([line]) {
+            match runs.last_mut() {
+                Some((kind, run)) if *kind == translate => {
+                    run.push('\n');
+                    run.push_str(line);
+                }
+                _ => runs.push((translate, line.to_string())),
+            }
+        }
+    }
+    if !pending.is_empty() {
+        runs.push((true, pending.join("\n")));
+    }
+    runs
+}
+
+/// One translation request for `text`; `keep_target` adds an instruction to
+/// leave passages already in the target language unchanged, and `context`
+/// (the end of the previous page's translation) is shown for reference only
+async fn translate_chunk(
+    config: &Config,
+    text: &str,
+    target: TargetLang,
+    context: Option<&str>,
+    task_id: &str,
+    fallback_state: &ModelFallbackState,
+    keep_target: bool,
+) -> Result<Completion, String> {
+    let mut extra_rules = String::new();
+    let mut rule = 6;
+    if keep_target {
+        extra_rules.push_str(&format!("\n{}. 原文中已经是{}的段落原样保留，不要改写", rule, target.name()));
+        rule += 1;
+    }
+    if text.contains(pii::PLACEHOLDER_PREFIX) {
+        extra_rules.push_str(&format!("\n{}. 形如 {}1]] 的占位符原样保留，不要翻译或改动", rule, pii::PLACEHOLDER_PREFIX));
+        rule += 1;
+    }
+    let context = match context {
+        Some(context) => {
+            extra_rules.push_str(&format!("\n{}. 术语、人名和指代与前文译文保持一致；前文译文只作参考，不要输出", rule));
+            format!("前文译文：\n{}\n\n", context)
+        }
+        None => String::new(),
+    };
     let prompt = format!(
-r#"你是一个专业的多语言翻译专家。请将以下内容翻译成简体中文。
+r#"你是一个专业的多语言翻译专家。请将以下内容翻译成{lang}。
 
 翻译要求：
-1. 翻译准确、流畅、符合中文表达习惯
+1. 翻译准确、流畅、符合{lang}表达习惯
 2. 可以自由调整段落和换行，使译文更易读
 3. 专有名词、品牌名、人名可保留原文或音译
-4. 技术术语使用常见的中文译法
-5. 只输出翻译结果，不要添加任何解释
-
-原文内容：
-{}"#, trimmed);
+4. 技术术语使用常见的{lang}译法
+5. 只输出翻译结果，不要添加任何解释{extra_rules}
 
-    let model = if fallback_state.translate.is_using_fallback() {
-        config.translate_model_fallback.as_deref().unwrap_or(&config.translate_model)
-    } else {
-        &config.translate_model
-    };
+{context}原文内容：
+{text}"#, lang = target.name(), text = text);
 
     let request = ChatRequest {
-        model,
+        model: &config.translate_model,
         messages: vec![Message {
             role: "user".to_string(),
             content: MessageContent::Text(prompt),
         }],
         max_tokens: Some(8192),
+        stream: config.api_stream,
     };
 
-    let result = with_retry(|| call_api_inner(config, &request), 3, task_id).await;
-    
-    match &result {
-        Ok(_) => {
-            fallback_state.translate.record_success();
-        }
-        Err(_) => {
-            let switched = fallback_state.translate.record_failure(config.translate_model_fallback.is_some());
-            if switched {
-                eprintln!("[{}] 翻译主模型连续失败 {} 次，切换到备用模型: {:?}", 
-                    task_id, FALLBACK_THRESHOLD, config.translate_model_fallback);
-                // Retry immediately with fallback model
-                let fallback_model = config.translate_model_fallback.as_deref().unwrap();
-                let fallback_request = ChatRequest {
-                    model: fallback_model,
-                    messages: request.messages,
-                    max_tokens: request.max_tokens,
-                };
-                return with_retry(|| call_api_inner(config, &fallback_request), 3, task_id).await;
-            }
-        }
-    }
-    
-    result
+    call_with_fallback(config, request, CallKind::Translate, task_id, &fallback_state.translate, config.translate_model_fallback.as_deref())
+        .await
+        .map_err(|e| e.to_string())
 }
 
+/// Send a request with the primary model, retrying it on the fallback model
+/// (if configured) once the primary fails for good. After FALLBACK_THRESHOLD
+/// consecutive failures the rest of the task goes straight to the fallback.
+async fn call_with_fallback(
+    config: &Config,
+    mut request: ChatRequest<'_>,
+    kind: CallKind,
+    task_id: &str,
+    op_state: &OpFallbackState,
+    fallback_model: Option<&str>,
+) -> Result<Completion, ApiError> {
+    if op_state.is_using_fallback() && let Some(model) = fallback_model {
+        switch_model(config, &mut request, kind, model, task_id).await;
+    }
+
+    let err = match call_api(config, &request, kind, task_id).await {
+        Ok(completion) => {
+            op_state.record_success();
+            return Ok(completion);
+        }
+        Err(e) => e,
+    };
+    let Some(fallback) = fallback_model.filter(|m| *m != request.model) else {
+        return Err(err);
+    };
 
+    if op_state.record_failure() {
+        eprintln!("[{}] {} 主模型连续失败 {} 次，后续请求改用备用模型: {}",
+            task_id, kind.label(), FALLBACK_THRESHOLD, fallback);
+    }
+    eprintln!("[{}] {} 主模型 {} 失败，改用备用模型 {} 重试: {}", task_id, kind.label(), request.model, fallback, err);
+    switch_model(config, &mut request, kind, fallback, task_id).await;
+    call_api(config, &request, kind, task_id)
+        .await
+        .map_err(|e| e.with_suffix(&format!(" (备用模型 {})", fallback)))
+}
 
-fn count_chinese_chars(text: &str) -> usize {
-    text.chars()
-        .filter(|c| {
-            let code = *c as u32;
-            (0x4E00..=0x9FFF).contains(&code) ||
-            (0x3400..=0x4DBF).contains(&code) ||
-            (0x20000..=0x2A6DF).contains(&code)
-        })
-        .count()
+/// Point a request at the fallback model, re-encoding OCR page images when
+/// that model is configured for a different image format
+async fn switch_model<'a>(config: &Config, request: &mut ChatRequest<'a>, kind: CallKind, model: &'a str, task_id: &str) {
+    request.model = model;
+    let Some(format) = config.ocr_image_format_fallback.filter(|f| *f != config.ocr_image_format) else {
+        return;
+    };
+    if !matches!(kind, CallKind::Ocr) {
+        return;
+    }
+    for message in &mut request.messages {
+        let MessageContent::Multimodal(parts) = &mut message.content else { continue };
+        for part in parts {
+            let ContentPart::Image { mime_type, data } = part else { continue };
+            let (image, quality) = (data.clone(), config.ocr_image_quality);
+            let converted = workers::run(move || {
+                BASE64.decode(image)
+                    .map_err(|e| PdfError::Decode(e.to_string()))
+                    .and_then(|decoded| pdf::convert_image(&decoded, format, quality))
+            }).await;
+            match converted {
+                Ok(encoded) => {
+                    *mime_type = format.mime_type();
+                    *data = BASE64.encode(encoded);
+                }
+                // The original image is still worth a try
+                Err(e) => eprintln!("[{}] 图片转换为 {} 失败: {}", task_id, format.as_str(), e),
+            }
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, thiserror::Error)]
 pub enum ApiError {
+    #[error("{0}")]
     Retryable(String),
+    /// Request timed out; retryable, but kept distinct so callers can shrink the payload
+    #[error("{0}")]
+    Timeout(String),
+    /// HTTP 429 or 408; retried after the delay the provider asked for, if it did
+    #[error("{message}")]
+    RateLimited { message: String, retry_after: Option<Duration> },
+    #[error("{0}")]
     NonRetryable(String),
 }
 
-impl std::fmt::Display for ApiError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl ApiError {
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, ApiError::Timeout(_))
+    }
+
+    fn with_suffix(self, suffix: &str) -> Self {
         match self {
-            ApiError::Retryable(msg) => write!(f, "{}", msg),
-            ApiError::NonRetryable(msg) => write!(f, "{}", msg),
+            ApiError::Retryable(msg) => ApiError::Retryable(msg + suffix),
+            ApiError::Timeout(msg) => ApiError::Timeout(msg + suffix),
+            ApiError::RateLimited { message, retry_after } => ApiError::RateLimited { message: message + suffix, retry_after },
+            ApiError::NonRetryable(msg) => ApiError::NonRetryable(msg + suffix),
         }
     }
 }
 
-fn classify_reqwest_error(e: &reqwest::Error) -> ApiError {
-    if e.is_timeout() || e.is_connect() {
-        ApiError::Retryable(format!("网络错误: {}", e))
-    } else {
-        ApiError::NonRetryable(format!("请求失败: {}", e))
-    }
+/// A failed request about to be retried
+pub struct RetryNotice {
+    /// 1 for the first retry
+    pub attempt: u32,
+    pub max_retries: u32,
+    pub delay_ms: u64,
+    pub error: String,
 }
 
-fn classify_http_status(status: reqwest::StatusCode, body: &str) -> ApiError {
-    if status.is_server_error() {
-        ApiError::Retryable(format!("API 错误 {}: {}", status, body))
-    } else {
-        ApiError::NonRetryable(format!("API 错误 {}: {}", status, body))
-    }
+/// Told how the requests of a page are going, for its progress
+pub trait PageObserver: Send + Sync {
+    /// A request failed and is about to be retried
+    fn retry(&self, notice: &RetryNotice);
+    /// Text of a streaming reply received so far
+    fn partial(&self, text: &str);
+}
+
+tokio::task_local! {
+    /// Observer of the page work running on this task, reported to the same
+    /// way heartbeats are
+    static OBSERVER: Arc<dyn PageObserver>;
 }
 
+/// Run work with its requests reported to `observer`
+pub async fn observe<F: Future>(observer: Arc<dyn PageObserver>, work: F) -> F::Output {
+    OBSERVER.scope(observer, work).await
+}
+
+/// Shortest time between two reports of a streaming reply's text
+const PARTIAL_INTERVAL: Duration = Duration::from_millis(250);
+
 async fn with_retry<F, Fut, T>(
     f: F,
     max_retries: u32,
     task_id: &str,
-) -> Result<T, String>
+) -> Result<T, ApiError>
 where
     F: Fn() -> Fut,
     Fut: Future<Output = Result<T, ApiError>>,
@@ -320,26 +509,39 @@ where
         match f().await {
             Ok(result) => return Ok(result),
             Err(ApiError::NonRetryable(msg)) => {
-                return Err(msg);
+                return Err(ApiError::NonRetryable(msg));
             }
-            Err(ApiError::Retryable(msg)) => {
+            Err(err) => {
                 if attempt == max_retries {
-                    return Err(format!("{} (已重试 {} 次)", msg, max_retries));
+                    return Err(err.with_suffix(&format!(" (已重试 {} 次)", max_retries)));
                 }
                 
-                let base_delay = base_delays.get(attempt as usize).copied().unwrap_or(4000);
-                let jitter = {
-                    let mut rng = rand::rng();
-                    let jitter_range = (base_delay as f64 * 0.1) as u64;
-                    rng.random_range(0..=jitter_range * 2) as i64 - jitter_range as i64
+                // A rate-limited reply says when to come back; otherwise back off
+                let delay = match &err {
+                    ApiError::RateLimited { retry_after: Some(wait), .. } => (wait.as_millis() as u64).max(100),
+                    _ => {
+                        let base_delay = base_delays.get(attempt as usize).copied().unwrap_or(4000);
+                        let jitter = {
+                            let mut rng = rand::rng();
+                            let jitter_range = (base_delay as f64 * 0.1) as u64;
+                            rng.random_range(0..=jitter_range * 2) as i64 - jitter_range as i64
+                        };
+                        (base_delay as i64 + jitter).max(100) as u64
+                    }
                 };
-                let delay = (base_delay as i64 + jitter).max(100) as u64;
                 
                 eprintln!(
                     "[{}] 重试 {}/{}: {} (等待 {}ms)",
-                    task_id, attempt + 1, max_retries, msg, delay
+                    task_id, attempt + 1, max_retries, err, delay
                 );
                 
+                watchdog::beat(|| format!("等待重试 {}/{}: {}", attempt + 1, max_retries, err));
+                let _ = OBSERVER.try_with(|observer| observer.retry(&RetryNotice {
+                    attempt: attempt + 1,
+                    max_retries,
+                    delay_ms: delay,
+                    error: err.to_string(),
+                }));
                 sleep(Duration::from_millis(delay)).await;
             }
         }
@@ -348,31 +550,280 @@ where
     unreachable!()
 }
 
-async fn call_api_inner(config: &Config, request: &ChatRequest<'_>) -> Result<String, ApiError> {
-    let url = format!("{}/v1/chat/completions", config.base_url.trim_end_matches('/'));
+/// Which pipeline stage a request belongs to; each has its own timeout and retry budget
+#[derive(Clone, Copy)]
+enum CallKind {
+    Ocr,
+    Translate,
+}
+
+impl CallKind {
+    fn label(self) -> &'static str {
+        match self {
+            CallKind::Ocr => "OCR",
+            CallKind::Translate => "翻译",
+        }
+    }
+
+    fn timeout(self, config: &Config) -> Duration {
+        match self {
+            CallKind::Ocr => Duration::from_secs(config.ocr_timeout_secs),
+            CallKind::Translate => Duration::from_secs(config.translate_timeout_secs),
+        }
+    }
+
+    fn max_retries(self, config: &Config) -> u32 {
+        match self {
+            CallKind::Ocr => config.ocr_max_retries,
+            CallKind::Translate => config.translate_max_retries,
+        }
+    }
+}
+
+/// Most follow-up requests for one reply that keeps hitting max_tokens
+const MAX_CONTINUATIONS: u32 = 3;
+
+/// Send a chat request with retries. A reply cut off at max_tokens is
+/// continued with follow-up requests and the pieces joined.
+async fn call_api(config: &Config, request: &ChatRequest<'_>, kind: CallKind, task_id: &str) -> Result<Completion, ApiError> {
+    let reply = call_api_retrying(config, request, kind, task_id).await?;
+    let mut usage = reply.usage.unwrap_or_else(|| Usage::estimate(request.estimate_prompt_tokens(), &reply.text));
+    let mut text = reply.text;
+    let mut truncated = reply.truncated;
+    let mut continuations = 0;
+    while truncated && continuations < MAX_CONTINUATIONS {
+        continuations += 1;
+        let mut messages = request.messages.clone();
+        messages.push(Message { role: "assistant".to_string(), content: MessageContent::Text(text.clone()) });
+        messages.push(Message { role: "user".to_string(), content: MessageContent::Text(CONTINUE_PROMPT.to_string()) });
+        let continuation = ChatRequest { model: request.model, messages, max_tokens: request.max_tokens, stream: request.stream };
+        eprintln!("[{}] {} 回复达到长度上限，续写第 {} 次 (已有 {} 字符)", task_id, kind.label(), continuations, text.chars().count());
+        match call_api_retrying(config, &continuation, kind, task_id).await {
+            Ok(reply) => {
+                usage.add(&reply.usage.unwrap_or_else(|| Usage::estimate(continuation.estimate_prompt_tokens(), &reply.text)));
+                text.push_str(&reply.text);
+                truncated = reply.truncated;
+            }
+            Err(e) => {
+                // The part already received is still worth keeping
+                eprintln!("[{}] {} 续写失败: {}", task_id, kind.label(), e);
+                break;
+            }
+        }
+    }
+    Ok(Completion { text, usage, model: request.model.to_string(), continuations, truncated })
+}
+
+/// One reply, with retries. In streaming mode, text received before a
+/// connection drop is kept and the retry asks the model to continue from there.
+async fn call_api_retrying(config: &Config, request: &ChatRequest<'_>, kind: CallKind, task_id: &str) -> Result<Reply, ApiError> {
+    let max_retries = kind.max_retries(config);
+    if request.stream {
+        let salvaged = parking_lot::Mutex::new(String::new());
+        with_retry(|| call_api_stream(config, request, kind, &salvaged, task_id), max_retries, task_id).await
+    } else {
+        with_retry(|| call_api_inner(config, request, kind), max_retries, task_id).await
+    }
+}
+
+/// What a streamed response has delivered so far
+#[derive(Default)]
+pub(crate) struct StreamedReply {
+    pub received: String,
+    /// A finish reason or [DONE] arrived
+    pub finished: bool,
+    pub truncated: bool,
+    /// Usage from the final chunk, sent when the request asks for it
+    pub reported: Option<Usage>,
+}
+
+impl StreamedReply {
+    /// Take in one line of the SSE body
+    pub fn line(&mut self, line: &str) {
+        let Some(data) = line.trim().strip_prefix("data:") else { return };
+        let data = data.trim();
+        if data == "[DONE]" {
+            self.finished = true;
+            return;
+        }
+        let Ok(chunk) = serde_json::from_str::<StreamChunk>(data) else { return };
+        if let Some(u) = chunk.usage.as_ref().and_then(usage::normalize) {
+            self.reported = Some(u);
+        }
+        for choice in chunk.choices {
+            if let Some(text) = choice.delta.and_then(|d| d.content) {
+                self.received.push_str(&text);
+            }
+            if let Some(reason) = choice.finish_reason {
+                self.truncated = reason == "length";
+                self.finished = true;
+            }
+        }
+    }
+}
+
+async fn call_api_stream(
+    config: &Config,
+    request: &ChatRequest<'_>,
+    kind: CallKind,
+    salvaged: &parking_lot::Mutex<String>,
+    task_id: &str,
+) -> Result<Reply, ApiError> {
+    let partial = salvaged.lock().clone();
+    let continuation;
+    let request = if partial.is_empty() {
+        request
+    } else {
+        let mut messages = request.messages.clone();
+        messages.push(Message { role: "assistant".to_string(), content: MessageContent::Text(partial.clone()) });
+        messages.push(Message { role: "user".to_string(), content: MessageContent::Text(CONTINUE_PROMPT.to_string()) });
+        continuation = ChatRequest { model: request.model, messages, max_tokens: request.max_tokens, stream: true };
+        eprintln!("[{}] 续传: 已保留 {} 字符", task_id, partial.chars().count());
+        &continuation
+    };
     
-    let response = get_client()
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", config.api_key))
-        .timeout(Duration::from_secs(30))
-        .json(request)
+    watchdog::beat(|| format!("{} 请求已发送 ({}, 流式)", kind.label(), request.model));
+    let mut response = provider::json_body(OpenAi::request(config), &OpenAi::body(request, true))
+        .timeout(kind.timeout(config))
         .send()
         .await
         .map_err(|e| classify_reqwest_error(&e))?;
     
     let status = response.status();
-    let body = response.text().await.unwrap_or_default();
-    
     if !status.is_success() {
-        return Err(classify_http_status(status, &body));
+        let headers = response.headers().clone();
+        let body = response.text().await.unwrap_or_default();
+        resources::count_received(body.len());
+        return Err(classify_http_status(status, &headers, &body));
     }
     
-    let chat_response: ChatResponse = serde_json::from_str(&body)
-        .map_err(|e| ApiError::NonRetryable(format!("解析失败: {} - 响应: {}", e, &body[..body.len().min(500)])))?;
+    let mut stream = StreamedReply::default();
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut last_partial = std::time::Instant::now();
+    
+    let outcome: Result<(), ApiError> = loop {
+        let chunk = match response.chunk().await {
+            Ok(Some(c)) => c,
+            Ok(None) => break Ok(()),
+            Err(e) => break Err(ApiError::Retryable(format!("流式响应中断: {}", e))),
+        };
+        resources::count_received(chunk.len());
+        buffer.extend_from_slice(&chunk);
+        watchdog::beat(|| format!("{} 流式接收中 (已收到 {} 字节)", kind.label(), stream.received.len() + buffer.len()));
+        
+        while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=pos).collect();
+            stream.line(&String::from_utf8_lossy(&line));
+        }
+        if last_partial.elapsed() >= PARTIAL_INTERVAL && !stream.received.is_empty() {
+            last_partial = std::time::Instant::now();
+            let _ = OBSERVER.try_with(|observer| observer.partial(&format!("{}{}", partial, stream.received)));
+        }
+    };
+    
+    let StreamedReply { received, finished, truncated, reported } = stream;
+    let outcome = outcome.and_then(|_| {
+        if finished {
+            Ok(())
+        } else {
+            Err(ApiError::Retryable("流式响应未完成即结束".to_string()))
+        }
+    });
     
-    chat_response
-        .choices
-        .first()
-        .map(|c| c.message.content.clone())
-        .ok_or_else(|| ApiError::NonRetryable("空响应".to_string()))
+    let mut salvaged = salvaged.lock();
+    salvaged.push_str(&received);
+    match outcome {
+        Ok(()) => {
+            if salvaged.is_empty() {
+                return Err(ApiError::NonRetryable("空响应".to_string()));
+            }
+            // Usage from the final attempt alone undercounts a salvaged response
+            let reported = if partial.is_empty() { reported } else { None };
+            Ok(Reply { text: salvaged.clone(), usage: reported, truncated })
+        }
+        Err(e) => Err(e),
+    }
+}
+
+async fn call_api_inner(config: &Config, request: &ChatRequest<'_>, kind: CallKind) -> Result<Reply, ApiError> {
+    watchdog::beat(|| format!("{} 请求已发送 ({})", kind.label(), request.model));
+    provider::chat(config, request, kind.timeout(config)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::provider::{ChatRequest, ModelListCache, OpenAi};
+    use crate::scheduler::PageScheduler;
+    use crate::testing::config;
+
+    /// Requests in flight at the mock API, and the most seen at once
+    #[derive(Default)]
+    struct InFlight {
+        now: AtomicUsize,
+        peak: AtomicUsize,
+    }
+
+    /// An API whose model listing takes a while; returns its base URL
+    async fn slow_upstream(in_flight: Arc<InFlight>) -> String {
+        let app = axum::Router::new().route("/v1/models", axum::routing::get(move || async move {
+            let now = in_flight.now.fetch_add(1, Ordering::SeqCst) + 1;
+            in_flight.peak.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            in_flight.now.fetch_sub(1, Ordering::SeqCst);
+            axum::Json(serde_json::json!({ "data": [{ "id": "mock-model" }] }))
+        }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn warm_up_keepalive_and_model_listing_hold_api_permits() {
+        for permits in [1, 3] {
+            let in_flight = Arc::new(InFlight::default());
+            let config = Config { base_url: slow_upstream(in_flight.clone()).await, ..config() };
+            let scheduler = Arc::new(PageScheduler::new(permits));
+            let models = ModelListCache::default();
+            let (warm_up, keepalive, listed) = tokio::join!(
+                warm_up(&config, &scheduler, false),
+                warm_up(&config, &scheduler, true),
+                models.get(&config, &scheduler),
+            );
+            warm_up.unwrap();
+            keepalive.unwrap();
+            assert_eq!(listed.unwrap().models, ["mock-model"]);
+            assert_eq!(in_flight.peak.load(Ordering::SeqCst), permits);
+        }
+    }
+
+    #[test]
+    fn streamed_replies_take_usage_from_the_final_chunk() {
+        let request = ChatRequest { model: "m", messages: Vec::new(), max_tokens: None, stream: true };
+        let body = OpenAi::body(&request, true);
+        assert_eq!(body["stream_options"]["include_usage"], true);
+        assert!(OpenAi::body(&request, false).get("stream_options").is_none());
+
+        let sse = concat!(
+            "data: {\"choices\":[{\"delta\":{\"content\":\"你好\"}}]}\n",
+            "\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"世界\"},\"finish_reason\":\"stop\"}]}\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":12,\"completion_tokens\":4,\"total_tokens\":16}}\n",
+            "data: [DONE]\n",
+        );
+        let mut reply = StreamedReply::default();
+        for line in sse.lines() {
+            reply.line(line);
+        }
+        assert_eq!(reply.received, "你好世界");
+        assert!(reply.finished && !reply.truncated);
+        let usage = reply.reported.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens, usage.estimated), (12, 4, false));
+    }
 }
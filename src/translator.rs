@@ -0,0 +1,304 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+use tokio_stream::Stream;
+
+use crate::config::Config;
+use crate::error::SetupError;
+use crate::lang::TargetLang;
+use crate::pipeline::{process_pdf_parallel, spawn_warm_up};
+use crate::state::{self, AppState, LogEntry, PageSummary, TaskOptions, TaskProgress, TaskStatus};
+use crate::{render, workers};
+
+/// PDF translation without the HTTP server, for programs that embed it.
+///
+/// Tasks run through the same pipeline as uploads and are stored the same
+/// way, under `tasks` in DATA_DIR (`data` by default); concurrency follows
+/// MAX_CONCURRENT_TASKS and the other limits in `Config`.
+///
+/// ```no_run
+/// use pdftrans::{Config, ProgressEvent, TaskOptions, Translator};
+/// use tokio_stream::StreamExt;
+///
+/// # async fn run(pdf: Vec<u8>) {
+/// let translator = Translator::new(Config::from_env()).expect("renderer settings");
+/// let events = translator.process("paper.pdf", pdf, TaskOptions::default());
+/// tokio::pin!(events);
+/// while let Some(event) = events.next().await {
+///     if let ProgressEvent::Finished { outputs, .. } = event {
+///         for (lang, pdf) in outputs {
+///             std::fs::write(format!("paper_{}.pdf", lang.code()), pdf).unwrap();
+///         }
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Translator {
+    state: Arc<AppState>,
+}
+
+impl Translator {
+    /// A translator with its own task limits and stores. Sets up the page
+    /// renderer and the CPU pool the first time one is created; fails when
+    /// PDFTOPPM_PATH or RENDER_TEMP_DIR is unusable.
+    pub fn new(config: Config) -> Result<Self, SetupError> {
+        if let Some(path) = &config.pdftoppm_path {
+            render::set_pdftoppm(path).map_err(SetupError::Pdftoppm)?;
+        }
+        render::init(config.pdfium_path.as_deref(), config.renderer);
+        if let Some(dir) = &config.render_temp_dir {
+            render::set_temp_dir(dir).map_err(|source| SetupError::TempDir { dir: dir.clone(), source })?;
+        }
+        workers::init(config.cpu_workers);
+        Ok(Self { state: Arc::new(AppState::new(config)) })
+    }
+
+    /// Translate a PDF (or a JPEG/PNG image) named `filename`. The stream
+    /// reports progress as it changes and always ends with `Finished`, also
+    /// when the task is refused or deleted while it runs. OCR review is not
+    /// available here: `review_ocr` is ignored.
+    pub fn process(&self, filename: &str, data: Vec<u8>, mut options: TaskOptions) -> impl Stream<Item = ProgressEvent> + use<> {
+        let state = self.state.clone();
+        let task_id = uuid::Uuid::new_v4().to_string();
+        let filename = filename.to_string();
+        options.review_ocr = false;
+        async_stream::stream! {
+            if !state.try_acquire_task_slot(options.background) {
+                yield ProgressEvent::Finished {
+                    task_id,
+                    status: TaskStatus::Error,
+                    message: state.busy_message(options.background),
+                    outputs: Vec::new(),
+                };
+                return;
+            }
+            if let Err(e) = state::save_input_pdf(&task_id, &data) {
+                state.release_task_slot(options.background);
+                yield ProgressEvent::Finished {
+                    task_id,
+                    status: TaskStatus::Error,
+                    message: format!("保存文件失败: {}", e),
+                    outputs: Vec::new(),
+                };
+                return;
+            }
+            state.create_task(&task_id, &filename, options);
+            state.stats.record_task();
+            spawn_warm_up(&state);
+            if let Some(mut updates) = state.subscribe(&task_id) {
+                tokio::spawn(process_pdf_parallel(state.clone(), task_id.clone(), data));
+
+                let mut previous: Option<TaskProgress> = None;
+                while let Some(progress) = state.get_progress(&task_id) {
+                    for event in ProgressEvent::changes(previous.as_ref(), &progress) {
+                        yield event;
+                    }
+                    if progress.is_done() {
+                        yield ProgressEvent::Finished {
+                            outputs: outputs(&state, &task_id),
+                            task_id,
+                            status: progress.status,
+                            message: progress.message,
+                        };
+                        return;
+                    }
+                    previous = Some(progress);
+                    if updates.changed().await.is_err() {
+                        break;
+                    }
+                }
+            }
+            // Deleted (e.g. through the HTTP API) before it finished
+            yield ProgressEvent::Finished {
+                task_id,
+                status: TaskStatus::Error,
+                message: "任务已被删除".to_string(),
+                outputs: Vec::new(),
+            };
+        }
+    }
+}
+
+/// The generated PDFs of a finished task, primary language first
+fn outputs(state: &AppState, task_id: &str) -> Vec<(TargetLang, Vec<u8>)> {
+    let options = state.get_options(task_id).unwrap_or_default();
+    options.all_langs().into_iter()
+        .filter_map(|lang| {
+            let path = state.get_output_path(task_id, Some(lang))?;
+            std::fs::read(path).ok().map(|pdf| (lang, pdf))
+        })
+        .collect()
+}
+
+/// A change in a task's progress. The server's progress stream sends the
+/// same changes as named SSE events.
+#[derive(Clone, Serialize)]
+pub enum ProgressEvent {
+    Status(StatusChange),
+    /// A page is translated (or left untranslated in best-effort mode)
+    PageDone(Box<PageSummary>),
+    PageError { page_num: usize, error: Option<String> },
+    Log(LogEntry),
+    /// Last event of `Translator::process`: the task completed, was skipped
+    /// or failed. `outputs` holds the PDF of each target language.
+    Finished {
+        task_id: String,
+        status: TaskStatus,
+        message: String,
+        #[serde(skip)]
+        outputs: Vec<(TargetLang, Vec<u8>)>,
+    },
+}
+
+#[derive(Clone, Serialize)]
+pub struct StatusChange {
+    pub status: TaskStatus,
+    pub message: String,
+    pub overall_percent: u8,
+    pub ocr_done: usize,
+    pub translate_done: usize,
+    pub total_pages: usize,
+    pub pages_per_minute: Option<f64>,
+    pub eta_seconds: Option<u64>,
+}
+
+impl ProgressEvent {
+    /// What changed since the previous snapshot. The first snapshot is the
+    /// baseline and only gets a `Status` event.
+    pub fn changes(previous: Option<&TaskProgress>, current: &TaskProgress) -> Vec<ProgressEvent> {
+        let mut events = Vec::new();
+        let status_changed = previous.is_none_or(|p| {
+            p.status != current.status || p.message != current.message || p.overall_percent != current.overall_percent
+        });
+        if status_changed {
+            events.push(ProgressEvent::Status(StatusChange {
+                status: current.status.clone(),
+                message: current.message.clone(),
+                overall_percent: current.overall_percent,
+                ocr_done: current.ocr_done,
+                translate_done: current.translate_done,
+                total_pages: current.total_pages,
+                pages_per_minute: current.pages_per_minute,
+                eta_seconds: current.eta_seconds,
+            }));
+        }
+        let Some(previous) = previous else { return events };
+
+        for page in &current.page_summaries {
+            let before = previous.page_summaries.iter().find(|p| p.page_num == page.page_num);
+            let was = |status: &str| before.is_some_and(|p| p.status == status);
+            if page.status == "done" && !was("done") {
+                events.push(ProgressEvent::PageDone(Box::new(page.clone())));
+            }
+            if page.error.is_some() && before.is_none_or(|p| p.error != page.error) {
+                events.push(ProgressEvent::PageError { page_num: page.page_num, error: page.error.clone() });
+            }
+        }
+
+        // Old entries fall off the front of the log, so new ones are those
+        // after the last entry already sent
+        let last_sent = previous.logs.last();
+        let new_from = last_sent
+            .and_then(|last| current.logs.iter().rposition(|l| l.ts == last.ts && l.msg == last.msg))
+            .map_or(0, |i| i + 1);
+        events.extend(current.logs[new_from..].iter().cloned().map(ProgressEvent::Log));
+        events
+    }
+
+    /// SSE event name
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProgressEvent::Status(_) => "status",
+            ProgressEvent::PageDone(_) => "page_done",
+            ProgressEvent::PageError { .. } => "page_error",
+            ProgressEvent::Log(_) => "log",
+            ProgressEvent::Finished { .. } => "finished",
+        }
+    }
+
+    /// SSE event data: the event's fields, without the variant name
+    pub fn data(&self) -> Value {
+        match self {
+            ProgressEvent::Status(change) => serde_json::to_value(change),
+            ProgressEvent::PageDone(page) => serde_json::to_value(page),
+            ProgressEvent::PageError { page_num, error } => Ok(serde_json::json!({ "page_num": page_num, "error": error })),
+            ProgressEvent::Log(entry) => serde_json::to_value(entry),
+            ProgressEvent::Finished { task_id, status, message, .. } => {
+                Ok(serde_json::json!({ "task_id": task_id, "status": status, "message": message }))
+            }
+        }
+        .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_stream::StreamExt;
+
+    use super::*;
+    use crate::testing::config;
+
+    /// Progress of a fresh two-page task
+    fn two_page_progress() -> TaskProgress {
+        let state = AppState::new(config());
+        let task_id = uuid::Uuid::new_v4().to_string();
+        state.create_task(&task_id, "report.pdf", TaskOptions::default());
+        let mut progress = state.get_progress(&task_id).unwrap();
+        let _ = std::fs::remove_dir_all(state::task_dir(&task_id));
+        progress.page_summaries = (1..=2)
+            .map(|page_num| PageSummary { page_num, status: "pending".to_string(), ..Default::default() })
+            .collect();
+        progress
+    }
+
+    #[test]
+    fn progress_event_changes_skip_logs_already_sent() {
+        let log = |ts: u64, msg: &str| LogEntry { ts, msg: msg.to_string() };
+        let mut before = two_page_progress();
+        before.logs = vec![log(1, "任务开始"), log(2, "渲染完成")];
+        // The oldest entry fell off the front while two were added
+        let mut after = before.clone();
+        after.logs = vec![log(2, "渲染完成"), log(3, "第 1 页完成"), log(4, "第 2 页完成")];
+        after.page_summaries[1].status = "done".to_string();
+        after.overall_percent = 90;
+
+        let events = ProgressEvent::changes(Some(&before), &after);
+        assert!(matches!(&events[0], ProgressEvent::Status(change) if change.overall_percent == 90));
+        assert!(matches!(&events[1], ProgressEvent::PageDone(page) if page.page_num == 2));
+        let logs: Vec<&str> = events[2..].iter().map(|event| match event {
+            ProgressEvent::Log(entry) => entry.msg.as_str(),
+            _ => panic!("unexpected {} event", event.name()),
+        }).collect();
+        assert_eq!(logs, ["第 1 页完成", "第 2 页完成"]);
+    }
+
+    /// Every event of a `Translator::process` run
+    async fn translate_events(config: Config, data: &[u8]) -> Vec<ProgressEvent> {
+        let translator = Translator::new(config).unwrap();
+        translator.process("broken.pdf", data.to_vec(), TaskOptions::default()).collect().await
+    }
+
+    #[tokio::test]
+    async fn translator_refused_task_still_finishes() {
+        let events = translate_events(Config { max_concurrent_tasks: 0, ..config() }, b"%PDF-1.4").await;
+        let [ProgressEvent::Finished { status, message, outputs, .. }] = events.as_slice() else {
+            panic!("expected only a finished event");
+        };
+        assert_eq!(*status, TaskStatus::Error);
+        assert!(message.contains("服务繁忙") && outputs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn translator_reports_failed_task() {
+        let events = translate_events(config(), b"not a pdf").await;
+        assert!(matches!(events.first(), Some(ProgressEvent::Status(_))));
+        let Some(ProgressEvent::Finished { task_id, status, outputs, .. }) = events.last() else {
+            panic!("expected a finished event last");
+        };
+        let _ = std::fs::remove_dir_all(state::task_dir(task_id));
+        assert_eq!(*status, TaskStatus::Error);
+        assert!(outputs.is_empty());
+        assert_eq!(events.iter().filter(|e| e.name() == "finished").count(), 1);
+    }
+}
@@ -0,0 +1,35 @@
+/// Built-in single-page UI; operators can replace it with INDEX_HTML_PATH
+pub const DEFAULT_INDEX_HTML: &str = include_str!("index.html");
+
+/// Branding values substituted into the index page
+pub struct Branding {
+    pub title: String,
+    pub logo_url: Option<String>,
+    pub disclaimer: Option<String>,
+}
+
+/// Fill the template placeholders: `{{title}}` and `{{logo_url}}` (escaped
+/// text), `{{logo}}` (an <img> or the default icon) and `{{disclaimer}}`
+/// (a paragraph, or nothing when unset).
+pub fn render_index(template: &str, branding: &Branding) -> String {
+    let logo = match &branding.logo_url {
+        Some(url) => format!("<img class=\"brand-logo\" src=\"{}\" alt=\"\">", escape_html(url)),
+        None => "📄 ".to_string(),
+    };
+    let disclaimer = branding.disclaimer.as_ref()
+        .map(|text| format!("<p class=\"disclaimer\">{}</p>", escape_html(text)))
+        .unwrap_or_default();
+    template
+        .replace("{{title}}", &escape_html(&branding.title))
+        .replace("{{logo_url}}", &escape_html(branding.logo_url.as_deref().unwrap_or_default()))
+        .replace("{{logo}}", &logo)
+        .replace("{{disclaimer}}", &disclaimer)
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
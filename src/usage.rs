@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+
+/// Approximate prompt cost of one OCR page image (≈800px JPEG, 2x2 tiles)
+pub const IMAGE_TOKEN_ESTIMATE: u64 = 765;
+
+/// Token usage in a provider-independent shape
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// true when the provider reported nothing and the numbers were estimated locally
+    pub estimated: bool,
+}
+
+impl Usage {
+    pub fn total(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    pub fn add(&mut self, other: &Usage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.estimated |= other.estimated;
+    }
+
+    pub fn estimate(prompt_tokens: u64, completion_text: &str) -> Self {
+        Self {
+            prompt_tokens,
+            completion_tokens: estimate_tokens(completion_text),
+            estimated: true,
+        }
+    }
+}
+
+/// Read a `usage` object from any of the common provider formats:
+/// OpenAI (prompt_tokens/completion_tokens), Anthropic (input_tokens/output_tokens)
+/// and Gemini (promptTokenCount/candidatesTokenCount).
+pub fn normalize(value: &serde_json::Value) -> Option<Usage> {
+    let field = |names: &[&str]| names.iter().find_map(|n| value.get(*n).and_then(|v| v.as_u64()));
+    let prompt = field(&["prompt_tokens", "input_tokens", "promptTokenCount"]);
+    let completion = field(&["completion_tokens", "output_tokens", "candidatesTokenCount"]);
+
+    match (prompt, completion) {
+        (None, None) => {
+            // Only a total is reported; attribute it all to the prompt side
+            field(&["total_tokens", "totalTokenCount"]).map(|total| Usage {
+                prompt_tokens: total,
+                completion_tokens: 0,
+                estimated: false,
+            })
+        }
+        (p, c) => Some(Usage {
+            prompt_tokens: p.unwrap_or(0),
+            completion_tokens: c.unwrap_or(0),
+            estimated: false,
+        }),
+    }
+}
+
+/// Rough tiktoken-like count: CJK characters are about one token each,
+/// other scripts about four characters per token.
+pub fn estimate_tokens(text: &str) -> u64 {
+    let mut wide = 0u64;
+    let mut narrow = 0u64;
+    for c in text.chars() {
+        if is_wide_char(c) {
+            wide += 1;
+        } else {
+            narrow += 1;
+        }
+    }
+    wide + narrow.div_ceil(4)
+}
+
+fn is_wide_char(c: char) -> bool {
+    let code = c as u32;
+    (0x3040..=0x30FF).contains(&code)    // Kana
+        || (0x3400..=0x4DBF).contains(&code)
+        || (0x4E00..=0x9FFF).contains(&code)
+        || (0xAC00..=0xD7AF).contains(&code) // Hangul
+        || (0xFF00..=0xFFEF).contains(&code) // Fullwidth forms
+        || (0x20000..=0x2A6DF).contains(&code)
+}
@@ -0,0 +1,51 @@
+use parking_lot::Mutex;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Last sign of life from a page being processed: when, and what it was
+struct Heartbeat {
+    last: Mutex<(Instant, String)>,
+}
+
+tokio::task_local! {
+    /// Heartbeat of the page whose work is running on this task; the API
+    /// layer reports into it without having to thread it through every call
+    static HEARTBEAT: Arc<Heartbeat>;
+}
+
+/// Record progress (a request sent, a response or stream chunk received, a
+/// retry scheduled). Does nothing outside `watch`.
+pub fn beat(activity: impl FnOnce() -> String) {
+    let _ = HEARTBEAT.try_with(|heartbeat| {
+        *heartbeat.last.lock() = (Instant::now(), activity());
+    });
+}
+
+/// A page that went too long without a heartbeat
+pub struct Stall {
+    pub idle: Duration,
+    /// The last thing the page was seen doing
+    pub activity: String,
+}
+
+/// Run a page's work, aborting it (by dropping the future, which cancels any
+/// request in flight) once `limit` passes without a heartbeat
+pub async fn watch<F: Future>(limit: Duration, work: F) -> Result<F::Output, Stall> {
+    let heartbeat = Arc::new(Heartbeat { last: Mutex::new((Instant::now(), "开始处理".to_string())) });
+    let work = HEARTBEAT.scope(heartbeat.clone(), work);
+    tokio::pin!(work);
+
+    let mut ticker = tokio::time::interval((limit / 10).clamp(Duration::from_secs(1), Duration::from_secs(10)));
+    loop {
+        tokio::select! {
+            output = &mut work => return Ok(output),
+            _ = ticker.tick() => {
+                let (at, activity) = heartbeat.last.lock().clone();
+                if at.elapsed() >= limit {
+                    return Err(Stall { idle: at.elapsed(), activity });
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use serde::Serialize;
+use std::sync::{Arc, OnceLock};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Blocking threads for CPU-bound steps (page rendering, image re-encoding,
+/// PDF assembly), so they never hold up the async runtime serving progress
+/// streams. At most `size` jobs run at once; the rest wait their turn.
+struct CpuPool {
+    size: usize,
+    permits: Arc<Semaphore>,
+    queued: AtomicUsize,
+    running: AtomicUsize,
+    completed: AtomicU64,
+}
+
+/// Pool load, for the metrics endpoint
+#[derive(Clone, Copy, Serialize)]
+pub struct PoolStats {
+    pub size: usize,
+    pub running: usize,
+    /// Jobs waiting for a free worker
+    pub queued: usize,
+    pub completed: u64,
+}
+
+static POOL: OnceLock<CpuPool> = OnceLock::new();
+
+/// Size the pool; CPU_WORKERS defaults to the number of CPUs. Returns a short
+/// description for the startup banner.
+pub fn init(size: usize) -> String {
+    let pool = pool_with(size);
+    format!("{} worker threads", pool.size)
+}
+
+fn pool_with(size: usize) -> &'static CpuPool {
+    POOL.get_or_init(|| CpuPool {
+        size,
+        permits: Arc::new(Semaphore::new(size)),
+        queued: AtomicUsize::new(0),
+        running: AtomicUsize::new(0),
+        completed: AtomicU64::new(0),
+    })
+}
+
+/// The pool, sized by CPU count if `init` was never called (tests)
+fn pool() -> &'static CpuPool {
+    pool_with(std::thread::available_parallelism().map_or(1, |n| n.get()))
+}
+
+/// Run `job` on a worker thread once one is free. A caller that gives up
+/// (e.g. a stalled page being aborted) leaves a started job to finish; it
+/// keeps its worker until then.
+pub async fn run<T, F>(job: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    run_on(pool(), job).await
+}
+
+async fn run_on<T, F>(pool: &'static CpuPool, job: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let waiting = Waiting::enter(&pool.queued);
+    let permit = pool.permits.clone().acquire_owned().await.expect("worker pool closed");
+    drop(waiting);
+    let result = tokio::task::spawn_blocking(move || {
+        let _running = Running::start(pool, permit);
+        job()
+    }).await;
+    result.unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+}
+
+/// Holds a worker while a job runs, and gives it back even if the job panics
+struct Running {
+    pool: &'static CpuPool,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Running {
+    fn start(pool: &'static CpuPool, permit: OwnedSemaphorePermit) -> Self {
+        pool.running.fetch_add(1, Ordering::SeqCst);
+        Self { pool, _permit: permit }
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.pool.running.fetch_sub(1, Ordering::SeqCst);
+        self.pool.completed.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Counts a caller in the queue until it gets a worker or gives up
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl<'a> Waiting<'a> {
+    fn enter(queued: &'a AtomicUsize) -> Self {
+        queued.fetch_add(1, Ordering::SeqCst);
+        Self(queued)
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub fn stats() -> PoolStats {
+    let pool = pool();
+    PoolStats {
+        size: pool.size,
+        running: pool.running.load(Ordering::SeqCst),
+        queued: pool.queued.load(Ordering::SeqCst),
+        completed: pool.completed.load(Ordering::SeqCst),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn panicking_job_gives_its_worker_back() {
+        let pool: &'static CpuPool = Box::leak(Box::new(CpuPool {
+            size: 1,
+            permits: Arc::new(Semaphore::new(1)),
+            queued: AtomicUsize::new(0),
+            running: AtomicUsize::new(0),
+            completed: AtomicU64::new(0),
+        }));
+        let panicked = tokio::spawn(run_on(pool, || panic!("render failed"))).await;
+        assert!(panicked.is_err());
+        assert_eq!(pool.running.load(Ordering::SeqCst), 0);
+        assert_eq!(pool.completed.load(Ordering::SeqCst), 1);
+        // The only worker is free again
+        assert_eq!(run_on(pool, || 42).await, 42);
+    }
+}